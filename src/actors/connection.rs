@@ -1,94 +1,126 @@
-use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
 use bytes::BytesMut;
 use futures::SinkExt;
-use tokio::net::TcpStream;
-use tokio::sync::{mpsc, RwLock};
-use tokio::time::{interval, timeout};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::{interval_at, sleep_until, timeout, Instant, Interval};
 use tokio_stream::StreamExt;
 use tokio_util::codec::Framed;
 use tracing::{debug, error, info, warn, trace};
 
+use crate::commands::{CommandContext, COMMANDS};
+use crate::history::{HistoryItem, MessageType};
 use crate::protocol::{Command, IrcCodec, Message, Reply};
-use crate::security::RateLimiter;
-use crate::state::{Connection, ServerState};
+use crate::security::{ConnectClass, FloodProtection, FloodVerdict, SecurityError};
+use crate::security::auth::SaslMechanism;
+use crate::security::sasl::{self, Payload, SaslSession, SaslStep};
+use crate::state::{channel_modes, sendq, Connection, SendQueue, SendQueueReceiver, ServerState};
+use crate::state::connection::USER_MODES;
+use crate::utils::generate_message_id;
 
-const PING_INTERVAL: Duration = Duration::from_secs(120);
 const PING_TIMEOUT: Duration = Duration::from_secs(60);
-const MAX_MESSAGE_RATE: u32 = 10; // messages per second
 
-pub struct ConnectionActor {
+/// Drives a single client connection. Generic over the transport so that
+/// plaintext TCP and TLS clients share the same code path.
+pub struct ConnectionActor<S> {
     id: u64,
     addr: SocketAddr,
-    stream: Framed<TcpStream, IrcCodec>,
-    server_state: Arc<RwLock<ServerState>>,
-    rx: mpsc::Receiver<Message>,
-    tx: mpsc::Sender<Message>,
-    rate_limiter: RateLimiter,
+    secure: bool,
+    stream: Framed<S, IrcCodec>,
+    server_state: Arc<ServerState>,
+    rx: SendQueueReceiver,
+    tx: SendQueue,
+    /// Connection class the client is counted against
+    class: Arc<ConnectClass>,
+    flood: FloodProtection,
+    /// Commands held back by fakelag, each with when it may run
+    recvq: VecDeque<(Instant, Message)>,
+    ping_interval: Interval,
     ping_token: Option<String>,
     registered: bool,
     capabilities_negotiating: bool,
     capabilities_enabled: Vec<String>,
+    sasl: Option<SaslSession>,
+    /// Reason shown to channel peers in the QUIT sent on cleanup
+    quit_reason: Option<String>,
 }
 
-impl ConnectionActor {
+impl<S> ConnectionActor<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
     pub async fn new(
         id: u64,
-        tcp_stream: TcpStream,
+        io: S,
         addr: SocketAddr,
-        server_state: Arc<RwLock<ServerState>>,
+        server_state: Arc<ServerState>,
+        secure: bool,
+        certfp: Option<String>,
+        class: Arc<ConnectClass>,
     ) -> Self {
-        let stream = Framed::new(tcp_stream, IrcCodec::new());
-        let (tx, rx) = mpsc::channel(256);
+        let stream = Framed::new(io, IrcCodec::new());
+        let (tx, rx) = sendq::channel(class.sendq);
         
         // Register connection in server state
-        {
-            let mut state = server_state.write().await;
-            state.connections.insert(id, Connection::new(id, addr, tx.clone()));
-        }
+        let mut connection = Connection::new(id, addr, tx.clone());
+        connection.secure = secure;
+        connection.certfp = certfp;
+        server_state.connections.insert(id, connection);
         
         Self {
             id,
             addr,
+            secure,
             stream,
             server_state,
             rx,
             tx,
-            rate_limiter: RateLimiter::new(MAX_MESSAGE_RATE, Duration::from_secs(1)),
+            flood: flood_protection(&class),
+            recvq: VecDeque::new(),
+            ping_interval: ping_interval(&class),
+            class,
             ping_token: None,
             registered: false,
             capabilities_negotiating: false,
             capabilities_enabled: Vec::new(),
+            sasl: None,
+            quit_reason: None,
         }
     }
     
     pub async fn run(mut self) {
         info!("Connection actor {} started for {}", self.id, self.addr);
         
-        let mut ping_interval = interval(PING_INTERVAL);
-        ping_interval.tick().await; // Skip first immediate tick
-        
         loop {
+            let next_due = self.recvq.front().map_or_else(Instant::now, |(due, _)| *due);
+            
             tokio::select! {
                 // Handle incoming messages from client
                 result = self.stream.next() => {
                     match result {
                         Some(Ok(msg)) => {
-                            if !self.rate_limiter.check().await {
+                            let FloodVerdict::Due(due) = self.charge_penalty(&msg) else {
                                 self.send_error("Flood protection triggered").await;
+                                self.quit_reason = Some("Excess Flood".to_string());
                                 break;
-                            }
+                            };
                             
-                            if let Err(e) = self.handle_client_message(msg).await {
+                            // Commands over the allowance wait their turn;
+                            // reading carries on so the ceiling is enforced
+                            let due = Instant::from_std(due);
+                            if !self.recvq.is_empty() || due > Instant::now() {
+                                self.recvq.push_back((due, msg));
+                            } else if let Err(e) = self.handle_client_message(msg).await {
                                 error!("Error handling message: {}", e);
                                 break;
                             }
                         }
                         Some(Err(e)) => {
                             error!("Error reading from stream: {}", e);
+                            self.quit_reason = Some("Read error".to_string());
                             break;
                         }
                         None => {
@@ -98,16 +130,41 @@ impl ConnectionActor {
                     }
                 }
                 
+                // Run held-back commands once they are due
+                _ = sleep_until(next_due), if !self.recvq.is_empty() => {
+                    if let Some((_, msg)) = self.recvq.pop_front() {
+                        if let Err(e) = self.handle_client_message(msg).await {
+                            error!("Error handling message: {}", e);
+                            break;
+                        }
+                    }
+                }
+                
                 // Handle messages to send to client
                 Some(msg) = self.rx.recv() => {
-                    if let Err(e) = self.stream.send(msg).await {
+                    // A socket that has stopped draining mustn't hold off
+                    // the SendQ disconnect
+                    let sent = tokio::select! {
+                        sent = self.stream.send(msg) => sent,
+                        reason = self.tx.closed() => {
+                            self.close_link(reason).await;
+                            break;
+                        }
+                    };
+                    if let Err(e) = sent {
                         error!("Error sending message: {}", e);
                         break;
                     }
                 }
                 
+                // Someone overran this client's SendQ, or it was banned
+                reason = self.tx.closed() => {
+                    self.close_link(reason).await;
+                    break;
+                }
+                
                 // Send periodic PING
-                _ = ping_interval.tick() => {
+                _ = self.ping_interval.tick() => {
                     if let Err(e) = self.send_ping().await {
                         error!("Error sending ping: {}", e);
                         break;
@@ -124,16 +181,11 @@ impl ConnectionActor {
         debug!("Received from {}: {:?}", self.addr, msg);
         
         // Update activity timestamp
-        {
-            let mut state = self.server_state.write().await;
-            if let Some(mut conn) = state.connections.get_mut(&self.id) {
-                conn.update_activity();
-                drop(conn);
-            }
-            drop(state);
+        if let Some(mut conn) = self.server_state.connections.get_mut(&self.id) {
+            conn.update_activity();
         }
         
-        let command = Command::parse(&msg.command, msg.params);
+        let command = Command::parse(&msg.command, msg.params.clone());
         
         match command {
             Command::Cap { subcommand, params } => {
@@ -146,6 +198,9 @@ impl ConnectionActor {
             Command::User { username, realname } => {
                 self.handle_user(username, realname).await?;
             }
+            Command::Authenticate(data) => {
+                self.handle_authenticate(data).await?;
+            }
             Command::Ping(token) => {
                 self.handle_ping(token).await?;
             }
@@ -157,16 +212,17 @@ impl ConnectionActor {
                 return Err("Client quit".into());
             }
             _ => {
-                if !self.registered {
-                    self.send_reply(Reply::NotRegistered { 
-                        nick: "*".to_string() 
-                    }).await?;
-                } else {
-                    // Handle other commands through command processor
-                    let tags = msg.tags.clone().into_iter()
-                        .filter_map(|(k, v)| v.map(|value| (k, value)))
-                        .collect::<HashMap<String, String>>();
-                    self.process_command(command, tags).await?;
+                // Everything else goes through the command registry
+                let mut ctx = CommandContext::new(self.id);
+                ctx.registered = self.registered;
+                ctx.capabilities = self.capabilities_enabled.clone();
+                ctx.tags = msg.tags.clone().into_iter()
+                    .filter_map(|(k, v)| v.map(|value| (k, value)))
+                    .collect();
+                let responses = COMMANDS.dispatch(self.server_state.clone(), &ctx, msg).await?;
+                
+                for response in responses {
+                    self.send_message(response).await?;
                 }
             }
         }
@@ -186,15 +242,37 @@ impl ConnectionActor {
                     301
                 };
                 
+                // CAP 302 clients get the mechanism list as the sasl value
+                let sasl_cap = if cap_version >= 302 {
+                    format!("sasl={}", sasl::mechanism_list())
+                } else {
+                    "sasl".to_string()
+                };
+                
                 // Only advertise the core capabilities that our client actually supports
-                let caps = vec![
-                    "sasl",
-                    "message-tags",
-                    "server-time",
-                    "batch",
-                    "echo-message",
+                let mut caps = vec![
+                    sasl_cap,
+                    "message-tags".to_string(),
+                    "server-time".to_string(),
+                    "batch".to_string(),
+                    "echo-message".to_string(),
+                    "invite-notify".to_string(),
+                    "draft/pre-away".to_string(),
                 ];
                 
+                if self.server_state.config.features.enable_away_notify {
+                    caps.push("away-notify".to_string());
+                }
+                
+                let registration = crate::commands::handlers::register::capability_value(&self.server_state);
+                if let Some(value) = registration {
+                    caps.push(if cap_version >= 302 {
+                        format!("draft/account-registration={}", value)
+                    } else {
+                        "draft/account-registration".to_string()
+                    });
+                }
+                
                 let cap_string = caps.join(" ");
                 
                 // For now, use the simple 301 format to avoid breaking clients
@@ -225,11 +303,22 @@ impl ConnectionActor {
                     debug!("Parsed capabilities: {:?}", requested_caps);
                     
                     let mut ack_caps = Vec::new();
+                    let mut supported = vec!["sasl", "message-tags", "server-time", "batch", "echo-message", "invite-notify", "draft/pre-away"];
+                    let (registration_enabled, away_notify_enabled) = {
+                        let config = &self.server_state.config;
+                        (config.registration.enabled, config.features.enable_away_notify)
+                    };
+                    if registration_enabled {
+                        supported.push("draft/account-registration");
+                    }
+                    if away_notify_enabled {
+                        supported.push("away-notify");
+                    }
                     
                     for cap in requested_caps {
                         // Check if we support this capability
                         let cap_name = cap.split('=').next().unwrap_or(cap); // Handle capabilities with values
-                        if ["sasl", "message-tags", "server-time", "batch", "echo-message"].contains(&cap_name) {
+                        if supported.contains(&cap_name) {
                             ack_caps.push(cap.to_string());
                             self.capabilities_enabled.push(cap.to_string());
                         }
@@ -240,11 +329,8 @@ impl ConnectionActor {
                         info!("Client {} enabled capabilities: {:?}", self.id, ack_caps);
                         
                         // Update capabilities in server state
-                        {
-                            let mut state = self.server_state.write().await;
-                            if let Some(mut conn) = state.connections.get_mut(&self.id) {
-                                conn.capabilities = self.capabilities_enabled.clone();
-                            };
+                        if let Some(mut conn) = self.server_state.connections.get_mut(&self.id) {
+                            conn.capabilities = self.capabilities_enabled.clone();
                         }
                         
                         self.send_message(
@@ -262,6 +348,12 @@ impl ConnectionActor {
             }
             "END" => {
                 self.capabilities_negotiating = false;
+                
+                // Finishing registration mid-exchange aborts SASL
+                if self.sasl.take().is_some() {
+                    self.send_sasl_numeric("906", vec!["SASL authentication aborted".to_string()]).await?;
+                }
+                
                 self.check_registration().await?;
             }
             _ => {}
@@ -280,52 +372,68 @@ impl ConnectionActor {
             return Ok(());
         }
         
-        let nickname_available = {
-            let state = self.server_state.write().await;
-            state.is_nickname_available(&nick)
-        };
+        // Members can't change nickname to dodge a ban, or to a banned one
+        if self.registered {
+            let state = &*self.server_state;
+            let banned_in = state.connections.get(&self.id).and_then(|conn| {
+                let mut renamed = conn.clone();
+                renamed.nickname = Some(nick.clone());
+                state.channels.iter()
+                    .find(|channel| channel.is_member(self.id)
+                        && !channel.has_voice(self.id)
+                        && (channel.is_banned(&conn) || channel.is_banned(&renamed)))
+                    .map(|channel| (channel.name.clone(), state.server_name.clone()))
+            });
+            
+            if let Some((channel, server_name)) = banned_in {
+                let current = self.get_nick().await;
+                self.send_message(Message::new("435")
+                    .with_prefix(server_name)
+                    .with_params(vec![
+                        current,
+                        nick,
+                        channel,
+                        "Cannot change nickname while banned on channel".to_string(),
+                    ])).await?;
+                return Ok(());
+            }
+        }
         
-        // Check if nickname is available
-        if !nickname_available {
+        // Claim the new nickname and release the old one in one step, so
+        // two clients racing for the same nickname can't both get it
+        let current = self.server_state.connections.get(&self.id).and_then(|conn| conn.nickname.clone());
+        if !self.server_state.change_nickname(current.as_deref(), &nick, self.id) {
             self.send_reply(Reply::NicknameInUse {
-                nick: "*".to_string(),
+                nick: current.unwrap_or_else(|| "*".to_string()),
                 attempted: nick,
             }).await?;
             return Ok(());
         }
         
         // Update connection info
-        {
-            let mut state = self.server_state.write().await;
-            if let Some(mut conn) = state.connections.get_mut(&self.id) {
-                // Unregister old nickname if any
-                if let Some(old_nick) = &conn.nickname {
-                    state.unregister_nickname(old_nick);
-                }
-                
-                // Register new nickname
-                if state.register_nickname(nick.clone(), self.id) {
-                    conn.nickname = Some(nick);
-                } else {
-                    // Registration failed - nickname taken by someone else now
-                    return Ok(());
-                }
-                drop(conn);
+        let renamed = self.server_state.connections.get_mut(&self.id).and_then(|mut conn| {
+            let old_identity = conn.nickname.is_some().then(|| conn.clone());
+            conn.nickname = Some(nick.clone());
+            old_identity.map(|old| (old, conn.full_mask()))
+        });
+        
+        // MONITOR sees a nick change as the old nick leaving and the new one arriving
+        if let Some((old, mask)) = renamed.filter(|_| self.registered) {
+            let state = &*self.server_state;
+            state.record_whowas(&old);
+            if let Some(old_nick) = &old.nickname {
+                state.notify_monitors(old_nick, None).await;
             }
-            drop(state);
+            state.notify_monitors(&nick, Some(&mask)).await;
         }
         
-        // Now check registration without holding the lock
         self.check_registration().await?;
         
         Ok(())
     }
     
     async fn handle_user(&mut self, username: String, realname: String) -> Result<(), Box<dyn std::error::Error>> {
-        let conn_info = {
-            let state = self.server_state.write().await;
-            state.connections.get(&self.id).map(|conn| conn.username.is_some())
-        };
+        let conn_info = self.server_state.connections.get(&self.id).map(|conn| conn.username.is_some());
         
         let already_registered = match conn_info {
             Some(registered) => registered,
@@ -333,26 +441,15 @@ impl ConnectionActor {
         };
         
         if already_registered {
-            let nick = {
-                let state = self.server_state.read().await;
-                state.connections.get(&self.id)
-                    .map(|conn| conn.nickname.clone().unwrap_or_else(|| "*".to_string()))
-                    .unwrap_or_else(|| "*".to_string())
-            };
-            
+            let nick = self.get_nick().await;
             self.send_reply(Reply::AlreadyRegistered { nick }).await?;
             return Ok(());
         }
         
         // Update connection info
-        {
-            let mut state = self.server_state.write().await;
-            if let Some(mut conn) = state.connections.get_mut(&self.id) {
-                conn.username = Some(username);
-                conn.realname = Some(realname);
-                drop(conn);
-            }
-            drop(state);
+        if let Some(mut conn) = self.server_state.connections.get_mut(&self.id) {
+            conn.username = Some(username);
+            conn.realname = Some(realname);
         }
         
         self.check_registration().await?;
@@ -362,102 +459,214 @@ impl ConnectionActor {
     
     async fn check_registration(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         debug!("check_registration: registered={}, capabilities_negotiating={}", self.registered, self.capabilities_negotiating);
-        if self.registered || self.capabilities_negotiating {
+        if self.registered || self.capabilities_negotiating || self.sasl.is_some() {
             return Ok(());
         }
         
-        let registration_info = {
-            let state = self.server_state.read().await;
-            state.connections.get(&self.id).and_then(|conn| {
-                debug!("Connection state: nickname={:?}, username={:?}, registered={}", 
-                       conn.nickname, conn.username, conn.registered);
-                if conn.is_registered() {
-                    debug!("Connection is registered, proceeding with welcome");
-                    Some((conn.nickname.clone().unwrap(), state.server_name.clone()))
-                } else {
-                    debug!("Connection not fully registered yet");
-                    None
-                }
-            })
-        };
+        let state = self.server_state.clone();
+        let registration_info = state.connections.get(&self.id).and_then(|conn| {
+            debug!("Connection state: nickname={:?}, username={:?}, registered={}", 
+                   conn.nickname, conn.username, conn.registered);
+            if conn.is_registered() {
+                debug!("Connection is registered, proceeding with welcome");
+                Some((conn.nickname.clone().unwrap(), state.server_name.clone()))
+            } else {
+                debug!("Connection not fully registered yet");
+                None
+            }
+        });
         
         let (nick, server_name) = match registration_info {
             Some(info) => info,
             None => return Ok(()),
         };
         
+        if state.config.security.require_tls && !self.secure {
+            info!("Rejecting plaintext registration from {}", self.addr);
+            self.send_error("Closing link: this server requires a TLS connection, please reconnect using a TLS port").await;
+            return Err("TLS required".into());
+        }
+        
+        let kline = state.connections.get(&self.id).and_then(|conn| {
+            let username = conn.username.as_deref().unwrap_or("*");
+            state.server_bans.check_user(username, &conn.hostname, self.addr.ip()).err()
+        });
+        if let Some(SecurityError::Banned(reason)) = kline {
+            info!("Rejecting K-lined registration from {}: {}", self.addr, reason);
+            self.send_message(Message::new("465")
+                .with_prefix(server_name)
+                .with_params(vec![nick, format!("You are banned from this server- {}", reason)])).await?;
+            self.send_error(&format!("Closing Link: {} (K-Lined: {})", self.addr.ip(), reason)).await;
+            self.quit_reason = Some("K-Lined".to_string());
+            return Err("K-lined".into());
+        }
+        
         self.registered = true;
+        self.apply_account_class();
         
         // Update registered status in server state
-        {
-            let state = self.server_state.write().await;
-            if let Some(mut conn) = state.connections.get_mut(&self.id) {
-                conn.registered = true;
-            };
+        let mask = state.connections.get_mut(&self.id).map(|mut conn| {
+            conn.registered = true;
+            if conn.secure {
+                conn.modes.push('Z');
+            }
+            conn.full_mask()
+        });
+        if let Some(mask) = mask {
+            state.notify_monitors(&nick, Some(&mask)).await;
         }
+        
+        // Send welcome messages
+        self.send_reply(Reply::Welcome {
+            nick: nick.clone(),
+            network: "IronChat".to_string(),
+        }).await?;
+        
+        self.send_reply(Reply::YourHost {
+            nick: nick.clone(),
+            servername: server_name.clone(),
+            version: "ironchatd-0.1.0".to_string(),
+        }).await?;
+        
+        self.send_reply(Reply::Created {
+            nick: nick.clone(),
+            date: "2025-01-01".to_string(),
+        }).await?;
+        
+        self.send_reply(Reply::MyInfo {
+            nick: nick.clone(),
+            servername: server_name,
+            version: "ironchatd-0.1.0".to_string(),
+            usermodes: USER_MODES.to_string(),
+            chanmodes: channel_modes::all_letters(),
+        }).await?;
+        
+        let limits = &state.config.limits;
+        
+        // Send ISUPPORT - Only advertise features we actually implement
+        self.send_reply(Reply::ISupport {
+            nick,
+            tokens: vec![
+                "CASEMAPPING=ascii".to_string(),
+                format!("CHANMODES={}", channel_modes::chanmodes_token()),
+                "EXCEPTS=e".to_string(),
+                "INVEX=I".to_string(),
+                "EXTBAN=$,a".to_string(),  // $a:<account> matches by account name
+                "BOT=B".to_string(),
+                format!("MONITOR={}", limits.max_monitor_entries),
+                format!("MAXLIST=beI:{}", limits.max_list_entries),
+                "CHANTYPES=#&!+".to_string(),  // All supported channel types
+                format!("MODES={}", channel_modes::MAX_PARAM_MODES),
+                "NICKLEN=30".to_string(),
+                "CHANNELLEN=50".to_string(),
+                "TOPICLEN=390".to_string(),
+                "KICKLEN=255".to_string(),
+                format!("AWAYLEN={}", limits.max_away_length),
+                format!("PREFIX={}", channel_modes::prefix_token()),
+                "CHANLIMIT=#&!+:50".to_string(),
+                "TARGMAX=NAMES:1,LIST:1,KICK:1,WHO:1,PRIVMSG:4,NOTICE:4".to_string(),
+                "MSGREFTYPES=msgid,timestamp".to_string(), // For message tagging
+                "are supported by this server".to_string(),
+            ],
+        }).await?;
+        
+        // Send MOTD
+        let motd_messages = crate::commands::handlers::motd::send_motd(state.clone(), self.id).await?;
+        
+        for msg in motd_messages {
+            self.send_message(msg).await?;
+        }
+        
+        Ok(())
+    }
+    
+    async fn handle_authenticate(&mut self, data: String) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.capabilities_enabled.iter().any(|cap| cap == "sasl") {
+            return self.send_reply(Reply::UnknownCommand {
+                nick: self.get_nick().await,
+                command: "AUTHENTICATE".to_string(),
+            }).await;
+        }
+        
+        let Some(session) = self.sasl.as_mut() else {
+            // The first AUTHENTICATE names the mechanism
+            let (authenticated, certfp) = self.server_state.connections.get(&self.id)
+                .map(|conn| (conn.account.is_some(), conn.certfp.clone()))
+                .unwrap_or((false, None));
+            
+            if authenticated {
+                return self.send_sasl_numeric("907", vec!["You have already authenticated using SASL".to_string()]).await;
+            }
+            if data == "*" {
+                return self.send_sasl_numeric("906", vec!["SASL authentication aborted".to_string()]).await;
+            }
+            
+            match SaslMechanism::from_str(&data).filter(|m| sasl::supported_mechanisms().contains(m)) {
+                Some(mechanism) => {
+                    debug!("Client {} starting SASL {}", self.id, mechanism.as_str());
+                    self.sasl = Some(SaslSession::new(mechanism, certfp));
+                    self.send_message(Message::new("AUTHENTICATE").with_params(vec!["+".to_string()])).await?;
+                }
+                None => {
+                    self.send_sasl_numeric("908", vec![
+                        sasl::mechanism_list(),
+                        "are available SASL mechanisms".to_string(),
+                    ]).await?;
+                    self.send_sasl_numeric("904", vec!["SASL authentication failed".to_string()]).await?;
+                }
+            }
+            return Ok(());
+        };
+        
+        let step = match session.feed(&data) {
+            Payload::Pending => return Ok(()),
+            Payload::Complete(payload) => session.step(&payload, &self.server_state.accounts).await,
+            Payload::TooLong => {
+                self.sasl = None;
+                return self.send_sasl_numeric("905", vec!["SASL message too long".to_string()]).await;
+            }
+            Payload::Aborted => {
+                self.sasl = None;
+                return self.send_sasl_numeric("906", vec!["SASL authentication aborted".to_string()]).await;
+            }
+            Payload::Invalid => SaslStep::Failure,
+        };
+        
+        match step {
+            SaslStep::Challenge(challenge) => {
+                for chunk in sasl::encode_payload(&challenge) {
+                    self.send_message(Message::new("AUTHENTICATE").with_params(vec![chunk])).await?;
+                }
+            }
+            SaslStep::Success(account) => {
+                self.sasl = None;
+                info!("Client {} authenticated as {}", self.id, account);
                 
-                // Send welcome messages
-                self.send_reply(Reply::Welcome {
-                    nick: nick.clone(),
-                    network: "IronChat".to_string(),
-                }).await?;
-                
-                self.send_reply(Reply::YourHost {
-                    nick: nick.clone(),
-                    servername: server_name.clone(),
-                    version: "ironchatd-0.1.0".to_string(),
-                }).await?;
-                
-                self.send_reply(Reply::Created {
-                    nick: nick.clone(),
-                    date: "2025-01-01".to_string(),
-                }).await?;
-                
-                self.send_reply(Reply::MyInfo {
-                    nick: nick.clone(),
-                    servername: server_name,
-                    version: "ironchatd-0.1.0".to_string(),
-                    usermodes: "aiwroOs".to_string(),
-                    chanmodes: "k,l,imnpst".to_string(),
-                }).await?;
-                
-                // Send ISUPPORT - Only advertise features we actually implement
-                self.send_reply(Reply::ISupport {
-                    nick,
-                    tokens: vec![
-                        "CASEMAPPING=ascii".to_string(),
-                        "CHANMODES=,k,l,imnpst".to_string(),  // No ban/except/invite modes implemented yet
-                        "CHANTYPES=#&!+".to_string(),  // All supported channel types
-                        "MODES=3".to_string(),  // Max 3 mode changes per command (RFC 2812 limit)
-                        "NICKLEN=30".to_string(),
-                        "CHANNELLEN=50".to_string(),
-                        "TOPICLEN=390".to_string(),
-                        "KICKLEN=255".to_string(),
-                        "PREFIX=(ov)@+".to_string(),  // Only operator (@) and voice (+) implemented
-                        "CHANLIMIT=#&!+:50".to_string(),
-                        "TARGMAX=NAMES:1,LIST:1,KICK:1,WHO:1,PRIVMSG:4,NOTICE:4".to_string(),
-                        "MSGREFTYPES=msgid,timestamp".to_string(), // For message tagging
-                        "are supported by this server".to_string(),
-                    ],
-                }).await?;
-                
-                // Send MOTD
-                let motd_messages = crate::commands::handlers::motd::send_motd(
-                    self.server_state.clone(),
-                    self.id
-                ).await?;
+                let mask = self.server_state.connections.get_mut(&self.id).map(|mut conn| {
+                    conn.account = Some(account.clone());
+                    conn.full_mask()
+                });
                 
-                for msg in motd_messages {
-                    self.send_message(msg).await?;
-                }
+                self.send_sasl_numeric("900", vec![
+                    mask.unwrap_or_else(|| "*".to_string()),
+                    account.clone(),
+                    format!("You are now logged in as {}", account),
+                ]).await?;
+                self.send_sasl_numeric("903", vec!["SASL authentication successful".to_string()]).await?;
+                self.check_registration().await?;
+            }
+            SaslStep::Failure => {
+                self.sasl = None;
+                self.send_sasl_numeric("904", vec!["SASL authentication failed".to_string()]).await?;
+                self.check_registration().await?;
+            }
+        }
         
         Ok(())
     }
     
     async fn handle_ping(&mut self, token: String) -> Result<(), Box<dyn std::error::Error>> {
-        let state = self.server_state.read().await;
-        let server_name = state.server_name.clone();
-        drop(state);
+        let server_name = self.server_state.server_name.clone();
         
         self.send_message(
             Message::new("PONG")
@@ -474,210 +683,11 @@ impl ConnectionActor {
     }
     
     async fn handle_quit(&mut self, reason: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
-        let reason = reason.unwrap_or_else(|| "Client quit".to_string());
+        let reason = reason
+            .filter(|reason| crate::security::validation::validate_quit_message(reason))
+            .unwrap_or_else(|| "Client quit".to_string());
         info!("Client {} quit: {}", self.addr, reason);
-        Ok(())
-    }
-    
-    async fn process_command(&mut self, command: Command, msg_tags: HashMap<String, String>) -> Result<(), Box<dyn std::error::Error>> {
-        // Forward to command processor
-        match command {
-            Command::Join(channels, keys) => {
-                // Handle JOIN command
-                let responses = crate::commands::handlers::join::handle_join(
-                    self.server_state.clone(),
-                    self.id,
-                    channels,
-                    keys
-                ).await?;
-                
-                for response in responses {
-                    self.send_message(response).await?;
-                }
-            }
-            Command::Part(channels, message) => {
-                // Handle PART command
-                let responses = crate::commands::handlers::part::handle_part(
-                    self.server_state.clone(),
-                    self.id,
-                    channels,
-                    message
-                ).await?;
-                
-                for response in responses {
-                    self.send_message(response).await?;
-                }
-            }
-            Command::Privmsg { target, message } => {
-                // Handle PRIVMSG command
-                let responses = crate::commands::handlers::privmsg::handle_privmsg(
-                    self.server_state.clone(),
-                    self.id,
-                    target,
-                    message
-                ).await?;
-                
-                for response in responses {
-                    self.send_message(response).await?;
-                }
-            }
-            Command::TagMsg { target } => {
-                // Handle TAGMSG command (for reactions and other client tags)
-                // TAGMSG requires message-tags capability
-                if !self.capabilities_enabled.contains(&"message-tags".to_string()) {
-                    self.send_reply(Reply::UnknownCommand {
-                        nick: self.get_nick().await,
-                        command: "TAGMSG".to_string(),
-                    }).await?;
-                    return Ok(());
-                }
-                
-                // Get the message tags from the incoming message
-                let tags = msg_tags;
-                
-                // Forward the TAGMSG to the target
-                let responses = crate::commands::handlers::tagmsg::handle_tagmsg(
-                    self.server_state.clone(),
-                    self.id,
-                    target,
-                    tags
-                ).await?;
-                
-                for response in responses {
-                    self.send_message(response).await?;
-                }
-            }
-            Command::ChatHistory { subcommand, target, params } => {
-                // Handle CHATHISTORY command
-                let mut full_params = vec![subcommand, target];
-                full_params.extend(params);
-                let responses = crate::commands::handlers::chathistory::handle_chathistory(
-                    self.server_state.clone(),
-                    self.id,
-                    full_params
-                ).await?;
-                
-                for response in responses {
-                    self.send_message(response).await?;
-                }
-            }
-            Command::Whois(targets) => {
-                let responses = crate::commands::handlers::whois::handle_whois(
-                    self.server_state.clone(),
-                    self.id,
-                    targets
-                ).await?;
-                
-                for response in responses {
-                    self.send_message(response).await?;
-                }
-            }
-            Command::Query(target) => {
-                let responses = crate::commands::handlers::query::handle_query(
-                    self.server_state.clone(),
-                    self.id,
-                    target
-                ).await?;
-                
-                for response in responses {
-                    self.send_message(response).await?;
-                }
-            }
-            Command::Mode { target, modes, params } => {
-                let mut mode_params = vec![target];
-                if let Some(modes) = modes {
-                    mode_params.push(modes);
-                }
-                mode_params.extend(params);
-                let responses = crate::commands::handlers::mode::handle_mode(
-                    self.server_state.clone(),
-                    self.id,
-                    mode_params
-                ).await?;
-                
-                for response in responses {
-                    self.send_message(response).await?;
-                }
-            }
-            Command::Kick { channel, user, reason } => {
-                let mut params = vec![channel, user];
-                if let Some(reason) = reason {
-                    params.push(reason);
-                }
-                let responses = crate::commands::handlers::kick::handle_kick(
-                    self.server_state.clone(),
-                    self.id,
-                    params
-                ).await?;
-                
-                for response in responses {
-                    self.send_message(response).await?;
-                }
-            }
-            Command::Topic { channel, topic } => {
-                let mut params = vec![channel];
-                if let Some(topic) = topic {
-                    params.push(topic);
-                }
-                let responses = crate::commands::handlers::topic::handle_topic(
-                    self.server_state.clone(),
-                    self.id,
-                    params
-                ).await?;
-                
-                for response in responses {
-                    self.send_message(response).await?;
-                }
-            }
-            Command::Who(target) => {
-                let mut params = vec![];
-                if let Some(target) = target {
-                    params.push(target);
-                }
-                let responses = crate::commands::handlers::who::handle_who(
-                    self.server_state.clone(),
-                    self.id,
-                    params
-                ).await?;
-                
-                for response in responses {
-                    self.send_message(response).await?;
-                }
-            }
-            Command::List(channels) => {
-                let mut params = vec![];
-                if let Some(channels) = channels {
-                    params.extend(channels);
-                }
-                let responses = crate::commands::handlers::list::handle_list(
-                    self.server_state.clone(),
-                    self.id,
-                    params
-                ).await?;
-                
-                for response in responses {
-                    self.send_message(response).await?;
-                }
-            }
-            Command::Names(channels) => {
-                let responses = crate::commands::handlers::names::handle_names(
-                    self.server_state.clone(),
-                    self.id,
-                    channels
-                ).await?;
-                
-                for response in responses {
-                    self.send_message(response).await?;
-                }
-            }
-            // ... handle other commands
-            _ => {
-                self.send_reply(Reply::UnknownCommand {
-                    nick: self.get_nick().await,
-                    command: format!("{:?}", command),
-                }).await?;
-            }
-        }
+        self.quit_reason = Some(format!("Quit: {}", reason));
         Ok(())
     }
     
@@ -690,9 +700,7 @@ impl ConnectionActor {
         let token = format!("{}", self.id);
         self.ping_token = Some(token.clone());
         
-        let state = self.server_state.read().await;
-        let server_name = state.server_name.clone();
-        drop(state);
+        let server_name = self.server_state.server_name.clone();
         
         self.send_message(
             Message::new("PING")
@@ -707,14 +715,74 @@ impl ConnectionActor {
     }
     
     async fn send_reply(&mut self, reply: Reply) -> Result<(), Box<dyn std::error::Error>> {
-        let state = self.server_state.read().await;
-        let server_name = state.server_name.clone();
-        drop(state);
+        let server_name = self.server_state.server_name.clone();
         
         let msg = reply.to_message(&server_name);
         self.send_message(msg).await
     }
     
+    /// Send a 90x numeric addressed to the current nick, or "*" before NICK
+    async fn send_sasl_numeric(&mut self, numeric: &str, params: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+        let server_name = self.server_state.server_name.clone();
+        let mut all_params = vec![self.get_nick().await];
+        all_params.extend(params);
+        
+        self.send_message(
+            Message::new(numeric)
+                .with_prefix(server_name)
+                .with_params(all_params)
+        ).await
+    }
+    
+    /// Drop a client whose send queue was closed. Its queue is abandoned
+    /// and the ERROR goes straight to the socket, if the socket will take it.
+    async fn close_link(&mut self, reason: String) {
+        warn!("Closing link to {}: {} ({} bytes queued)", self.addr, reason, self.tx.queued());
+        let error = format!("Closing Link: {} ({})", self.addr.ip(), reason);
+        let _ = timeout(Duration::from_secs(5), self.send_error(&error)).await;
+        self.quit_reason = Some(reason);
+    }
+    
+    /// Charge `msg` against the client's flood allowance. Opers and
+    /// exempt classes are never held back.
+    fn charge_penalty(&mut self, msg: &Message) -> FloodVerdict {
+        let now = std::time::Instant::now();
+        let state = &*self.server_state;
+        let is_oper = state.connections.get(&self.id).is_some_and(|conn| conn.modes.contains(&'o'));
+        if self.class.flood_exempt || is_oper {
+            return FloodVerdict::Due(now);
+        }
+        
+        let weight = COMMANDS.penalty(&msg.command, &state.config.limits.command_penalties);
+        self.flood.penalize(weight, now)
+    }
+    
+    /// Move a logged-in client into its account's connection class, if it
+    /// has one with room. Otherwise it stays where it was admitted.
+    fn apply_account_class(&mut self) {
+        let state = &*self.server_state;
+        let Some(account) = state.connections.get(&self.id).and_then(|conn| conn.account.clone()) else {
+            return;
+        };
+        let ip = self.addr.ip();
+        let Some(class) = state.classes.for_account(ip, &account) else {
+            return;
+        };
+        if class.name == self.class.name {
+            return;
+        }
+        if let Err(e) = state.connection_limiter.move_class(ip, &self.class, &class) {
+            info!("Keeping {} in class {}: class {} refused it ({})", self.addr, self.class.name, class.name, e);
+            return;
+        }
+        
+        debug!("Moving {} from class {} to {}", self.addr, self.class.name, class.name);
+        self.tx.set_limit(class.sendq);
+        self.flood = flood_protection(&class);
+        self.ping_interval = ping_interval(&class);
+        self.class = class;
+    }
+    
     async fn send_error(&mut self, error: &str) {
         let _ = self.send_message(
             Message::new("ERROR")
@@ -723,30 +791,59 @@ impl ConnectionActor {
     }
     
     async fn get_nick(&self) -> String {
-        let state = self.server_state.read().await;
+        let state = &*self.server_state;
         state.connections.get(&self.id)
             .and_then(|conn| conn.nickname.clone())
             .unwrap_or_else(|| "*".to_string())
     }
     
     async fn cleanup(&mut self) {
-        let state = self.server_state.write().await;
+        let state = &*self.server_state;
         
-        // Remove from channels
-        // TODO: Implement channel cleanup
-        
-        // Unregister nickname
-        if let Some(conn) = state.connections.get(&self.id) {
+        if let Some(conn) = state.connections.get(&self.id).map(|conn| conn.clone()) {
+            // Leave every channel, telling each peer once
+            let reason = self.quit_reason.take().unwrap_or_else(|| "Connection closed".to_string());
+            let quit = Message::new("QUIT")
+                .with_prefix(conn.full_mask())
+                .with_params(vec![reason.clone()]);
+            let channels = state.quit_channels(self.id, &quit).await;
+            
             if let Some(nick) = &conn.nickname {
+                let history = channels.into_iter().map(|channel| HistoryItem::new(
+                    generate_message_id(),
+                    MessageType::Quit,
+                    nick.clone(),
+                    conn.account.clone().unwrap_or_else(|| "*".to_string()),
+                    reason.clone(),
+                    channel,
+                )).collect();
+                COMMANDS.store_history(state, history);
+                
                 state.unregister_nickname(nick);
+                if self.registered {
+                    state.record_whowas(&conn);
+                    state.notify_monitors(nick, None).await;
+                }
             }
         }
+        state.monitors.clear(self.id);
         
         // Remove connection
         state.connections.remove(&self.id);
+        state.connection_limiter.remove(self.addr.ip(), &self.class);
     }
 }
 
+fn flood_protection(class: &ConnectClass) -> FloodProtection {
+    FloodProtection::new(class.flood_messages, class.flood_interval, class.flood_ceiling)
+}
+
+/// Pings every `ping_frequency`, starting one period from now
+fn ping_interval(class: &ConnectClass) -> Interval {
+    let period = class.ping_frequency.max(Duration::from_secs(1));
+    interval_at(Instant::now() + period, period)
+}
+
 fn is_valid_nickname(nick: &str) -> bool {
     if nick.is_empty() || nick.len() > 30 {
         return false;
@@ -763,4 +860,181 @@ fn is_valid_nickname(nick: &str) -> bool {
         c.is_alphanumeric() || c == '_' || c == '-' || c == '[' || c == ']' || 
         c == '{' || c == '}' || c == '\\' || c == '|' || c == '^' || c == '`'
     })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    /// Feed `input` to a fresh actor and collect lines until one contains `until`
+    async fn exchange(state: ServerState, secure: bool, input: &str, until: &str) -> Vec<String> {
+        let state = Arc::new(state);
+
+        let (client, server) = tokio::io::duplex(4096);
+        let addr: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let class = state.classes.for_address(addr.ip());
+        let actor = ConnectionActor::new(1, server, addr, state, secure, None, class).await;
+        tokio::spawn(actor.run());
+
+        let (read_half, mut write_half) = tokio::io::split(client);
+        write_half.write_all(input.as_bytes()).await.unwrap();
+
+        let mut lines = Vec::new();
+        let mut reader = BufReader::new(read_half).lines();
+        while let Ok(Ok(Some(line))) = timeout(Duration::from_secs(2), reader.next_line()).await {
+            let done = line.starts_with("ERROR") || line.contains(until);
+            lines.push(line);
+            if done {
+                break;
+            }
+        }
+        lines
+    }
+
+    async fn register(require_tls: bool, secure: bool) -> Vec<String> {
+        let mut state = ServerState::new();
+        state.config.security.require_tls = require_tls;
+        exchange(state, secure, "NICK alice\r\nUSER alice 0 * :Alice\r\n", " 001 ").await
+    }
+
+    /// Default settings with account registration on and cheap password
+    /// hashing
+    fn test_state() -> ServerState {
+        let mut config = crate::utils::config::ServerConfig::default();
+        config.security.argon2_memory_cost = 8 * 1024;
+        config.security.argon2_time_cost = 1;
+        config.registration.enabled = true;
+        ServerState::with_config(config).unwrap()
+    }
+
+    async fn state_with_account() -> ServerState {
+        let state = test_state();
+        state.accounts.create("alice", "hunter2").await.unwrap();
+        state
+    }
+
+    #[tokio::test]
+    async fn test_require_tls_rejects_plaintext_registration() {
+        let lines = register(true, false).await;
+        let last = lines.last().unwrap();
+        assert!(last.starts_with("ERROR"));
+        assert!(last.contains("TLS"));
+    }
+
+    #[tokio::test]
+    async fn test_require_tls_accepts_secure_registration() {
+        let lines = register(true, true).await;
+        assert!(lines.last().unwrap().contains(" 001 "));
+    }
+
+    #[tokio::test]
+    async fn test_sasl_plain_holds_registration_until_success() {
+        // "\0alice\0hunter2"
+        let input = "CAP LS 302\r\nNICK alice\r\nUSER alice 0 * :Alice\r\nCAP REQ :sasl\r\n\
+                     AUTHENTICATE PLAIN\r\nAUTHENTICATE AGFsaWNlAGh1bnRlcjI=\r\nCAP END\r\n";
+        let lines = exchange(state_with_account().await, false, input, " 001 ").await;
+
+        let position = |needle: &str| lines.iter().position(|line| line.contains(needle)).unwrap();
+        assert!(lines.iter().any(|line| line.contains("sasl=PLAIN,SCRAM-SHA-256,EXTERNAL")));
+        assert!(lines[position(" 900 ")].contains("You are now logged in as alice"));
+        assert!(position(" 903 ") < position(" 001 "));
+    }
+
+    #[tokio::test]
+    async fn test_sasl_plain_failure() {
+        // "\0alice\0wrong"
+        let input = "CAP LS 302\r\nCAP REQ :sasl\r\nAUTHENTICATE PLAIN\r\nAUTHENTICATE AGFsaWNlAHdyb25n\r\n";
+        let lines = exchange(state_with_account().await, false, input, " 904 ").await;
+        assert!(lines.last().unwrap().contains(" 904 "));
+    }
+
+    #[tokio::test]
+    async fn test_register_before_connect() {
+        let input = "CAP LS 302\r\nNICK alice\r\nREGISTER * * correcthorse\r\nUSER alice 0 * :Alice\r\nCAP END\r\n";
+        let lines = exchange(test_state(), false, input, " 001 ").await;
+
+        let position = |needle: &str| lines.iter().position(|line| line.contains(needle)).unwrap();
+        assert!(lines[0].contains("draft/account-registration=custom-account-name,before-connect"));
+        assert!(lines[position("REGISTER SUCCESS")].contains("alice"));
+        assert!(position(" 900 ") < position(" 001 "));
+    }
+
+    #[tokio::test]
+    async fn test_channel_mode_commands_are_dispatched() {
+        let input = "NICK alice\r\nUSER alice 0 * :Alice\r\nJOIN #rust\r\nMODE #rust +b bob\r\nMODE #rust b\r\n";
+        let lines = exchange(ServerState::new(), false, input, " 368 ").await;
+        assert!(lines.iter().any(|line| line.contains("MODE #rust +b bob!*@*")));
+        assert!(lines.iter().any(|line| line.contains(" 367 alice #rust bob!*@* ")));
+    }
+
+    #[tokio::test]
+    async fn test_pre_away_before_registration() {
+        let input = "CAP LS 302\r\nCAP REQ :draft/pre-away\r\nAWAY *\r\nNICK alice\r\nUSER alice 0 * :Alice\r\n\
+                     CAP END\r\nWHO alice\r\n";
+        let lines = exchange(ServerState::new(), false, input, " 315 ").await;
+        assert!(lines.iter().any(|line| line.contains(" 306 * ")));
+        assert!(lines.iter().any(|line| line.contains(" 352 ") && line.contains(" G ")));
+    }
+
+    /// Five penalty units a second with room for `ceiling` more
+    fn flood_state(ceiling: usize, exempt: bool) -> ServerState {
+        let mut config = crate::utils::config::ServerConfig::default();
+        config.limits.flood_messages = 5;
+        config.limits.flood_ceiling = ceiling;
+        config.classes.push(crate::utils::config::ClassSettings {
+            name: "local".to_string(),
+            hosts: vec!["127.0.0.0/8".parse().unwrap()],
+            flood_exempt: exempt,
+            ..Default::default()
+        });
+        ServerState::with_config(config).unwrap()
+    }
+
+    fn pings(count: usize) -> String {
+        (1..=count).map(|token| format!("PING t{}\r\n", token)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_fakelag_delays_excess_commands() {
+        let started = std::time::Instant::now();
+        let lines = exchange(flood_state(100, false), false, &pings(7), "t7").await;
+
+        assert_eq!(lines.iter().filter(|line| line.contains("PONG")).count(), 7);
+        // Two commands past the allowance at 200ms each
+        assert!(started.elapsed() >= Duration::from_millis(350));
+    }
+
+    #[tokio::test]
+    async fn test_flood_past_ceiling_disconnects() {
+        let lines = exchange(flood_state(3, false), false, &pings(20), "t20").await;
+        assert!(lines.last().unwrap().starts_with("ERROR"));
+
+        let lines = exchange(flood_state(3, true), false, &pings(20), "t20").await;
+        assert!(lines.last().unwrap().contains("t20"));
+    }
+
+    #[tokio::test]
+    async fn test_klined_registration_is_refused() {
+        let state = ServerState::new();
+        state.server_bans.add(crate::state::BanKind::Kline, crate::db::models::ServerBan {
+            kind: "K".to_string(),
+            mask: "alice@127.0.0.*".to_string(),
+            set_by: "oper".to_string(),
+            set_at: chrono::Utc::now(),
+            reason: "spam".to_string(),
+            expires_at: None,
+        });
+
+        let lines = exchange(state, false, "NICK alice\r\nUSER alice 0 * :Alice\r\n", " 001 ").await;
+        assert!(lines.iter().any(|line| line.contains(" 465 alice ") && line.contains("spam")));
+        assert!(lines.last().unwrap().starts_with("ERROR"));
+    }
+
+    #[tokio::test]
+    async fn test_sasl_unknown_mechanism_lists_mechanisms() {
+        let input = "CAP LS 302\r\nCAP REQ :sasl\r\nAUTHENTICATE GSSAPI\r\n";
+        let lines = exchange(state_with_account().await, false, input, " 904 ").await;
+        assert!(lines.iter().any(|line| line.contains(" 908 ") && line.contains("PLAIN,SCRAM-SHA-256")));
+    }
+}
@@ -1,7 +1,3 @@
 pub mod connection;
-pub mod channel;
-pub mod server;
 
 pub use self::connection::ConnectionActor;
-pub use self::channel::ChannelActor;
-pub use self::server::ServerActor;
\ No newline at end of file
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+use crate::commands::standard_replies::{StandardReply, StandardReplyCode};
+use crate::protocol::{Command, Message};
+use crate::security::validation::validate_away_message;
+use crate::state::ServerState;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult, Registration};
+
+/// AWAY [message]
+///
+/// Sets or clears the away message, truncated to AWAYLEN. Channel peers
+/// with away-notify are told. With draft/pre-away this is also accepted
+/// before registration, where `*` means away without a message.
+pub async fn handle_away(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    message: Option<String>,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+    let server_name = state.server_name.clone();
+    
+    let mut message = message.filter(|message| !message.is_empty());
+    if let Some(text) = message.as_mut() {
+        if let Some((end, _)) = text.char_indices().nth(state.config.limits.max_away_length) {
+            text.truncate(end);
+        }
+        if !validate_away_message(text) {
+            return Ok(vec![StandardReply::fail("AWAY", StandardReplyCode::InvalidParams, "Invalid away message")
+                .to_message(&server_name)]);
+        }
+    }
+    
+    let (nick, mask, registered) = {
+        let mut conn = state.connections.get_mut(&connection_id)
+            .ok_or("Connection not found")?;
+        if !conn.registered && message.as_deref() == Some("*") {
+            message = Some("Away".to_string());
+        }
+        conn.away = message.clone();
+        (conn.nickname.clone().unwrap_or_else(|| "*".to_string()), conn.full_mask(), conn.registered)
+    };
+    
+    if registered && state.config.features.enable_away_notify {
+        let away_msg = Message::new("AWAY")
+            .with_prefix(mask)
+            .with_params(message.iter().cloned().collect());
+        for peer_id in state.channel_peers(connection_id) {
+            if peer_id == connection_id {
+                continue;
+            }
+            if let Some(peer) = state.connections.get(&peer_id) {
+                if peer.capabilities.iter().any(|cap| cap == "away-notify") {
+                    let _ = peer.tx.try_send(away_msg.clone());
+                }
+            }
+        }
+    }
+    
+    let reply = match message {
+        Some(_) => Message::new("306")
+            .with_prefix(server_name)
+            .with_params(vec![nick, "You have been marked as being away".to_string()]),
+        None => Message::new("305")
+            .with_prefix(server_name)
+            .with_params(vec![nick, "You are no longer marked as being away".to_string()]),
+    };
+    Ok(vec![reply])
+}
+
+/// AWAY [message], allowed before registration with draft/pre-away
+pub struct Away;
+
+#[async_trait]
+impl CommandHandler for Away {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("AWAY").registration(Registration::OptionalWithCap("draft/pre-away"))
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Away(message) = command else {
+            return Ok(Vec::new());
+        };
+        handle_away(server_state, ctx.connection_id, message).await
+    }
+}
+
+#[cfg(test)]
+#[path = "away_test.rs"]
+mod tests;
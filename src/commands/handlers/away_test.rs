@@ -0,0 +1,54 @@
+use super::*;
+use crate::commands::handlers::test_support::{add_user, context};
+use crate::commands::handlers::{privmsg::handle_privmsg, who::handle_who};
+use crate::state::Channel;
+use crate::state::sendq::SendQueueReceiver;
+
+/// alice and bob share #test; bob has away-notify
+fn setup() -> (Arc<ServerState>, Vec<SendQueueReceiver>) {
+    let state = ServerState::new();
+    let receivers = vec![add_user(1, "alice").insert(&state), add_user(2, "bob").insert(&state), add_user(3, "carol").insert(&state)];
+    state.connections.get_mut(&2).unwrap().capabilities.push("away-notify".to_string());
+    state.connections.get_mut(&3).unwrap().capabilities.push("away-notify".to_string());
+
+    let channel = Channel::new("#test".to_string());
+    channel.add_member(1, true);
+    channel.add_member(2, false);
+    state.channels.insert("#test".to_string(), channel);
+
+    (Arc::new(state), receivers)
+}
+
+#[tokio::test]
+async fn test_away_is_set_notified_and_cleared() {
+    let (state, mut rx) = setup();
+
+    let messages = handle_away(state.clone(), 1, Some("Lunch".to_string())).await.unwrap();
+    assert_eq!(messages[0].command, "306");
+    let notify = rx[1].try_recv().unwrap();
+    assert_eq!(notify.command, "AWAY");
+    assert_eq!(notify.params, ["Lunch"]);
+    // carol shares no channel with alice
+    assert!(rx[2].try_recv().is_err());
+
+    let messages = handle_away(state.clone(), 1, None).await.unwrap();
+    assert_eq!(messages[0].command, "305");
+    assert!(rx[1].try_recv().unwrap().params.is_empty());
+    assert!(state.connections.get(&1).unwrap().away.is_none());
+}
+
+#[tokio::test]
+async fn test_away_shows_in_privmsg_and_who() {
+    let (mut state, _rx) = setup();
+    Arc::get_mut(&mut state).unwrap().config.limits.max_away_length = 4;
+    handle_away(state.clone(), 2, Some("Gone fishing".to_string())).await.unwrap();
+
+    let messages = handle_privmsg(state.clone(), &context(1), "bob".to_string(), "hi".to_string()).await.unwrap();
+    assert_eq!(messages[0].command, "301");
+    assert_eq!(messages[0].params, ["alice", "bob", "Gone"]);
+
+    let messages = handle_who(state.clone(), 1, vec!["#test".to_string()]).await.unwrap();
+    let flags: Vec<_> = messages.iter().filter(|message| message.command == "352").map(|message| message.params[6].clone()).collect();
+    assert!(flags.contains(&"G".to_string()));
+    assert!(flags.contains(&"H@".to_string()));
+}
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+use crate::commands::standard_replies::{StandardReply, StandardReplyCode};
+use crate::protocol::{Command, Message};
+use crate::state::ServerState;
+use crate::state::account::{is_valid_certfp, normalize_certfp};
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
+
+/// CERTFP ADD [fingerprint] | DEL <fingerprint> | LIST
+///
+/// Manages the TLS client certificate fingerprints bound to the caller's
+/// account for SASL EXTERNAL. ADD without an argument uses the certificate
+/// of the current connection.
+pub async fn handle_certfp(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    params: Vec<String>,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+    let server_name = state.server_name.clone();
+
+    let (account, current_certfp) = match state.connections.get(&connection_id) {
+        Some(conn) => (conn.account.clone(), conn.certfp.clone()),
+        None => return Ok(vec![]),
+    };
+
+    let Some(account) = account else {
+        return Ok(vec![StandardReply::fail(
+            "CERTFP",
+            StandardReplyCode::Custom("ACCOUNT_REQUIRED".to_string()),
+            "You must be logged in to manage certificate fingerprints",
+        ).to_message(&server_name)]);
+    };
+
+    let subcommand = params.first().map(|s| s.to_uppercase()).unwrap_or_default();
+    let argument = params.get(1).cloned();
+
+    let reply = match subcommand.as_str() {
+        "ADD" => {
+            let Some(certfp) = argument.or(current_certfp) else {
+                return Ok(vec![StandardReply::fail(
+                    "CERTFP",
+                    StandardReplyCode::NeedMoreParams,
+                    "No fingerprint given and no client certificate presented",
+                ).to_message(&server_name)]);
+            };
+
+            if !is_valid_certfp(&certfp) {
+                StandardReply::fail("CERTFP", StandardReplyCode::InvalidParams, "Fingerprint must be a SHA-256 hex digest")
+                    .add_context(certfp)
+            } else if state.accounts.add_certfp(&account, &certfp) {
+                StandardReply::note("CERTFP", StandardReplyCode::Custom("ADDED".to_string()), "Certificate fingerprint added")
+                    .add_context(normalize_certfp(&certfp))
+            } else {
+                StandardReply::fail("CERTFP", StandardReplyCode::Custom("FINGERPRINT_IN_USE".to_string()), "Fingerprint is already registered")
+                    .add_context(normalize_certfp(&certfp))
+            }
+        }
+        "DEL" => {
+            let Some(certfp) = argument else {
+                return Ok(vec![StandardReply::fail("CERTFP", StandardReplyCode::NeedMoreParams, "Not enough parameters")
+                    .to_message(&server_name)]);
+            };
+
+            if state.accounts.remove_certfp(&account, &certfp) {
+                StandardReply::note("CERTFP", StandardReplyCode::Custom("REMOVED".to_string()), "Certificate fingerprint removed")
+                    .add_context(normalize_certfp(&certfp))
+            } else {
+                StandardReply::fail("CERTFP", StandardReplyCode::InvalidParams, "Fingerprint is not registered to your account")
+                    .add_context(certfp)
+            }
+        }
+        "LIST" => {
+            let certfps = state.accounts.get(&account)
+                .map(|account| account.certfps)
+                .unwrap_or_default();
+
+            if certfps.is_empty() {
+                return Ok(vec![StandardReply::note(
+                    "CERTFP",
+                    StandardReplyCode::Custom("LIST".to_string()),
+                    "No certificate fingerprints registered",
+                ).to_message(&server_name)]);
+            }
+
+            return Ok(certfps.into_iter()
+                .map(|certfp| StandardReply::note("CERTFP", StandardReplyCode::Custom("LIST".to_string()), "Registered certificate fingerprint")
+                    .add_context(certfp)
+                    .to_message(&server_name))
+                .collect());
+        }
+        _ => StandardReply::fail("CERTFP", StandardReplyCode::InvalidParams, "Unknown subcommand, use ADD, DEL or LIST")
+            .add_context(subcommand),
+    };
+
+    Ok(vec![reply.to_message(&server_name)])
+}
+
+/// CERTFP [subcommand] [params]
+pub struct Certfp;
+
+#[async_trait]
+impl CommandHandler for Certfp {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("CERTFP")
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Certfp { subcommand, params } = command else {
+            return Ok(Vec::new());
+        };
+        let mut full_params = vec![subcommand];
+        full_params.extend(params);
+        handle_certfp(server_state, ctx.connection_id, full_params).await
+    }
+}
+
+#[cfg(test)]
+#[path = "certfp_test.rs"]
+mod tests;
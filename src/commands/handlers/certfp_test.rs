@@ -0,0 +1,37 @@
+use super::*;
+use crate::commands::handlers::test_support::add_user;
+use crate::security::auth::LocalPasswordHasher;
+use crate::state::AccountStore;
+
+#[tokio::test]
+async fn test_certfp_requires_account() {
+    let state = ServerState::new();
+    let _rx = add_user(1, "alice").insert(&state);
+    let state = Arc::new(state);
+
+    let messages = handle_certfp(state, 1, vec!["LIST".to_string()]).await.unwrap();
+
+    assert_eq!(messages[0].command, "FAIL");
+    assert_eq!(messages[0].params[1], "ACCOUNT_REQUIRED");
+}
+
+#[tokio::test]
+async fn test_certfp_add_current_certificate() {
+    let certfp = "0f".repeat(32);
+    let mut state = ServerState::new();
+    state.accounts = AccountStore::new(LocalPasswordHasher::new(8 * 1024, 1, 1).unwrap());
+    state.accounts.create("bot", "secret").await.unwrap();
+    let _rx = add_user(1, "bot").account("bot").certfp(&certfp).insert(&state);
+    let state = Arc::new(state);
+
+    let messages = handle_certfp(state.clone(), 1, vec!["ADD".to_string()]).await.unwrap();
+    assert_eq!(messages[0].command, "NOTE");
+    assert_eq!(messages[0].params[2], certfp);
+
+    // A fingerprint can only belong to one account
+    let messages = handle_certfp(state.clone(), 1, vec!["ADD".to_string()]).await.unwrap();
+    assert_eq!(messages[0].params[1], "FINGERPRINT_IN_USE");
+
+    let state = &*state;
+    assert_eq!(state.accounts.find_by_certfp(&certfp).unwrap().name, "bot");
+}
@@ -0,0 +1,184 @@
+use std::sync::Arc;
+use crate::commands::standard_replies::{StandardReply, StandardReplyCode};
+use crate::db::models::Channel as ChannelRecord;
+use crate::protocol::{Command, Message};
+use crate::state::ServerState;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
+
+/// CHANREG REGISTER <channel> | DROP <channel> | INFO <channel>
+///       | SET <channel> <SUCCESSOR|ENTRYMSG|DESCRIPTION|URL> [value]
+///
+/// Registers a channel to the caller's account. Registered channels keep
+/// their topic and modes while empty, op their founder on join and greet
+/// joiners with the entry message. SET without a value clears the setting.
+pub async fn handle_chanreg(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    params: Vec<String>,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+    let server_name = state.server_name.clone();
+    let fail = |code: StandardReplyCode, channel: &str, description: &str| {
+        Ok(vec![StandardReply::fail("CHANREG", code, description)
+            .add_context(channel.to_string())
+            .to_message(&server_name)])
+    };
+    let note = |code: &str, context: Vec<String>, description: &str| {
+        StandardReply::note("CHANREG", StandardReplyCode::Custom(code.to_string()), description)
+            .with_context(context)
+            .to_message(&server_name)
+    };
+
+    let Some(account) = state.connections.get(&connection_id).map(|conn| conn.account.clone()) else {
+        return Ok(vec![]);
+    };
+
+    let subcommand = params.first().map(|s| s.to_uppercase()).unwrap_or_default();
+    let Some(channel_name) = params.get(1).cloned() else {
+        return Ok(vec![StandardReply::fail("CHANREG", StandardReplyCode::NeedMoreParams, "Not enough parameters")
+            .to_message(&server_name)]);
+    };
+
+    if subcommand == "INFO" {
+        let Some(record) = state.registered_channels.get(&channel_name) else {
+            return fail(StandardReplyCode::Custom("NOT_REGISTERED".to_string()), &channel_name, "Channel is not registered");
+        };
+
+        let mut lines = vec![
+            format!("Founder: {}", record.founder.as_deref().unwrap_or("*")),
+            format!("Registered: {}", record.created_at.to_rfc3339()),
+        ];
+        let optional = [
+            ("Successor", &record.successor),
+            ("Description", &record.description),
+            ("URL", &record.url),
+            ("Entry message", &record.entry_message),
+        ];
+        for (label, value) in optional {
+            if let Some(value) = value {
+                lines.push(format!("{}: {}", label, value));
+            }
+        }
+
+        return Ok(lines.iter()
+            .map(|line| note("INFO", vec![record.name.clone()], line))
+            .collect());
+    }
+
+    let Some(account) = account else {
+        return fail(
+            StandardReplyCode::Custom("ACCOUNT_REQUIRED".to_string()),
+            &channel_name,
+            "You must be logged in to manage channel registrations",
+        );
+    };
+
+    match subcommand.as_str() {
+        "REGISTER" => {
+            if state.registered_channels.is_registered(&channel_name) {
+                return fail(StandardReplyCode::AlreadyRegistered, &channel_name, "Channel is already registered");
+            }
+            let Some(channel) = state.channels.get(&channel_name) else {
+                return fail(StandardReplyCode::NoSuchChannel, &channel_name, "No such channel");
+            };
+            if !channel.is_operator(connection_id) {
+                return fail(
+                    StandardReplyCode::Custom("CHANOP_REQUIRED".to_string()),
+                    &channel_name,
+                    "You must be a channel operator to register the channel",
+                );
+            }
+
+            let mut record = ChannelRecord::new(channel.name.clone(), Some(account));
+            channel.save_to(&mut record);
+            if !state.registered_channels.register(record) {
+                return fail(StandardReplyCode::AlreadyRegistered, &channel_name, "Channel is already registered");
+            }
+            for entry in channel.list_records() {
+                state.registered_channels.add_list_entry(entry);
+            }
+
+            Ok(vec![note("REGISTERED", vec![channel_name], "Channel registered")])
+        }
+        "DROP" => {
+            if !state.registered_channels.is_registered(&channel_name) {
+                return fail(StandardReplyCode::Custom("NOT_REGISTERED".to_string()), &channel_name, "Channel is not registered");
+            }
+            if !state.registered_channels.is_founder(&channel_name, &account) {
+                return fail(StandardReplyCode::Custom("NOT_FOUNDER".to_string()), &channel_name, "Only the founder can do that");
+            }
+
+            state.registered_channels.unregister(&channel_name);
+            Ok(vec![note("DROPPED", vec![channel_name], "Channel registration dropped")])
+        }
+        "SET" => {
+            if !state.registered_channels.is_registered(&channel_name) {
+                return fail(StandardReplyCode::Custom("NOT_REGISTERED".to_string()), &channel_name, "Channel is not registered");
+            }
+            if !state.registered_channels.is_founder(&channel_name, &account) {
+                return fail(StandardReplyCode::Custom("NOT_FOUNDER".to_string()), &channel_name, "Only the founder can do that");
+            }
+
+            let Some(setting) = params.get(2).map(|s| s.to_uppercase()) else {
+                return fail(StandardReplyCode::NeedMoreParams, &channel_name, "Not enough parameters");
+            };
+            let value = params.get(3).filter(|value| !value.is_empty()).cloned();
+
+            let value = match setting.as_str() {
+                // Successors must be real accounts, stored under their canonical name
+                "SUCCESSOR" => match value {
+                    Some(successor) => match state.accounts.get(&successor) {
+                        Some(successor) if !successor.name.eq_ignore_ascii_case(&account) => Some(successor.name),
+                        Some(_) => return fail(StandardReplyCode::InvalidParams, &channel_name, "The founder cannot be their own successor"),
+                        None => return fail(StandardReplyCode::Custom("NO_SUCH_ACCOUNT".to_string()), &channel_name, "No such account"),
+                    },
+                    None => None,
+                },
+                "ENTRYMSG" | "DESCRIPTION" | "URL" => value,
+                _ => return fail(StandardReplyCode::InvalidParams, &channel_name, "Unknown setting, use SUCCESSOR, ENTRYMSG, DESCRIPTION or URL"),
+            };
+
+            state.registered_channels.update(&channel_name, |record| {
+                let field = match setting.as_str() {
+                    "SUCCESSOR" => &mut record.successor,
+                    "ENTRYMSG" => &mut record.entry_message,
+                    "DESCRIPTION" => &mut record.description,
+                    _ => &mut record.url,
+                };
+                *field = value;
+            });
+
+            Ok(vec![note("UPDATED", vec![channel_name, setting], "Channel setting updated")])
+        }
+        _ => fail(StandardReplyCode::InvalidParams, &subcommand, "Unknown subcommand, use REGISTER, DROP, SET or INFO"),
+    }
+}
+
+/// CHANREG <subcommand> [params]
+pub struct ChanReg;
+
+#[async_trait]
+impl CommandHandler for ChanReg {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("CHANREG").min_params(1)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::ChanReg { subcommand, params } = command else {
+            return Ok(Vec::new());
+        };
+        let mut full_params = vec![subcommand];
+        full_params.extend(params);
+        handle_chanreg(server_state, ctx.connection_id, full_params).await
+    }
+}
+
+#[cfg(test)]
+#[path = "chanreg_test.rs"]
+mod tests;
@@ -0,0 +1,74 @@
+use super::*;
+use crate::commands::handlers::test_support::{add_user, params};
+use crate::commands::handlers::join::handle_join;
+
+#[tokio::test]
+async fn test_register_and_set() {
+    let state = ServerState::new();
+    let _alice = add_user(1, "alice").account("alice").insert(&state);
+    let _bob = add_user(2, "bob").account("bob").insert(&state);
+    let _carol = add_user(3, "carol").insert(&state);
+    let state = Arc::new(state);
+
+    handle_join(state.clone(), 1, vec!["#rust".to_string()], vec![]).await.unwrap();
+    handle_join(state.clone(), 2, vec!["#rust".to_string()], vec![]).await.unwrap();
+
+    let code = |id: u64, line: &'static str| {
+        let state = state.clone();
+        async move { handle_chanreg(state, id, params(line)).await.unwrap()[0].params[1].clone() }
+    };
+    assert_eq!(code(3, "REGISTER #rust").await, "ACCOUNT_REQUIRED");
+    assert_eq!(code(2, "REGISTER #rust").await, "CHANOP_REQUIRED");
+    assert_eq!(code(1, "REGISTER #rust").await, "REGISTERED");
+    assert_eq!(code(1, "REGISTER #Rust").await, "ALREADY_REGISTERED");
+
+    assert_eq!(code(2, "SET #rust ENTRYMSG hi").await, "NOT_FOUNDER");
+    assert_eq!(code(1, "SET #rust SUCCESSOR nobody").await, "NO_SUCH_ACCOUNT");
+    assert_eq!(code(1, "SET #rust COLOUR red").await, "INVALID_PARAMS");
+    assert_eq!(code(1, "SET #rust ENTRYMSG Welcome").await, "UPDATED");
+
+    let info = handle_chanreg(state.clone(), 3, params("INFO #RUST")).await.unwrap();
+    assert_eq!(info[0].params.last().unwrap(), "Founder: alice");
+    assert!(info.iter().any(|message| message.params.last().unwrap() == "Entry message: Welcome"));
+
+    assert_eq!(code(2, "DROP #rust").await, "NOT_FOUNDER");
+    assert_eq!(code(1, "DROP #rust").await, "DROPPED");
+    assert_eq!(code(1, "INFO #rust").await, "NOT_REGISTERED");
+}
+
+#[tokio::test]
+async fn test_registration_survives_empty_channel() {
+    let state = ServerState::new();
+    let _alice = add_user(1, "alice").account("alice").insert(&state);
+    let _bob = add_user(2, "bob").insert(&state);
+    let state = Arc::new(state);
+
+    handle_join(state.clone(), 1, vec!["#rust".to_string()], vec![]).await.unwrap();
+    {
+        let state = &*state;
+        let mut channel = state.channels.get_mut("#rust").unwrap();
+        channel.topic = Some("Rust talk".to_string());
+        channel.modes = vec!['n', 't'];
+    }
+    handle_chanreg(state.clone(), 1, params("REGISTER #rust")).await.unwrap();
+    handle_chanreg(state.clone(), 1, params("SET #rust ENTRYMSG Behave")).await.unwrap();
+
+    // Everyone leaves and the channel is destroyed
+    state.channels.remove("#rust");
+
+    // A non-founder recreating it is not opped, but sees the saved state
+    let messages = handle_join(state.clone(), 2, vec!["#rust".to_string()], vec![]).await.unwrap();
+    assert_eq!(messages[1].command, "332");
+    assert_eq!(messages.last().unwrap().command, "NOTICE");
+    assert_eq!(messages.last().unwrap().params[1], "[#rust] Behave");
+    {
+        let state = &*state;
+        let channel = state.channels.get("#rust").unwrap();
+        assert!(!channel.is_operator(2));
+        assert_eq!(channel.modes, vec!['n', 't']);
+    }
+
+    // The founder is opped on join
+    handle_join(state.clone(), 1, vec!["#rust".to_string()], vec![]).await.unwrap();
+    assert!(state.channels.get("#rust").unwrap().is_operator(1));
+}
@@ -1,17 +1,18 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
+use crate::protocol::{Command, Message, Reply};
 use crate::state::ServerState;
 use crate::history::{HistoryItem, HistoryQuery, QueryResult, MessageType};
 use crate::commands::standard_replies::{StandardReply, common};
 use std::time::{SystemTime, UNIX_EPOCH};
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_chathistory(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
     params: Vec<String>,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
-    let state = server_state.read().await;
+    let state = &*server_state;
     
     // Get connection info
     let connection = state.connections.get(&connection_id)
@@ -31,7 +32,7 @@ pub async fn handle_chathistory(
     let query = match HistoryQuery::parse_chathistory_command(&params) {
         Ok(query) => query,
         Err(err) => {
-            let state = server_state.read().await;
+            let state = &*server_state;
             return Ok(vec![
                 common::invalid_params("CHATHISTORY", &err).to_message(&state.server_name)
             ]);
@@ -215,4 +216,28 @@ fn format_timestamp(timestamp: SystemTime) -> String {
     // Convert to RFC3339 format with milliseconds
     let datetime = chrono::DateTime::from_timestamp(secs as i64, nanos).unwrap();
     format!("timestamp={}", datetime.format("%Y-%m-%dT%H:%M:%S.%3fZ"))
-}
\ No newline at end of file
+}
+
+/// CHATHISTORY <subcommand> <target> [params]
+pub struct ChatHistory;
+
+#[async_trait]
+impl CommandHandler for ChatHistory {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("CHATHISTORY").min_params(2).penalty(3)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::ChatHistory { subcommand, target, params } = command else {
+            return Ok(Vec::new());
+        };
+        let mut full_params = vec![subcommand, target];
+        full_params.extend(params);
+        handle_chathistory(server_state, ctx.connection_id, full_params).await
+    }
+}
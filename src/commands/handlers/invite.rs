@@ -0,0 +1,155 @@
+use std::sync::Arc;
+use crate::history::{HistoryItem, MessageType};
+use crate::protocol::{Command, Message, Reply};
+use crate::state::ServerState;
+use crate::state::channel_modes::Rank;
+use crate::utils::generate_message_id;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
+
+/// INVITE <nick> <channel>
+///
+/// Invites a user to a channel. The invite lets them past +i, +l and +k
+/// once, until it expires. Operators with invite-notify see the invite.
+pub async fn handle_invite(
+    server_state: Arc<ServerState>,
+    ctx: &CommandContext,
+    params: Vec<String>,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let connection_id = ctx.connection_id;
+    let mut responses = Vec::new();
+    let state = &*server_state;
+    let server_name = state.server_name.clone();
+
+    let connection = state.connections.get(&connection_id)
+        .ok_or("Connection not found")?
+        .clone();
+
+    let nick = connection.nickname.clone()
+        .ok_or("No nickname set")?;
+
+    if params.len() < 2 {
+        responses.push(Message::from(Reply::NeedMoreParams {
+            nick,
+            command: "INVITE".to_string(),
+        }));
+        return Ok(responses);
+    }
+
+    let target_nick = params[0].clone();
+    let channel_name = params[1].clone();
+
+    let target_id = match state.nicknames.get(&target_nick.to_lowercase()) {
+        Some(id) => *id,
+        None => {
+            responses.push(Message::from(Reply::NoSuchNick {
+                nick,
+                target: target_nick,
+            }));
+            return Ok(responses);
+        }
+    };
+    let Some(target) = state.connections.get(&target_id).map(|conn| conn.clone()) else {
+        return Ok(responses);
+    };
+    let target_nick = target.nickname.clone().unwrap_or(target_nick);
+
+    let mut channel = match state.channels.get_mut(&channel_name) {
+        Some(channel) => channel,
+        None => {
+            responses.push(Message::from(Reply::NoSuchChannel {
+                nick,
+                channel: channel_name,
+            }));
+            return Ok(responses);
+        }
+    };
+
+    if !channel.is_member(connection_id) {
+        responses.push(Message::from(Reply::NotOnChannel {
+            nick,
+            channel: channel_name,
+        }));
+        return Ok(responses);
+    }
+
+    // Anyone on the channel may invite unless it is invite-only
+    if channel.modes.contains(&'i') && !channel.is_operator(connection_id) {
+        responses.push(Message::from(Reply::ChanOpPrivsNeeded {
+            nick,
+            channel: channel_name,
+        }));
+        return Ok(responses);
+    }
+
+    if channel.is_member(target_id) {
+        responses.push(Message::new("443")
+            .with_prefix(server_name)
+            .with_params(vec![nick, target_nick, channel_name, "is already on channel".to_string()]));
+        return Ok(responses);
+    }
+
+    let expiry = chrono::Duration::seconds(state.config.limits.invite_expiry as i64);
+    channel.add_invite(target_id, expiry);
+
+    let invite_msg = Message::new("INVITE")
+        .with_prefix(connection.full_mask())
+        .with_params(vec![target_nick.clone(), channel.name.clone()]);
+    let _ = target.tx.try_send(invite_msg.clone());
+
+    // invite-notify: let the other operators know
+    for entry in channel.members.iter() {
+        let member_id = *entry.key();
+        if member_id == connection_id || Rank::of(&entry.value().modes) < Rank::Op {
+            continue;
+        }
+        if let Some(member_conn) = state.connections.get(&member_id) {
+            if member_conn.capabilities.iter().any(|cap| cap == "invite-notify") {
+                let _ = member_conn.tx.try_send(invite_msg.clone());
+            }
+        }
+    }
+
+    let mut history_item = HistoryItem::new(
+        generate_message_id(),
+        MessageType::Invite,
+        nick.clone(),
+        connection.account.clone().unwrap_or_else(|| "*".to_string()),
+        String::new(),
+        channel.name.clone(),
+    );
+    history_item.params = vec![target_nick.clone()];
+    ctx.record(history_item);
+
+    responses.push(Message::new("341")
+        .with_prefix(server_name)
+        .with_params(vec![nick, target_nick, channel.name.clone()]));
+
+    Ok(responses)
+}
+
+/// INVITE <nick> <channel>
+pub struct Invite;
+
+#[async_trait]
+impl CommandHandler for Invite {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("INVITE").min_params(2).history()
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Invite { nick, channel } = command else {
+            return Ok(Vec::new());
+        };
+        handle_invite(server_state, ctx, vec![nick, channel]).await
+    }
+}
+
+#[cfg(test)]
+#[path = "invite_test.rs"]
+mod tests;
@@ -0,0 +1,77 @@
+use super::*;
+use crate::commands::COMMANDS;
+use crate::commands::handlers::test_support::{add_user, context, params};
+use crate::commands::handlers::join::handle_join;
+use crate::state::Channel;
+use crate::state::sendq::SendQueueReceiver;
+
+/// #test is invite-only, keyed and full; alice and carol are ops, carol has
+/// invite-notify
+fn setup() -> (Arc<ServerState>, Vec<SendQueueReceiver>) {
+    let state = ServerState::new();
+    let receivers = vec![add_user(1, "alice").insert(&state), add_user(2, "bob").insert(&state), add_user(3, "carol").insert(&state)];
+    state.connections.get_mut(&3).unwrap().capabilities.push("invite-notify".to_string());
+
+    let mut channel = Channel::new("#test".to_string());
+    channel.add_member(1, true);
+    channel.add_member(3, true);
+    channel.modes = vec!['i', 'k', 'l'];
+    channel.key = Some("secret".to_string());
+    channel.limit = Some(2);
+    state.channels.insert("#test".to_string(), channel);
+
+    (Arc::new(state), receivers)
+}
+
+#[tokio::test]
+async fn test_invite_bypasses_channel_restrictions_once() {
+    let (state, mut rx) = setup();
+
+    let messages = handle_join(state.clone(), 2, vec!["#test".to_string()], vec![]).await.unwrap();
+    assert_eq!(messages[0].command, "473");
+
+    // Through the registry, which records the invite in history
+    let invite = Message::new("INVITE").with_params(params("BOB #test"));
+    let messages = COMMANDS.dispatch(state.clone(), &context(1), invite).await.unwrap();
+    assert_eq!(messages[0].command, "341");
+    assert_eq!(messages[0].params, ["alice", "bob", "#test"]);
+
+    let invite = rx[1].try_recv().unwrap();
+    assert_eq!(invite.command, "INVITE");
+    assert_eq!(invite.prefix.as_deref(), Some("alice!alice@127.0.0.1"));
+    assert_eq!(rx[2].try_recv().unwrap().command, "INVITE");
+
+    let messages = handle_join(state.clone(), 2, vec!["#test".to_string()], vec![]).await.unwrap();
+    assert_eq!(messages[0].command, "JOIN");
+    assert!(state.channels.get("#test").unwrap().invites.is_empty());
+
+    let history = state.history.get_messages_between("#test", None, None, 10, true);
+    assert!(history.iter().any(|item| item.message_type == MessageType::Invite && item.params == ["bob"]));
+}
+
+#[tokio::test]
+async fn test_invite_requires_op_on_invite_only_channel() {
+    let (state, _rx) = setup();
+    state.channels.get_mut("#test").unwrap().add_member(2, false);
+    let _dave = add_user(4, "dave").insert(&state);
+
+    let messages = handle_invite(state.clone(), &context(2), params("dave #test")).await.unwrap();
+    assert_eq!(messages[0].command, "482");
+
+    let messages = handle_invite(state.clone(), &context(1), params("bob #test")).await.unwrap();
+    assert_eq!(messages[0].command, "443");
+}
+
+#[tokio::test]
+async fn test_expired_invites_are_ignored() {
+    let (mut state, _rx) = setup();
+    Arc::get_mut(&mut state).unwrap().config.limits.invite_expiry = 0;
+
+    handle_invite(state.clone(), &context(1), params("bob #test")).await.unwrap();
+    let messages = handle_join(state.clone(), 2, vec!["#test".to_string()], vec!["secret".to_string()]).await.unwrap();
+    assert_eq!(messages[0].command, "473");
+
+    // Adding a new invite drops the stale one
+    state.channels.get_mut("#test").unwrap().add_invite(9, chrono::Duration::hours(1));
+    assert!(!state.channels.get("#test").unwrap().invites.contains_key(&2));
+}
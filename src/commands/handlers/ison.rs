@@ -0,0 +1,115 @@
+use std::sync::Arc;
+use crate::protocol::{Command, Message};
+use crate::state::ServerState;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
+
+/// USERHOST looks up at most this many nicknames
+const MAX_USERHOST_TARGETS: usize = 5;
+
+/// ISON <nick> [nick ...]
+///
+/// Replies with the given nicknames that are online (303).
+pub async fn handle_ison(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    nicks: Vec<String>,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+    
+    let nick = state.connections.get(&connection_id)
+        .ok_or("Connection not found")?
+        .nickname.clone()
+        .ok_or("No nickname set")?;
+    
+    let online: Vec<String> = nicks.into_iter()
+        .filter(|target| state.online_mask(target).is_some())
+        .collect();
+    
+    Ok(vec![Message::new("303")
+        .with_prefix(state.server_name.clone())
+        .with_params(vec![nick, online.join(" ")])])
+}
+
+/// USERHOST <nick> [nick ...]
+///
+/// Replies with `nick[*]=<+|->user@host` for up to five online nicknames
+/// (302). `*` marks IRC operators and `-` away users.
+pub async fn handle_userhost(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    nicks: Vec<String>,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+    
+    let nick = state.connections.get(&connection_id)
+        .ok_or("Connection not found")?
+        .nickname.clone()
+        .ok_or("No nickname set")?;
+    
+    let mut replies = Vec::new();
+    for target in nicks.iter().take(MAX_USERHOST_TARGETS) {
+        let Some(target_id) = state.nicknames.get(&target.to_lowercase()).map(|id| *id) else {
+            continue;
+        };
+        let Some(conn) = state.connections.get(&target_id).filter(|conn| conn.registered) else {
+            continue;
+        };
+        replies.push(format!(
+            "{}{}={}{}@{}",
+            conn.nickname.as_deref().unwrap_or(target),
+            if conn.modes.contains(&'o') { "*" } else { "" },
+            if conn.away.is_some() { '-' } else { '+' },
+            conn.username.as_deref().unwrap_or("*"),
+            conn.hostname,
+        ));
+    }
+    
+    Ok(vec![Message::new("302")
+        .with_prefix(state.server_name.clone())
+        .with_params(vec![nick, replies.join(" ")])])
+}
+
+/// ISON <nicks>
+pub struct Ison;
+
+#[async_trait]
+impl CommandHandler for Ison {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("ISON").min_params(1)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Ison(nicks) = command else {
+            return Ok(Vec::new());
+        };
+        handle_ison(server_state, ctx.connection_id, nicks).await
+    }
+}
+
+/// USERHOST <nicks>
+pub struct Userhost;
+
+#[async_trait]
+impl CommandHandler for Userhost {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("USERHOST").min_params(1)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Userhost(nicks) = command else {
+            return Ok(Vec::new());
+        };
+        handle_userhost(server_state, ctx.connection_id, nicks).await
+    }
+}
@@ -1,17 +1,19 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
+use crate::protocol::{Command, Message, Reply};
 use crate::state::{ServerState, Channel, ChannelMember};
+use crate::state::channel_modes::prefix_symbol;
 use chrono::Utc;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_join(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
     channels: Vec<String>,
-    _keys: Vec<String>,
+    keys: Vec<String>,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
     let mut responses = Vec::new();
-    let mut state = server_state.write().await;
+    let state = &*server_state;
     
     // Get connection info
     let connection = state.connections.get(&connection_id)
@@ -26,7 +28,7 @@ pub async fn handle_join(
     
     let prefix = format!("{}!{}@{}", nick, user, host);
     
-    for channel_name in channels {
+    for (index, channel_name) in channels.into_iter().enumerate() {
         // Validate channel name
         if !channel_name.starts_with('#') && !channel_name.starts_with('&') {
             responses.push(Message::from(
@@ -41,18 +43,15 @@ pub async fn handle_join(
         // Check if channel exists and if user is already in it
         let is_new_channel = !state.channels.contains_key(&channel_name);
         
+        // Registered channels come back with their saved topic, modes and lists
+        let registration = state.registered_channels.get(&channel_name);
+        let list_entries = state.registered_channels.list_entries(&channel_name);
+        
         // Get or create channel
-        let channel = state.channels.entry(channel_name.clone())
-            .or_insert_with(|| Channel {
-                name: channel_name.clone(),
-                topic: None,
-                topic_set_by: None,
-                topic_set_at: None,
-                modes: Vec::new(),
-                members: dashmap::DashMap::new(),
-                created_at: Utc::now(),
-                key: None,
-                limit: None,
+        let mut channel = state.channels.entry(channel_name.clone())
+            .or_insert_with(|| match &registration {
+                Some(record) => Channel::from_registration(record, &list_entries),
+                None => Channel::new(channel_name.clone()),
             });
         
         // Check if already in channel
@@ -60,11 +59,57 @@ pub async fn handle_join(
             continue; // Already in channel, skip
         }
         
-        // Add member to channel
-        // If this is a new channel, make the first user an operator (channel founder)
+        // The founder of a registered channel always gets +q; unregistered
+        // channels op whoever creates them
+        let is_founder = match (&registration, &connection.account) {
+            (Some(record), Some(account)) => record.founder.as_deref()
+                .is_some_and(|founder| founder.eq_ignore_ascii_case(account)),
+            _ => false,
+        };
+        
+        // +R, +S and +O apply to everyone. The founder can otherwise always
+        // get in; everyone else is subject to bans, and an invite gets them
+        // past +i, +k and +l.
+        let invited = channel.is_invited(connection_id);
+        let refusal = if channel.modes.contains(&'R') && connection.account.is_none() {
+            Some(("477", "Cannot join channel (+R) - you need to be logged into your account"))
+        } else if channel.modes.contains(&'S') && !connection.secure {
+            Some(("489", "Cannot join channel (+S) - you need to be connected with TLS"))
+        } else if channel.modes.contains(&'O') && !connection.modes.contains(&'o') {
+            Some(("520", "Cannot join channel (+O) - you need to be an IRC operator"))
+        } else if is_founder {
+            None
+        } else if channel.is_banned(&connection) {
+            Some(("474", "Cannot join channel (+b)"))
+        } else if invited {
+            None
+        } else if channel.modes.contains(&'i') && !channel.is_invite_exempt(&connection) {
+            Some(("473", "Cannot join channel (+i)"))
+        } else if channel.key.as_ref().is_some_and(|key| keys.get(index) != Some(key)) {
+            Some(("475", "Cannot join channel (+k)"))
+        } else if channel.limit.is_some_and(|limit| channel.member_count() >= limit) {
+            Some(("471", "Cannot join channel (+l)"))
+        } else {
+            None
+        };
+        if let Some((numeric, reason)) = refusal {
+            // Don't leave behind a channel that was only recreated for this join
+            drop(channel);
+            state.channels.remove_if(&channel_name, |_, channel| channel.members.is_empty());
+            responses.push(Message::new(numeric)
+                .with_prefix(state.server_name.clone())
+                .with_params(vec![nick.clone(), channel_name.clone(), reason.to_string()]));
+            continue;
+        }
+        
+        // Invites are good for one join
+        channel.invites.remove(&connection_id);
+        
         let mut member_modes = Vec::new();
-        if is_new_channel {
-            member_modes.push('o'); // Grant operator status to channel founder
+        if is_founder {
+            member_modes.push('q');
+        } else if is_new_channel && registration.is_none() {
+            member_modes.push('o');
         }
         
         channel.members.insert(connection_id, ChannelMember {
@@ -83,7 +128,24 @@ pub async fn handle_join(
             let member_id = *entry.key();
             if member_id != connection_id {  // Don't send to joiner yet
                 if let Some(member_conn) = state.connections.get(&member_id) {
-                    let _ = member_conn.tx.send(join_msg.clone()).await;
+                    let _ = member_conn.tx.try_send(join_msg.clone());
+                }
+            }
+        }
+        
+        // away-notify clients learn that the joiner is away
+        if let Some(away) = connection.away.clone().filter(|_| state.config.features.enable_away_notify) {
+            let away_msg = Message::new("AWAY")
+                .with_prefix(prefix.clone())
+                .with_params(vec![away]);
+            for entry in channel.members.iter() {
+                if *entry.key() == connection_id {
+                    continue;
+                }
+                if let Some(member_conn) = state.connections.get(entry.key()) {
+                    if member_conn.capabilities.iter().any(|cap| cap == "away-notify") {
+                        let _ = member_conn.tx.try_send(away_msg.clone());
+                    }
                 }
             }
         }
@@ -116,13 +178,9 @@ pub async fn handle_join(
             let member = entry.value();
             if let Some(member_conn) = state.connections.get(&member_id) {
                 if let Some(member_nick) = &member_conn.nickname {
-                    let mut name = member_nick.clone();
-                    // Add prefix for channel status (highest status first)
-                    if member.modes.contains(&'o') {
-                        name = format!("@{}", name);
-                    } else if member.modes.contains(&'v') {
-                        name = format!("+{}", name);
-                    }
+                    let mut name = String::new();
+                    name.extend(prefix_symbol(&member.modes));
+                    name.push_str(member_nick);
                     names.push(name);
                 }
             }
@@ -132,7 +190,13 @@ pub async fn handle_join(
             responses.push(Message::from(
                 Reply::NamReply {
                     nick: nick.clone(),
-                    symbol: '=', // '=' for public channel
+                    symbol: if channel.modes.contains(&'s') {
+                        '@'
+                    } else if channel.modes.contains(&'p') {
+                        '*'
+                    } else {
+                        '='
+                    },
                     channel: channel_name.clone(),
                     names,
                 }
@@ -145,7 +209,35 @@ pub async fn handle_join(
                 channel: channel_name.clone(),
             }
         ));
+        
+        if let Some(entry_message) = registration.and_then(|record| record.entry_message) {
+            responses.push(Message::new("NOTICE")
+                .with_prefix(state.server_name.clone())
+                .with_params(vec![nick.clone(), format!("[{}] {}", channel_name, entry_message)]));
+        }
     }
     
     Ok(responses)
-}
\ No newline at end of file
+}
+
+/// JOIN <channels> [keys]
+pub struct Join;
+
+#[async_trait]
+impl CommandHandler for Join {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("JOIN").min_params(1).penalty(3)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Join(channels, keys) = command else {
+            return Ok(Vec::new());
+        };
+        handle_join(server_state, ctx.connection_id, channels, keys).await
+    }
+}
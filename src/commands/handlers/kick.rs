@@ -1,10 +1,11 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
-use crate::state::ServerState;
+use crate::protocol::{Command, Message, Reply};
+use crate::state::{OperPrivilege, ServerState};
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_kick(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
     params: Vec<String>,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
@@ -22,7 +23,7 @@ pub async fn handle_kick(
         "Kicked".to_string()
     };
     
-    let mut state = server_state.write().await;
+    let state = &*server_state;
     
     // Get kicker connection info
     let connection = state.connections.get(&connection_id)
@@ -58,8 +59,8 @@ pub async fn handle_kick(
         return Ok(responses);
     }
     
-    // Check if kicker has operator privileges
-    if !channel.is_operator(connection_id) {
+    // Check if kicker has operator privileges, or can override them
+    if !channel.is_operator(connection_id) && !connection.has_privilege(OperPrivilege::Override) {
         responses.push(Message::from(Reply::ChanOpPrivsNeeded {
             nick: kicker_nick,
             channel: channel_name,
@@ -112,26 +113,50 @@ pub async fn handle_kick(
     for entry in channel.members.iter() {
         let member_id = *entry.key();
         if let Some(member_conn) = state.connections.get(&member_id) {
-            let _ = member_conn.tx.send(kick_msg.clone()).await;
+            let _ = member_conn.tx.try_send(kick_msg.clone());
         }
     }
     
     // Send KICK message to the kicked user
     if let Some(target_conn) = state.connections.get(&target_connection_id) {
-        let _ = target_conn.tx.send(kick_msg.clone()).await;
+        let _ = target_conn.tx.try_send(kick_msg.clone());
     }
     
     // Send KICK confirmation to the kicker
     responses.push(kick_msg);
     
-    // If channel is empty, remove it
-    if channel.members.is_empty() {
-        state.channels.remove(&channel_name);
-    }
+    // If channel is empty, remove it. The guard has to go first, and the
+    // check is repeated in case someone joined meanwhile.
+    drop(channel);
+    state.channels.remove_if(&channel_name, |_, channel| channel.members.is_empty());
     
     Ok(responses)
 }
 
+/// KICK <channel> <nick> [reason]
+pub struct Kick;
+
+#[async_trait]
+impl CommandHandler for Kick {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("KICK").min_params(2)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Kick { channel, user, reason } = command else {
+            return Ok(Vec::new());
+        };
+        let mut params = vec![channel, user];
+        params.extend(reason);
+        handle_kick(server_state, ctx.connection_id, params).await
+    }
+}
+
 #[cfg(test)]
 #[path = "kick_test.rs"]
 mod tests;
@@ -0,0 +1,55 @@
+use super::*;
+use crate::commands::handlers::test_support::setup_channel;
+
+#[tokio::test]
+async fn test_operator_kicks_member() {
+    let (state, mut receivers) = setup_channel();
+
+    let messages = handle_kick(
+        state.clone(),
+        1,
+        vec!["#test".to_string(), "bob".to_string(), "bye".to_string()],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(messages[0].command, "KICK");
+    assert_eq!(messages[0].params, vec!["#test", "bob", "bye"]);
+    assert!(!state.channels.get("#test").unwrap().is_member(2));
+
+    let kicked = receivers[1].try_recv().unwrap();
+    assert_eq!(kicked.command, "KICK");
+}
+
+#[tokio::test]
+async fn test_non_operator_cannot_kick() {
+    let (state, _rx) = setup_channel();
+
+    let messages = handle_kick(state.clone(), 2, vec!["#test".to_string(), "alice".to_string()])
+        .await
+        .unwrap();
+
+    assert_eq!(messages[0].command, "482");
+    assert!(state.channels.get("#test").unwrap().is_member(1));
+}
+
+#[tokio::test]
+async fn test_kick_unknown_channel() {
+    let (state, _rx) = setup_channel();
+
+    let messages = handle_kick(state, 1, vec!["#nowhere".to_string(), "bob".to_string()])
+        .await
+        .unwrap();
+
+    assert_eq!(messages[0].command, "403");
+}
+
+#[tokio::test]
+async fn test_last_member_kicking_itself_removes_channel() {
+    let (state, _rx) = setup_channel();
+    handle_kick(state.clone(), 1, vec!["#test".to_string(), "bob".to_string()]).await.unwrap();
+
+    let messages = handle_kick(state.clone(), 1, vec!["#test".to_string(), "alice".to_string()]).await.unwrap();
+    assert_eq!(messages[0].command, "KICK");
+    assert!(!state.channels.contains_key("#test"));
+}
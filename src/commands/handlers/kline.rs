@@ -0,0 +1,238 @@
+use std::sync::Arc;
+use chrono::Utc;
+use tracing::info;
+use crate::db::models::ServerBan;
+use crate::protocol::{Command, Message, Reply};
+use crate::state::{BanKind, OperPrivilege, ServerState};
+use crate::state::server_bans::is_valid_dline;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
+
+fn notice(state: &ServerState, nick: &str, text: String) -> Message {
+    Message::new("NOTICE")
+        .with_prefix(state.server_name.clone())
+        .with_params(vec![nick.to_string(), text])
+}
+
+/// The registry only admits operators; ban changes also need the kline
+/// privilege (723)
+fn require_kline(state: &ServerState, connection_id: u64, nick: &str) -> Option<Message> {
+    let allowed = state.connections.get(&connection_id)
+        .is_some_and(|conn| conn.has_privilege(OperPrivilege::Kline));
+    (!allowed).then(|| Message::new("723")
+        .with_prefix(state.server_name.clone())
+        .with_params(vec![nick.to_string(), "kline".to_string(), "Insufficient oper privileges.".to_string()]))
+}
+
+/// Split `[minutes] <mask> [reason]`. No duration, or one of 0, makes the
+/// ban permanent.
+fn parse_ban(params: Vec<String>) -> Option<(Option<i64>, String, String)> {
+    let mut params = params.into_iter();
+    let mut mask = params.next()?;
+    let mut minutes = None;
+    if let Ok(duration) = mask.parse::<i64>() {
+        minutes = Some(duration).filter(|&duration| duration > 0);
+        mask = params.next()?;
+    }
+    let reason = params.next()
+        .filter(|reason| !reason.is_empty())
+        .unwrap_or_else(|| "No reason".to_string());
+    Some((minutes, mask, reason))
+}
+
+/// Turn a KLINE target into a user@host mask. A bare nickname bans that
+/// user's host and any other bare word is taken as a host. Returns None for
+/// masks whose host part is all wildcards.
+fn kline_mask(state: &ServerState, target: &str) -> Option<String> {
+    let mask = match target.rsplit_once('@') {
+        Some((user, host)) if !user.is_empty() && !host.is_empty() => target.to_string(),
+        Some(_) => return None,
+        None => match state.connection_by_nick(target) {
+            Some(conn) => format!("*@{}", conn.hostname),
+            None => format!("*@{}", target),
+        },
+    };
+    let (_, host) = mask.rsplit_once('@')?;
+    host.chars().any(|c| c != '*' && c != '?').then_some(mask)
+}
+
+/// KLINE [minutes] <user@host | nick> [reason]
+/// DLINE [minutes] <address | CIDR> [reason]
+///
+/// Adds a server ban and disconnects every client it already covers.
+/// Operators watching snomask `k` are told.
+pub async fn handle_add_ban(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    kind: BanKind,
+    params: Vec<String>,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+
+    let nick = state.connections.get(&connection_id)
+        .and_then(|conn| conn.nickname.clone())
+        .ok_or("Connection not found")?;
+    if let Some(denied) = require_kline(state, connection_id, &nick) {
+        return Ok(vec![denied]);
+    }
+    let command = match kind {
+        BanKind::Kline => "KLINE",
+        BanKind::Dline => "DLINE",
+    };
+
+    let Some((minutes, target, reason)) = parse_ban(params) else {
+        return Ok(vec![Reply::NeedMoreParams { nick, command: command.to_string() }.to_message(&state.server_name)]);
+    };
+    let mask = match kind {
+        BanKind::Kline => kline_mask(state, &target),
+        BanKind::Dline => is_valid_dline(&target).then(|| target.clone()),
+    };
+    let Some(mask) = mask else {
+        return Ok(vec![notice(state, &nick, format!("Invalid {} mask [{}]", kind.name(), target))]);
+    };
+
+    let now = Utc::now();
+    state.server_bans.add(kind, ServerBan {
+        kind: kind.letter().to_string(),
+        mask: mask.clone(),
+        set_by: nick.clone(),
+        set_at: now,
+        reason: reason.clone(),
+        expires_at: minutes.map(|minutes| now + chrono::Duration::minutes(minutes)),
+    });
+    let dropped = state.enforce_server_ban(kind, &mask, &reason);
+    info!("{} added {} for {} ({}), {} clients disconnected", nick, kind.name(), mask, reason, dropped.len());
+
+    let added = match minutes {
+        Some(minutes) => format!("Added temporary {} min. {} for [{}] [{}]", minutes, kind.name(), mask, reason),
+        None => format!("Added {} for [{}] [{}]", kind.name(), mask, reason),
+    };
+    state.server_notice('k', &format!("{} {}", nick, added.replacen("Added", "added", 1)));
+    Ok(vec![notice(state, &nick, added)])
+}
+
+/// UNKLINE <user@host>
+/// UNDLINE <address | CIDR>
+pub async fn handle_remove_ban(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    kind: BanKind,
+    mask: String,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+
+    let nick = state.connections.get(&connection_id)
+        .and_then(|conn| conn.nickname.clone())
+        .ok_or("Connection not found")?;
+    if let Some(denied) = require_kline(state, connection_id, &nick) {
+        return Ok(vec![denied]);
+    }
+
+    // A K-line given without a user part was set on *@host
+    let mask = match kind {
+        BanKind::Kline if !mask.contains('@') => format!("*@{}", mask),
+        _ => mask,
+    };
+
+    let text = match state.server_bans.remove(kind, &mask) {
+        Some(ban) => {
+            info!("{} removed {} for {}", nick, kind.name(), ban.mask);
+            state.server_notice('k', &format!("{} has removed the {} for [{}]", nick, kind.name(), ban.mask));
+            format!("{} for [{}] is removed", kind.name(), ban.mask)
+        }
+        None => format!("No {} for [{}]", kind.name(), mask),
+    };
+    Ok(vec![notice(state, &nick, text)])
+}
+
+/// KLINE [minutes] <user@host | nick> [reason]
+pub struct Kline;
+
+#[async_trait]
+impl CommandHandler for Kline {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("KLINE").min_params(1).oper_only()
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Kline(params) = command else {
+            return Ok(Vec::new());
+        };
+        handle_add_ban(server_state, ctx.connection_id, BanKind::Kline, params).await
+    }
+}
+
+/// UNKLINE <user@host>
+pub struct Unkline;
+
+#[async_trait]
+impl CommandHandler for Unkline {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("UNKLINE").min_params(1).oper_only()
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Unkline(mask) = command else {
+            return Ok(Vec::new());
+        };
+        handle_remove_ban(server_state, ctx.connection_id, BanKind::Kline, mask).await
+    }
+}
+
+/// DLINE [minutes] <address | CIDR> [reason]
+pub struct Dline;
+
+#[async_trait]
+impl CommandHandler for Dline {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("DLINE").min_params(1).oper_only()
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Dline(params) = command else {
+            return Ok(Vec::new());
+        };
+        handle_add_ban(server_state, ctx.connection_id, BanKind::Dline, params).await
+    }
+}
+
+/// UNDLINE <address | CIDR>
+pub struct Undline;
+
+#[async_trait]
+impl CommandHandler for Undline {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("UNDLINE").min_params(1).oper_only()
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Undline(mask) = command else {
+            return Ok(Vec::new());
+        };
+        handle_remove_ban(server_state, ctx.connection_id, BanKind::Dline, mask).await
+    }
+}
+
+#[cfg(test)]
+#[path = "kline_test.rs"]
+mod tests;
@@ -0,0 +1,77 @@
+use super::*;
+use crate::commands::handlers::test_support::{add_user, params};
+use crate::commands::handlers::stats::handle_stats;
+use crate::state::OperPrivilege;
+use crate::state::sendq;
+
+fn setup() -> (Arc<ServerState>, sendq::SendQueue) {
+    let state = ServerState::new();
+    add_user(1, "oper").insert(&state);
+    {
+        let mut oper = state.connections.get_mut(&1).unwrap();
+        oper.modes.push('o');
+        oper.privileges.push(OperPrivilege::Kline);
+    }
+    add_user(2, "mallory").addr("192.0.2.7:40000").insert(&state);
+    let mallory = state.connections.get(&2).unwrap().tx.clone();
+    (Arc::new(state), mallory)
+}
+
+#[tokio::test]
+async fn test_kline_by_nick_disconnects_and_lists() {
+    let (state, mallory) = setup();
+
+    let messages = handle_add_ban(state.clone(), 1, BanKind::Kline, params("60 mallory spamming")).await.unwrap();
+    assert!(messages[0].params[1].starts_with("Added temporary 60 min. K-Line for [*@192.0.2.7]"));
+    assert_eq!(mallory.closed().await, "K-Lined: spamming");
+    assert!(state.server_bans.check_user("someone", "192.0.2.7", "192.0.2.7".parse().unwrap()).is_err());
+
+    let messages = handle_stats(state.clone(), 1, "k".to_string()).await.unwrap();
+    assert_eq!(messages[0].command, "216");
+    assert_eq!(messages[0].params[1..5], ["K", "192.0.2.7", "*", "*"]);
+    assert_eq!(messages[1].command, "219");
+
+    let messages = handle_remove_ban(state.clone(), 1, BanKind::Kline, "192.0.2.7".to_string()).await.unwrap();
+    assert_eq!(messages[0].params[1], "K-Line for [*@192.0.2.7] is removed");
+    let messages = handle_stats(state, 1, "k".to_string()).await.unwrap();
+    assert_eq!(messages[0].command, "219");
+}
+
+#[tokio::test]
+async fn test_dline_masks_are_validated() {
+    let (state, mallory) = setup();
+
+    let messages = handle_add_ban(state.clone(), 1, BanKind::Dline, params("example.com")).await.unwrap();
+    assert_eq!(messages[0].params[1], "Invalid D-Line mask [example.com]");
+    for too_wide in ["0.0.0.0/0", "10.0.0.0/7", "::/0", "2001::/15"] {
+        let messages = handle_add_ban(state.clone(), 1, BanKind::Dline, params(too_wide)).await.unwrap();
+        assert_eq!(messages[0].params[1], format!("Invalid D-Line mask [{}]", too_wide));
+    }
+    assert!(state.server_bans.check_address("127.0.0.1".parse().unwrap()).is_ok());
+    let messages = handle_add_ban(state.clone(), 1, BanKind::Kline, params("*@*")).await.unwrap();
+    assert_eq!(messages[0].params[1], "Invalid K-Line mask [*@*]");
+
+    handle_add_ban(state.clone(), 1, BanKind::Dline, vec!["192.0.2.0/24".to_string(), "open proxy".to_string()]).await.unwrap();
+    assert_eq!(mallory.closed().await, "D-Lined: open proxy");
+    assert!(state.server_bans.check_address("192.0.2.200".parse().unwrap()).is_err());
+    assert!(state.server_bans.check_address("127.0.0.1".parse().unwrap()).is_ok());
+
+    let messages = handle_stats(state.clone(), 1, "d".to_string()).await.unwrap();
+    assert_eq!(messages[0].params[1..4], ["D", "192.0.2.0/24", "open proxy"]);
+
+    // Ban lists are for operators only
+    state.connections.get_mut(&1).unwrap().modes.clear();
+    let messages = handle_stats(state, 1, "d".to_string()).await.unwrap();
+    assert_eq!(messages[0].command, "481");
+}
+
+#[tokio::test]
+async fn test_ban_changes_need_kline_privilege() {
+    let (state, _mallory) = setup();
+    state.connections.get_mut(&1).unwrap().privileges.clear();
+
+    let messages = handle_add_ban(state.clone(), 1, BanKind::Kline, params("mallory")).await.unwrap();
+    assert_eq!(messages[0].command, "723");
+    assert_eq!(messages[0].params[1], "kline");
+    assert!(state.server_bans.list(BanKind::Kline).is_empty());
+}
@@ -1,14 +1,15 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
-use crate::state::ServerState;
+use crate::protocol::{Command, Message};
+use crate::state::{OperPrivilege, ServerState};
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_list(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
     params: Vec<String>,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
-    let state = server_state.read().await;
+    let state = &*server_state;
     let mut messages = Vec::new();
     
     // Get the requesting connection
@@ -18,6 +19,8 @@ pub async fn handle_list(
     }
     let req_conn = requesting_connection.unwrap();
     let requester_nick = req_conn.nickname.as_deref().unwrap_or("*");
+    // Operators with see-hidden are shown secret and private channels too
+    let see_hidden = req_conn.has_privilege(OperPrivilege::SeeHidden);
     
     // Parse parameters - LIST [channels] [target]
     let channel_filter = params.get(0).cloned();
@@ -40,7 +43,7 @@ pub async fn handle_list(
             if let Some(channel) = state.channels.get(channel_name) {
                 // Check if channel is visible (not secret/private or user is a member)
                 let can_see = !channel.modes.contains(&'s') && !channel.modes.contains(&'p') 
-                            || channel.is_member(connection_id);
+                            || channel.is_member(connection_id) || see_hidden;
                 
                 if can_see {
                     let topic = channel.topic.as_deref().unwrap_or("");
@@ -65,7 +68,7 @@ pub async fn handle_list(
             
             // Check if channel is visible
             let can_see = !channel.modes.contains(&'s') && !channel.modes.contains(&'p') 
-                        || channel.is_member(connection_id);
+                        || channel.is_member(connection_id) || see_hidden;
             
             if can_see {
                 let topic = channel.topic.as_deref().unwrap_or("");
@@ -96,6 +99,28 @@ pub async fn handle_list(
     Ok(messages)
 }
 
+/// LIST [channels]
+pub struct List;
+
+#[async_trait]
+impl CommandHandler for List {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("LIST").penalty(10)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::List(channels) = command else {
+            return Ok(Vec::new());
+        };
+        handle_list(server_state, ctx.connection_id, channels.unwrap_or_default()).await
+    }
+}
+
 #[cfg(test)]
 #[path = "list_test.rs"]
 mod tests;
@@ -0,0 +1,38 @@
+use super::*;
+use crate::commands::handlers::test_support::add_user;
+use crate::state::Channel;
+
+#[tokio::test]
+async fn test_list_hides_secret_channels_from_non_members() {
+    let state = ServerState::new();
+    let _alice = add_user(1, "alice").insert(&state);
+    let _bob = add_user(2, "bob").insert(&state);
+
+    let public = Channel::new("#public".to_string());
+    public.add_member(1, true);
+    state.channels.insert("#public".to_string(), public);
+
+    let mut secret = Channel::new("#secret".to_string());
+    secret.modes.push('s');
+    secret.add_member(1, true);
+    state.channels.insert("#secret".to_string(), secret);
+
+    let state = Arc::new(state);
+
+    let listed = |messages: &[Message]| -> Vec<String> {
+        messages.iter()
+            .filter(|m| m.command == "322")
+            .map(|m| m.params[1].clone())
+            .collect()
+    };
+
+    let messages = handle_list(state.clone(), 2, vec![]).await.unwrap();
+    assert_eq!(messages.first().unwrap().command, "321");
+    assert_eq!(messages.last().unwrap().command, "323");
+    assert_eq!(listed(&messages), vec!["#public".to_string()]);
+
+    let messages = handle_list(state, 1, vec![]).await.unwrap();
+    let mut channels = listed(&messages);
+    channels.sort();
+    assert_eq!(channels, vec!["#public".to_string(), "#secret".to_string()]);
+}
@@ -10,11 +10,24 @@ pub mod whois;
 pub mod topic;
 pub mod mode;
 pub mod kick;
+pub mod invite;
 pub mod list;
 pub mod names;
 pub mod motd;
 pub mod oper;
+pub mod wallops;
 pub mod nick;
 pub mod tagmsg;
 pub mod chathistory;
-pub mod query;
\ No newline at end of file
+pub mod query;
+pub mod certfp;
+pub mod register;
+pub mod chanreg;
+pub mod away;
+pub mod monitor;
+pub mod ison;
+pub mod whowas;
+pub mod kline;
+pub mod stats;
+#[cfg(test)]
+pub(crate) mod test_support;
@@ -1,10 +1,16 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
-use crate::state::ServerState;
+use chrono::Utc;
+use crate::db::models::BanEntry;
+use crate::protocol::{Command, Message, Reply};
+use crate::state::{Connection, ServerState};
+use crate::state::channel::ListEntry;
+use crate::state::channel_modes::{self, ModeChange, ModeKind, Rank};
+use crate::state::operators::{OperPrivilege, SNOMASKS};
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_mode(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
     params: Vec<String>,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
@@ -15,7 +21,7 @@ pub async fn handle_mode(
     }
     
     let target = params[0].clone();
-    let mut state = server_state.write().await;
+    let state = &*server_state;
     
     // Get connection info
     let connection = state.connections.get(&connection_id)
@@ -27,17 +33,16 @@ pub async fn handle_mode(
     
     // Check if target is a channel
     if target.starts_with('#') || target.starts_with('&') || target.starts_with('!') || target.starts_with('+') {
-        handle_channel_mode(&mut state, connection_id, &nick, &target, &params[1..], &mut responses).await?;
+        handle_channel_mode(state, connection_id, &nick, &target, &params[1..], &mut responses).await?;
     } else {
-        // User mode - not implemented yet
-        return Err("User modes not implemented yet".into());
+        handle_user_mode(state, &connection, &nick, &target, &params[1..], &mut responses);
     }
     
     Ok(responses)
 }
 
 async fn handle_channel_mode(
-    state: &mut tokio::sync::RwLockWriteGuard<'_, ServerState>,
+    state: &ServerState,
     connection_id: u64,
     nick: &str,
     channel_name: &str,
@@ -67,287 +72,416 @@ async fn handle_channel_mode(
     
     // If no mode parameters, return current channel modes
     if mode_params.is_empty() {
-        let mode_string = format_channel_modes(&channel);
+        let mut mode_params = channel.mode_params();
+        let modes = mode_params.remove(0);
         responses.push(Message::from(Reply::ChannelModeIs {
             nick: nick.to_string(),
             channel: channel_name.to_string(),
-            modes: mode_string,
-            params: Vec::new(),
+            modes,
+            params: mode_params,
         }));
         return Ok(());
     }
     
-    // Parse and apply mode changes
-    let mode_string = mode_params[0].clone();
-    let mut mode_args = mode_params[1..].to_vec();
-    let mut arg_index = 0;
+    let parsed = channel_modes::parse(&mode_params[0], &mode_params[1..]);
+    for letter in parsed.unknown {
+        responses.push(Message::new("472")
+            .with_prefix(state.server_name.clone())
+            .with_params(vec![nick.to_string(), letter.to_string(), "is unknown mode char to me".to_string()]));
+    }
     
-    let mut adding = true;
-    let mut mode_changes = Vec::new();
-    let mut param_changes = Vec::new();
+    let (is_oper, can_override) = state.connections.get(&connection_id)
+        .map(|conn| (conn.modes.contains(&'o'), conn.has_privilege(OperPrivilege::Override)))
+        .unwrap_or_default();
+    // Operators with the override privilege act as the channel founder
+    let rank = if can_override { Rank::Founder } else { channel.rank(connection_id) };
+    let mut denied = false;
+    let mut applied = Vec::new();
     
-    for ch in mode_string.chars() {
-        match ch {
-            '+' => adding = true,
-            '-' => adding = false,
-            'o' => {
-                // Operator status - requires parameter
-                if arg_index >= mode_args.len() {
-                    responses.push(Message::from(Reply::NeedMoreParams {
-                        nick: nick.to_string(),
-                        command: "MODE".to_string(),
-                    }));
-                    return Ok(());
-                }
+    for change in parsed.changes {
+        let Some(def) = channel_modes::lookup(change.mode) else {
+            continue;
+        };
+        let ch = change.mode;
+        
+        // Without a mask the list is shown instead of changed
+        if def.kind == ModeKind::List && change.param.is_none() {
+            let (entry_numeric, end_numeric, end_text) = list_numerics(ch);
+            for entry in channel.list(ch).unwrap_or_default() {
+                responses.push(Message::new(entry_numeric)
+                    .with_prefix(state.server_name.clone())
+                    .with_params(vec![
+                        nick.to_string(),
+                        channel_name.to_string(),
+                        entry.mask.clone(),
+                        entry.set_by.clone(),
+                        entry.set_at.timestamp().to_string(),
+                    ]));
+            }
+            responses.push(Message::new(end_numeric)
+                .with_prefix(state.server_name.clone())
+                .with_params(vec![nick.to_string(), channel_name.to_string(), end_text.to_string()]));
+            continue;
+        }
+        
+        if def.oper_only && !is_oper {
+            responses.push(Message::new("481")
+                .with_prefix(state.server_name.clone())
+                .with_params(vec![nick.to_string(), "Permission Denied- You're not an IRC operator".to_string()]));
+            continue;
+        }
+        
+        // Anyone may give up their own rank
+        let self_demotion = def.kind == ModeKind::Prefix && !change.adding
+            && change.param.as_deref().is_some_and(|target| target.eq_ignore_ascii_case(nick));
+        if rank < def.min_rank && !self_demotion {
+            if !denied {
+                responses.push(Message::from(Reply::ChanOpPrivsNeeded {
+                    nick: nick.to_string(),
+                    channel: channel_name.to_string(),
+                }));
+                denied = true;
+            }
+            continue;
+        }
+        
+        match def.kind {
+            ModeKind::List => {
+                let Some(param) = change.param else { continue };
+                let mut mask = normalize_mask(&param);
                 
-                let target_nick = &mode_args[arg_index];
-                arg_index += 1;
+                // $a is the only extban type
+                if mask.starts_with('$') && mask != "$a" && !mask.starts_with("$a:") {
+                    continue;
+                }
                 
-                if let Some(target_id) = state.nicknames.get(target_nick) {
-                    let target_id = *target_id;
-                    
-                    if !channel.is_member(target_id) {
-                        responses.push(Message::from(Reply::UserNotInChannel {
-                            nick: nick.to_string(),
-                            target: target_nick.clone(),
-                            channel: channel_name.to_string(),
-                        }));
+                let existing = channel.list(ch).unwrap_or_default().iter()
+                    .position(|entry| entry.mask.eq_ignore_ascii_case(&mask));
+                if change.adding {
+                    if existing.is_some() {
                         continue;
                     }
-                    
-                    // Check if user has op privileges (only ops can change modes)
-                    if !channel.is_operator(connection_id) {
-                        responses.push(Message::from(Reply::ChanOpPrivsNeeded {
-                            nick: nick.to_string(),
-                            channel: channel_name.to_string(),
-                        }));
-                        return Ok(());
+                    if channel.list_entry_count() >= state.config.limits.max_list_entries {
+                        responses.push(Message::new("478")
+                            .with_prefix(state.server_name.clone())
+                            .with_params(vec![
+                                nick.to_string(),
+                                channel_name.to_string(),
+                                mask,
+                                "Channel list is full".to_string(),
+                            ]));
+                        continue;
                     }
                     
-                    // Apply operator mode change
-                    if let Some(mut member) = channel.members.get_mut(&target_id) {
-                        if adding {
-                            if !member.modes.contains(&'o') {
-                                member.modes.push('o');
-                                mode_changes.push(format!("+o"));
-                                param_changes.push(target_nick.clone());
-                            }
-                        } else {
-                            if let Some(pos) = member.modes.iter().position(|&x| x == 'o') {
-                                member.modes.remove(pos);
-                                mode_changes.push(format!("-o"));
-                                param_changes.push(target_nick.clone());
-                            }
-                        }
+                    let entry = ListEntry {
+                        mask: mask.clone(),
+                        set_by: state.connections.get(&connection_id)
+                            .map(|conn| conn.full_mask())
+                            .unwrap_or_else(|| nick.to_string()),
+                        set_at: Utc::now(),
+                    };
+                    state.registered_channels.add_list_entry(BanEntry {
+                        channel_name: channel.name.clone(),
+                        mode: ch.to_string(),
+                        mask: entry.mask.clone(),
+                        set_by: entry.set_by.clone(),
+                        set_at: entry.set_at,
+                        reason: None,
+                        expires_at: None,
+                    });
+                    if let Some(list) = channel.list_mut(ch) {
+                        list.push(entry);
                     }
                 } else {
-                    responses.push(Message::from(Reply::NoSuchNick {
-                        nick: nick.to_string(),
-                        target: target_nick.clone(),
-                    }));
-                }
-            },
-            'v' => {
-                // Voice status - requires parameter
-                if arg_index >= mode_args.len() {
-                    responses.push(Message::from(Reply::NeedMoreParams {
-                        nick: nick.to_string(),
-                        command: "MODE".to_string(),
-                    }));
-                    return Ok(());
+                    let (Some(pos), Some(list)) = (existing, channel.list_mut(ch)) else {
+                        continue;
+                    };
+                    // Report the mask as it was set, not as the remover spelled it
+                    mask = list.remove(pos).mask;
+                    state.registered_channels.remove_list_entry(channel_name, ch, &mask);
                 }
-                
-                let target_nick = &mode_args[arg_index];
-                arg_index += 1;
-                
-                if let Some(target_id) = state.nicknames.get(target_nick) {
-                    let target_id = *target_id;
-                    
-                    if !channel.is_member(target_id) {
-                        responses.push(Message::from(Reply::UserNotInChannel {
+                applied.push(ModeChange { param: Some(mask), ..change });
+            }
+            ModeKind::Parameter => {
+                // The channel key; unsetting it ignores the key given
+                if change.adding {
+                    let Some(key) = change.param.clone() else {
+                        responses.push(Message::from(Reply::NeedMoreParams {
                             nick: nick.to_string(),
-                            target: target_nick.clone(),
-                            channel: channel_name.to_string(),
+                            command: "MODE".to_string(),
                         }));
                         continue;
-                    }
-                    
-                    // Check if user has op privileges
-                    if !channel.is_operator(connection_id) {
-                        responses.push(Message::from(Reply::ChanOpPrivsNeeded {
-                            nick: nick.to_string(),
-                            channel: channel_name.to_string(),
-                        }));
-                        return Ok(());
-                    }
-                    
-                    // Apply voice mode change
-                    if let Some(mut member) = channel.members.get_mut(&target_id) {
-                        if adding {
-                            if !member.modes.contains(&'v') {
-                                member.modes.push('v');
-                                mode_changes.push(format!("+v"));
-                                param_changes.push(target_nick.clone());
-                            }
-                        } else {
-                            if let Some(pos) = member.modes.iter().position(|&x| x == 'v') {
-                                member.modes.remove(pos);
-                                mode_changes.push(format!("-v"));
-                                param_changes.push(target_nick.clone());
-                            }
-                        }
-                    }
-                } else {
-                    responses.push(Message::from(Reply::NoSuchNick {
+                    };
+                    channel.key = Some(key);
+                    set_flag(&mut channel.modes, ch, true);
+                    applied.push(change);
+                } else if channel.key.take().is_some() {
+                    set_flag(&mut channel.modes, ch, false);
+                    applied.push(ModeChange { param: Some("*".to_string()), ..change });
+                }
+            }
+            ModeKind::SetParameter => {
+                // The user limit
+                if change.adding {
+                    let Some(limit) = change.param.as_deref().and_then(|limit| limit.parse::<usize>().ok()) else {
+                        continue;
+                    };
+                    channel.limit = Some(limit);
+                    set_flag(&mut channel.modes, ch, true);
+                    applied.push(ModeChange { param: Some(limit.to_string()), ..change });
+                } else if channel.limit.take().is_some() {
+                    set_flag(&mut channel.modes, ch, false);
+                    applied.push(change);
+                }
+            }
+            ModeKind::Flag => {
+                if set_flag(&mut channel.modes, ch, change.adding) {
+                    applied.push(change);
+                }
+            }
+            ModeKind::Prefix => {
+                let Some(target_nick) = change.param.clone() else {
+                    responses.push(Message::from(Reply::NeedMoreParams {
                         nick: nick.to_string(),
-                        target: target_nick.clone(),
+                        command: "MODE".to_string(),
                     }));
-                }
-            },
-            't' | 'n' | 'm' | 'i' | 's' | 'p' => {
-                // Channel modes that don't require parameters
-                if !channel.is_operator(connection_id) {
-                    responses.push(Message::from(Reply::ChanOpPrivsNeeded {
+                    continue;
+                };
+                let Some(target_id) = state.nicknames.get(&target_nick.to_lowercase()).map(|id| *id) else {
+                    responses.push(Message::from(Reply::NoSuchNick {
                         nick: nick.to_string(),
-                        channel: channel_name.to_string(),
+                        target: target_nick,
                     }));
-                    return Ok(());
-                }
-                
-                if adding {
-                    if !channel.modes.contains(&ch) {
-                        channel.modes.push(ch);
-                        mode_changes.push(format!("+{}", ch));
-                    }
-                } else {
-                    if let Some(pos) = channel.modes.iter().position(|&x| x == ch) {
-                        channel.modes.remove(pos);
-                        mode_changes.push(format!("-{}", ch));
-                    }
-                }
-            },
-            'k' => {
-                // Channel key - requires parameter
-                if !channel.is_operator(connection_id) {
-                    responses.push(Message::from(Reply::ChanOpPrivsNeeded {
+                    continue;
+                };
+                let Some(mut member) = channel.members.get_mut(&target_id) else {
+                    responses.push(Message::from(Reply::UserNotInChannel {
                         nick: nick.to_string(),
+                        target: target_nick,
                         channel: channel_name.to_string(),
                     }));
-                    return Ok(());
-                }
+                    continue;
+                };
                 
-                if adding {
-                    if arg_index >= mode_args.len() {
-                        responses.push(Message::from(Reply::NeedMoreParams {
+                // Members ranked above the setter are out of reach
+                if target_id != connection_id && Rank::of(&member.modes) > rank {
+                    if !denied {
+                        responses.push(Message::from(Reply::ChanOpPrivsNeeded {
                             nick: nick.to_string(),
-                            command: "MODE".to_string(),
+                            channel: channel_name.to_string(),
                         }));
-                        return Ok(());
-                    }
-                    
-                    let key = mode_args[arg_index].clone();
-                    arg_index += 1;
-                    
-                    channel.key = Some(key.clone());
-                    if !channel.modes.contains(&'k') {
-                        channel.modes.push('k');
-                    }
-                    mode_changes.push(format!("+k"));
-                    param_changes.push(key);
-                } else {
-                    if arg_index < mode_args.len() {
-                        let _old_key = &mode_args[arg_index];
-                        arg_index += 1;
+                        denied = true;
                     }
-                    
-                    channel.key = None;
-                    if let Some(pos) = channel.modes.iter().position(|&x| x == 'k') {
-                        channel.modes.remove(pos);
-                    }
-                    mode_changes.push(format!("-k"));
-                    param_changes.push("*".to_string());
-                }
-            },
-            'l' => {
-                // User limit
-                if !channel.is_operator(connection_id) {
-                    responses.push(Message::from(Reply::ChanOpPrivsNeeded {
-                        nick: nick.to_string(),
-                        channel: channel_name.to_string(),
-                    }));
-                    return Ok(());
+                    continue;
                 }
                 
-                if adding {
-                    if arg_index >= mode_args.len() {
-                        responses.push(Message::from(Reply::NeedMoreParams {
-                            nick: nick.to_string(),
-                            command: "MODE".to_string(),
-                        }));
-                        return Ok(());
-                    }
-                    
-                    let limit_str = &mode_args[arg_index];
-                    arg_index += 1;
-                    
-                    if let Ok(limit) = limit_str.parse::<usize>() {
-                        channel.limit = Some(limit);
-                        if !channel.modes.contains(&'l') {
-                            channel.modes.push('l');
-                        }
-                        mode_changes.push(format!("+l"));
-                        param_changes.push(limit_str.clone());
-                    }
-                } else {
-                    channel.limit = None;
-                    if let Some(pos) = channel.modes.iter().position(|&x| x == 'l') {
-                        channel.modes.remove(pos);
-                    }
-                    mode_changes.push(format!("-l"));
+                if set_flag(&mut member.modes, ch, change.adding) {
+                    let target_nick = state.connections.get(&target_id)
+                        .and_then(|conn| conn.nickname.clone())
+                        .unwrap_or(target_nick);
+                    applied.push(ModeChange { param: Some(target_nick), ..change });
                 }
-            },
-            _ => {
-                // Unknown mode - silently ignore for now
             }
         }
     }
     
-    // If we made any changes, broadcast MODE message to channel
-    if !mode_changes.is_empty() {
-        let connection = state.connections.get(&connection_id).unwrap();
-        let user = connection.username.clone().unwrap_or_else(|| nick.to_string());
-        let host = connection.hostname.clone();
-        let prefix = format!("{}!{}@{}", nick, user, host);
+    // Broadcast everything that changed as a single MODE message
+    if !applied.is_empty() {
+        state.registered_channels.update(channel_name, |record| channel.save_to(record));
         
-        // Build mode change message
-        let mode_str = mode_changes.join("");
-        let mut params = vec![channel_name.to_string(), mode_str];
-        params.extend(param_changes);
+        let prefix = state.connections.get(&connection_id)
+            .map(|conn| conn.full_mask())
+            .unwrap_or_else(|| nick.to_string());
+        let mut params = vec![channel.name.clone()];
+        params.extend(channel_modes::format_changes(&applied));
         
         let mode_msg = Message::new("MODE")
             .with_prefix(prefix)
             .with_params(params);
         
-        // Send to all channel members
         for entry in channel.members.iter() {
             let member_id = *entry.key();
+            if member_id == connection_id {
+                continue;
+            }
             if let Some(member_conn) = state.connections.get(&member_id) {
-                let _ = member_conn.tx.send(mode_msg.clone()).await;
+                let _ = member_conn.tx.try_send(mode_msg.clone());
             }
         }
         
-        // Also send to the mode setter
         responses.push(mode_msg);
     }
     
     Ok(())
 }
 
-fn format_channel_modes(channel: &crate::state::channel::Channel) -> String {
-    if channel.modes.is_empty() {
-        "+".to_string()
-    } else {
-        format!("+{}", channel.modes.iter().collect::<String>())
+/// MODE <nick> [modestring]
+///
+/// Clients may only query and change their own modes. +o can be dropped
+/// but only OPER grants it, and +Z follows the connection. Operators may
+/// set +s with an optional snomask, such as `MODE nick +s ko`.
+fn handle_user_mode(
+    state: &ServerState,
+    connection: &Connection,
+    nick: &str,
+    target: &str,
+    mode_params: &[String],
+    responses: &mut Vec<Message>,
+) {
+    let Some(target_id) = state.nicknames.get(&target.to_lowercase()).map(|id| *id) else {
+        responses.push(Message::from(Reply::NoSuchNick {
+            nick: nick.to_string(),
+            target: target.to_string(),
+        }));
+        return;
+    };
+    if target_id != connection.id {
+        responses.push(Message::new("502")
+            .with_prefix(state.server_name.clone())
+            .with_params(vec![nick.to_string(), "Cant change mode for other users".to_string()]));
+        return;
+    }
+    let Some(mut conn) = state.connections.get_mut(&connection.id) else {
+        return;
+    };
+    
+    let Some(modestring) = mode_params.first() else {
+        let mut modes = conn.modes.clone();
+        modes.sort_unstable();
+        responses.push(Message::new("221")
+            .with_prefix(state.server_name.clone())
+            .with_params(vec![nick.to_string(), format!("+{}", modes.into_iter().collect::<String>())]));
+        return;
+    };
+    
+    let mut adding = true;
+    let mut unknown = false;
+    let mut applied = Vec::new();
+    let mut snomask_params = mode_params[1..].iter();
+    for mode in modestring.chars() {
+        match mode {
+            '+' => adding = true,
+            '-' => adding = false,
+            'B' | 'R' | 'i' | 'w' => {
+                if set_flag(&mut conn.modes, mode, adding) {
+                    applied.push(ModeChange { adding, mode, param: None });
+                }
+            }
+            'o' if !adding => {
+                if set_flag(&mut conn.modes, mode, false) {
+                    applied.push(ModeChange { adding, mode, param: None });
+                    conn.privileges.clear();
+                    // Server notices are for operators only
+                    if set_flag(&mut conn.modes, 's', false) {
+                        applied.push(ModeChange { adding, mode: 's', param: None });
+                    }
+                    conn.snomask.clear();
+                }
+            }
+            's' if !adding => {
+                if set_flag(&mut conn.modes, mode, false) {
+                    applied.push(ModeChange { adding, mode, param: None });
+                }
+                conn.snomask.clear();
+            }
+            's' if conn.modes.contains(&'o') => {
+                // An optional snomask follows, otherwise every one is taken
+                conn.snomask = match snomask_params.next() {
+                    Some(letters) => SNOMASKS.chars().filter(|&letter| letters.contains(letter)).collect(),
+                    None => SNOMASKS.chars().collect(),
+                };
+                if set_flag(&mut conn.modes, mode, true) {
+                    applied.push(ModeChange { adding, mode, param: None });
+                }
+                responses.push(Message::new("008")
+                    .with_prefix(state.server_name.clone())
+                    .with_params(vec![
+                        nick.to_string(),
+                        format!("+{}", conn.snomask.iter().collect::<String>()),
+                        "Server notice mask".to_string(),
+                    ]));
+            }
+            'o' | 's' | 'Z' => {}
+            _ => unknown = true,
+        }
+    }
+    
+    if unknown {
+        responses.push(Message::new("501")
+            .with_prefix(state.server_name.clone())
+            .with_params(vec![nick.to_string(), "Unknown MODE flag".to_string()]));
+    }
+    if !applied.is_empty() {
+        let mut params = vec![nick.to_string()];
+        params.extend(channel_modes::format_changes(&applied));
+        responses.push(Message::new("MODE")
+            .with_prefix(conn.full_mask())
+            .with_params(params));
+    }
+}
+
+/// Add or remove a mode letter, returning whether anything changed
+fn set_flag(modes: &mut Vec<char>, mode: char, adding: bool) -> bool {
+    match modes.iter().position(|&existing| existing == mode) {
+        Some(_) if adding => false,
+        None if !adding => false,
+        Some(pos) => {
+            modes.remove(pos);
+            true
+        }
+        None => {
+            modes.push(mode);
+            true
+        }
+    }
+}
+
+/// Entry numeric, end numeric and end text for listing a list mode
+fn list_numerics(mode: char) -> (&'static str, &'static str, &'static str) {
+    match mode {
+        'e' => ("348", "349", "End of channel exception list"),
+        'I' => ("346", "347", "End of channel invite exception list"),
+        _ => ("367", "368", "End of channel ban list"),
+    }
+}
+
+/// Expand a partial mask to nick!user@host form. Extbans pass through.
+fn normalize_mask(mask: &str) -> String {
+    if mask.starts_with('$') {
+        return mask.to_string();
+    }
+    match (mask.contains('!'), mask.contains('@')) {
+        (true, true) => mask.to_string(),
+        (false, true) => format!("*!{}", mask),
+        (true, false) => format!("{}@*", mask),
+        // Nicknames can't contain dots or colons, so this is a host
+        (false, false) if mask.contains('.') || mask.contains(':') => format!("*!*@{}", mask),
+        (false, false) => format!("{}!*@*", mask),
+    }
+}
+
+/// MODE <target> [modes] [params]
+pub struct Mode;
+
+#[async_trait]
+impl CommandHandler for Mode {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("MODE").min_params(1)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Mode { target, modes, params } = command else {
+            return Ok(Vec::new());
+        };
+        let mut mode_params = vec![target];
+        mode_params.extend(modes);
+        mode_params.extend(params);
+        handle_mode(server_state, ctx.connection_id, mode_params).await
     }
 }
 
@@ -0,0 +1,181 @@
+use super::*;
+use crate::commands::handlers::test_support::{add_user, context, params, setup_channel};
+
+#[tokio::test]
+async fn test_mode_query_returns_channel_modes() {
+    let (state, _rx) = setup_channel();
+    state.channels.get_mut("#test").unwrap().modes = vec!['n', 't'];
+
+    let messages = handle_mode(state, 2, vec!["#test".to_string()]).await.unwrap();
+
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].command, "324");
+    assert_eq!(messages[0].params[2], "+nt");
+}
+
+#[tokio::test]
+async fn test_operator_can_set_simple_mode() {
+    let (state, _rx) = setup_channel();
+
+    let messages = handle_mode(state.clone(), 1, vec!["#test".to_string(), "+m".to_string()])
+        .await
+        .unwrap();
+
+    assert_eq!(messages.last().unwrap().command, "MODE");
+    assert!(state.channels.get("#test").unwrap().modes.contains(&'m'));
+}
+
+#[tokio::test]
+async fn test_non_operator_cannot_set_modes() {
+    let (state, _rx) = setup_channel();
+
+    let messages = handle_mode(state.clone(), 2, vec!["#test".to_string(), "+m".to_string()])
+        .await
+        .unwrap();
+
+    assert_eq!(messages[0].command, "482");
+    assert!(!state.channels.get("#test").unwrap().modes.contains(&'m'));
+}
+
+#[tokio::test]
+async fn test_operator_can_voice_member() {
+    let (state, _rx) = setup_channel();
+
+    handle_mode(
+        state.clone(),
+        1,
+        vec!["#test".to_string(), "+v".to_string(), "bob".to_string()],
+    )
+    .await
+    .unwrap();
+
+    let state = &*state;
+    let channel = state.channels.get("#test").unwrap();
+    assert!(channel.members.get(&2).unwrap().modes.contains(&'v'));
+}
+
+#[tokio::test]
+async fn test_ban_list_is_set_listed_and_enforced() {
+    let (state, _rx) = setup_channel();
+    let mode = |id: u64, args: &[&str]| {
+        let state = state.clone();
+        let mut params = vec!["#test".to_string()];
+        params.extend(args.iter().map(|arg| arg.to_string()));
+        async move { handle_mode(state, id, params).await.unwrap() }
+    };
+
+    let messages = mode(1, &["+b", "bob"]).await;
+    assert_eq!(messages.last().unwrap().params[1..], ["+b".to_string(), "bob!*@*".to_string()]);
+
+    let messages = mode(2, &["+b"]).await;
+    assert_eq!(messages[0].command, "367");
+    assert_eq!(messages[0].params[2], "bob!*@*");
+    assert_eq!(messages[0].params[3], "alice!alice@127.0.0.1");
+    assert_eq!(messages[1].command, "368");
+
+    // Banned members can't speak unless voiced
+    let messages = crate::commands::handlers::privmsg::handle_privmsg(state.clone(), &context(2), "#test".to_string(), "hi".to_string())
+        .await
+        .unwrap();
+    assert_eq!(messages[0].command, "404");
+    mode(1, &["+e", "$a:bob"]).await;
+    state.connections.get_mut(&2).unwrap().account = Some("bob".to_string());
+    assert!(crate::commands::handlers::privmsg::handle_privmsg(state.clone(), &context(2), "#test".to_string(), "hi".to_string())
+        .await
+        .unwrap()
+        .is_empty());
+
+    let messages = mode(1, &["-b", "BOB!*@*"]).await;
+    assert_eq!(messages.last().unwrap().params[1..], ["-b".to_string(), "bob!*@*".to_string()]);
+    assert!(state.channels.get("#test").unwrap().bans.is_empty());
+}
+
+#[tokio::test]
+async fn test_list_modes_share_maxlist() {
+    let (mut state, _rx) = setup_channel();
+    Arc::get_mut(&mut state).unwrap().config.limits.max_list_entries = 2;
+
+    for mask in ["a!*@*", "b!*@*"] {
+        handle_mode(state.clone(), 1, vec!["#test".to_string(), "+I".to_string(), mask.to_string()]).await.unwrap();
+    }
+    let messages = handle_mode(state.clone(), 1, vec!["#test".to_string(), "+b".to_string(), "c!*@*".to_string()])
+        .await
+        .unwrap();
+
+    assert_eq!(messages[0].command, "478");
+    let state = &*state;
+    let channel = state.channels.get("#test").unwrap();
+    assert_eq!(channel.invite_exceptions.len(), 2);
+    assert!(channel.bans.is_empty());
+}
+
+#[tokio::test]
+async fn test_changes_are_merged_into_one_mode_line() {
+    let (state, mut rx) = setup_channel();
+
+    let messages = handle_mode(state.clone(), 1, params("#test +kv-t+lX secret BOB 5")).await.unwrap();
+    assert_eq!(messages[0].command, "472");
+    assert_eq!(messages[1].command, "MODE");
+    assert_eq!(messages[1].params, params("#test +kvl secret bob 5"));
+    assert_eq!(rx[1].try_recv().unwrap().params, messages[1].params);
+    assert!(rx[0].try_recv().is_err());
+
+    let messages = handle_mode(state.clone(), 2, params("#test")).await.unwrap();
+    assert_eq!(messages[0].params[2..], params("+kl secret 5"));
+}
+
+#[tokio::test]
+async fn test_prefix_ranks() {
+    let (state, _rx) = setup_channel();
+    let _carol = add_user(3, "carol").insert(&state);
+    {
+        let state = &*state;
+        let channel = state.channels.get("#test").unwrap();
+        channel.members.get_mut(&1).unwrap().modes = vec!['q'];
+        channel.members.get_mut(&2).unwrap().modes = vec!['h'];
+        channel.add_member(3, false);
+    }
+
+    // Halfops can voice but not op
+    let messages = handle_mode(state.clone(), 2, params("#test +vo carol carol")).await.unwrap();
+    assert_eq!(messages[0].command, "482");
+    assert_eq!(messages[1].params, params("#test +v carol"));
+
+    // Ops can't touch the founder, but anyone can step down
+    handle_mode(state.clone(), 1, params("#test +o carol")).await.unwrap();
+    let messages = handle_mode(state.clone(), 3, params("#test -q alice")).await.unwrap();
+    assert_eq!(messages[0].command, "482");
+    let messages = handle_mode(state.clone(), 2, params("#test -h bob")).await.unwrap();
+    assert_eq!(messages[0].params, params("#test -h bob"));
+
+    let messages = handle_mode(state.clone(), 1, params("#test +a carol")).await.unwrap();
+    assert_eq!(messages[0].params, params("#test +a carol"));
+    let state = &*state;
+    let channel = state.channels.get("#test").unwrap();
+    assert_eq!(channel.rank(3), Rank::Protected);
+    assert_eq!(channel.rank(2), Rank::Member);
+}
+
+#[tokio::test]
+async fn test_user_modes() {
+    let (state, _rx) = setup_channel();
+
+    let messages = handle_mode(state.clone(), 1, params("ALICE +iwBZox")).await.unwrap();
+    assert_eq!(messages[0].command, "501");
+    assert_eq!(messages[1].command, "MODE");
+    assert_eq!(messages[1].params, params("alice +iwB"));
+
+    let messages = handle_mode(state.clone(), 1, params("alice")).await.unwrap();
+    assert_eq!(messages[0].command, "221");
+    assert_eq!(messages[0].params[1], "+Biw");
+
+    let messages = handle_mode(state.clone(), 1, params("bob -i")).await.unwrap();
+    assert_eq!(messages[0].command, "502");
+
+    // +R keeps out private messages from clients who aren't logged in
+    handle_mode(state.clone(), 1, params("alice +R")).await.unwrap();
+    let messages = crate::commands::handlers::privmsg::handle_privmsg(state.clone(), &context(2), "alice".to_string(), "hi".to_string())
+        .await
+        .unwrap();
+    assert_eq!(messages[0].command, "486");
+}
@@ -0,0 +1,149 @@
+use std::sync::Arc;
+use crate::protocol::{Command, Message, Reply};
+use crate::state::ServerState;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
+
+/// Longest comma-separated target list put in one numeric
+const MAX_TARGETS_LEN: usize = 400;
+
+/// MONITOR <+|-|C|L|S> [targets]
+///
+/// Maintains the client's MONITOR list. Adding targets and S report their
+/// current state with 730/731; later changes are pushed by
+/// `ServerState::notify_monitors`.
+pub async fn handle_monitor(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    subcommand: String,
+    targets: Vec<String>,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+    let server_name = state.server_name.clone();
+    
+    let nick = state.connections.get(&connection_id)
+        .ok_or("Connection not found")?
+        .nickname.clone()
+        .ok_or("No nickname set")?;
+    
+    let mut responses = Vec::new();
+    match subcommand.as_str() {
+        "+" => {
+            if targets.is_empty() {
+                responses.push(Message::from(Reply::NeedMoreParams {
+                    nick,
+                    command: "MONITOR".to_string(),
+                }));
+                return Ok(responses);
+            }
+            
+            let limit = state.config.limits.max_monitor_entries;
+            let mut added = Vec::new();
+            for (index, target) in targets.iter().enumerate() {
+                if state.monitors.count(connection_id) >= limit {
+                    responses.push(Message::new("734")
+                        .with_prefix(server_name.clone())
+                        .with_params(vec![
+                            nick.clone(),
+                            limit.to_string(),
+                            targets[index..].join(","),
+                            "Monitor list is full.".to_string(),
+                        ]));
+                    break;
+                }
+                state.monitors.add(connection_id, target);
+                added.push(target.clone());
+            }
+            responses.splice(0..0, status_replies(state, &nick, &added));
+        }
+        "-" => {
+            for target in &targets {
+                state.monitors.remove(connection_id, target);
+            }
+        }
+        "C" | "c" => state.monitors.clear(connection_id),
+        "L" | "l" => {
+            responses.extend(target_lines(&server_name, &nick, "732", &state.monitors.list(connection_id)));
+            responses.push(Message::new("733")
+                .with_prefix(server_name)
+                .with_params(vec![nick, "End of MONITOR list".to_string()]));
+        }
+        "S" | "s" => {
+            let watched = state.monitors.list(connection_id);
+            responses.extend(status_replies(state, &nick, &watched));
+        }
+        _ => {
+            responses.push(Message::from(Reply::NeedMoreParams {
+                nick,
+                command: "MONITOR".to_string(),
+            }));
+        }
+    }
+    
+    Ok(responses)
+}
+
+/// 730 for the targets that are online, 731 for the rest
+fn status_replies(state: &ServerState, nick: &str, targets: &[String]) -> Vec<Message> {
+    let (mut online, mut offline) = (Vec::new(), Vec::new());
+    for target in targets {
+        match state.online_mask(target) {
+            Some(mask) => online.push(mask),
+            None => offline.push(target.clone()),
+        }
+    }
+    
+    let mut replies = target_lines(&state.server_name, nick, "730", &online);
+    replies.extend(target_lines(&state.server_name, nick, "731", &offline));
+    replies
+}
+
+/// Comma-separated targets, split over as many numerics as needed
+fn target_lines(server_name: &str, nick: &str, numeric: &str, targets: &[String]) -> Vec<Message> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for target in targets {
+        if !current.is_empty() && current.len() + target.len() + 1 > MAX_TARGETS_LEN {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(',');
+        }
+        current.push_str(target);
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    
+    lines.into_iter()
+        .map(|line| Message::new(numeric)
+            .with_prefix(server_name.to_string())
+            .with_params(vec![nick.to_string(), line]))
+        .collect()
+}
+
+/// MONITOR <subcommand> [targets]
+pub struct Monitor;
+
+#[async_trait]
+impl CommandHandler for Monitor {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("MONITOR").min_params(1)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Monitor { subcommand, targets } = command else {
+            return Ok(Vec::new());
+        };
+        handle_monitor(server_state, ctx.connection_id, subcommand, targets).await
+    }
+}
+
+#[cfg(test)]
+#[path = "monitor_test.rs"]
+mod tests;
@@ -0,0 +1,67 @@
+use super::*;
+use crate::commands::handlers::test_support::add_user;
+use crate::commands::handlers::ison::{handle_ison, handle_userhost};
+
+fn targets(list: &str) -> Vec<String> {
+    list.split(',').map(str::to_string).collect()
+}
+
+#[tokio::test]
+async fn test_monitor_add_list_and_notify() {
+    let state = ServerState::new();
+    let mut alice = add_user(1, "alice").insert(&state);
+    let _bob = add_user(2, "bob").insert(&state);
+    let state = Arc::new(state);
+
+    let messages = handle_monitor(state.clone(), 1, "+".to_string(), targets("Bob,carol")).await.unwrap();
+    assert_eq!(messages[0].command, "730");
+    assert_eq!(messages[0].params, ["alice", "bob!bob@127.0.0.1"]);
+    assert_eq!(messages[1].command, "731");
+    assert_eq!(messages[1].params, ["alice", "carol"]);
+
+    let messages = handle_monitor(state.clone(), 1, "L".to_string(), vec![]).await.unwrap();
+    assert_eq!(messages[0].params, ["alice", "Bob,carol"]);
+    assert_eq!(messages[1].command, "733");
+
+    // Going offline is pushed to watchers
+    {
+        let state = &*state;
+        state.unregister_nickname("bob");
+        state.notify_monitors("bob", None).await;
+    }
+    let pushed = alice.try_recv().unwrap();
+    assert_eq!(pushed.command, "731");
+    assert_eq!(pushed.params, ["alice", "bob"]);
+
+    handle_monitor(state.clone(), 1, "-".to_string(), targets("BOB")).await.unwrap();
+    assert_eq!(state.monitors.list(1), ["carol"]);
+}
+
+#[tokio::test]
+async fn test_monitor_list_full() {
+    let mut state = ServerState::new();
+    state.config.limits.max_monitor_entries = 2;
+    let _alice = add_user(1, "alice").insert(&state);
+    let state = Arc::new(state);
+
+    let messages = handle_monitor(state.clone(), 1, "+".to_string(), targets("a,b,c,d")).await.unwrap();
+    assert_eq!(messages.last().unwrap().command, "734");
+    assert_eq!(messages.last().unwrap().params[1..3], ["2".to_string(), "c,d".to_string()]);
+    assert_eq!(state.monitors.count(1), 2);
+}
+
+#[tokio::test]
+async fn test_ison_and_userhost() {
+    let state = ServerState::new();
+    let _alice = add_user(1, "alice").insert(&state);
+    let _bob = add_user(2, "bob").insert(&state);
+    state.connections.get_mut(&2).unwrap().away = Some("out".to_string());
+    state.connections.get_mut(&1).unwrap().modes.push('o');
+    let state = Arc::new(state);
+
+    let messages = handle_ison(state.clone(), 1, targets("BOB,carol,alice")).await.unwrap();
+    assert_eq!(messages[0].params[1], "BOB alice");
+
+    let messages = handle_userhost(state.clone(), 1, targets("alice,bob,nobody")).await.unwrap();
+    assert_eq!(messages[0].params[1], "alice*=+alice@127.0.0.1 bob=-bob@127.0.0.1");
+}
@@ -1,17 +1,18 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
+use crate::protocol::{Command, Message, Reply};
 use crate::state::ServerState;
 use std::fs;
 use std::path::Path;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_motd(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
     _params: Vec<String>,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
     let mut responses = Vec::new();
-    let state = server_state.read().await;
+    let state = &*server_state;
     
     // Get connection nick
     let connection = state.connections.get(&connection_id)
@@ -63,8 +64,30 @@ pub async fn handle_motd(
 }
 
 pub async fn send_motd(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
     handle_motd(server_state, connection_id, vec![]).await
 }
+
+/// MOTD [server]
+pub struct Motd;
+
+#[async_trait]
+impl CommandHandler for Motd {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("MOTD")
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Motd(server) = command else {
+            return Ok(Vec::new());
+        };
+        handle_motd(server_state, ctx.connection_id, server.into_iter().collect()).await
+    }
+}
@@ -1,14 +1,16 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
+use crate::protocol::{Command, Message, Reply};
 use crate::state::ServerState;
+use crate::state::channel_modes::prefix_symbol;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_names(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
     params: Vec<String>,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
-    let state = server_state.read().await;
+    let state = &*server_state;
     let mut messages = Vec::new();
     
     // Get the requesting connection
@@ -35,6 +37,8 @@ pub async fn handle_names(
         params[0].split(',').map(|s| s.to_string()).collect()
     };
     
+    let peers = state.channel_peers(connection_id);
+    
     // Generate NAMES reply for each channel
     for channel_name in &channels_to_list {
         if let Some(channel) = state.channels.get(channel_name) {
@@ -49,15 +53,15 @@ pub async fn handle_names(
                 for member_entry in channel.members.iter() {
                     let member_id = *member_entry.key();
                     if let Some(member_conn) = state.connections.get(&member_id) {
+                        // Invisible users only show up to those sharing a channel
+                        if member_conn.modes.contains(&'i') && !peers.contains(&member_id) {
+                            continue;
+                        }
                         if let Some(member_nick) = &member_conn.nickname {
                             let mut name_with_prefix = String::new();
                             
-                            // Add status prefixes (highest status first)
-                            if member_entry.value().modes.contains(&'o') {
-                                name_with_prefix.push('@');
-                            } else if member_entry.value().modes.contains(&'v') {
-                                name_with_prefix.push('+');
-                            }
+                            // Add the highest status prefix
+                            name_with_prefix.extend(prefix_symbol(&member_entry.value().modes));
                             
                             name_with_prefix.push_str(member_nick);
                             names.push(name_with_prefix);
@@ -102,3 +106,25 @@ pub async fn handle_names(
     
     Ok(messages)
 }
+
+/// NAMES [channels]
+pub struct Names;
+
+#[async_trait]
+impl CommandHandler for Names {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("NAMES").penalty(2)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Names(channels) = command else {
+            return Ok(Vec::new());
+        };
+        handle_names(server_state, ctx.connection_id, channels).await
+    }
+}
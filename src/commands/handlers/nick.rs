@@ -1,5 +1,4 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use tracing::{debug, warn};
 use crate::protocol::{Message, Reply};
 use crate::state::ServerState;
@@ -7,13 +6,13 @@ use crate::commands::standard_replies::common;
 
 /// Handle NICK command - change or set nickname
 pub async fn handle_nick(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
     new_nick: String,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
     debug!("Processing NICK command for connection {}: {}", connection_id, new_nick);
     
-    let mut state = server_state.write().await;
+    let state = &*server_state;
     let mut responses = Vec::new();
     
     // Get connection info
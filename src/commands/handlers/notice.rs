@@ -1,15 +1,17 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
+use crate::protocol::{Command, Message};
 use crate::state::ServerState;
 use crate::utils::generate_message_id;
 use crate::history::{HistoryItem, MessageType};
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_notice(
-    server_state: Arc<RwLock<ServerState>>,
-    connection_id: u64,
+    server_state: Arc<ServerState>,
+    ctx: &CommandContext,
     params: Vec<String>,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let connection_id = ctx.connection_id;
     if params.len() < 2 {
         return Ok(vec![]); // NOTICE doesn't send error responses
     }
@@ -18,8 +20,8 @@ pub async fn handle_notice(
     let message = params[1].clone();
     
     // Get sender info and prepare message  
-    let (nick, user, host, has_echo_message, msg_id, notice_msg) = {
-        let state = server_state.read().await;
+    let (nick, user, host, has_echo_message, msg_id, message, notice_msg) = {
+        let state = &*server_state;
         
         let connection = state.connections.get(&connection_id)
             .ok_or("Connection not found")?;
@@ -30,38 +32,44 @@ pub async fn handle_notice(
         let host = connection.hostname.clone();
         let has_echo_message = connection.capabilities.contains(&"echo-message".to_string());
         
+        if (target.starts_with('#') || target.starts_with('&')) && !state.can_send_to_channel(&target, connection_id, &message) {
+            return Ok(vec![]);
+        }
+        let message = state.channel_text(&target, message);
+        if state.rejects_unregistered(&target) && connection.account.is_none() {
+            return Ok(vec![]);
+        }
+        
         let prefix = format!("{}!{}@{}", nick, user, host);
         
         // Generate message ID for tracking
         let msg_id = generate_message_id();
         
         // Create NOTICE message
-        let notice_msg = Message::new("NOTICE")
+        let mut notice_msg = Message::new("NOTICE")
             .with_prefix(prefix)
             .with_params(vec![target.clone(), message.clone()])
             .with_tag("msgid".to_string(), Some(msg_id.clone()));
+        if connection.modes.contains(&'B') {
+            notice_msg = notice_msg.with_tag("bot".to_string(), None);
+        }
 
-        (nick, user, host, has_echo_message, msg_id, notice_msg)
+        (nick, user, host, has_echo_message, msg_id, message, notice_msg)
     };
 
-    // Store message in history
-    let history_item = HistoryItem::new(
+    // Recorded in history by the registry
+    ctx.record(HistoryItem::new(
         msg_id.clone(),
         MessageType::Notice,
         nick.clone(),
         "*".to_string(), // TODO: Get actual account name
         message.clone(),
         target.clone(),
-    );
-    
-    {
-        let state = server_state.read().await;
-        state.history.store_message(history_item);
-    }
+    ));
     
     // Send the message
     {
-        let state = server_state.read().await;
+        let state = &*server_state;
         
         if target.starts_with('#') || target.starts_with('&') {
             // Channel notice
@@ -72,7 +80,7 @@ pub async fn handle_notice(
                     // Echo back to sender only if they have echo-message capability
                     if member_id != connection_id || has_echo_message {
                         if let Some(member_conn) = state.connections.get(&member_id) {
-                            let _ = member_conn.tx.send(notice_msg.clone()).await;
+                            let _ = member_conn.tx.try_send(notice_msg.clone());
                         }
                     }
                 }
@@ -81,26 +89,15 @@ pub async fn handle_notice(
         } else {
             // Private notice to user
             let target_nick = target.clone();
-            let mut target_connection = None;
             
-            // Find target user
-            for entry in state.connections.iter() {
-                if let Some(ref nickname) = entry.nickname {
-                    if nickname == &target_nick {
-                        target_connection = Some(entry.clone());
-                        break;
-                    }
-                }
-            }
-            
-            if let Some(target_conn) = target_connection {
+            if let Some(target_conn) = state.connection_by_nick(&target_nick) {
                 // Send to target user
-                let _ = target_conn.tx.send(notice_msg.clone()).await;
+                let _ = target_conn.tx.try_send(notice_msg.clone());
                 
                 // Echo back to sender if they have echo-message capability
                 if has_echo_message {
                     if let Some(sender_conn) = state.connections.get(&connection_id) {
-                        let _ = sender_conn.tx.send(notice_msg.clone()).await;
+                        let _ = sender_conn.tx.try_send(notice_msg.clone());
                     }
                 }
             }
@@ -110,3 +107,25 @@ pub async fn handle_notice(
     
     Ok(vec![])  // NOTICE never sends responses
 }
+
+/// NOTICE <target> <text>
+pub struct Notice;
+
+#[async_trait]
+impl CommandHandler for Notice {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("NOTICE").min_params(2).history()
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Notice { target, message } = command else {
+            return Ok(Vec::new());
+        };
+        handle_notice(server_state, ctx, vec![target, message]).await
+    }
+}
@@ -1,13 +1,120 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::Message;
+use chrono::Utc;
+use tracing::{info, warn};
+use crate::db::models::OperLogEntry;
+use crate::protocol::{Command, Message};
 use crate::state::ServerState;
+use crate::state::channel_modes::{self, ModeChange};
+use crate::state::operators::SNOMASKS;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
+/// OPER <name> <password>
+///
+/// Checks the credential's host mask, certificate fingerprint and password,
+/// then grants +o with its privileges and +s with every snomask. Each
+/// attempt goes to the audit log and to operators watching snomask `o`.
 pub async fn handle_oper(
-    _server_state: Arc<RwLock<ServerState>>,
-    _connection_id: u64,
-    _params: Vec<String>,
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    name: String,
+    password: String,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
-    // TODO: Implement oper command
-    Ok(vec![])
+    let state = &*server_state;
+
+    let connection = state.connections.get(&connection_id)
+        .ok_or("Connection not found")?
+        .clone();
+    let nick = connection.nickname.clone().ok_or("No nickname set")?;
+    let username = connection.username.as_deref().unwrap_or("*");
+    let user_hosts = [
+        format!("{}@{}", username, connection.hostname),
+        format!("{}@{}", username, connection.addr.ip()),
+    ];
+    let who = format!("{} ({})", nick, user_hosts[0]);
+
+    let result = state.operators.authenticate(&name, &password, &user_hosts, connection.certfp.as_deref()).await;
+    let detail = match &result {
+        Ok(privileges) => privileges.iter().map(|privilege| privilege.name()).collect::<Vec<_>>().join(","),
+        Err(failure) => failure.reason().to_string(),
+    };
+    state.operators.record(OperLogEntry {
+        name: name.clone(),
+        nick: nick.clone(),
+        mask: connection.full_mask(),
+        succeeded: result.is_ok(),
+        detail: detail.clone(),
+        at: Utc::now(),
+    });
+
+    let privileges = match result {
+        Ok(privileges) => privileges,
+        Err(_) => {
+            warn!("Failed OPER attempt by {} as {}: {}", who, name, detail);
+            state.server_notice('o', &format!("Failed OPER attempt by {} as {}: {}", who, name, detail));
+            // The client learns only that it failed; the reason stays in
+            // the audit log and the server notice
+            return Ok(vec![Message::new("491")
+                .with_prefix(state.server_name.clone())
+                .with_params(vec![nick, "Invalid oper credentials".to_string()])]);
+        }
+    };
+
+    info!("{} is now an operator as {} [{}]", who, name, detail);
+    state.server_notice('o', &format!("{} is now an operator as {} [{}]", who, name, detail));
+
+    let Some(mut conn) = state.connections.get_mut(&connection_id) else {
+        return Ok(vec![]);
+    };
+    let mut applied = Vec::new();
+    for mode in ['o', 's'] {
+        if !conn.modes.contains(&mode) {
+            conn.modes.push(mode);
+            applied.push(ModeChange { adding: true, mode, param: None });
+        }
+    }
+    conn.privileges = privileges;
+    conn.snomask = SNOMASKS.chars().collect();
+
+    let mut responses = Vec::new();
+    if !applied.is_empty() {
+        let mut params = vec![nick.clone()];
+        params.extend(channel_modes::format_changes(&applied));
+        responses.push(Message::new("MODE")
+            .with_prefix(conn.full_mask())
+            .with_params(params));
+    }
+    responses.push(Message::new("381")
+        .with_prefix(state.server_name.clone())
+        .with_params(vec![nick.clone(), "You are now an IRC operator".to_string()]));
+    responses.push(Message::new("008")
+        .with_prefix(state.server_name.clone())
+        .with_params(vec![nick, format!("+{}", SNOMASKS), "Server notice mask".to_string()]));
+    Ok(responses)
 }
+
+/// OPER <name> <password>
+pub struct Oper;
+
+#[async_trait]
+impl CommandHandler for Oper {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("OPER").min_params(2)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Oper { name, password } = command else {
+            return Ok(Vec::new());
+        };
+        handle_oper(server_state, ctx.connection_id, name, password).await
+    }
+}
+
+#[cfg(test)]
+#[path = "oper_test.rs"]
+mod tests;
@@ -0,0 +1,92 @@
+use super::*;
+use crate::commands::handlers::test_support::add_user;
+use crate::commands::handlers::mode::handle_mode;
+use crate::db::models::OperatorCredential;
+use crate::security::auth::LocalPasswordHasher;
+use crate::state::OperPrivilege;
+use crate::state::sendq::SendQueueReceiver;
+
+fn setup() -> (Arc<ServerState>, SendQueueReceiver) {
+    let state = ServerState::new();
+    state.operators.insert(OperatorCredential {
+        id: "1".to_string(),
+        name: "alice".to_string(),
+        password_hash: LocalPasswordHasher::default().hash_password("hunter2").unwrap(),
+        host_mask: Some("alice@127.0.0.1".to_string()),
+        certfp: None,
+        privileges: "kline,override".to_string(),
+        created_at: Utc::now(),
+        last_used: None,
+    });
+    add_user(1, "alice").insert(&state);
+    add_user(2, "mallory").addr("192.0.2.7:40000").insert(&state);
+
+    // An operator already watching OPER attempts
+    let watcher = add_user(3, "watcher").addr("127.0.0.1:40001").insert(&state);
+    {
+        let mut conn = state.connections.get_mut(&3).unwrap();
+        conn.modes.extend(['o', 's']);
+        conn.snomask.push('o');
+    }
+    (Arc::new(state), watcher)
+}
+
+#[tokio::test]
+async fn test_oper_grants_privileges_and_notifies_operators() {
+    let (state, mut watcher) = setup();
+
+    let messages = handle_oper(state.clone(), 1, "alice".to_string(), "wrong".to_string()).await.unwrap();
+    assert_eq!(messages[0].command, "491");
+    assert_eq!(messages[0].params[1], "Invalid oper credentials");
+    assert!(!state.connections.get(&1).unwrap().modes.contains(&'o'));
+    assert_eq!(
+        watcher.try_recv().unwrap().params[1],
+        "*** Notice -- Failed OPER attempt by alice (alice@127.0.0.1) as alice: bad password",
+    );
+
+    let messages = handle_oper(state.clone(), 1, "alice".to_string(), "hunter2".to_string()).await.unwrap();
+    let commands: Vec<&str> = messages.iter().map(|message| message.command.as_str()).collect();
+    assert_eq!(commands, ["MODE", "381", "008"]);
+    assert_eq!(messages[0].params, ["alice", "+os"]);
+    assert!(watcher.try_recv().unwrap().params[1].ends_with("alice (alice@127.0.0.1) is now an operator as alice [kline,override]"));
+    {
+        let conn = state.connections.get(&1).unwrap();
+        assert!(conn.has_privilege(OperPrivilege::Kline));
+        assert!(!conn.has_privilege(OperPrivilege::Die));
+    }
+
+    // Dropping +o takes the privileges and server notices with it
+    handle_mode(state.clone(), 1, vec!["alice".to_string(), "-o".to_string()]).await.unwrap();
+    let conn = state.connections.get(&1).unwrap();
+    assert!(!conn.has_privilege(OperPrivilege::Kline));
+    assert!(!conn.modes.contains(&'s'));
+}
+
+#[tokio::test]
+async fn test_oper_checks_host_and_certfp() {
+    let (state, _watcher) = setup();
+
+    // Every failure gets the same reply, whichever check refused it
+    let refused = |messages: Vec<Message>| messages.len() == 1 && messages[0].command == "491"
+        && messages[0].params == ["mallory", "Invalid oper credentials"];
+    for (name, password) in [("alice", "hunter2"), ("alice", "wrong"), ("nobody", "hunter2")] {
+        assert!(refused(handle_oper(state.clone(), 2, name.to_string(), password.to_string()).await.unwrap()));
+    }
+
+    state.operators.insert(OperatorCredential {
+        id: "2".to_string(),
+        name: "bob".to_string(),
+        password_hash: LocalPasswordHasher::default().hash_password("secret").unwrap(),
+        host_mask: None,
+        certfp: Some("aa:bb".to_string()),
+        privileges: "all".to_string(),
+        created_at: Utc::now(),
+        last_used: None,
+    });
+    assert!(refused(handle_oper(state.clone(), 2, "bob".to_string(), "secret".to_string()).await.unwrap()));
+
+    state.connections.get_mut(&2).unwrap().certfp = Some("AA:BB".to_string());
+    let messages = handle_oper(state.clone(), 2, "bob".to_string(), "secret".to_string()).await.unwrap();
+    assert!(messages.iter().any(|message| message.command == "381"));
+    assert!(state.connections.get(&2).unwrap().has_privilege(OperPrivilege::Die));
+}
@@ -1,16 +1,17 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
+use crate::protocol::{Command, Message, Reply};
 use crate::state::ServerState;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_part(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
     channels: Vec<String>,
     message: Option<String>,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
     let mut responses = Vec::new();
-    let mut state = server_state.write().await;
+    let state = &*server_state;
     
     // Get connection info
     let connection = state.connections.get(&connection_id)
@@ -46,17 +47,17 @@ pub async fn handle_part(
                 for entry in channel.members.iter() {
                     let member_id = *entry.key();
                     if let Some(member_conn) = state.connections.get(&member_id) {
-                        let _ = member_conn.tx.send(part_msg.clone()).await;
+                        let _ = member_conn.tx.try_send(part_msg.clone());
                     }
                 }
                 
                 // Send PART confirmation to the user who left
                 responses.push(part_msg);
                 
-                // If channel is empty, remove it
-                if channel.members.is_empty() {
-                    state.channels.remove(&channel_name);
-                }
+                // If channel is empty, remove it. The guard has to go first,
+                // and the check is repeated in case someone joined meanwhile.
+                drop(channel);
+                state.channels.remove_if(&channel_name, |_, channel| channel.members.is_empty());
             } else {
                 // User not in channel
                 responses.push(Message::from(Reply::NotOnChannel {
@@ -75,3 +76,29 @@ pub async fn handle_part(
     
     Ok(responses)
 }
+
+/// PART <channels> [message]
+pub struct Part;
+
+#[async_trait]
+impl CommandHandler for Part {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("PART").min_params(1)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Part(channels, message) = command else {
+            return Ok(Vec::new());
+        };
+        handle_part(server_state, ctx.connection_id, channels, message).await
+    }
+}
+
+#[cfg(test)]
+#[path = "part_test.rs"]
+mod tests;
@@ -0,0 +1,25 @@
+use super::*;
+use crate::commands::handlers::test_support::add_user;
+use crate::state::Channel;
+
+#[tokio::test]
+async fn test_last_member_part_removes_channel() {
+    let state = ServerState::new();
+    let _rx = [add_user(1, "alice").insert(&state), add_user(2, "bob").insert(&state)];
+    let channel = Channel::new("#test".to_string());
+    channel.add_member(1, true);
+    channel.add_member(2, false);
+    state.channels.insert("#test".to_string(), channel);
+    let state = Arc::new(state);
+
+    let messages = handle_part(state.clone(), 2, vec!["#test".to_string()], None).await.unwrap();
+    assert_eq!(messages[0].command, "PART");
+    assert!(state.channels.contains_key("#test"));
+
+    let messages = handle_part(state.clone(), 1, vec!["#test".to_string()], Some("bye".to_string())).await.unwrap();
+    assert_eq!(messages[0].params, ["#test", "bye"]);
+    assert!(!state.channels.contains_key("#test"));
+
+    let messages = handle_part(state, 1, vec!["#test".to_string()], None).await.unwrap();
+    assert_eq!(messages[0].command, "403");
+}
@@ -1,19 +1,21 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
+use crate::protocol::{Command, Message, Reply};
 use crate::state::ServerState;
 use crate::utils::generate_message_id;
 use crate::history::{HistoryItem, MessageType};
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_privmsg(
-    server_state: Arc<RwLock<ServerState>>,
-    connection_id: u64,
+    server_state: Arc<ServerState>,
+    ctx: &CommandContext,
     target: String,
     message: String,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let connection_id = ctx.connection_id;
     // Get sender info and prepare message  
-    let (nick, user, host, has_echo_message, msg_id, privmsg) = {
-        let state = server_state.read().await;
+    let (nick, user, host, has_echo_message, msg_id, message, privmsg) = {
+        let state = &*server_state;
         
         let connection = state.connections.get(&connection_id)
             .ok_or("Connection not found")?;
@@ -24,6 +26,19 @@ pub async fn handle_privmsg(
         let host = connection.hostname.clone();
         let has_echo_message = connection.capabilities.contains(&"echo-message".to_string());
         
+        if (target.starts_with('#') || target.starts_with('&')) && !state.can_send_to_channel(&target, connection_id, &message) {
+            return Ok(vec![Message::from(Reply::CannotSendToChan {
+                nick,
+                channel: target,
+            })]);
+        }
+        let message = state.channel_text(&target, message);
+        if state.rejects_unregistered(&target) && connection.account.is_none() {
+            return Ok(vec![Message::new("486")
+                .with_prefix(state.server_name.clone())
+                .with_params(vec![nick, target, "You must be logged in to message this user".to_string()])]);
+        }
+        
         let prefix = format!("{}!{}@{}", nick, user, host);
         
         // Generate message ID for tracking reactions/replies
@@ -39,46 +54,49 @@ pub async fn handle_privmsg(
             privmsg = privmsg.with_tag("msgid".to_string(), Some(msg_id.clone()));
         }
         
+        // Messages from bots (+B) carry the bot tag
+        if connection.modes.contains(&'B') {
+            privmsg = privmsg.with_tag("bot".to_string(), None);
+        }
+        
         // Add server-time tag if client supports server-time
         if connection.capabilities.contains(&"server-time".to_string()) {
             let timestamp = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
             privmsg = privmsg.with_tag("time".to_string(), Some(timestamp));
         }
 
-        (nick, user, host, has_echo_message, msg_id, privmsg)
+        (nick, user, host, has_echo_message, msg_id, message, privmsg)
     };
 
-    // Store message in history
-    let history_item = HistoryItem::new(
+    // Recorded in history by the registry
+    ctx.record(HistoryItem::new(
         msg_id.clone(),
         MessageType::Privmsg,
         nick.clone(),
         "*".to_string(), // TODO: Get actual account name
         message.clone(),
         target.clone(),
-    );
-    
-    {
-        let state = server_state.read().await;
-        state.history.store_message(history_item);
-    }
+    ));
     
     // Send the message
     {
-        let state = server_state.read().await;
+        let state = &*server_state;
         
         if target.starts_with('#') || target.starts_with('&') {
             // Channel message
-            if let Some(channel) = state.channels.get(&target) {
-                // Send to all channel members
-                for entry in channel.members.iter() {
-                    let member_id = *entry.key();
+            // Collect recipients first so no map shard stays locked while sending
+            let recipients: Option<Vec<_>> = state.channels.get(&target).map(|channel| {
+                channel.members.iter()
+                    .map(|entry| *entry.key())
                     // Echo back to sender only if they have echo-message capability
-                    if member_id != connection_id || has_echo_message {
-                        if let Some(member_conn) = state.connections.get(&member_id) {
-                            let _ = member_conn.tx.send(privmsg.clone()).await;
-                        }
-                    }
+                    .filter(|&member_id| member_id != connection_id || has_echo_message)
+                    .filter_map(|member_id| state.connections.get(&member_id).map(|conn| conn.tx.clone()))
+                    .collect()
+            });
+            
+            if let Some(recipients) = recipients {
+                for tx in recipients {
+                    let _ = tx.try_send(privmsg.clone());
                 }
             } else {
                 // Channel doesn't exist or user not in channel
@@ -90,28 +108,24 @@ pub async fn handle_privmsg(
         } else {
             // Private message to user
             let target_nick = target.clone();
-            let mut target_connection = None;
             
-            // Find target user
-            for entry in state.connections.iter() {
-                if let Some(ref nickname) = entry.nickname {
-                    if nickname == &target_nick {
-                        target_connection = Some(entry.clone());
-                        break;
-                    }
-                }
-            }
-            
-            if let Some(target_conn) = target_connection {
+            if let Some(target_conn) = state.connection_by_nick(&target_nick) {
                 // Send to target user
-                let _ = target_conn.tx.send(privmsg.clone()).await;
+                let _ = target_conn.tx.try_send(privmsg.clone());
                 
                 // Echo back to sender if they have echo-message capability
                 if has_echo_message {
                     if let Some(sender_conn) = state.connections.get(&connection_id) {
-                        let _ = sender_conn.tx.send(privmsg.clone()).await;
+                        let _ = sender_conn.tx.try_send(privmsg.clone());
                     }
                 }
+                
+                // Let the sender know the target is away (RPL_AWAY)
+                if let Some(away) = target_conn.away {
+                    return Ok(vec![Message::new("301")
+                        .with_prefix(state.server_name.clone())
+                        .with_params(vec![nick.clone(), target_conn.nickname.unwrap_or(target_nick), away])]);
+                }
             } else {
                 // User not found
                 return Ok(vec![Message::from(Reply::NoSuchNick {
@@ -124,3 +138,29 @@ pub async fn handle_privmsg(
     
     Ok(vec![])  // No response to sender (unless error)
 }
+
+/// PRIVMSG <target> <text>
+pub struct Privmsg;
+
+#[async_trait]
+impl CommandHandler for Privmsg {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("PRIVMSG").min_params(2).history()
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Privmsg { target, message } = command else {
+            return Ok(Vec::new());
+        };
+        handle_privmsg(server_state, ctx, target, message).await
+    }
+}
+
+#[cfg(test)]
+#[path = "privmsg_test.rs"]
+mod tests;
@@ -0,0 +1,151 @@
+use super::*;
+use crate::commands::handlers::test_support::{add_user, context};
+use crate::state::{Channel, Connection};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use crate::state::sendq;
+
+#[tokio::test]
+async fn test_private_message_finds_target_by_nickname() {
+    let state = ServerState::new();
+    let _alice = add_user(1, "alice").insert(&state);
+    let mut bob = add_user(2, "Bob").insert(&state);
+    let state = Arc::new(state);
+
+    let messages = handle_privmsg(state.clone(), &context(1), "bob".to_string(), "hi".to_string()).await.unwrap();
+    assert!(messages.is_empty());
+    assert_eq!(bob.try_recv().unwrap().params, ["bob", "hi"]);
+
+    let messages = handle_privmsg(state, &context(1), "carol".to_string(), "hi".to_string()).await.unwrap();
+    assert_eq!(messages[0].command, "401");
+}
+
+#[tokio::test]
+async fn test_slow_member_does_not_hold_up_channel() {
+    let state = ServerState::new();
+    let _alice = add_user(1, "alice").insert(&state);
+    let mut bob = add_user(2, "bob").insert(&state);
+
+    // carol never reads, and her SendQ holds a single line
+    let (tx, _carol) = sendq::channel(64);
+    let mut conn = Connection::new(3, "127.0.0.1:40000".parse().unwrap(), tx.clone());
+    conn.nickname = Some("carol".to_string());
+    conn.registered = true;
+    state.connections.insert(3, conn);
+
+    let channel = Channel::new("#test".to_string());
+    for id in 1..=3 {
+        channel.add_member(id, false);
+    }
+    state.channels.insert("#test".to_string(), channel);
+    let state = Arc::new(state);
+
+    for _ in 0..10 {
+        handle_privmsg(state.clone(), &context(1), "#test".to_string(), "hi".to_string()).await.unwrap();
+    }
+
+    let mut received = 0;
+    while bob.try_recv().is_ok() {
+        received += 1;
+    }
+    assert_eq!(received, 10);
+    let reason = tokio::time::timeout(Duration::from_secs(1), tx.closed()).await.unwrap();
+    assert_eq!(reason, "SendQ exceeded");
+}
+
+const CHANNELS: u64 = 64;
+const MEMBERS: u64 = 8;
+const MESSAGES: u64 = 200;
+
+/// Every member of every channel sends MESSAGES lines to its channel at
+/// once. Returns deliveries per second.
+async fn channel_load() -> f64 {
+    let state = ServerState::new();
+    let delivered = Arc::new(AtomicU64::new(0));
+
+    for channel_index in 0..CHANNELS {
+        let name = format!("#load{}", channel_index);
+        let channel = Channel::new(name.clone());
+        for member in 0..MEMBERS {
+            let id = channel_index * MEMBERS + member + 1;
+            let mut rx = add_user(id, &format!("user{}", id)).insert(&state);
+            channel.add_member(id, false);
+
+            let delivered = delivered.clone();
+            tokio::spawn(async move {
+                while rx.recv().await.is_some() {
+                    delivered.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+        state.channels.insert(name, channel);
+    }
+    let state = Arc::new(state);
+
+    let started = Instant::now();
+    let senders: Vec<_> = (0..CHANNELS * MEMBERS).map(|index| {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let target = format!("#load{}", index / MEMBERS);
+            for _ in 0..MESSAGES {
+                handle_privmsg(state.clone(), &context(index + 1), target.clone(), "load".to_string()).await.unwrap();
+            }
+        })
+    }).collect();
+    for sender in senders {
+        sender.await.unwrap();
+    }
+
+    // Senders don't get their own lines back without echo-message
+    let expected = CHANNELS * MEMBERS * MESSAGES * (MEMBERS - 1);
+    while delivered.load(Ordering::Relaxed) < expected {
+        assert!(started.elapsed() < Duration::from_secs(120), "deliveries stalled");
+        tokio::task::yield_now().await;
+    }
+    expected as f64 / started.elapsed().as_secs_f64()
+}
+
+/// Four workers must deliver at least this many times what one does
+const MIN_SPEEDUP_AT_4: f64 = 1.5;
+
+/// PRIVMSG fan-out throughput on 1, 2, 4, ... worker threads, up to the
+/// number of cores. Nothing serializes senders in different channels, so
+/// four workers must beat one by MIN_SPEEDUP_AT_4. Skipped on machines with
+/// fewer than four cores. Run with
+/// `cargo test --release privmsg_throughput -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn privmsg_throughput_scales_across_cores() {
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    if cores < 4 {
+        println!("skipped: the scaling check needs at least 4 cores, found {}", cores);
+        return;
+    }
+
+    let mut baseline = None;
+    let mut at_four = None;
+    let mut workers = 1;
+    while workers <= cores {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(workers)
+            .enable_all()
+            .build()
+            .unwrap();
+        let rate = runtime.block_on(channel_load());
+        let baseline = *baseline.get_or_insert(rate);
+        println!("{:>3} workers: {:>12.0} deliveries/s ({:.2}x)", workers, rate, rate / baseline);
+        if workers == 4 {
+            at_four = Some(rate / baseline);
+        }
+
+        workers = if workers == cores { cores + 1 } else { (workers * 2).min(cores) };
+    }
+
+    let speedup = at_four.unwrap();
+    assert!(
+        speedup >= MIN_SPEEDUP_AT_4,
+        "4 workers were only {:.2}x as fast as 1, expected at least {:.1}x",
+        speedup,
+        MIN_SPEEDUP_AT_4,
+    );
+}
@@ -1,10 +1,11 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
+use crate::protocol::{Command, Message, Reply};
 use crate::state::ServerState;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_query(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
     target: String,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
@@ -12,7 +13,7 @@ pub async fn handle_query(
     
     // Get connection info
     let connection_info = {
-        let state = server_state.read().await;
+        let state = &*server_state;
         state.connections.get(&connection_id).map(|conn| {
             (conn.nickname.clone().unwrap_or_else(|| "*".to_string()))
         })
@@ -27,7 +28,7 @@ pub async fn handle_query(
     // and send appropriate feedback to help the client open a DM window
     
     let target_exists = {
-        let state = server_state.read().await;
+        let state = &*server_state;
         state.nicknames.contains_key(&target.to_lowercase())
     };
     
@@ -46,4 +47,26 @@ pub async fn handle_query(
     }
     
     Ok(responses)
-}
\ No newline at end of file
+}
+
+/// QUERY <target>
+pub struct Query;
+
+#[async_trait]
+impl CommandHandler for Query {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("QUERY").min_params(1)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Query(target) = command else {
+            return Ok(Vec::new());
+        };
+        handle_query(server_state, ctx.connection_id, target).await
+    }
+}
@@ -0,0 +1,433 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use crate::commands::standard_replies::{StandardReply, StandardReplyCode};
+use crate::db::{DatabaseError, models::User};
+use crate::protocol::{Command, Message};
+use crate::state::ServerState;
+use crate::state::account::Account;
+use crate::state::channel_registry::Succession;
+use crate::utils::mail::Mail;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult, Registration};
+
+/// Value of the draft/account-registration capability, or None when
+/// registration is disabled and the capability should not be offered
+pub fn capability_value(state: &ServerState) -> Option<String> {
+    let settings = &state.config.registration;
+    if !settings.enabled {
+        return None;
+    }
+
+    let mut flags = vec!["custom-account-name"];
+    if settings.before_connect {
+        flags.push("before-connect");
+    }
+    if settings.email_required || settings.email_verification {
+        flags.push("email-required");
+    }
+    Some(flags.join(","))
+}
+
+/// REGISTER <account> <email|*> <password>
+///
+/// Creates an account, implementing IRCv3 draft/account-registration. An
+/// account of `*` means the current nickname. When email verification is
+/// enabled the account is held until VERIFY supplies the mailed code,
+/// otherwise the client is logged in straight away.
+pub async fn handle_register(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    params: Vec<String>,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+    let server_name = state.server_name.clone();
+    let settings = state.config.registration.clone();
+    let fail = |code: &str, account: Option<&str>, description: &str| {
+        let mut reply = StandardReply::fail("REGISTER", StandardReplyCode::Custom(code.to_string()), description);
+        if let Some(account) = account {
+            reply = reply.add_context(account.to_string());
+        }
+        Ok(vec![reply.to_message(&server_name)])
+    };
+
+    let Some((nick, registered, logged_in)) = state.connections.get(&connection_id)
+        .map(|conn| (conn.nickname.clone(), conn.registered, conn.account.is_some()))
+    else {
+        return Ok(vec![]);
+    };
+
+    if !settings.enabled {
+        return fail("TEMPORARILY_UNAVAILABLE", None, "Account registration is disabled");
+    }
+    state.accounts.prune_pending();
+    if params.len() < 3 {
+        return Ok(vec![StandardReply::fail("REGISTER", StandardReplyCode::NeedMoreParams, "Not enough parameters")
+            .to_message(&server_name)]);
+    }
+    if !registered && !settings.before_connect {
+        return fail("COMPLETE_CONNECTION_REQUIRED", None, "Complete connection registration before registering an account");
+    }
+    if logged_in {
+        return fail("ALREADY_AUTHENTICATED", None, "You are already logged in to an account");
+    }
+
+    let account_name = if params[0] == "*" {
+        match nick {
+            Some(nick) => nick,
+            None => return fail("NEED_NICK", None, "Choose a nickname before registering an account"),
+        }
+    } else {
+        params[0].clone()
+    };
+
+    if !is_valid_account_name(&account_name, state.config.limits.max_nickname_length) {
+        return fail("BAD_ACCOUNT_NAME", Some(&account_name), "Account name is not valid");
+    }
+    if state.accounts.is_taken(&account_name) {
+        return fail("ACCOUNT_EXISTS", Some(&account_name), "Account already exists");
+    }
+
+    let email = match params[1].as_str() {
+        "*" => None,
+        email if is_valid_email(email) => Some(email.to_string()),
+        _ => return fail("INVALID_EMAIL", Some(&account_name), "Email address is not valid"),
+    };
+    if email.is_none() && (settings.email_required || settings.email_verification) {
+        return fail("INVALID_EMAIL", Some(&account_name), "An email address is required to register");
+    }
+
+    let password = &params[2];
+    if password == "*" || password.chars().count() < settings.min_password_length {
+        return fail("WEAK_PASSWORD", Some(&account_name), &format!(
+            "Password must be at least {} characters", settings.min_password_length
+        ));
+    }
+
+    if settings.email_verification {
+        let (Some(mailer), Some(email)) = (state.mailer.clone(), email) else {
+            return fail("TEMPORARILY_UNAVAILABLE", Some(&account_name), "Email verification is unavailable");
+        };
+
+        let ttl = Duration::from_secs(settings.verification_timeout);
+        let code = match state.accounts.create_pending(&account_name, password, Some(email.clone()), ttl).await {
+            Ok(Some(code)) => code,
+            Ok(None) => return fail("ACCOUNT_EXISTS", Some(&account_name), "Account already exists"),
+            Err(e) => {
+                warn!("Failed to hash password for {}: {}", account_name, e);
+                return fail("TEMPORARILY_UNAVAILABLE", Some(&account_name), "Could not create the account");
+            }
+        };
+
+        let mail = Mail {
+            from: state.config.network.admin_email.clone(),
+            to: email,
+            subject: format!("Verify your {} account", state.config.network.name),
+            body: format!(
+                "Your verification code for account {account} is {code}\n\n\
+                 To finish registering, send: /VERIFY {account} {code}\n\n\
+                 The code expires in {} minutes.",
+                settings.verification_timeout / 60,
+                account = account_name,
+            ),
+        };
+        if let Err(e) = mailer.send(&mail).await {
+            warn!("Failed to send verification mail for {}: {}", account_name, e);
+            state.accounts.cancel_pending(&account_name);
+            return fail("TEMPORARILY_UNAVAILABLE", Some(&account_name), "Could not send the verification email");
+        }
+
+        info!("Account {} registered, awaiting verification", account_name);
+        return Ok(vec![Message::new("REGISTER")
+            .with_prefix(server_name)
+            .with_params(vec![
+                "VERIFICATION_REQUIRED".to_string(),
+                account_name,
+                "Account created, check your email for the verification code".to_string(),
+            ])]);
+    }
+
+    // A concurrent REGISTER for the same name can still win between the
+    // is_taken check above and here
+    let account = match state.accounts.create(&account_name, password).await {
+        Ok(true) => state.accounts.get(&account_name),
+        Ok(false) => None,
+        Err(e) => {
+            warn!("Failed to hash password for {}: {}", account_name, e);
+            return fail("TEMPORARILY_UNAVAILABLE", Some(&account_name), "Could not create the account");
+        }
+    };
+    let Some(account) = account else {
+        return fail("ACCOUNT_EXISTS", Some(&account_name), "Account already exists");
+    };
+
+    info!("Account {} registered", account_name);
+    let mut replies = vec![Message::new("REGISTER")
+        .with_prefix(server_name.clone())
+        .with_params(vec![
+            "SUCCESS".to_string(),
+            account_name.clone(),
+            "Account successfully registered".to_string(),
+        ])];
+    replies.push(log_in(state, connection_id, &account, email).await);
+    Ok(replies)
+}
+
+/// VERIFY <account> <code>
+pub async fn handle_verify(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    params: Vec<String>,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+    let server_name = state.server_name.clone();
+
+    let Some(logged_in) = state.connections.get(&connection_id).map(|conn| conn.account.is_some()) else {
+        return Ok(vec![]);
+    };
+
+    if params.len() < 2 {
+        return Ok(vec![StandardReply::fail("VERIFY", StandardReplyCode::NeedMoreParams, "Not enough parameters")
+            .to_message(&server_name)]);
+    }
+    if logged_in {
+        return Ok(vec![StandardReply::fail(
+            "VERIFY",
+            StandardReplyCode::Custom("ALREADY_AUTHENTICATED".to_string()),
+            "You are already logged in to an account",
+        ).to_message(&server_name)]);
+    }
+
+    state.accounts.prune_pending();
+    let Some(pending) = state.accounts.verify(&params[0], &params[1]) else {
+        return Ok(vec![StandardReply::fail(
+            "VERIFY",
+            StandardReplyCode::Custom("INVALID_CODE".to_string()),
+            "Invalid or expired verification code",
+        ).add_context(params[0].clone()).to_message(&server_name)]);
+    };
+
+    info!("Account {} verified", pending.account.name);
+    let mut replies = vec![Message::new("VERIFY")
+        .with_prefix(server_name)
+        .with_params(vec![
+            "SUCCESS".to_string(),
+            pending.account.name.clone(),
+            "Account successfully verified".to_string(),
+        ])];
+    replies.push(log_in(state, connection_id, &pending.account, pending.email).await);
+    Ok(replies)
+}
+
+/// UNREGISTER <account> <password>
+///
+/// Drops the account the caller is logged in to. Every session using it is
+/// logged out, and channels it founded pass to their successors.
+pub async fn handle_unregister(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    params: Vec<String>,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+    let server_name = state.server_name.clone();
+
+    let Some((nick, mask, logged_in)) = state.connections.get(&connection_id)
+        .map(|conn| (conn.nickname.clone().unwrap_or_else(|| "*".to_string()), conn.full_mask(), conn.account.clone()))
+    else {
+        return Ok(vec![]);
+    };
+
+    if params.len() < 2 {
+        return Ok(vec![StandardReply::fail("UNREGISTER", StandardReplyCode::NeedMoreParams, "Not enough parameters")
+            .to_message(&server_name)]);
+    }
+    if !logged_in.is_some_and(|account| account.eq_ignore_ascii_case(&params[0])) {
+        return Ok(vec![StandardReply::fail(
+            "UNREGISTER",
+            StandardReplyCode::Custom("ACCOUNT_REQUIRED".to_string()),
+            "You must be logged in to the account you are dropping",
+        ).add_context(params[0].clone()).to_message(&server_name)]);
+    }
+    let Some(account) = state.accounts.authenticate(&params[0], &params[1]).await else {
+        return Ok(vec![StandardReply::fail("UNREGISTER", StandardReplyCode::InvalidCredentials, "Incorrect password")
+            .add_context(params[0].clone())
+            .to_message(&server_name)]);
+    };
+
+    // Other sessions on the account are logged out along with this one
+    for conn in state.connections.iter() {
+        if conn.id != connection_id && conn.account.as_deref().is_some_and(|name| name.eq_ignore_ascii_case(&account.name)) {
+            let logged_out = Message::new("901")
+                .with_prefix(server_name.clone())
+                .with_params(vec![
+                    conn.nickname.clone().unwrap_or_else(|| "*".to_string()),
+                    conn.full_mask(),
+                    "You are now logged out".to_string(),
+                ]);
+            let _ = conn.tx.try_send(logged_out);
+        }
+    }
+
+    let successions = state.drop_account(&account.name).unwrap_or_default();
+
+    let mut replies = vec![Message::new("UNREGISTER")
+        .with_prefix(server_name.clone())
+        .with_params(vec!["SUCCESS".to_string(), account.name.clone(), "Account dropped".to_string()])];
+    for succession in successions {
+        let reply = match succession {
+            Succession::Transferred { channel, founder } => StandardReply::note(
+                "UNREGISTER",
+                StandardReplyCode::Custom("FOUNDER_CHANGED".to_string()),
+                &format!("{} now belongs to {}", channel, founder),
+            ).with_context(vec![channel.clone(), founder.clone()]),
+            Succession::Dropped { channel } => StandardReply::note(
+                "UNREGISTER",
+                StandardReplyCode::Custom("CHANNEL_DROPPED".to_string()),
+                &format!("{} had no successor and is no longer registered", channel),
+            ).add_context(channel.clone()),
+        };
+        replies.push(reply.to_message(&server_name));
+    }
+    replies.push(Message::new("901")
+        .with_prefix(server_name)
+        .with_params(vec![nick, mask, "You are now logged out".to_string()]));
+    Ok(replies)
+}
+
+/// Log the connection in to a freshly created account, record the user in
+/// the database, and build the RPL_LOGGEDIN reply
+async fn log_in(state: &ServerState, connection_id: u64, account: &Account, email: Option<String>) -> Message {
+    let (nick, mask, username, realname) = state.connections.get_mut(&connection_id)
+        .map(|mut conn| {
+            conn.account = Some(account.name.clone());
+            (
+                conn.nickname.clone().unwrap_or_else(|| "*".to_string()),
+                conn.full_mask(),
+                conn.username.clone(),
+                conn.realname.clone(),
+            )
+        })
+        .unwrap_or_else(|| ("*".to_string(), "*".to_string(), None, None));
+
+    // The password stays with the account, which is the only copy a rehash
+    // updates, so the user row carries no hash of its own
+    if let Some(db) = state.database() {
+        let mut user = User::new(
+            account.name.clone(),
+            username.unwrap_or_else(|| account.name.clone()),
+            realname.unwrap_or_default(),
+            String::new(),
+        );
+        user.email = email;
+        user.account_name = Some(account.name.clone());
+
+        match db.create_user(&user).await {
+            Ok(_) => {}
+            Err(DatabaseError::DuplicateEntry) => warn!("User record for {} already exists", account.name),
+            Err(e) => warn!("Failed to record user {}: {}", account.name, e),
+        }
+    }
+
+    Message::new("900")
+        .with_prefix(state.server_name.clone())
+        .with_params(vec![
+            nick,
+            mask,
+            account.name.clone(),
+            format!("You are now logged in as {}", account.name),
+        ])
+}
+
+/// Account names follow nickname rules so that `REGISTER *` always works
+fn is_valid_account_name(name: &str, max_len: usize) -> bool {
+    let special = |c: char| "_-[]{}\\|^`".contains(c);
+    match name.chars().next() {
+        Some(first) if first.is_ascii_alphabetic() || (special(first) && first != '-') => {}
+        _ => return false,
+    }
+    name.len() <= max_len && name.chars().all(|c| c.is_ascii_alphanumeric() || special(c))
+}
+
+fn is_valid_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !email.chars().any(|c| c.is_whitespace() || c == ',')
+                && !domain.contains('@')
+        }
+        None => false,
+    }
+}
+
+/// REGISTER, allowed before registration
+pub struct Register;
+
+#[async_trait]
+impl CommandHandler for Register {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("REGISTER").registration(Registration::Optional).penalty(10)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Register(params) = command else {
+            return Ok(Vec::new());
+        };
+        handle_register(server_state, ctx.connection_id, params).await
+    }
+}
+
+/// VERIFY, allowed before registration
+pub struct Verify;
+
+#[async_trait]
+impl CommandHandler for Verify {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("VERIFY").registration(Registration::Optional).penalty(5)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Verify(params) = command else {
+            return Ok(Vec::new());
+        };
+        handle_verify(server_state, ctx.connection_id, params).await
+    }
+}
+
+/// UNREGISTER
+pub struct Unregister;
+
+#[async_trait]
+impl CommandHandler for Unregister {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("UNREGISTER")
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Unregister(params) = command else {
+            return Ok(Vec::new());
+        };
+        handle_unregister(server_state, ctx.connection_id, params).await
+    }
+}
+
+#[cfg(test)]
+#[path = "register_test.rs"]
+mod tests;
@@ -0,0 +1,110 @@
+use super::*;
+use crate::commands::handlers::test_support::{add_user, params};
+use crate::utils::config::ServerConfig;
+use crate::utils::mail::FileDropSender;
+
+fn test_config() -> ServerConfig {
+    let mut config = ServerConfig::default();
+    config.security.argon2_memory_cost = 8 * 1024;
+    config.security.argon2_time_cost = 1;
+    config.registration.enabled = true;
+    config
+}
+
+#[tokio::test]
+async fn test_register_logs_in() {
+    let state = ServerState::with_config(test_config()).unwrap();
+    let _rx = add_user(1, "alice").insert(&state);
+    let state = Arc::new(state);
+
+    let messages = handle_register(state.clone(), 1, params("* * correcthorse")).await.unwrap();
+    assert_eq!(messages[0].command, "REGISTER");
+    assert_eq!(messages[0].params[..2], ["SUCCESS".to_string(), "alice".to_string()]);
+    assert_eq!(messages[1].command, "900");
+    assert_eq!(state.connections.get(&1).unwrap().account.as_deref(), Some("alice"));
+
+    // Logged in now, and the name is taken for everyone else
+    let messages = handle_register(state.clone(), 1, params("other * correcthorse")).await.unwrap();
+    assert_eq!(messages[0].params[1], "ALREADY_AUTHENTICATED");
+    let _rx2 = add_user(2, "bob").insert(&state);
+    let messages = handle_register(state.clone(), 2, params("Alice * correcthorse")).await.unwrap();
+    assert_eq!(messages[0].params[1..3], ["ACCOUNT_EXISTS".to_string(), "Alice".to_string()]);
+}
+
+#[tokio::test]
+async fn test_register_rejects_bad_input() {
+    let state = ServerState::with_config(test_config()).unwrap();
+    let _rx = add_user(1, "alice").insert(&state);
+    let state = Arc::new(state);
+
+    let code = |line: &'static str| {
+        let state = state.clone();
+        async move { handle_register(state, 1, params(line)).await.unwrap()[0].params[1].clone() }
+    };
+    assert_eq!(code("alice *").await, "NEED_MORE_PARAMS");
+    assert_eq!(code("#alice * correcthorse").await, "BAD_ACCOUNT_NAME");
+    assert_eq!(code("alice not-an-email correcthorse").await, "INVALID_EMAIL");
+    assert_eq!(code("alice * short").await, "WEAK_PASSWORD");
+}
+
+#[tokio::test]
+async fn test_register_with_email_verification() {
+    let dir = std::env::temp_dir().join(format!("centurion-register-{}", crate::utils::generate_message_id()));
+    let mut config = test_config();
+    config.registration.email_verification = true;
+    let mut state = ServerState::with_config(config).unwrap();
+    state.mailer = Some(Arc::new(FileDropSender::new(&dir)));
+    let _rx = add_user(1, "alice").insert(&state);
+    let state = Arc::new(state);
+
+    let messages = handle_register(state.clone(), 1, params("* * correcthorse")).await.unwrap();
+    assert_eq!(messages[0].params[1], "INVALID_EMAIL");
+
+    let messages = handle_register(state.clone(), 1, params("* alice@example.com correcthorse")).await.unwrap();
+    assert_eq!(messages[0].params[..2], ["VERIFICATION_REQUIRED".to_string(), "alice".to_string()]);
+    assert!(state.accounts.get("alice").is_none());
+
+    // Pull the code back out of the dropped mail
+    let path = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+    let mail = std::fs::read_to_string(path).unwrap();
+    let code = mail.split("/VERIFY alice ").nth(1).unwrap().split_whitespace().next().unwrap().to_string();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let messages = handle_verify(state.clone(), 1, params("alice wrongcode")).await.unwrap();
+    assert_eq!(messages[0].params[1], "INVALID_CODE");
+
+    let messages = handle_verify(state.clone(), 1, vec!["alice".to_string(), code]).await.unwrap();
+    assert_eq!(messages[0].params[..2], ["SUCCESS".to_string(), "alice".to_string()]);
+    assert_eq!(messages[1].command, "900");
+    assert!(state.accounts.authenticate("alice", "correcthorse").await.is_some());
+}
+
+#[tokio::test]
+async fn test_unregister_hands_channels_to_successor() {
+    let state = ServerState::with_config(test_config()).unwrap();
+    let _alice = add_user(1, "alice").insert(&state);
+    let mut other_session = add_user(2, "alice_").insert(&state);
+    state.accounts.create("alice", "correcthorse").await.unwrap();
+    state.accounts.create("bob", "batterystaple").await.unwrap();
+    for id in [1, 2] {
+        state.connections.get_mut(&id).unwrap().account = Some("alice".to_string());
+    }
+    let mut record = crate::db::models::Channel::new("#rust".to_string(), Some("alice".to_string()));
+    record.successor = Some("bob".to_string());
+    state.registered_channels.register(record);
+    let state = Arc::new(state);
+
+    let messages = handle_unregister(state.clone(), 1, params("alice wrongpassword")).await.unwrap();
+    assert_eq!(messages[0].params[1], "INVALID_CREDENTIALS");
+
+    let messages = handle_unregister(state.clone(), 1, params("alice correcthorse")).await.unwrap();
+    assert_eq!(messages[0].params[..2], ["SUCCESS".to_string(), "alice".to_string()]);
+    assert_eq!(messages[1].params[1], "FOUNDER_CHANGED");
+    assert_eq!(messages[2].command, "901");
+    assert_eq!(other_session.try_recv().unwrap().command, "901");
+
+    let state = &*state;
+    assert!(state.accounts.get("alice").is_none());
+    assert!(state.connections.get(&2).unwrap().account.is_none());
+    assert!(state.registered_channels.is_founder("#rust", "bob"));
+}
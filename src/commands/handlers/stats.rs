@@ -0,0 +1,77 @@
+use std::sync::Arc;
+use crate::protocol::{Command, Message};
+use crate::state::{BanKind, ServerState};
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
+
+/// STATS <query>
+///
+/// `k` lists K-lines (216) and `d` D-lines (225); both are for operators
+/// only. Every query ends with RPL_ENDOFSTATS (219).
+pub async fn handle_stats(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    query: String,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+
+    let (nick, is_oper) = state.connections.get(&connection_id)
+        .map(|conn| (conn.nickname.clone().unwrap_or_else(|| "*".to_string()), conn.modes.contains(&'o')))
+        .ok_or("Connection not found")?;
+    let reply = |numeric: &str, params: Vec<String>| {
+        Message::new(numeric)
+            .with_prefix(state.server_name.clone())
+            .with_params([vec![nick.clone()], params].concat())
+    };
+
+    let kind = match query.chars().next() {
+        Some('k' | 'K') => Some(BanKind::Kline),
+        Some('d' | 'D') => Some(BanKind::Dline),
+        _ => None,
+    };
+
+    let mut responses = Vec::new();
+    if let Some(kind) = kind {
+        if !is_oper {
+            return Ok(vec![reply("481", vec!["Permission Denied- You're not an IRC operator".to_string()])]);
+        }
+        for ban in state.server_bans.list(kind) {
+            let reason = match ban.expires_at {
+                Some(expires) => format!("{} (expires {})", ban.reason, expires.format("%Y-%m-%d %H:%M UTC")),
+                None => ban.reason,
+            };
+            responses.push(match kind {
+                BanKind::Kline => {
+                    let (user, host) = ban.mask.rsplit_once('@').unwrap_or(("*", &ban.mask));
+                    reply("216", vec!["K".to_string(), host.to_string(), "*".to_string(), user.to_string(), reason])
+                }
+                BanKind::Dline => reply("225", vec!["D".to_string(), ban.mask.clone(), reason]),
+            });
+        }
+    }
+
+    responses.push(reply("219", vec![query, "End of /STATS report".to_string()]));
+    Ok(responses)
+}
+
+/// STATS <query>
+pub struct Stats;
+
+#[async_trait]
+impl CommandHandler for Stats {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("STATS").min_params(1)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Stats(Some(query), _) = command else {
+            return Ok(Vec::new());
+        };
+        handle_stats(server_state, ctx.connection_id, query).await
+    }
+}
@@ -1,9 +1,10 @@
-use crate::protocol::Message;
+use crate::protocol::{Command, Message};
 use crate::state::ServerState;
 use crate::utils::generate_message_id;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 // Helper function to add server tags to a message
 fn add_server_tags(mut message: Message, connection_capabilities: &Vec<String>) -> Message {
@@ -23,7 +24,7 @@ fn add_server_tags(mut message: Message, connection_capabilities: &Vec<String>)
 }
 
 pub async fn handle_tagmsg(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     sender_id: u64,
     target: String,
     tags: HashMap<String, String>,
@@ -32,15 +33,17 @@ pub async fn handle_tagmsg(
     
     // Get sender info
     let sender_info = {
-        let state = server_state.read().await;
+        let state = &*server_state;
         state.connections.get(&sender_id).map(|conn| (
             conn.nickname.clone().unwrap_or_else(|| "*".to_string()),
             conn.username.clone().unwrap_or_else(|| "~unknown".to_string()),
             conn.hostname.clone(),
+            conn.account.is_some(),
+            conn.modes.contains(&'B'),
         ))
     };
 
-    let (sender_nick, sender_user, sender_host) = match sender_info {
+    let (sender_nick, sender_user, sender_host, logged_in, is_bot) = match sender_info {
         Some(info) => info,
         None => return Ok(responses),
     };
@@ -51,7 +54,10 @@ pub async fn handle_tagmsg(
     if target.starts_with('#') || target.starts_with('&') {
         // Handle channel TAGMSG
         let channel_member_ids = {
-            let state = server_state.read().await;
+            let state = &*server_state;
+            if !state.can_send_to_channel(&target, sender_id, "") {
+                return Ok(responses);
+            }
             state.channels.get(&target).map(|channel| {
                 channel.members.iter().map(|member| *member.key()).collect::<Vec<u64>>()
             })
@@ -60,14 +66,14 @@ pub async fn handle_tagmsg(
         if let Some(member_ids) = channel_member_ids {
             // Check if sender has echo-message capability
             let sender_has_echo = {
-                let state = server_state.read().await;
+                let state = &*server_state;
                 state.connections.get(&sender_id)
                     .map(|conn| conn.capabilities.contains(&"echo-message".to_string()))
                     .unwrap_or(false)
             };
             
             // Send to all channel members
-            let state = server_state.read().await;
+            let state = &*server_state;
             for member_id in member_ids {
                 // Echo back to sender only if they have echo-message capability
                 if member_id == sender_id && !sender_has_echo {
@@ -84,13 +90,16 @@ pub async fn handle_tagmsg(
                         
                         // Add server tags based on client capabilities
                         tagmsg = add_server_tags(tagmsg, &conn.capabilities);
+                        if is_bot {
+                            tagmsg = tagmsg.with_tag("bot".to_string(), None);
+                        }
                         
                         // Add all client tags
                         for (key, value) in &tags {
                             tagmsg = tagmsg.with_tag(key.clone(), Some(value.clone()));
                         }
                         
-                        let _ = conn.tx.send(tagmsg).await;
+                        let _ = conn.tx.try_send(tagmsg);
                     }
                 }
             }
@@ -98,14 +107,17 @@ pub async fn handle_tagmsg(
     } else {
         // Handle private TAGMSG
         let target_id = {
-            let state = server_state.read().await;
+            let state = &*server_state;
+            if !logged_in && state.rejects_unregistered(&target) {
+                return Ok(responses);
+            }
             state.nicknames.get(&target.to_lowercase()).map(|entry| *entry.value())
         };
         
         if let Some(target_id) = target_id {
             // Check if target has message-tags capability
             let has_capability = {
-                let state = server_state.read().await;
+                let state = &*server_state;
                 state.connections.get(&target_id)
                     .map(|conn| conn.capabilities.contains(&"message-tags".to_string()))
                     .unwrap_or(false)
@@ -114,7 +126,7 @@ pub async fn handle_tagmsg(
             if has_capability {
                 // Get target connection for server tags
                 let target_capabilities = {
-                    let state = server_state.read().await;
+                    let state = &*server_state;
                     state.connections.get(&target_id).map(|conn| conn.capabilities.clone())
                 };
                 
@@ -125,6 +137,9 @@ pub async fn handle_tagmsg(
                     
                     // Add server tags based on target capabilities
                     tagmsg = add_server_tags(tagmsg, &capabilities);
+                    if is_bot {
+                        tagmsg = tagmsg.with_tag("bot".to_string(), None);
+                    }
                     
                     // Add all client tags
                     for (key, value) in &tags {
@@ -133,17 +148,17 @@ pub async fn handle_tagmsg(
                     
                     // Send the message
                     let tx = {
-                        let state = server_state.read().await;
+                        let state = &*server_state;
                         state.connections.get(&target_id).map(|conn| conn.tx.clone())
                     };
                     
                     if let Some(tx) = tx {
-                        let _ = tx.send(tagmsg.clone()).await;
+                        let _ = tx.try_send(tagmsg.clone());
                     }
                 
                     // Echo back to sender if they have echo-message capability
                     let sender_echo_info = {
-                        let state = server_state.read().await;
+                        let state = &*server_state;
                         state.connections.get(&sender_id).map(|conn| (
                             conn.capabilities.contains(&"echo-message".to_string()),
                             conn.capabilities.clone(),
@@ -166,7 +181,7 @@ pub async fn handle_tagmsg(
                                 echo_tagmsg = echo_tagmsg.with_tag(key.clone(), Some(value.clone()));
                             }
                             
-                            let _ = sender_tx.send(echo_tagmsg).await;
+                            let _ = sender_tx.try_send(echo_tagmsg);
                         }
                     }
                 }
@@ -175,4 +190,26 @@ pub async fn handle_tagmsg(
     }
     
     Ok(responses)
-}
\ No newline at end of file
+}
+
+/// TAGMSG <target>, carrying only client tags
+pub struct TagMsg;
+
+#[async_trait]
+impl CommandHandler for TagMsg {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("TAGMSG").min_params(1).capability("message-tags")
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::TagMsg { target } = command else {
+            return Ok(Vec::new());
+        };
+        handle_tagmsg(server_state, ctx.connection_id, target, ctx.tags.clone()).await
+    }
+}
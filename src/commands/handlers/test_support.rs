@@ -0,0 +1,91 @@
+// Fixtures shared by the handler tests
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::commands::CommandContext;
+use crate::state::{Channel, Connection, ServerState};
+use crate::state::sendq::{self, SendQueueReceiver};
+
+/// A registered client for a handler test
+pub(crate) struct UserBuilder {
+    id: u64,
+    nick: String,
+    addr: SocketAddr,
+    account: Option<String>,
+    certfp: Option<String>,
+}
+
+/// A registered client `nick`, with the nickname as its username, connecting
+/// from 127.0.0.1. Finish with `insert` to add it to a server.
+pub(crate) fn add_user(id: u64, nick: &str) -> UserBuilder {
+    UserBuilder {
+        id,
+        nick: nick.to_string(),
+        addr: "127.0.0.1:40000".parse().unwrap(),
+        account: None,
+        certfp: None,
+    }
+}
+
+impl UserBuilder {
+    pub(crate) fn addr(mut self, addr: &str) -> Self {
+        self.addr = addr.parse().unwrap();
+        self
+    }
+
+    pub(crate) fn account(mut self, account: &str) -> Self {
+        self.account = Some(account.to_string());
+        self
+    }
+
+    pub(crate) fn certfp(mut self, certfp: &str) -> Self {
+        self.certfp = Some(certfp.to_string());
+        self
+    }
+
+    /// The connection on its own, without adding it to a server
+    pub(crate) fn build(self) -> (Connection, SendQueueReceiver) {
+        let (tx, rx) = sendq::channel(64 * 1024);
+        let mut conn = Connection::new(self.id, self.addr, tx);
+        conn.nickname = Some(self.nick.clone());
+        conn.username = Some(self.nick);
+        conn.registered = true;
+        conn.account = self.account;
+        conn.certfp = self.certfp;
+        (conn, rx)
+    }
+
+    /// Add the connection to `state` and claim its nickname. Keep the
+    /// receiver alive for as long as the client should count as connected.
+    pub(crate) fn insert(self, state: &ServerState) -> SendQueueReceiver {
+        let (conn, rx) = self.build();
+        let (id, nick) = (conn.id, conn.nickname.clone().unwrap_or_default());
+        state.connections.insert(id, conn);
+        state.change_nickname(None, &nick, id);
+        rx
+    }
+}
+
+/// The context dispatch would give registered client `connection_id`
+pub(crate) fn context(connection_id: u64) -> CommandContext {
+    CommandContext { registered: true, ..CommandContext::new(connection_id) }
+}
+
+/// alice (id 1, operator) and bob (id 2) in #test
+pub(crate) fn setup_channel() -> (Arc<ServerState>, Vec<SendQueueReceiver>) {
+    let state = ServerState::new();
+    let receivers = vec![add_user(1, "alice").insert(&state), add_user(2, "bob").insert(&state)];
+
+    let channel = Channel::new("#test".to_string());
+    channel.add_member(1, true);
+    channel.add_member(2, false);
+    state.channels.insert("#test".to_string(), channel);
+
+    (Arc::new(state), receivers)
+}
+
+/// Split a space-separated parameter line, e.g. `params("#test +o bob")`
+pub(crate) fn params(line: &str) -> Vec<String> {
+    line.split(' ').map(str::to_string).collect()
+}
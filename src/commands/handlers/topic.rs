@@ -1,10 +1,11 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
+use crate::protocol::{Command, Message, Reply};
 use crate::state::ServerState;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_topic(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
     params: Vec<String>,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
@@ -17,7 +18,7 @@ pub async fn handle_topic(
     let channel_name = params[0].clone();
     let new_topic = params.get(1).cloned();
     
-    let mut state = server_state.write().await;
+    let state = &*server_state;
     
     // Get connection info
     let connection = state.connections.get(&connection_id)
@@ -69,6 +70,7 @@ pub async fn handle_topic(
             channel.topic = if topic.is_empty() { None } else { Some(topic.clone()) };
             channel.topic_set_by = Some(prefix.clone());
             channel.topic_set_at = Some(chrono::Utc::now());
+            state.registered_channels.update(&channel_name, |record| channel.save_to(record));
             
             // Create TOPIC message
             let topic_msg = Message::new("TOPIC")
@@ -79,7 +81,7 @@ pub async fn handle_topic(
             for entry in channel.members.iter() {
                 let member_id = *entry.key();
                 if let Some(member_conn) = state.connections.get(&member_id) {
-                    let _ = member_conn.tx.send(topic_msg.clone()).await;
+                    let _ = member_conn.tx.try_send(topic_msg.clone());
                 }
             }
             
@@ -112,6 +114,30 @@ pub async fn handle_topic(
     Ok(responses)
 }
 
+/// TOPIC <channel> [topic]
+pub struct Topic;
+
+#[async_trait]
+impl CommandHandler for Topic {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("TOPIC").min_params(1)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Topic { channel, topic } = command else {
+            return Ok(Vec::new());
+        };
+        let mut params = vec![channel];
+        params.extend(topic);
+        handle_topic(server_state, ctx.connection_id, params).await
+    }
+}
+
 #[cfg(test)]
 #[path = "topic_test.rs"]
 mod tests;
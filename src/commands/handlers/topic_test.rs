@@ -0,0 +1,37 @@
+use super::*;
+use crate::commands::handlers::test_support::setup_channel;
+
+#[tokio::test]
+async fn test_member_sets_topic() {
+    let (state, _rx) = setup_channel();
+
+    let messages = handle_topic(state.clone(), 2, vec!["#test".to_string(), "hello".to_string()])
+        .await
+        .unwrap();
+
+    assert_eq!(messages[0].command, "TOPIC");
+    let state = &*state;
+    assert_eq!(state.channels.get("#test").unwrap().topic.as_deref(), Some("hello"));
+}
+
+#[tokio::test]
+async fn test_topic_lock_requires_operator() {
+    let (state, _rx) = setup_channel();
+    state.channels.get_mut("#test").unwrap().modes.push('t');
+
+    let messages = handle_topic(state.clone(), 2, vec!["#test".to_string(), "hello".to_string()])
+        .await
+        .unwrap();
+
+    assert_eq!(messages[0].command, "482");
+    assert!(state.channels.get("#test").unwrap().topic.is_none());
+}
+
+#[tokio::test]
+async fn test_topic_query_without_topic() {
+    let (state, _rx) = setup_channel();
+
+    let messages = handle_topic(state, 1, vec!["#test".to_string()]).await.unwrap();
+
+    assert_eq!(messages[0].command, "331");
+}
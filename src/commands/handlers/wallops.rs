@@ -0,0 +1,57 @@
+use std::sync::Arc;
+use crate::protocol::{Command, Message};
+use crate::state::ServerState;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
+
+/// WALLOPS <text>
+///
+/// Sends a message from an IRC operator to every user with +w set. The
+/// registry only lets operators through.
+pub async fn handle_wallops(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    text: String,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+    
+    let connection = state.connections.get(&connection_id)
+        .ok_or("Connection not found")?
+        .clone();
+    
+    let wallops = Message::new("WALLOPS")
+        .with_prefix(connection.full_mask())
+        .with_params(vec![text]);
+    
+    let recipients: Vec<_> = state.connections.iter()
+        .filter(|conn| conn.modes.contains(&'w'))
+        .map(|conn| conn.tx.clone())
+        .collect();
+    for tx in recipients {
+        let _ = tx.try_send(wallops.clone());
+    }
+    
+    Ok(vec![])
+}
+
+/// WALLOPS <text>
+pub struct Wallops;
+
+#[async_trait]
+impl CommandHandler for Wallops {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("WALLOPS").min_params(1).oper_only()
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Wallops(text) = command else {
+            return Ok(Vec::new());
+        };
+        handle_wallops(server_state, ctx.connection_id, text).await
+    }
+}
@@ -1,14 +1,17 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
-use crate::state::ServerState;
+use crate::protocol::{Command, Message, Reply};
+use crate::security::mask_matches;
+use crate::state::{Connection, ServerState};
+use crate::state::channel_modes::prefix_symbol;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_who(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
     params: Vec<String>,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
-    let state = server_state.read().await;
+    let state = &*server_state;
     let mut messages = Vec::new();
     
     // Get the requesting connection
@@ -20,7 +23,8 @@ pub async fn handle_who(
     let requester_nick = req_conn.nickname.as_deref().unwrap_or("*");
     
     // Default to "*" (all visible users) if no parameter given
-    let target = params.get(0).cloned().unwrap_or_else(|| "*".to_string());
+    let target = params.first().cloned().unwrap_or_else(|| "*".to_string());
+    let peers = state.channel_peers(connection_id);
     
     if target.starts_with('#') || target.starts_with('&') || target.starts_with('!') {
         // WHO for a specific channel
@@ -34,50 +38,36 @@ pub async fn handle_who(
                 for member_entry in channel.members.iter() {
                     let member_id = *member_entry.key();
                     if let Some(member_conn) = state.connections.get(&member_id) {
-                        if let Some(member_nick) = &member_conn.nickname {
-                            // Determine status flags (H = here, G = gone/away, @ = op, + = voice)
-                            // TODO: Implement AWAY support in Connection struct
-                            let mut flags = "H".to_string(); // Always "here" for now
-                            
-                            // Add channel status (highest status first)
-                            if member_entry.value().modes.contains(&'o') {
-                                flags.push('@');
-                            } else if member_entry.value().modes.contains(&'v') {
-                                flags.push('+');
-                            }
-                            
-                            // Create a basic WHO reply using available reply types
-                            // Since WHO reply (352) isn't available, we'll document this limitation
-                            // TODO: Implement proper RPL_WHOREPLY (352) when available in legion-protocol
-                            // For now, we'll skip individual WHO replies and just send end of WHO
+                        if member_conn.modes.contains(&'i') && !peers.contains(&member_id) {
+                            continue;
                         }
+                        let status = prefix_symbol(&member_entry.value().modes);
+                        messages.push(who_reply(state, requester_nick, &channel.name, &member_conn, status));
                     }
                 }
             }
         }
-    } else if target == "*" {
-        // WHO for all visible users - very limited implementation
-        // In a real implementation, this would show all visible users not in channels
-        // For now, we'll just acknowledge the command
-    } else {
-        // WHO for a specific user
-        if let Some(target_id_ref) = state.nicknames.get(&target.to_lowercase()) {
-            let target_id = *target_id_ref;
-            if let Some(target_conn) = state.connections.get(&target_id) {
-                // TODO: Implement proper RPL_WHOREPLY (352) when available in legion-protocol
-                // For now, we'll just acknowledge the user exists by not sending NoSuchNick
-            } else {
-                messages.push(Message::from(Reply::NoSuchNick {
-                    nick: requester_nick.to_string(),
-                    target: target.clone(),
-                }));
+    } else if let Some(target_id) = state.nicknames.get(&target.to_lowercase()).map(|id| *id) {
+        // WHO for a specific user, who is shown even when invisible
+        if let Some(target_conn) = state.connections.get(&target_id) {
+            messages.push(who_reply(state, requester_nick, "*", &target_conn, None));
+        }
+    } else if target.contains('*') || target.contains('?') {
+        // WHO for a mask, matched against nicknames and hostmasks
+        for conn in state.connections.iter() {
+            if conn.nickname.is_none() || (conn.modes.contains(&'i') && !peers.contains(&conn.id)) {
+                continue;
+            }
+            let nick = conn.nickname.as_deref().unwrap_or("*");
+            if mask_matches(&target, nick) || mask_matches(&target, &conn.full_mask()) {
+                messages.push(who_reply(state, requester_nick, "*", &conn, None));
             }
-        } else {
-            messages.push(Message::from(Reply::NoSuchNick {
-                nick: requester_nick.to_string(),
-                target: target.clone(),
-            }));
         }
+    } else {
+        messages.push(Message::from(Reply::NoSuchNick {
+            nick: requester_nick.to_string(),
+            target: target.clone(),
+        }));
     }
     
     // Always send end of WHO
@@ -94,6 +84,55 @@ pub async fn handle_who(
     Ok(messages)
 }
 
+/// RPL_WHOREPLY (352). Flags are H (here) or G (gone), * for IRC
+/// operators, the channel status prefix and B for bots.
+fn who_reply(state: &ServerState, requester_nick: &str, channel: &str, conn: &Connection, status: Option<char>) -> Message {
+    let mut flags = if conn.away.is_some() { "G" } else { "H" }.to_string();
+    if conn.modes.contains(&'o') {
+        flags.push('*');
+    }
+    flags.extend(status);
+    if conn.modes.contains(&'B') {
+        flags.push('B');
+    }
+    
+    let nick = conn.nickname.clone().unwrap_or_else(|| "*".to_string());
+    Message::new("352")
+        .with_prefix(state.server_name.clone())
+        .with_params(vec![
+            requester_nick.to_string(),
+            channel.to_string(),
+            conn.username.clone().unwrap_or_else(|| nick.clone()),
+            conn.hostname.clone(),
+            state.server_name.clone(),
+            nick,
+            flags,
+            format!("0 {}", conn.realname.as_deref().unwrap_or("")),
+        ])
+}
+
+/// WHO [mask]
+pub struct Who;
+
+#[async_trait]
+impl CommandHandler for Who {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("WHO").penalty(4)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Who(mask) = command else {
+            return Ok(Vec::new());
+        };
+        handle_who(server_state, ctx.connection_id, mask.into_iter().collect()).await
+    }
+}
+
 #[cfg(test)]
 #[path = "who_test.rs"]
 mod tests;
@@ -0,0 +1,54 @@
+use super::*;
+use crate::commands::handlers::test_support::add_user;
+
+#[tokio::test]
+async fn test_who_unknown_nick() {
+    let state = ServerState::new();
+    let _rx = add_user(1, "alice").insert(&state);
+    let state = Arc::new(state);
+
+    let messages = handle_who(state, 1, vec!["nobody".to_string()]).await.unwrap();
+
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].command, "401");
+    assert_eq!(messages[1].command, "315");
+}
+
+#[tokio::test]
+async fn test_who_always_ends_with_end_of_who() {
+    let state = ServerState::new();
+    let _rx = add_user(1, "alice").insert(&state);
+    let state = Arc::new(state);
+
+    let messages = handle_who(state, 1, vec![]).await.unwrap();
+
+    let last = messages.last().unwrap();
+    assert_eq!(last.command, "315");
+    assert_eq!(last.params[1], "*");
+}
+
+#[tokio::test]
+async fn test_invisible_users_need_a_common_channel() {
+    let state = ServerState::new();
+    let _alice = add_user(1, "alice").insert(&state);
+    let _bob = add_user(2, "bob").insert(&state);
+    state.connections.get_mut(&2).unwrap().modes = vec!['i', 'B'];
+    let state = Arc::new(state);
+
+    let messages = handle_who(state.clone(), 1, vec!["b*".to_string()]).await.unwrap();
+    assert_eq!(messages.len(), 1);
+
+    // Asking for the nickname directly still finds them
+    let messages = handle_who(state.clone(), 1, vec!["bob".to_string()]).await.unwrap();
+    assert_eq!(messages[0].command, "352");
+    assert_eq!(messages[0].params[6], "HB");
+
+    let channel = crate::state::Channel::new("#test".to_string());
+    channel.add_member(1, false);
+    channel.add_member(2, true);
+    state.channels.insert("#test".to_string(), channel);
+    let messages = handle_who(state.clone(), 1, vec!["b*".to_string()]).await.unwrap();
+    assert_eq!(messages[0].params[5], "bob");
+    let messages = handle_who(state.clone(), 1, vec!["#test".to_string()]).await.unwrap();
+    assert!(messages.iter().any(|message| message.params.get(6).map(String::as_str) == Some("H@B")));
+}
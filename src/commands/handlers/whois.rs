@@ -1,14 +1,15 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::protocol::{Message, Reply};
+use crate::protocol::{Command, Message, Reply};
 use crate::state::ServerState;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
 
 pub async fn handle_whois(
-    server_state: Arc<RwLock<ServerState>>,
+    server_state: Arc<ServerState>,
     connection_id: u64,
     params: Vec<String>,
 ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
-    let state = server_state.read().await;
+    let state = &*server_state;
     let mut messages = Vec::new();
     
     // Get the requesting connection
@@ -40,6 +41,30 @@ pub async fn handle_whois(
                     server: state.server_name.clone(),
                     info: "IronChat IRC Server".to_string(),
                 }));
+                
+                if let Some(away) = &target_conn.away {
+                    messages.push(whois_line(state, requester_nick, target_nick, "301", away));
+                }
+                if target_conn.modes.contains(&'o') {
+                    messages.push(whois_line(state, requester_nick, target_nick, "313", "is an IRC operator"));
+                }
+                if target_conn.modes.contains(&'B') {
+                    messages.push(whois_line(state, requester_nick, target_nick, "335", "is a bot"));
+                }
+                if target_conn.modes.contains(&'Z') {
+                    messages.push(whois_line(state, requester_nick, target_nick, "671", "is using a secure connection"));
+                }
+                
+                // Certificate fingerprint (276), only shown to the user themselves
+                if let Some(certfp) = target_conn.certfp.as_ref().filter(|_| target_id == connection_id) {
+                    messages.push(Message::new("276")
+                        .with_prefix(state.server_name.clone())
+                        .with_params(vec![
+                            requester_nick.to_string(),
+                            target_nick.clone(),
+                            format!("has client certificate fingerprint {}", certfp),
+                        ]));
+                }
             } else {
                 // User not found
                 messages.push(Message::from(Reply::NoSuchNick {
@@ -64,3 +89,32 @@ pub async fn handle_whois(
     
     Ok(messages)
 }
+
+/// A WHOIS numeric carrying only a description
+fn whois_line(state: &ServerState, requester_nick: &str, target_nick: &str, numeric: &str, text: &str) -> Message {
+    Message::new(numeric)
+        .with_prefix(state.server_name.clone())
+        .with_params(vec![requester_nick.to_string(), target_nick.to_string(), text.to_string()])
+}
+
+/// WHOIS [server] <nick>
+pub struct Whois;
+
+#[async_trait]
+impl CommandHandler for Whois {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("WHOIS").min_params(1).penalty(2)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Whois(targets) = command else {
+            return Ok(Vec::new());
+        };
+        handle_whois(server_state, ctx.connection_id, targets).await
+    }
+}
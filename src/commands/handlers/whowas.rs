@@ -0,0 +1,99 @@
+use std::sync::Arc;
+use crate::protocol::{Command, Message};
+use crate::state::ServerState;
+use async_trait::async_trait;
+use crate::commands::{CommandContext, CommandHandler, CommandSpec, HandlerResult};
+
+/// WHOWAS <nick> [count]
+///
+/// Lists past identities behind a nickname, newest first. A count of zero
+/// or less lists everything kept. IRC operators also see the IP address.
+pub async fn handle_whowas(
+    server_state: Arc<ServerState>,
+    connection_id: u64,
+    target: String,
+    count: Option<i32>,
+) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+    let state = &*server_state;
+    let server_name = state.server_name.clone();
+    
+    let connection = state.connections.get(&connection_id)
+        .ok_or("Connection not found")?
+        .clone();
+    let nick = connection.nickname.clone()
+        .ok_or("No nickname set")?;
+    let is_oper = connection.modes.contains(&'o');
+    
+    let count = count.and_then(|count| usize::try_from(count).ok()).filter(|&count| count > 0);
+    let entries = state.whowas.lookup(&target, count);
+    
+    let mut responses = Vec::new();
+    if entries.is_empty() {
+        responses.push(Message::new("406")
+            .with_prefix(server_name.clone())
+            .with_params(vec![nick.clone(), target.clone(), "There was no such nickname".to_string()]));
+    }
+    
+    for entry in entries {
+        responses.push(Message::new("314")
+            .with_prefix(server_name.clone())
+            .with_params(vec![
+                nick.clone(),
+                entry.nick.clone(),
+                entry.username,
+                entry.hostname,
+                "*".to_string(),
+                entry.realname,
+            ]));
+        if let Some(account) = entry.account {
+            responses.push(Message::new("330")
+                .with_prefix(server_name.clone())
+                .with_params(vec![nick.clone(), entry.nick.clone(), account, "was logged in as".to_string()]));
+        }
+        if is_oper {
+            responses.push(Message::new("338")
+                .with_prefix(server_name.clone())
+                .with_params(vec![nick.clone(), entry.nick.clone(), entry.ip.to_string(), "was connecting from".to_string()]));
+        }
+        responses.push(Message::new("312")
+            .with_prefix(server_name.clone())
+            .with_params(vec![
+                nick.clone(),
+                entry.nick,
+                entry.server,
+                entry.signoff.format("%a %b %e %H:%M:%S %Y").to_string(),
+            ]));
+    }
+    
+    responses.push(Message::new("369")
+        .with_prefix(server_name)
+        .with_params(vec![nick, target, "End of WHOWAS".to_string()]));
+    
+    Ok(responses)
+}
+
+/// WHOWAS <nick> [count]
+pub struct Whowas;
+
+#[async_trait]
+impl CommandHandler for Whowas {
+    fn spec(&self) -> CommandSpec {
+        CommandSpec::new("WHOWAS").min_params(1).penalty(2)
+    }
+
+    async fn handle(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        command: Command,
+    ) -> HandlerResult {
+        let Command::Whowas(nick, count) = command else {
+            return Ok(Vec::new());
+        };
+        handle_whowas(server_state, ctx.connection_id, nick, count).await
+    }
+}
+
+#[cfg(test)]
+#[path = "whowas_test.rs"]
+mod tests;
@@ -0,0 +1,40 @@
+use super::*;
+use crate::commands::handlers::test_support::add_user;
+use crate::state::Connection;
+
+fn connection(id: u64, nick: &str) -> Connection {
+    add_user(id, nick).addr("192.0.2.7:40000").build().0
+}
+
+#[tokio::test]
+async fn test_whowas_lists_past_identities() {
+    let state = ServerState::new();
+    state.connections.insert(1, connection(1, "alice"));
+    let mut evader = connection(2, "mallory");
+    evader.account = Some("mallory".to_string());
+    state.record_whowas(&evader);
+    evader.username = Some("m2".to_string());
+    state.record_whowas(&evader);
+    let state = Arc::new(state);
+
+    let messages = handle_whowas(state.clone(), 1, "Mallory".to_string(), Some(1)).await.unwrap();
+    let commands: Vec<_> = messages.iter().map(|message| message.command.as_str()).collect();
+    assert_eq!(commands, ["314", "330", "312", "369"]);
+    assert_eq!(messages[0].params[1..4], ["mallory".to_string(), "m2".to_string(), "192.0.2.7".to_string()]);
+
+    // Operators also see where the client connected from
+    state.connections.get_mut(&1).unwrap().modes.push('o');
+    let messages = handle_whowas(state.clone(), 1, "mallory".to_string(), Some(0)).await.unwrap();
+    assert_eq!(messages.iter().filter(|message| message.command == "338").count(), 2);
+}
+
+#[tokio::test]
+async fn test_whowas_unknown_nick() {
+    let state = ServerState::new();
+    state.connections.insert(1, connection(1, "alice"));
+    let state = Arc::new(state);
+
+    let messages = handle_whowas(state, 1, "nobody".to_string(), None).await.unwrap();
+    assert_eq!(messages[0].command, "406");
+    assert_eq!(messages[1].command, "369");
+}
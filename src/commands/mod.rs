@@ -1,105 +1,328 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
-use tokio::sync::RwLock;
-use tracing::{debug, info};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use tracing::debug;
 
+use crate::history::HistoryItem;
 use crate::protocol::{Command, Message, Reply};
 use crate::state::ServerState;
 
 pub mod handlers;
 pub mod standard_replies;
 
-pub struct CommandProcessor {
-    server_state: Arc<RwLock<ServerState>>,
+pub type HandlerResult = Result<Vec<Message>, Box<dyn std::error::Error>>;
+
+/// When a command may be used relative to connection registration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registration {
+    Required,
+    /// Usable before registration as well (REGISTER, VERIFY)
+    Optional,
+    /// Usable before registration once the capability is enabled
+    OptionalWithCap(&'static str),
+}
+
+/// Per-command metadata checked by the registry before the handler runs
+#[derive(Debug, Clone, Copy)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub registration: Registration,
+    /// Fewer parameters than this get ERR_NEEDMOREPARAMS (461)
+    pub min_params: usize,
+    /// Only IRC operators may use it (481)
+    pub oper_only: bool,
+    /// The command is unknown (421) unless this capability is enabled
+    pub capability: Option<&'static str>,
+    /// Entries the handler records on its context go to chat history
+    pub history: bool,
+    /// Penalty units charged against the client's flood allowance, unless
+    /// `limits.command_penalties` says otherwise
+    pub penalty: usize,
+}
+
+impl CommandSpec {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            registration: Registration::Required,
+            min_params: 0,
+            oper_only: false,
+            capability: None,
+            history: false,
+            penalty: 1,
+        }
+    }
+
+    pub const fn min_params(mut self, min_params: usize) -> Self {
+        self.min_params = min_params;
+        self
+    }
+
+    pub const fn registration(mut self, registration: Registration) -> Self {
+        self.registration = registration;
+        self
+    }
+
+    pub const fn oper_only(mut self) -> Self {
+        self.oper_only = true;
+        self
+    }
+
+    pub const fn capability(mut self, capability: &'static str) -> Self {
+        self.capability = Some(capability);
+        self
+    }
+
+    pub const fn history(mut self) -> Self {
+        self.history = true;
+        self
+    }
+
+    pub const fn penalty(mut self, penalty: usize) -> Self {
+        self.penalty = penalty;
+        self
+    }
 }
 
-impl CommandProcessor {
-    pub fn new(server_state: Arc<RwLock<ServerState>>) -> Self {
-        Self { server_state }
+/// The client a command came from
+#[derive(Debug, Default)]
+pub struct CommandContext {
+    pub connection_id: u64,
+    pub registered: bool,
+    pub capabilities: Vec<String>,
+    /// Client tags sent with the command
+    pub tags: HashMap<String, String>,
+    /// Entries waiting for the registry to store in chat history
+    history: Mutex<Vec<HistoryItem>>,
+}
+
+impl CommandContext {
+    pub fn new(connection_id: u64) -> Self {
+        Self { connection_id, ..Default::default() }
+    }
+
+    /// Queue `item` for chat history. The registry stores it once the
+    /// handler succeeds, if the command's spec has `history`.
+    pub fn record(&self, item: HistoryItem) {
+        self.history.lock().unwrap().push(item);
+    }
+
+    fn take_history(&self) -> Vec<HistoryItem> {
+        std::mem::take(&mut *self.history.lock().unwrap())
     }
-    
-    pub async fn process(
+}
+
+#[async_trait]
+pub trait CommandHandler: Send + Sync {
+    fn spec(&self) -> CommandSpec;
+
+    /// Run the command. `command` is the parse of a message that passed
+    /// every check in `spec`.
+    async fn handle(
         &self,
-        connection_id: u64,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
         command: Command,
-    ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
-        debug!("Processing command for connection {}: {:?}", connection_id, command);
-        
-        match command {
-            Command::Nick(new_nick) => {
-                handlers::nick::handle_nick(self.server_state.clone(), connection_id, new_nick).await
-            }
-            Command::Join(channels, keys) => {
-                handlers::join::handle_join(self.server_state.clone(), connection_id, channels, keys).await
-            }
-            Command::Part(channels, message) => {
-                handlers::part::handle_part(self.server_state.clone(), connection_id, channels, message).await
-            }
-            Command::Privmsg { target, message } => {
-                handlers::privmsg::handle_privmsg(self.server_state.clone(), connection_id, target, message).await
-            }
-            Command::Notice { target, message } => {
-                handlers::notice::handle_notice(self.server_state.clone(), connection_id, vec![target, message]).await
-            }
-            Command::Who(mask) => {
-                let params = mask.map_or(vec![], |m| vec![m]);
-                handlers::who::handle_who(self.server_state.clone(), connection_id, params).await
-            }
-            Command::Whois(targets) => {
-                handlers::whois::handle_whois(self.server_state.clone(), connection_id, targets).await
-            }
-            Command::Topic { channel, topic } => {
-                let mut params = vec![channel];
-                if let Some(t) = topic {
-                    params.push(t);
-                }
-                handlers::topic::handle_topic(self.server_state.clone(), connection_id, params).await
-            }
-            Command::Mode { target, modes, params } => {
-                let mut all_params = vec![target];
-                if let Some(m) = modes {
-                    all_params.push(m);
-                }
-                all_params.extend(params);
-                handlers::mode::handle_mode(self.server_state.clone(), connection_id, all_params).await
-            }
-            Command::Kick { channel, user, reason } => {
-                let mut params = vec![channel, user];
-                if let Some(r) = reason {
-                    params.push(r);
-                }
-                handlers::kick::handle_kick(self.server_state.clone(), connection_id, params).await
-            }
-            Command::List(channels) => {
-                let params = channels.unwrap_or_default();
-                handlers::list::handle_list(self.server_state.clone(), connection_id, params).await
-            }
-            Command::Names(channels) => {
-                handlers::names::handle_names(self.server_state.clone(), connection_id, channels).await
-            }
-            Command::Motd(server) => {
-                let params = server.map_or(vec![], |s| vec![s]);
-                handlers::motd::handle_motd(self.server_state.clone(), connection_id, params).await
-            }
-            Command::Oper { name, password } => {
-                handlers::oper::handle_oper(self.server_state.clone(), connection_id, vec![name, password]).await
-            }
-            Command::ChatHistory { subcommand, target, params } => {
-                let mut full_params = vec![subcommand, target];
-                full_params.extend(params);
-                handlers::chathistory::handle_chathistory(self.server_state.clone(), connection_id, full_params).await
-            }
-            _ => {
-                let state = self.server_state.read().await;
-                let nick = state.connections.get(&connection_id)
-                    .and_then(|c| c.nickname.clone())
-                    .unwrap_or_else(|| "*".to_string());
-                
-                Ok(vec![Reply::UnknownCommand {
-                    nick,
-                    command: format!("{:?}", command),
-                }.to_message(&state.server_name)])
-            }
+    ) -> HandlerResult;
+}
+
+/// Every command handled outside the connection actor, by name
+pub static COMMANDS: Lazy<CommandRegistry> = Lazy::new(CommandRegistry::default);
+
+pub struct CommandRegistry {
+    handlers: HashMap<&'static str, Box<dyn CommandHandler>>,
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        use handlers::*;
+
+        let mut registry = Self { handlers: HashMap::new() };
+        let all: Vec<Box<dyn CommandHandler>> = vec![
+            Box::new(join::Join),
+            Box::new(part::Part),
+            Box::new(privmsg::Privmsg),
+            Box::new(notice::Notice),
+            Box::new(tagmsg::TagMsg),
+            Box::new(chathistory::ChatHistory),
+            Box::new(who::Who),
+            Box::new(whois::Whois),
+            Box::new(whowas::Whowas),
+            Box::new(query::Query),
+            Box::new(topic::Topic),
+            Box::new(mode::Mode),
+            Box::new(kick::Kick),
+            Box::new(invite::Invite),
+            Box::new(list::List),
+            Box::new(names::Names),
+            Box::new(motd::Motd),
+            Box::new(oper::Oper),
+            Box::new(wallops::Wallops),
+            Box::new(kline::Kline),
+            Box::new(kline::Unkline),
+            Box::new(kline::Dline),
+            Box::new(kline::Undline),
+            Box::new(stats::Stats),
+            Box::new(away::Away),
+            Box::new(monitor::Monitor),
+            Box::new(ison::Ison),
+            Box::new(ison::Userhost),
+            Box::new(certfp::Certfp),
+            Box::new(chanreg::ChanReg),
+            Box::new(register::Register),
+            Box::new(register::Verify),
+            Box::new(register::Unregister),
+        ];
+        for handler in all {
+            registry.register(handler);
+        }
+        registry
+    }
+}
+
+impl CommandRegistry {
+    pub fn register(&mut self, handler: Box<dyn CommandHandler>) {
+        self.handlers.insert(handler.spec().name, handler);
+    }
+
+    /// Penalty units `command` costs, with `overrides` taking precedence
+    /// over the command's spec. Commands the registry doesn't handle cost
+    /// 1, and every command costs at least 1.
+    pub fn penalty(&self, command: &str, overrides: &HashMap<String, usize>) -> usize {
+        let command = command.to_uppercase();
+        overrides.get(&command).copied()
+            .or_else(|| self.handlers.get(command.as_str()).map(|handler| handler.spec().penalty))
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    /// Put client events in chat history. Dispatch stores what handlers
+    /// record here, as does the connection actor for QUIT.
+    pub fn store_history(&self, server_state: &ServerState, items: Vec<HistoryItem>) {
+        for item in items {
+            server_state.history.store_message(item);
         }
     }
-}
\ No newline at end of file
+
+    /// Check `message` against its command's spec and run the handler,
+    /// returning the replies for the client.
+    pub async fn dispatch(
+        &self,
+        server_state: Arc<ServerState>,
+        ctx: &CommandContext,
+        message: Message,
+    ) -> HandlerResult {
+        let name = message.command.to_uppercase();
+        debug!("Dispatching {} for connection {}", name, ctx.connection_id);
+
+        let (nick, is_oper) = server_state.connections.get(&ctx.connection_id)
+            .map(|conn| (conn.nickname.clone().unwrap_or_else(|| "*".to_string()), conn.modes.contains(&'o')))
+            .unwrap_or_else(|| ("*".to_string(), false));
+        let server_name = server_state.server_name.clone();
+
+        let handler = self.handlers.get(name.as_str());
+        let has_cap = |cap: &str| ctx.capabilities.iter().any(|enabled| enabled == cap);
+
+        let allowed = match handler.map(|handler| handler.spec().registration) {
+            _ if ctx.registered => true,
+            Some(Registration::Optional) => true,
+            Some(Registration::OptionalWithCap(cap)) => has_cap(cap),
+            _ => false,
+        };
+        if !allowed {
+            return Ok(vec![Reply::NotRegistered { nick }.to_message(&server_name)]);
+        }
+
+        let Some(handler) = handler else {
+            return Ok(vec![Reply::UnknownCommand { nick, command: name }.to_message(&server_name)]);
+        };
+        let spec = handler.spec();
+
+        if spec.capability.is_some_and(|cap| !has_cap(cap)) {
+            return Ok(vec![Reply::UnknownCommand { nick, command: name }.to_message(&server_name)]);
+        }
+        if spec.oper_only && !is_oper {
+            return Ok(vec![Message::new("481")
+                .with_prefix(server_name)
+                .with_params(vec![nick, "Permission Denied- You're not an IRC operator".to_string()])]);
+        }
+        if message.params.len() < spec.min_params {
+            return Ok(vec![Reply::NeedMoreParams { nick, command: name }.to_message(&server_name)]);
+        }
+
+        let command = Command::parse(&message.command, message.params);
+        let responses = handler.handle(server_state.clone(), ctx, command).await?;
+        if spec.history {
+            self.store_history(&server_state, ctx.take_history());
+        }
+        Ok(responses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Connection;
+    use crate::state::sendq;
+
+    fn setup(registered: bool) -> (Arc<ServerState>, CommandContext) {
+        let state = ServerState::new();
+        let (tx, _rx) = sendq::channel(64 * 1024);
+        let mut conn = Connection::new(1, "127.0.0.1:40000".parse().unwrap(), tx);
+        conn.nickname = Some("alice".to_string());
+        conn.registered = registered;
+        state.connections.insert(1, conn);
+        let ctx = CommandContext { connection_id: 1, registered, ..Default::default() };
+        (Arc::new(state), ctx)
+    }
+
+    fn message(line: &str) -> Message {
+        let mut words = line.split(' ').map(str::to_string);
+        Message::new(words.next().unwrap()).with_params(words.collect())
+    }
+
+    async fn reply(state: &Arc<ServerState>, ctx: &CommandContext, line: &str) -> String {
+        let responses = COMMANDS.dispatch(state.clone(), ctx, message(line)).await.unwrap();
+        responses.first().map(|response| response.command.clone()).unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn test_specs_are_enforced() {
+        let (state, mut ctx) = setup(true);
+
+        assert_eq!(reply(&state, &ctx, "FROB").await, "421");
+        assert_eq!(reply(&state, &ctx, "KICK #test").await, "461");
+        assert_eq!(reply(&state, &ctx, "WALLOPS hi").await, "481");
+        assert_eq!(reply(&state, &ctx, "TAGMSG #test").await, "421");
+
+        ctx.capabilities.push("message-tags".to_string());
+        assert_ne!(reply(&state, &ctx, "TAGMSG #test").await, "421");
+    }
+
+    #[test]
+    fn test_command_penalties() {
+        let mut overrides = HashMap::new();
+        overrides.insert("PRIVMSG".to_string(), 2);
+        overrides.insert("PONG".to_string(), 0);
+
+        assert_eq!(COMMANDS.penalty("list", &overrides), 10);
+        assert_eq!(COMMANDS.penalty("REGISTER", &overrides), 10);
+        assert_eq!(COMMANDS.penalty("PRIVMSG", &overrides), 2);
+        assert_eq!(COMMANDS.penalty("PONG", &overrides), 1);
+        assert_eq!(COMMANDS.penalty("NOTICE", &overrides), 1);
+    }
+
+    #[tokio::test]
+    async fn test_registration_gates() {
+        let (state, mut ctx) = setup(false);
+
+        assert_eq!(reply(&state, &ctx, "JOIN #test").await, "451");
+        assert_eq!(reply(&state, &ctx, "FROB").await, "451");
+        assert_eq!(reply(&state, &ctx, "AWAY :brb").await, "451");
+
+        ctx.capabilities.push("draft/pre-away".to_string());
+        assert_eq!(reply(&state, &ctx, "AWAY brb").await, "306");
+    }
+}
@@ -1,7 +1,229 @@
-// Migration support for database schema management
+// Versioned schema migrations. Every migration is applied once, in version
+// order, inside a transaction and recorded in `schema_migrations` along with a
+// checksum of the SQL that ran, so edits to already-shipped migrations are
+// caught at startup instead of silently diverging between deployments.
 
-pub fn create_schema_sql() -> &'static str {
-    r#"
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+
+use super::{Database, DatabaseError};
+
+/// SQL flavour of the backing database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Postgres,
+    Sqlite,
+}
+
+impl Dialect {
+    /// sqlx maps `DateTime<Utc>` to TIMESTAMPTZ on Postgres
+    fn timestamp_type(self) -> &'static str {
+        match self {
+            Dialect::Postgres => "TIMESTAMPTZ",
+            Dialect::Sqlite => "TIMESTAMP",
+        }
+    }
+
+    fn json_type(self) -> &'static str {
+        match self {
+            Dialect::Postgres => "JSONB",
+            Dialect::Sqlite => "JSON",
+        }
+    }
+
+    /// Substitute the `{timestamp}` and `{json}` placeholders used by migrations
+    fn render(self, sql: &str) -> String {
+        sql.replace("{timestamp}", self.timestamp_type())
+            .replace("{json}", self.json_type())
+    }
+}
+
+/// A single schema change with its inverse
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    up: &'static str,
+    down: &'static str,
+}
+
+impl Migration {
+    pub fn up_sql(&self, dialect: Dialect) -> String {
+        dialect.render(self.up)
+    }
+
+    pub fn down_sql(&self, dialect: Dialect) -> String {
+        dialect.render(self.down)
+    }
+
+    /// Hex SHA-256 of the rendered up script
+    pub fn checksum(&self, dialect: Dialect) -> String {
+        hex::encode(Sha256::digest(self.up_sql(dialect).as_bytes()))
+    }
+}
+
+/// All known migrations, in the order they must be applied. Never edit a
+/// migration that has shipped; add a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        up: INITIAL_SCHEMA_UP,
+        down: INITIAL_SCHEMA_DOWN,
+    },
+    Migration {
+        version: 2,
+        name: "accounts",
+        up: ACCOUNTS_UP,
+        down: ACCOUNTS_DOWN,
+    },
+    Migration {
+        version: 3,
+        name: "channel_list_modes",
+        up: CHANNEL_LIST_MODES_UP,
+        down: CHANNEL_LIST_MODES_DOWN,
+    },
+    Migration {
+        version: 4,
+        name: "server_bans",
+        up: SERVER_BANS_UP,
+        down: SERVER_BANS_DOWN,
+    },
+    Migration {
+        version: 5,
+        name: "operators",
+        up: OPERATORS_UP,
+        down: OPERATORS_DOWN,
+    },
+];
+
+const CREATE_MIGRATIONS_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS schema_migrations (
+        version BIGINT PRIMARY KEY,
+        name TEXT NOT NULL,
+        checksum TEXT NOT NULL,
+        applied_at {timestamp} NOT NULL
+    );
+    "#;
+
+/// An entry in `schema_migrations`
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub checksum: String,
+}
+
+/// Migrations recorded as applied, lowest version first
+pub async fn applied_migrations(db: &Database) -> Result<Vec<AppliedMigration>, DatabaseError> {
+    let create = db.dialect().render(CREATE_MIGRATIONS_TABLE);
+    let applied = with_pool!(db, |pool| {
+        async {
+            sqlx::raw_sql(&create).execute(pool).await?;
+            sqlx::query_as::<_, AppliedMigration>(
+                "SELECT version, name, checksum FROM schema_migrations ORDER BY version",
+            )
+            .fetch_all(pool)
+            .await
+        }
+        .await
+    })?;
+
+    Ok(applied)
+}
+
+/// Check the recorded migrations against the ones compiled in
+fn verify(applied: &[AppliedMigration], dialect: Dialect) -> Result<(), DatabaseError> {
+    for record in applied {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|m| m.version == record.version)
+            .ok_or(DatabaseError::UnknownMigration(record.version))?;
+
+        if migration.checksum(dialect) != record.checksum {
+            return Err(DatabaseError::MigrationChecksumMismatch(record.version));
+        }
+    }
+
+    Ok(())
+}
+
+/// Apply every pending migration and return the versions that ran
+pub async fn migrate(db: &Database) -> Result<Vec<i64>, DatabaseError> {
+    let dialect = db.dialect();
+    let applied = applied_migrations(db).await?;
+    verify(&applied, dialect)?;
+
+    let mut ran = Vec::new();
+    for migration in MIGRATIONS {
+        if applied.iter().any(|record| record.version == migration.version) {
+            continue;
+        }
+
+        let up = migration.up_sql(dialect);
+        let checksum = migration.checksum(dialect);
+        with_pool!(db, |pool| {
+            async {
+                let mut tx = pool.begin().await?;
+                sqlx::raw_sql(&up).execute(&mut *tx).await?;
+                sqlx::query(
+                    "INSERT INTO schema_migrations (version, name, checksum, applied_at) VALUES ($1, $2, $3, $4)",
+                )
+                .bind(migration.version)
+                .bind(migration.name)
+                .bind(&checksum)
+                .bind(Utc::now())
+                .execute(&mut *tx)
+                .await?;
+                tx.commit().await
+            }
+            .await
+        })?;
+
+        tracing::info!("Applied migration {} ({})", migration.version, migration.name);
+        ran.push(migration.version);
+    }
+
+    Ok(ran)
+}
+
+/// Revert applied migrations newer than `target`, newest first, and return
+/// the versions that were rolled back. A target of 0 empties the schema.
+pub async fn rollback(db: &Database, target: i64) -> Result<Vec<i64>, DatabaseError> {
+    let dialect = db.dialect();
+    let applied = applied_migrations(db).await?;
+    verify(&applied, dialect)?;
+
+    let mut reverted = Vec::new();
+    for record in applied.iter().rev().filter(|record| record.version > target) {
+        // verify() guarantees every recorded version is known
+        let migration = MIGRATIONS
+            .iter()
+            .find(|m| m.version == record.version)
+            .ok_or(DatabaseError::UnknownMigration(record.version))?;
+
+        let down = migration.down_sql(dialect);
+        with_pool!(db, |pool| {
+            async {
+                let mut tx = pool.begin().await?;
+                sqlx::raw_sql(&down).execute(&mut *tx).await?;
+                sqlx::query("DELETE FROM schema_migrations WHERE version = $1")
+                    .bind(migration.version)
+                    .execute(&mut *tx)
+                    .await?;
+                tx.commit().await
+            }
+            .await
+        })?;
+
+        tracing::info!("Rolled back migration {} ({})", migration.version, migration.name);
+        reverted.push(migration.version);
+    }
+
+    Ok(reverted)
+}
+
+const INITIAL_SCHEMA_UP: &str = r#"
     -- Users table
     CREATE TABLE IF NOT EXISTS users (
         id TEXT PRIMARY KEY,
@@ -15,10 +237,10 @@ pub fn create_schema_sql() -> &'static str {
         is_services BOOLEAN NOT NULL DEFAULT FALSE,
         modes TEXT NOT NULL DEFAULT '',
         away_message TEXT,
-        created_at TIMESTAMP NOT NULL,
-        last_seen TIMESTAMP NOT NULL,
+        created_at {timestamp} NOT NULL,
+        last_seen {timestamp} NOT NULL,
         vhost TEXT,
-        metadata JSON
+        metadata {json}
     );
     
     -- Channels table
@@ -26,18 +248,18 @@ pub fn create_schema_sql() -> &'static str {
         name TEXT PRIMARY KEY,
         topic TEXT,
         topic_set_by TEXT,
-        topic_set_at TIMESTAMP,
+        topic_set_at {timestamp},
         modes TEXT NOT NULL DEFAULT '',
         key TEXT,
-        limit INTEGER,
-        created_at TIMESTAMP NOT NULL,
+        "limit" INTEGER,
+        created_at {timestamp} NOT NULL,
         founder TEXT,
         successor TEXT,
         description TEXT,
         url TEXT,
         email TEXT,
         entry_message TEXT,
-        metadata JSON
+        metadata {json}
     );
     
     -- Channel members table
@@ -45,8 +267,8 @@ pub fn create_schema_sql() -> &'static str {
         channel_name TEXT NOT NULL,
         user_id TEXT NOT NULL,
         modes TEXT NOT NULL DEFAULT '',
-        joined_at TIMESTAMP NOT NULL,
-        last_active TIMESTAMP NOT NULL,
+        joined_at {timestamp} NOT NULL,
+        last_active {timestamp} NOT NULL,
         PRIMARY KEY (channel_name, user_id),
         FOREIGN KEY (channel_name) REFERENCES channels(name) ON DELETE CASCADE,
         FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
@@ -57,9 +279,9 @@ pub fn create_schema_sql() -> &'static str {
         channel_name TEXT NOT NULL,
         mask TEXT NOT NULL,
         set_by TEXT NOT NULL,
-        set_at TIMESTAMP NOT NULL,
+        set_at {timestamp} NOT NULL,
         reason TEXT,
-        expires_at TIMESTAMP,
+        expires_at {timestamp},
         PRIMARY KEY (channel_name, mask),
         FOREIGN KEY (channel_name) REFERENCES channels(name) ON DELETE CASCADE
     );
@@ -88,18 +310,18 @@ pub fn create_schema_sql() -> &'static str {
         flood_messages INTEGER NOT NULL DEFAULT 10,
         flood_interval INTEGER NOT NULL DEFAULT 1,
         throttle_duration INTEGER NOT NULL DEFAULT 60,
-        metadata JSON
+        metadata {json}
     );
     
     -- Message logs (optional, for history)
     CREATE TABLE IF NOT EXISTS message_logs (
         id TEXT PRIMARY KEY,
-        timestamp TIMESTAMP NOT NULL,
+        timestamp {timestamp} NOT NULL,
         sender_id TEXT NOT NULL,
         target TEXT NOT NULL,
         message_type TEXT NOT NULL,
         content TEXT NOT NULL,
-        tags JSON,
+        tags {json},
         FOREIGN KEY (sender_id) REFERENCES users(id) ON DELETE CASCADE
     );
     
@@ -110,8 +332,8 @@ pub fn create_schema_sql() -> &'static str {
         password_hash TEXT NOT NULL,
         host_mask TEXT,
         privileges TEXT NOT NULL,
-        created_at TIMESTAMP NOT NULL,
-        last_used TIMESTAMP
+        created_at {timestamp} NOT NULL,
+        last_used {timestamp}
     );
     
     -- Indexes for performance
@@ -120,5 +342,170 @@ pub fn create_schema_sql() -> &'static str {
     CREATE INDEX IF NOT EXISTS idx_channel_members_user ON channel_members(user_id);
     CREATE INDEX IF NOT EXISTS idx_message_logs_timestamp ON message_logs(timestamp);
     CREATE INDEX IF NOT EXISTS idx_message_logs_target ON message_logs(target);
-    "#
-}
\ No newline at end of file
+    "#;
+
+const INITIAL_SCHEMA_DOWN: &str = r#"
+    DROP INDEX IF EXISTS idx_message_logs_target;
+    DROP INDEX IF EXISTS idx_message_logs_timestamp;
+    DROP INDEX IF EXISTS idx_channel_members_user;
+    DROP INDEX IF EXISTS idx_users_account;
+    DROP INDEX IF EXISTS idx_users_nickname;
+    DROP TABLE IF EXISTS operator_credentials;
+    DROP TABLE IF EXISTS message_logs;
+    DROP TABLE IF EXISTS server_config;
+    DROP TABLE IF EXISTS bans;
+    DROP TABLE IF EXISTS channel_members;
+    DROP TABLE IF EXISTS channels;
+    DROP TABLE IF EXISTS users;
+    "#;
+
+// SASL accounts. Binary SCRAM material is stored hex-encoded so the column
+// types stay the same on both dialects.
+const ACCOUNTS_UP: &str = r#"
+    CREATE TABLE accounts (
+        name TEXT PRIMARY KEY,
+        password_hash TEXT NOT NULL,
+        scram_salt TEXT NOT NULL,
+        scram_iterations INTEGER NOT NULL,
+        scram_stored_key TEXT NOT NULL,
+        scram_server_key TEXT NOT NULL,
+        created_at {timestamp} NOT NULL
+    );
+
+    CREATE TABLE account_certfps (
+        certfp TEXT PRIMARY KEY,
+        account_name TEXT NOT NULL,
+        FOREIGN KEY (account_name) REFERENCES accounts(name) ON DELETE CASCADE
+    );
+
+    CREATE INDEX idx_account_certfps_account ON account_certfps(account_name);
+    "#;
+
+const ACCOUNTS_DOWN: &str = r#"
+    DROP INDEX IF EXISTS idx_account_certfps_account;
+    DROP TABLE IF EXISTS account_certfps;
+    DROP TABLE IF EXISTS accounts;
+    "#;
+
+// The bans table also holds ban exceptions (+e) and invite exceptions (+I),
+// told apart by `mode`. The table is rebuilt because the primary key changes.
+const CHANNEL_LIST_MODES_UP: &str = r#"
+    CREATE TABLE bans_new (
+        channel_name TEXT NOT NULL,
+        mode TEXT NOT NULL DEFAULT 'b',
+        mask TEXT NOT NULL,
+        set_by TEXT NOT NULL,
+        set_at {timestamp} NOT NULL,
+        reason TEXT,
+        expires_at {timestamp},
+        PRIMARY KEY (channel_name, mode, mask),
+        FOREIGN KEY (channel_name) REFERENCES channels(name) ON DELETE CASCADE
+    );
+
+    INSERT INTO bans_new (channel_name, mode, mask, set_by, set_at, reason, expires_at)
+        SELECT channel_name, 'b', mask, set_by, set_at, reason, expires_at FROM bans;
+    DROP TABLE bans;
+    ALTER TABLE bans_new RENAME TO bans;
+    "#;
+
+const CHANNEL_LIST_MODES_DOWN: &str = r#"
+    CREATE TABLE bans_old (
+        channel_name TEXT NOT NULL,
+        mask TEXT NOT NULL,
+        set_by TEXT NOT NULL,
+        set_at {timestamp} NOT NULL,
+        reason TEXT,
+        expires_at {timestamp},
+        PRIMARY KEY (channel_name, mask),
+        FOREIGN KEY (channel_name) REFERENCES channels(name) ON DELETE CASCADE
+    );
+
+    INSERT INTO bans_old (channel_name, mask, set_by, set_at, reason, expires_at)
+        SELECT channel_name, mask, set_by, set_at, reason, expires_at FROM bans WHERE mode = 'b';
+    DROP TABLE bans;
+    ALTER TABLE bans_old RENAME TO bans;
+    "#;
+
+// K-lines and D-lines, told apart by `kind`
+const SERVER_BANS_UP: &str = r#"
+    CREATE TABLE server_bans (
+        kind TEXT NOT NULL,
+        mask TEXT NOT NULL,
+        set_by TEXT NOT NULL,
+        set_at {timestamp} NOT NULL,
+        reason TEXT NOT NULL,
+        expires_at {timestamp},
+        PRIMARY KEY (kind, mask)
+    );
+    "#;
+
+const SERVER_BANS_DOWN: &str = r#"
+    DROP TABLE IF EXISTS server_bans;
+    "#;
+
+// Operators may be tied to a client certificate, and every OPER attempt,
+// good or bad, is kept in oper_log
+const OPERATORS_UP: &str = r#"
+    ALTER TABLE operator_credentials ADD COLUMN certfp TEXT;
+
+    CREATE TABLE oper_log (
+        name TEXT NOT NULL,
+        nick TEXT NOT NULL,
+        mask TEXT NOT NULL,
+        succeeded BOOLEAN NOT NULL,
+        detail TEXT NOT NULL,
+        at {timestamp} NOT NULL
+    );
+
+    CREATE INDEX idx_oper_log_at ON oper_log(at);
+    "#;
+
+const OPERATORS_DOWN: &str = r#"
+    DROP INDEX IF EXISTS idx_oper_log_at;
+    DROP TABLE IF EXISTS oper_log;
+    ALTER TABLE operator_credentials DROP COLUMN certfp;
+    "#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+
+    #[test]
+    fn test_versions_are_ordered() {
+        assert!(MIGRATIONS.windows(2).all(|pair| pair[0].version < pair[1].version));
+        assert!(MIGRATIONS[0].up_sql(Dialect::Postgres).contains("TIMESTAMPTZ"));
+        assert!(!MIGRATIONS[0].up_sql(Dialect::Sqlite).contains('{'));
+    }
+
+    #[tokio::test]
+    async fn test_migrate_is_idempotent_and_reversible() {
+        let db = test_database().await;
+        let versions: Vec<i64> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert_eq!(applied_migrations(&db).await.unwrap().len(), versions.len());
+        assert!(migrate(&db).await.unwrap().is_empty());
+
+        let mut reverted = versions.clone();
+        reverted.reverse();
+        assert_eq!(db.rollback_migrations(0).await.unwrap(), reverted);
+        assert!(applied_migrations(&db).await.unwrap().is_empty());
+        assert!(db.get_channel("#rust").await.is_err());
+
+        assert_eq!(migrate(&db).await.unwrap(), versions);
+        assert!(db.get_channel("#rust").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_checksum_mismatch_is_rejected() {
+        let db = test_database().await;
+        with_pool!(&db, |pool| {
+            sqlx::query("UPDATE schema_migrations SET checksum = 'tampered' WHERE version = 1")
+                .execute(pool)
+                .await
+                .map(|_| ())
+        })
+        .unwrap();
+
+        assert!(matches!(migrate(&db).await, Err(DatabaseError::MigrationChecksumMismatch(1))));
+    }
+}
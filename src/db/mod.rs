@@ -1,12 +1,39 @@
+use sqlx::postgres::PgPoolOptions;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
 use sqlx::{Pool, Postgres, Sqlite};
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 
+/// Run the same sqlx expression against whichever pool backs `$db`. The body
+/// is expanded once per backend, so it only needs to be written once as long
+/// as the SQL is portable (`$N` placeholders work on both). Both expansions
+/// must evaluate to the same type, so map backend-specific results such as
+/// `QueryResult` to `rows_affected()` or `()` inside the body.
+macro_rules! with_pool {
+    ($db:expr, |$pool:ident| $body:expr) => {
+        match $db {
+            $crate::db::Database::Postgres(pool) => {
+                let $pool = pool.as_ref();
+                $body
+            }
+            $crate::db::Database::Sqlite(pool) => {
+                let $pool = pool.as_ref();
+                $body
+            }
+        }
+    };
+}
+
 pub mod models;
 pub mod queries;
 pub mod migrations;
 
-use self::models::{User, Channel, ChannelMember, BanEntry, ServerConfig};
+use self::migrations::Dialect;
+use self::models::{User, Channel, ChannelMember, BanEntry, ServerBan, ServerConfig, OperatorCredential, OperLogEntry};
+use crate::state::account::Account;
+use crate::utils::config::DatabaseSettings;
 
 #[derive(Error, Debug)]
 pub enum DatabaseError {
@@ -24,6 +51,15 @@ pub enum DatabaseError {
     
     #[error("Duplicate entry")]
     DuplicateEntry,
+    
+    #[error("Unsupported database URL '{0}', expected sqlite:// or postgres://")]
+    UnsupportedUrl(String),
+    
+    #[error("Migration {0} has been modified since it was applied")]
+    MigrationChecksumMismatch(i64),
+    
+    #[error("Database has migration {0} applied, which this build does not know about")]
+    UnknownMigration(i64),
 }
 
 #[derive(Clone)]
@@ -33,6 +69,32 @@ pub enum Database {
 }
 
 impl Database {
+    /// Open a pool for `settings.url`, picking the backend from its scheme
+    pub async fn connect(settings: &DatabaseSettings) -> Result<Self, DatabaseError> {
+        let timeout = Duration::from_secs(settings.connection_timeout);
+        let url = settings.url.as_str();
+
+        if url.starts_with("sqlite:") {
+            // A missing database file is created rather than treated as an error
+            let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+            let pool = SqlitePoolOptions::new()
+                .max_connections(settings.max_connections)
+                .acquire_timeout(timeout)
+                .connect_with(options)
+                .await?;
+            Ok(Database::Sqlite(Arc::new(pool)))
+        } else if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            let pool = PgPoolOptions::new()
+                .max_connections(settings.max_connections)
+                .acquire_timeout(timeout)
+                .connect(url)
+                .await?;
+            Ok(Database::Postgres(Arc::new(pool)))
+        } else {
+            Err(DatabaseError::UnsupportedUrl(url.to_string()))
+        }
+    }
+    
     pub async fn connect_postgres(url: &str) -> Result<Self, DatabaseError> {
         let pool = Pool::<Postgres>::connect(url).await?;
         Ok(Database::Postgres(Arc::new(pool)))
@@ -43,10 +105,25 @@ impl Database {
         Ok(Database::Sqlite(Arc::new(pool)))
     }
     
+    pub fn dialect(&self) -> Dialect {
+        match self {
+            Database::Postgres(_) => Dialect::Postgres,
+            Database::Sqlite(_) => Dialect::Sqlite,
+        }
+    }
+    
+    /// Bring the schema up to date, refusing to start if an applied
+    /// migration no longer matches its checksum
     pub async fn run_migrations(&self) -> Result<(), DatabaseError> {
-        // TODO: Implement migrations
+        migrations::migrate(self).await?;
         Ok(())
     }
+
+    /// Revert applied migrations newer than `target`, newest first, and
+    /// return the versions that were rolled back
+    pub async fn rollback_migrations(&self, target: i64) -> Result<Vec<i64>, DatabaseError> {
+        migrations::rollback(self, target).await
+    }
     
     // User operations
     pub async fn create_user(&self, user: &User) -> Result<User, DatabaseError> {
@@ -112,8 +189,8 @@ impl Database {
         queries::bans::add_ban(self, ban).await
     }
     
-    pub async fn remove_ban(&self, channel: &str, mask: &str) -> Result<(), DatabaseError> {
-        queries::bans::remove_ban(self, channel, mask).await
+    pub async fn remove_ban(&self, channel: &str, mode: &str, mask: &str) -> Result<(), DatabaseError> {
+        queries::bans::remove_ban(self, channel, mode, mask).await
     }
     
     pub async fn get_channel_bans(&self, channel: &str) -> Result<Vec<BanEntry>, DatabaseError> {
@@ -124,6 +201,41 @@ impl Database {
         queries::bans::is_banned(self, channel, user_mask).await
     }
     
+    // Server bans
+    pub async fn save_server_ban(&self, ban: &ServerBan) -> Result<(), DatabaseError> {
+        queries::server_bans::save_server_ban(self, ban).await
+    }
+    
+    pub async fn delete_server_ban(&self, kind: &str, mask: &str) -> Result<(), DatabaseError> {
+        queries::server_bans::delete_server_ban(self, kind, mask).await
+    }
+    
+    pub async fn load_server_bans(&self) -> Result<Vec<ServerBan>, DatabaseError> {
+        queries::server_bans::load_server_bans(self).await
+    }
+    
+    // Operators
+    pub async fn load_operators(&self) -> Result<Vec<OperatorCredential>, DatabaseError> {
+        queries::operators::load_operators(self).await
+    }
+    
+    pub async fn log_oper_attempt(&self, entry: &OperLogEntry) -> Result<(), DatabaseError> {
+        queries::operators::log_oper_attempt(self, entry).await
+    }
+    
+    // Accounts
+    pub async fn save_account(&self, account: &Account) -> Result<(), DatabaseError> {
+        queries::accounts::save_account(self, account).await
+    }
+    
+    pub async fn delete_account(&self, name: &str) -> Result<(), DatabaseError> {
+        queries::accounts::delete_account(self, name).await
+    }
+    
+    pub async fn load_accounts(&self) -> Result<Vec<Account>, DatabaseError> {
+        queries::accounts::load_accounts(self).await
+    }
+    
     // Server configuration
     pub async fn get_server_config(&self) -> Result<ServerConfig, DatabaseError> {
         queries::config::get_server_config(self).await
@@ -132,4 +244,51 @@ impl Database {
     pub async fn update_server_config(&self, config: &ServerConfig) -> Result<(), DatabaseError> {
         queries::config::update_server_config(self, config).await
     }
-}
\ No newline at end of file
+}
+
+/// Turn unique-constraint violations into `DuplicateEntry`
+pub(crate) fn map_unique_violation(error: sqlx::Error) -> DatabaseError {
+    match &error {
+        sqlx::Error::Database(db_error) if db_error.is_unique_violation() => DatabaseError::DuplicateEntry,
+        _ => DatabaseError::ConnectionError(error),
+    }
+}
+
+/// A migrated in-memory SQLite database. A single connection is used because
+/// every `:memory:` connection opens its own empty database.
+#[cfg(test)]
+pub(crate) async fn test_database() -> Database {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    let db = Database::Sqlite(Arc::new(pool));
+    db.run_migrations().await.unwrap();
+    db
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(url: &str) -> DatabaseSettings {
+        DatabaseSettings {
+            url: url.to_string(),
+            max_connections: 1,
+            connection_timeout: 5,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_by_url_scheme() {
+        let db = Database::connect(&settings("sqlite::memory:")).await.unwrap();
+        assert_eq!(db.dialect(), Dialect::Sqlite);
+        db.run_migrations().await.unwrap();
+
+        assert!(matches!(
+            Database::connect(&settings("mysql://localhost/irc")).await,
+            Err(DatabaseError::UnsupportedUrl(_))
+        ));
+    }
+}
@@ -96,6 +96,9 @@ pub struct ChannelMember {
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct BanEntry {
     pub channel_name: String,
+    /// List mode the entry belongs to: `b`, `e` (ban exception) or `I`
+    /// (invite exception)
+    pub mode: String,
     pub mask: String,
     pub set_by: String,
     pub set_at: DateTime<Utc>,
@@ -103,6 +106,19 @@ pub struct BanEntry {
     pub expires_at: Option<DateTime<Utc>>,
 }
 
+/// A server-wide ban: a K-line on a user@host mask or a D-line on an IP
+/// address or CIDR range
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ServerBan {
+    /// `K` or `D`
+    pub kind: String,
+    pub mask: String,
+    pub set_by: String,
+    pub set_at: DateTime<Utc>,
+    pub reason: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct ServerConfig {
     pub id: i32,
@@ -177,7 +193,24 @@ pub struct OperatorCredential {
     pub name: String,
     pub password_hash: String,
     pub host_mask: Option<String>,
+    /// Client certificate fingerprint the operator must also present
+    pub certfp: Option<String>,
+    /// Comma-separated privilege names, or `all`
     pub privileges: String,
     pub created_at: DateTime<Utc>,
     pub last_used: Option<DateTime<Utc>>,
+}
+
+/// One OPER attempt, kept for auditing
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct OperLogEntry {
+    /// The operator name given to OPER
+    pub name: String,
+    pub nick: String,
+    /// nick!user@host of the client at the time
+    pub mask: String,
+    pub succeeded: bool,
+    /// Why the attempt failed, or the privileges granted
+    pub detail: String,
+    pub at: DateTime<Utc>,
 }
\ No newline at end of file
@@ -0,0 +1,166 @@
+use chrono::Utc;
+
+use crate::db::{Database, DatabaseError};
+use crate::security::auth::ScramCredentials;
+use crate::state::account::Account;
+
+#[derive(sqlx::FromRow)]
+struct AccountRow {
+    name: String,
+    password_hash: String,
+    scram_salt: String,
+    scram_iterations: i32,
+    scram_stored_key: String,
+    scram_server_key: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct CertfpRow {
+    certfp: String,
+    account_name: String,
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, DatabaseError> {
+    hex::decode(value).map_err(|e| DatabaseError::ConnectionError(sqlx::Error::Decode(Box::new(e))))
+}
+
+impl AccountRow {
+    fn into_account(self) -> Result<Account, DatabaseError> {
+        Ok(Account {
+            scram: ScramCredentials {
+                salt: decode_hex(&self.scram_salt)?,
+                iterations: self.scram_iterations as u32,
+                stored_key: decode_hex(&self.scram_stored_key)?,
+                server_key: decode_hex(&self.scram_server_key)?,
+            },
+            name: self.name,
+            password_hash: self.password_hash,
+            certfps: Vec::new(),
+        })
+    }
+}
+
+/// Insert or update an account together with its certificate fingerprints
+pub async fn save_account(db: &Database, account: &Account) -> Result<(), DatabaseError> {
+    with_pool!(db, |pool| {
+        async {
+            let mut tx = pool.begin().await?;
+            sqlx::query(
+                "INSERT INTO accounts (name, password_hash, scram_salt, scram_iterations, scram_stored_key, \
+                 scram_server_key, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7) \
+                 ON CONFLICT (name) DO UPDATE SET password_hash = $2, scram_salt = $3, \
+                 scram_iterations = $4, scram_stored_key = $5, scram_server_key = $6",
+            )
+            .bind(&account.name)
+            .bind(&account.password_hash)
+            .bind(hex::encode(&account.scram.salt))
+            .bind(account.scram.iterations as i32)
+            .bind(hex::encode(&account.scram.stored_key))
+            .bind(hex::encode(&account.scram.server_key))
+            .bind(Utc::now())
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query("DELETE FROM account_certfps WHERE account_name = $1")
+                .bind(&account.name)
+                .execute(&mut *tx)
+                .await?;
+
+            for certfp in &account.certfps {
+                sqlx::query("INSERT INTO account_certfps (certfp, account_name) VALUES ($1, $2)")
+                    .bind(certfp)
+                    .bind(&account.name)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await
+        }
+        .await
+    })?;
+
+    Ok(())
+}
+
+/// Delete an account; its fingerprints go with it
+pub async fn delete_account(db: &Database, name: &str) -> Result<(), DatabaseError> {
+    with_pool!(db, |pool| {
+        async {
+            let mut tx = pool.begin().await?;
+            sqlx::query("DELETE FROM account_certfps WHERE account_name = $1")
+                .bind(name)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM accounts WHERE name = $1")
+                .bind(name)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await
+        }
+        .await
+    })?;
+
+    Ok(())
+}
+
+/// Every stored account with its fingerprints attached
+pub async fn load_accounts(db: &Database) -> Result<Vec<Account>, DatabaseError> {
+    let (rows, certfps) = with_pool!(db, |pool| {
+        async {
+            let rows = sqlx::query_as::<_, AccountRow>(
+                "SELECT name, password_hash, scram_salt, scram_iterations, scram_stored_key, \
+                 scram_server_key FROM accounts ORDER BY name",
+            )
+            .fetch_all(pool)
+            .await?;
+            let certfps = sqlx::query_as::<_, CertfpRow>(
+                "SELECT certfp, account_name FROM account_certfps ORDER BY certfp",
+            )
+            .fetch_all(pool)
+            .await?;
+            Ok::<_, sqlx::Error>((rows, certfps))
+        }
+        .await
+    })?;
+
+    let mut accounts = rows
+        .into_iter()
+        .map(AccountRow::into_account)
+        .collect::<Result<Vec<_>, _>>()?;
+    for row in certfps {
+        if let Some(account) = accounts.iter_mut().find(|account| account.name == row.account_name) {
+            account.certfps.push(row.certfp);
+        }
+    }
+
+    Ok(accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+    use crate::security::auth::LocalPasswordHasher;
+
+    #[tokio::test]
+    async fn test_account_round_trip() {
+        let db = test_database().await;
+        let hasher = LocalPasswordHasher::new(8 * 1024, 1, 1).unwrap();
+        let mut account = Account::new("Alice".to_string(), "hunter2", &hasher).unwrap();
+        account.certfps.push("ab".repeat(32));
+        save_account(&db, &account).await.unwrap();
+
+        account.certfps = vec!["cd".repeat(32)];
+        save_account(&db, &account).await.unwrap();
+
+        let loaded = load_accounts(&db).await.unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].name, "Alice");
+        assert_eq!(loaded[0].certfps, vec!["cd".repeat(32)]);
+        assert_eq!(loaded[0].scram.stored_key, account.scram.stored_key);
+        assert!(loaded[0].verify_password("hunter2"));
+
+        delete_account(&db, "Alice").await.unwrap();
+        assert!(load_accounts(&db).await.unwrap().is_empty());
+    }
+}
@@ -1,21 +1,136 @@
-use crate::db::{Database, DatabaseError, models::BanEntry};
+use chrono::Utc;
 
-pub async fn add_ban(_db: &Database, _ban: &BanEntry) -> Result<(), DatabaseError> {
-    // TODO: Implement ban addition
+use crate::db::{Database, DatabaseError, map_unique_violation, models::BanEntry};
+use crate::security::mask_matches;
+
+/// Channel names are matched without regard to case, as in the channels
+/// queries. The row takes the channel's stored spelling so the foreign key
+/// holds whichever case the caller used.
+pub async fn add_ban(db: &Database, ban: &BanEntry) -> Result<(), DatabaseError> {
+    let inserted = with_pool!(db, |pool| {
+        sqlx::query(
+            "INSERT INTO bans (channel_name, mode, mask, set_by, set_at, reason, expires_at) \
+             SELECT name, $2, $3, $4, $5, $6, $7 FROM channels WHERE LOWER(name) = LOWER($1)",
+        )
+        .bind(&ban.channel_name)
+        .bind(&ban.mode)
+        .bind(&ban.mask)
+        .bind(&ban.set_by)
+        .bind(ban.set_at)
+        .bind(&ban.reason)
+        .bind(ban.expires_at)
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected())
+    })
+    .map_err(map_unique_violation)?;
+
+    if inserted == 0 {
+        return Err(DatabaseError::ChannelNotFound);
+    }
     Ok(())
 }
 
-pub async fn remove_ban(_db: &Database, _channel: &str, _mask: &str) -> Result<(), DatabaseError> {
-    // TODO: Implement ban removal
+pub async fn remove_ban(db: &Database, channel: &str, mode: &str, mask: &str) -> Result<(), DatabaseError> {
+    with_pool!(db, |pool| {
+        sqlx::query("DELETE FROM bans WHERE LOWER(channel_name) = LOWER($1) AND mode = $2 AND mask = $3")
+            .bind(channel)
+            .bind(mode)
+            .bind(mask)
+            .execute(pool)
+            .await
+            .map(|_| ())
+    })?;
+
     Ok(())
 }
 
-pub async fn get_channel_bans(_db: &Database, _channel: &str) -> Result<Vec<BanEntry>, DatabaseError> {
-    // TODO: Implement ban listing
-    Ok(Vec::new())
+/// Active list entries (bans and both kinds of exception) for a channel,
+/// oldest first. Expired entries are skipped.
+pub async fn get_channel_bans(db: &Database, channel: &str) -> Result<Vec<BanEntry>, DatabaseError> {
+    let bans = with_pool!(db, |pool| {
+        sqlx::query_as::<_, BanEntry>(
+            "SELECT channel_name, mode, mask, set_by, set_at, reason, expires_at FROM bans \
+             WHERE LOWER(channel_name) = LOWER($1) ORDER BY set_at",
+        )
+        .bind(channel)
+        .fetch_all(pool)
+        .await
+    })?;
+
+    // Expiry is checked here rather than in SQL, since SQLite stores
+    // timestamps as text and would compare them lexically
+    let now = Utc::now();
+    Ok(bans
+        .into_iter()
+        .filter(|ban| ban.expires_at.is_none_or(|expires| expires > now))
+        .collect())
+}
+
+/// Whether a hostmask is banned from a channel and not covered by a ban
+/// exception. Extbans are not considered here.
+pub async fn is_banned(db: &Database, channel: &str, user_mask: &str) -> Result<bool, DatabaseError> {
+    let entries = get_channel_bans(db, channel).await?;
+    let matches = |mode: &str| entries.iter().any(|entry| entry.mode == mode && mask_matches(&entry.mask, user_mask));
+    Ok(matches("b") && !matches("e"))
 }
 
-pub async fn is_banned(_db: &Database, _channel: &str, _user_mask: &str) -> Result<bool, DatabaseError> {
-    // TODO: Implement ban checking
-    Ok(false)
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::Channel;
+    use crate::db::queries::channels::create_channel;
+    use crate::db::test_database;
+
+    fn ban(mask: &str, expires_in: Option<i64>) -> BanEntry {
+        BanEntry {
+            channel_name: "#rust".to_string(),
+            mode: "b".to_string(),
+            mask: mask.to_string(),
+            set_by: "alice".to_string(),
+            set_at: Utc::now(),
+            reason: None,
+            expires_at: expires_in.map(|secs| Utc::now() + chrono::Duration::seconds(secs)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bans() {
+        let db = test_database().await;
+        create_channel(&db, &Channel::new("#rust".to_string(), None)).await.unwrap();
+
+        add_ban(&db, &ban("*!*@spam.example", None)).await.unwrap();
+        add_ban(&db, &ban("old!*@*", Some(-60))).await.unwrap();
+        assert!(matches!(add_ban(&db, &ban("*!*@spam.example", None)).await, Err(DatabaseError::DuplicateEntry)));
+
+        assert_eq!(get_channel_bans(&db, "#rust").await.unwrap().len(), 1);
+        assert_eq!(get_channel_bans(&db, "#rust").await.unwrap()[0].mode, "b");
+        assert!(is_banned(&db, "#rust", "bob!bob@spam.example").await.unwrap());
+        assert!(!is_banned(&db, "#rust", "old!old@host").await.unwrap());
+
+        let mut exception = ban("bob!*@*", None);
+        exception.mode = "e".to_string();
+        add_ban(&db, &exception).await.unwrap();
+        assert!(!is_banned(&db, "#rust", "bob!bob@spam.example").await.unwrap());
+        assert!(is_banned(&db, "#rust", "carol!carol@spam.example").await.unwrap());
+
+        remove_ban(&db, "#rust", "b", "*!*@spam.example").await.unwrap();
+        assert!(!is_banned(&db, "#rust", "bob!bob@spam.example").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_channel_names_ignore_case() {
+        let db = test_database().await;
+        create_channel(&db, &Channel::new("#Rust".to_string(), None)).await.unwrap();
+
+        add_ban(&db, &ban("*!*@spam.example", None)).await.unwrap();
+        let bans = get_channel_bans(&db, "#RUST").await.unwrap();
+        assert_eq!(bans.len(), 1);
+        assert_eq!(bans[0].channel_name, "#Rust");
+
+        remove_ban(&db, "#rust", "b", "*!*@spam.example").await.unwrap();
+        assert!(get_channel_bans(&db, "#Rust").await.unwrap().is_empty());
+        assert!(matches!(add_ban(&db, &BanEntry { channel_name: "#none".to_string(), ..ban("*!*@*", None) }).await,
+            Err(DatabaseError::ChannelNotFound)));
+    }
+}
@@ -1,46 +1,231 @@
-use crate::db::{Database, DatabaseError, models::{Channel, ChannelMember}};
+use crate::db::{Database, DatabaseError, map_unique_violation, models::{Channel, ChannelMember}};
+
+// "limit" is a reserved word in both dialects and must stay quoted
+const CHANNEL_COLUMNS: &str = "name, topic, topic_set_by, topic_set_at, modes, key, \"limit\", created_at, \
+     founder, successor, description, url, email, entry_message, metadata";
+
+pub async fn create_channel(db: &Database, channel: &Channel) -> Result<Channel, DatabaseError> {
+    let sql = format!(
+        "INSERT INTO channels ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+        CHANNEL_COLUMNS
+    );
+
+    with_pool!(db, |pool| {
+        sqlx::query(&sql)
+            .bind(&channel.name)
+            .bind(&channel.topic)
+            .bind(&channel.topic_set_by)
+            .bind(channel.topic_set_at)
+            .bind(&channel.modes)
+            .bind(&channel.key)
+            .bind(channel.limit)
+            .bind(channel.created_at)
+            .bind(&channel.founder)
+            .bind(&channel.successor)
+            .bind(&channel.description)
+            .bind(&channel.url)
+            .bind(&channel.email)
+            .bind(&channel.entry_message)
+            .bind(&channel.metadata)
+            .execute(pool)
+            .await
+            .map(|_| ())
+    })
+    .map_err(map_unique_violation)?;
 
-pub async fn create_channel(_db: &Database, channel: &Channel) -> Result<Channel, DatabaseError> {
-    // TODO: Implement channel creation
     Ok(channel.clone())
 }
 
-pub async fn get_channel(_db: &Database, _name: &str) -> Result<Option<Channel>, DatabaseError> {
-    // TODO: Implement channel retrieval
-    Ok(None)
+pub async fn get_channel(db: &Database, name: &str) -> Result<Option<Channel>, DatabaseError> {
+    let sql = format!("SELECT {} FROM channels WHERE LOWER(name) = LOWER($1)", CHANNEL_COLUMNS);
+
+    let channel = with_pool!(db, |pool| {
+        sqlx::query_as::<_, Channel>(&sql)
+            .bind(name)
+            .fetch_optional(pool)
+            .await
+    })?;
+
+    Ok(channel)
 }
 
-pub async fn update_channel(_db: &Database, _channel: &Channel) -> Result<(), DatabaseError> {
-    // TODO: Implement channel update
+pub async fn update_channel(db: &Database, channel: &Channel) -> Result<(), DatabaseError> {
+    let sql = "UPDATE channels SET topic = $2, topic_set_by = $3, topic_set_at = $4, modes = $5, \
+               key = $6, \"limit\" = $7, founder = $8, successor = $9, description = $10, url = $11, \
+               email = $12, entry_message = $13, metadata = $14 WHERE LOWER(name) = LOWER($1)";
+
+    let updated = with_pool!(db, |pool| {
+        sqlx::query(sql)
+            .bind(&channel.name)
+            .bind(&channel.topic)
+            .bind(&channel.topic_set_by)
+            .bind(channel.topic_set_at)
+            .bind(&channel.modes)
+            .bind(&channel.key)
+            .bind(channel.limit)
+            .bind(&channel.founder)
+            .bind(&channel.successor)
+            .bind(&channel.description)
+            .bind(&channel.url)
+            .bind(&channel.email)
+            .bind(&channel.entry_message)
+            .bind(&channel.metadata)
+            .execute(pool)
+            .await
+            .map(|result| result.rows_affected())
+    })?;
+
+    if updated == 0 {
+        return Err(DatabaseError::ChannelNotFound);
+    }
+
     Ok(())
 }
 
-pub async fn delete_channel(_db: &Database, _name: &str) -> Result<(), DatabaseError> {
-    // TODO: Implement channel deletion
+pub async fn delete_channel(db: &Database, name: &str) -> Result<(), DatabaseError> {
+    let deleted = with_pool!(db, |pool| {
+        sqlx::query("DELETE FROM channels WHERE LOWER(name) = LOWER($1)")
+            .bind(name)
+            .execute(pool)
+            .await
+            .map(|result| result.rows_affected())
+    })?;
+
+    if deleted == 0 {
+        return Err(DatabaseError::ChannelNotFound);
+    }
+
     Ok(())
 }
 
-pub async fn list_channels(_db: &Database) -> Result<Vec<Channel>, DatabaseError> {
-    // TODO: Implement channel listing
-    Ok(Vec::new())
+pub async fn list_channels(db: &Database) -> Result<Vec<Channel>, DatabaseError> {
+    let sql = format!("SELECT {} FROM channels ORDER BY name", CHANNEL_COLUMNS);
+
+    let channels = with_pool!(db, |pool| {
+        sqlx::query_as::<_, Channel>(&sql)
+            .fetch_all(pool)
+            .await
+    })?;
+
+    Ok(channels)
 }
 
-pub async fn add_channel_member(_db: &Database, _member: &ChannelMember) -> Result<(), DatabaseError> {
-    // TODO: Implement member addition
+/// Like the bans, members are stored under the channel's own spelling
+pub async fn add_channel_member(db: &Database, member: &ChannelMember) -> Result<(), DatabaseError> {
+    let inserted = with_pool!(db, |pool| {
+        sqlx::query(
+            "INSERT INTO channel_members (channel_name, user_id, modes, joined_at, last_active) \
+             SELECT name, $2, $3, $4, $5 FROM channels WHERE LOWER(name) = LOWER($1)",
+        )
+        .bind(&member.channel_name)
+        .bind(&member.user_id)
+        .bind(&member.modes)
+        .bind(member.joined_at)
+        .bind(member.last_active)
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected())
+    })
+    .map_err(map_unique_violation)?;
+
+    if inserted == 0 {
+        return Err(DatabaseError::ChannelNotFound);
+    }
     Ok(())
 }
 
-pub async fn remove_channel_member(_db: &Database, _channel: &str, _user_id: &str) -> Result<(), DatabaseError> {
-    // TODO: Implement member removal
+pub async fn remove_channel_member(db: &Database, channel: &str, user_id: &str) -> Result<(), DatabaseError> {
+    with_pool!(db, |pool| {
+        sqlx::query("DELETE FROM channel_members WHERE LOWER(channel_name) = LOWER($1) AND user_id = $2")
+            .bind(channel)
+            .bind(user_id)
+            .execute(pool)
+            .await
+            .map(|_| ())
+    })?;
+
     Ok(())
 }
 
-pub async fn get_channel_members(_db: &Database, _channel: &str) -> Result<Vec<ChannelMember>, DatabaseError> {
-    // TODO: Implement member listing
-    Ok(Vec::new())
+pub async fn get_channel_members(db: &Database, channel: &str) -> Result<Vec<ChannelMember>, DatabaseError> {
+    let members = with_pool!(db, |pool| {
+        sqlx::query_as::<_, ChannelMember>(
+            "SELECT channel_name, user_id, modes, joined_at, last_active FROM channel_members \
+             WHERE LOWER(channel_name) = LOWER($1) ORDER BY joined_at",
+        )
+        .bind(channel)
+        .fetch_all(pool)
+        .await
+    })?;
+
+    Ok(members)
+}
+
+pub async fn get_user_channels(db: &Database, user_id: &str) -> Result<Vec<String>, DatabaseError> {
+    let channels = with_pool!(db, |pool| {
+        sqlx::query_scalar::<_, String>(
+            "SELECT channel_name FROM channel_members WHERE user_id = $1 ORDER BY channel_name",
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+    })?;
+
+    Ok(channels)
 }
 
-pub async fn get_user_channels(_db: &Database, _user_id: &str) -> Result<Vec<String>, DatabaseError> {
-    // TODO: Implement user channel listing
-    Ok(Vec::new())
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::User;
+    use crate::db::queries::users::create_user;
+    use crate::db::test_database;
+    use chrono::Utc;
+
+    #[tokio::test]
+    async fn test_channel_crud() {
+        let db = test_database().await;
+        let mut channel = Channel::new("#Rust".to_string(), Some("alice".to_string()));
+        channel.limit = Some(25);
+        create_channel(&db, &channel).await.unwrap();
+        assert!(matches!(create_channel(&db, &channel).await, Err(DatabaseError::DuplicateEntry)));
+
+        channel.topic = Some("Ferris".to_string());
+        channel.entry_message = Some("Welcome".to_string());
+        update_channel(&db, &channel).await.unwrap();
+
+        let stored = get_channel(&db, "#rust").await.unwrap().unwrap();
+        assert_eq!(stored.topic.as_deref(), Some("Ferris"));
+        assert_eq!(stored.limit, Some(25));
+        assert_eq!(list_channels(&db).await.unwrap().len(), 1);
+
+        delete_channel(&db, "#rust").await.unwrap();
+        assert!(get_channel(&db, "#rust").await.unwrap().is_none());
+        assert!(matches!(delete_channel(&db, "#rust").await, Err(DatabaseError::ChannelNotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_channel_members() {
+        let db = test_database().await;
+        let user = User::new("alice".to_string(), "alice".to_string(), "Alice".to_string(), String::new());
+        create_user(&db, &user).await.unwrap();
+        create_channel(&db, &Channel::new("#rust".to_string(), None)).await.unwrap();
+
+        let member = ChannelMember {
+            channel_name: "#rust".to_string(),
+            user_id: user.id.clone(),
+            modes: "o".to_string(),
+            joined_at: Utc::now(),
+            last_active: Utc::now(),
+        };
+        add_channel_member(&db, &member).await.unwrap();
+
+        let members = get_channel_members(&db, "#rust").await.unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].modes, "o");
+        assert_eq!(get_user_channels(&db, &user.id).await.unwrap(), vec!["#rust".to_string()]);
+
+        remove_channel_member(&db, "#rust", &user.id).await.unwrap();
+        assert!(get_channel_members(&db, "#rust").await.unwrap().is_empty());
+    }
+}
@@ -1,11 +1,91 @@
 use crate::db::{Database, DatabaseError, models::ServerConfig};
 
-pub async fn get_server_config(_db: &Database) -> Result<ServerConfig, DatabaseError> {
-    // TODO: Implement server config retrieval
-    Ok(ServerConfig::default())
+const CONFIG_COLUMNS: &str = "id, server_name, network_name, server_description, admin_name, admin_email, \
+     motd, max_clients, max_channels_per_user, max_nickname_length, max_channel_name_length, \
+     max_topic_length, max_kick_reason_length, max_away_length, max_message_length, default_modes, \
+     default_channel_modes, ping_frequency, ping_timeout, flood_messages, flood_interval, \
+     throttle_duration, metadata";
+
+/// The stored configuration row, or the defaults if none has been saved yet
+pub async fn get_server_config(db: &Database) -> Result<ServerConfig, DatabaseError> {
+    let sql = format!("SELECT {} FROM server_config WHERE id = 1", CONFIG_COLUMNS);
+
+    let config = with_pool!(db, |pool| {
+        sqlx::query_as::<_, ServerConfig>(&sql)
+            .fetch_optional(pool)
+            .await
+    })?;
+
+    Ok(config.unwrap_or_default())
 }
 
-pub async fn update_server_config(_db: &Database, _config: &ServerConfig) -> Result<(), DatabaseError> {
-    // TODO: Implement server config update
+/// Insert or replace the single configuration row
+pub async fn update_server_config(db: &Database, config: &ServerConfig) -> Result<(), DatabaseError> {
+    let sql = format!(
+        "INSERT INTO server_config ({}) VALUES (1, $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, \
+         $13, $14, $15, $16, $17, $18, $19, $20, $21, $22) \
+         ON CONFLICT (id) DO UPDATE SET server_name = $1, network_name = $2, server_description = $3, \
+         admin_name = $4, admin_email = $5, motd = $6, max_clients = $7, max_channels_per_user = $8, \
+         max_nickname_length = $9, max_channel_name_length = $10, max_topic_length = $11, \
+         max_kick_reason_length = $12, max_away_length = $13, max_message_length = $14, \
+         default_modes = $15, default_channel_modes = $16, ping_frequency = $17, ping_timeout = $18, \
+         flood_messages = $19, flood_interval = $20, throttle_duration = $21, metadata = $22",
+        CONFIG_COLUMNS
+    );
+
+    with_pool!(db, |pool| {
+        sqlx::query(&sql)
+            .bind(&config.server_name)
+            .bind(&config.network_name)
+            .bind(&config.server_description)
+            .bind(&config.admin_name)
+            .bind(&config.admin_email)
+            .bind(&config.motd)
+            .bind(config.max_clients)
+            .bind(config.max_channels_per_user)
+            .bind(config.max_nickname_length)
+            .bind(config.max_channel_name_length)
+            .bind(config.max_topic_length)
+            .bind(config.max_kick_reason_length)
+            .bind(config.max_away_length)
+            .bind(config.max_message_length)
+            .bind(&config.default_modes)
+            .bind(&config.default_channel_modes)
+            .bind(config.ping_frequency)
+            .bind(config.ping_timeout)
+            .bind(config.flood_messages)
+            .bind(config.flood_interval)
+            .bind(config.throttle_duration)
+            .bind(&config.metadata)
+            .execute(pool)
+            .await
+            .map(|_| ())
+    })?;
+
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+
+    #[tokio::test]
+    async fn test_server_config_round_trip() {
+        let db = test_database().await;
+        assert_eq!(get_server_config(&db).await.unwrap().server_name, ServerConfig::default().server_name);
+
+        let mut config = ServerConfig::default();
+        config.server_name = "irc.example.net".to_string();
+        config.motd = Some("Hello".to_string());
+        update_server_config(&db, &config).await.unwrap();
+
+        config.max_clients = 42;
+        update_server_config(&db, &config).await.unwrap();
+
+        let stored = get_server_config(&db).await.unwrap();
+        assert_eq!(stored.server_name, "irc.example.net");
+        assert_eq!(stored.motd.as_deref(), Some("Hello"));
+        assert_eq!(stored.max_clients, 42);
+    }
+}
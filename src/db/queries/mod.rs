@@ -1,4 +1,7 @@
-pub mod users;
-pub mod channels;
+pub mod accounts;
 pub mod bans;
-pub mod config;
\ No newline at end of file
+pub mod channels;
+pub mod config;
+pub mod operators;
+pub mod server_bans;
+pub mod users;
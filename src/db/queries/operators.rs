@@ -0,0 +1,140 @@
+use chrono::{DateTime, Utc};
+
+use crate::db::{Database, DatabaseError, models::{OperLogEntry, OperatorCredential}};
+
+pub async fn load_operators(db: &Database) -> Result<Vec<OperatorCredential>, DatabaseError> {
+    let operators = with_pool!(db, |pool| {
+        sqlx::query_as::<_, OperatorCredential>(
+            "SELECT id, name, password_hash, host_mask, certfp, privileges, created_at, last_used \
+             FROM operator_credentials ORDER BY name",
+        )
+        .fetch_all(pool)
+        .await
+    })?;
+
+    Ok(operators)
+}
+
+/// Append an OPER attempt to the audit log, stamping the operator's
+/// `last_used` when it succeeded
+pub async fn log_oper_attempt(db: &Database, entry: &OperLogEntry) -> Result<(), DatabaseError> {
+    with_pool!(db, |pool| {
+        sqlx::query(
+            "INSERT INTO oper_log (name, nick, mask, succeeded, detail, at) VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(&entry.name)
+        .bind(&entry.nick)
+        .bind(&entry.mask)
+        .bind(entry.succeeded)
+        .bind(&entry.detail)
+        .bind(entry.at)
+        .execute(pool)
+        .await
+        .map(|_| ())
+    })?;
+
+    if entry.succeeded {
+        touch_operator(db, &entry.name, entry.at).await?;
+    }
+    Ok(())
+}
+
+async fn touch_operator(db: &Database, name: &str, at: DateTime<Utc>) -> Result<(), DatabaseError> {
+    with_pool!(db, |pool| {
+        sqlx::query("UPDATE operator_credentials SET last_used = $1 WHERE LOWER(name) = LOWER($2)")
+            .bind(at)
+            .bind(name)
+            .execute(pool)
+            .await
+            .map(|_| ())
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+
+    // Operators are provisioned outside the server, so only the tests write
+    // them or read the log back
+    async fn save_operator(db: &Database, oper: &OperatorCredential) -> Result<(), DatabaseError> {
+        with_pool!(db, |pool| {
+            sqlx::query(
+                "INSERT INTO operator_credentials \
+                 (id, name, password_hash, host_mask, certfp, privileges, created_at, last_used) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8) \
+                 ON CONFLICT (name) DO UPDATE SET password_hash = $3, host_mask = $4, certfp = $5, privileges = $6",
+            )
+            .bind(&oper.id)
+            .bind(&oper.name)
+            .bind(&oper.password_hash)
+            .bind(&oper.host_mask)
+            .bind(&oper.certfp)
+            .bind(&oper.privileges)
+            .bind(oper.created_at)
+            .bind(oper.last_used)
+            .execute(pool)
+            .await
+            .map(|_| ())
+        })?;
+
+        Ok(())
+    }
+
+    /// The most recent OPER attempts, newest first
+    async fn load_oper_log(db: &Database, limit: i64) -> Result<Vec<OperLogEntry>, DatabaseError> {
+        let entries = with_pool!(db, |pool| {
+            sqlx::query_as::<_, OperLogEntry>(
+                "SELECT name, nick, mask, succeeded, detail, at FROM oper_log ORDER BY at DESC LIMIT $1",
+            )
+            .bind(limit)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(entries)
+    }
+
+    #[tokio::test]
+    async fn test_operators_and_oper_log() {
+        let db = test_database().await;
+
+        let mut oper = OperatorCredential {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: "alice".to_string(),
+            password_hash: "hash".to_string(),
+            host_mask: Some("*@staff.example".to_string()),
+            certfp: None,
+            privileges: "kline".to_string(),
+            created_at: Utc::now(),
+            last_used: None,
+        };
+        save_operator(&db, &oper).await.unwrap();
+        oper.privileges = "all".to_string();
+        save_operator(&db, &oper).await.unwrap();
+
+        let entry = |succeeded, at| OperLogEntry {
+            name: "alice".to_string(),
+            nick: "alice".to_string(),
+            mask: "alice!a@staff.example".to_string(),
+            succeeded,
+            detail: String::new(),
+            at,
+        };
+        let earlier = Utc::now() - chrono::Duration::minutes(1);
+        log_oper_attempt(&db, &entry(false, earlier)).await.unwrap();
+        let now = Utc::now();
+        log_oper_attempt(&db, &entry(true, now)).await.unwrap();
+
+        let operators = load_operators(&db).await.unwrap();
+        assert_eq!(operators.len(), 1);
+        assert_eq!(operators[0].privileges, "all");
+        assert_eq!(operators[0].last_used, Some(now));
+
+        let log = load_oper_log(&db, 10).await.unwrap();
+        assert_eq!(log.len(), 2);
+        assert!(log[0].succeeded && !log[1].succeeded);
+    }
+}
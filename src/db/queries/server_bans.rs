@@ -0,0 +1,94 @@
+use chrono::Utc;
+
+use crate::db::{Database, DatabaseError, models::ServerBan};
+
+/// Insert a K-line or D-line, replacing any existing one on the same mask
+pub async fn save_server_ban(db: &Database, ban: &ServerBan) -> Result<(), DatabaseError> {
+    with_pool!(db, |pool| {
+        sqlx::query(
+            "INSERT INTO server_bans (kind, mask, set_by, set_at, reason, expires_at) \
+             VALUES ($1, $2, $3, $4, $5, $6) \
+             ON CONFLICT (kind, mask) DO UPDATE SET set_by = $3, set_at = $4, reason = $5, expires_at = $6",
+        )
+        .bind(&ban.kind)
+        .bind(&ban.mask)
+        .bind(&ban.set_by)
+        .bind(ban.set_at)
+        .bind(&ban.reason)
+        .bind(ban.expires_at)
+        .execute(pool)
+        .await
+        .map(|_| ())
+    })?;
+
+    Ok(())
+}
+
+pub async fn delete_server_ban(db: &Database, kind: &str, mask: &str) -> Result<(), DatabaseError> {
+    with_pool!(db, |pool| {
+        sqlx::query("DELETE FROM server_bans WHERE kind = $1 AND mask = $2")
+            .bind(kind)
+            .bind(mask)
+            .execute(pool)
+            .await
+            .map(|_| ())
+    })?;
+
+    Ok(())
+}
+
+/// Every server ban that hasn't expired, oldest first
+pub async fn load_server_bans(db: &Database) -> Result<Vec<ServerBan>, DatabaseError> {
+    let bans = with_pool!(db, |pool| {
+        sqlx::query_as::<_, ServerBan>(
+            "SELECT kind, mask, set_by, set_at, reason, expires_at FROM server_bans ORDER BY set_at",
+        )
+        .fetch_all(pool)
+        .await
+    })?;
+
+    // As with channel bans, expiry is checked here rather than in SQL
+    let now = Utc::now();
+    Ok(bans
+        .into_iter()
+        .filter(|ban| ban.expires_at.is_none_or(|expires| expires > now))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+
+    fn ban(kind: &str, mask: &str, expires_in: Option<i64>) -> ServerBan {
+        ServerBan {
+            kind: kind.to_string(),
+            mask: mask.to_string(),
+            set_by: "oper".to_string(),
+            set_at: Utc::now(),
+            reason: "spam".to_string(),
+            expires_at: expires_in.map(|secs| Utc::now() + chrono::Duration::seconds(secs)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_bans() {
+        let db = test_database().await;
+
+        save_server_ban(&db, &ban("K", "*@spam.example", None)).await.unwrap();
+        save_server_ban(&db, &ban("D", "192.0.2.0/24", Some(3600))).await.unwrap();
+        save_server_ban(&db, &ban("K", "old@*", Some(-60))).await.unwrap();
+
+        // Saving the same mask again replaces it
+        let mut updated = ban("K", "*@spam.example", None);
+        updated.reason = "more spam".to_string();
+        save_server_ban(&db, &updated).await.unwrap();
+
+        let bans = load_server_bans(&db).await.unwrap();
+        assert_eq!(bans.len(), 2);
+        assert!(bans.iter().any(|ban| ban.kind == "K" && ban.reason == "more spam"));
+
+        delete_server_ban(&db, "D", "192.0.2.0/24").await.unwrap();
+        assert_eq!(load_server_bans(&db).await.unwrap().len(), 1);
+    }
+}
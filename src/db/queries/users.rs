@@ -1,33 +1,169 @@
-use crate::db::{Database, DatabaseError, models::User};
+use crate::db::{Database, DatabaseError, map_unique_violation, models::User};
+use crate::security::auth::LocalPasswordHasher;
+
+const USER_COLUMNS: &str = "id, nickname, username, realname, password_hash, email, account_name, \
+     is_operator, is_services, modes, away_message, created_at, last_seen, vhost, metadata";
+
+pub async fn create_user(db: &Database, user: &User) -> Result<User, DatabaseError> {
+    let sql = format!(
+        "INSERT INTO users ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+        USER_COLUMNS
+    );
+
+    with_pool!(db, |pool| {
+        sqlx::query(&sql)
+            .bind(&user.id)
+            .bind(&user.nickname)
+            .bind(&user.username)
+            .bind(&user.realname)
+            .bind(&user.password_hash)
+            .bind(&user.email)
+            .bind(&user.account_name)
+            .bind(user.is_operator)
+            .bind(user.is_services)
+            .bind(&user.modes)
+            .bind(&user.away_message)
+            .bind(user.created_at)
+            .bind(user.last_seen)
+            .bind(&user.vhost)
+            .bind(&user.metadata)
+            .execute(pool)
+            .await
+            .map(|_| ())
+    })
+    .map_err(map_unique_violation)?;
 
-pub async fn create_user(_db: &Database, user: &User) -> Result<User, DatabaseError> {
-    // TODO: Implement database user creation
     Ok(user.clone())
 }
 
 pub async fn get_user_by_nickname(
-    _db: &Database,
-    _nickname: &str,
+    db: &Database,
+    nickname: &str,
 ) -> Result<Option<User>, DatabaseError> {
-    // TODO: Implement database user lookup
-    Ok(None)
+    let sql = format!("SELECT {} FROM users WHERE LOWER(nickname) = LOWER($1)", USER_COLUMNS);
+
+    let user = with_pool!(db, |pool| {
+        sqlx::query_as::<_, User>(&sql)
+            .bind(nickname)
+            .fetch_optional(pool)
+            .await
+    })?;
+
+    Ok(user)
 }
 
-pub async fn get_user_by_id(_db: &Database, _id: &str) -> Result<Option<User>, DatabaseError> {
-    // TODO: Implement database user lookup
-    Ok(None)
+pub async fn get_user_by_id(db: &Database, id: &str) -> Result<Option<User>, DatabaseError> {
+    let sql = format!("SELECT {} FROM users WHERE id = $1", USER_COLUMNS);
+
+    let user = with_pool!(db, |pool| {
+        sqlx::query_as::<_, User>(&sql)
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    })?;
+
+    Ok(user)
 }
 
-pub async fn update_user(_db: &Database, _user: &User) -> Result<(), DatabaseError> {
-    // TODO: Implement database user update
+pub async fn update_user(db: &Database, user: &User) -> Result<(), DatabaseError> {
+    let sql = "UPDATE users SET nickname = $2, username = $3, realname = $4, password_hash = $5, \
+               email = $6, account_name = $7, is_operator = $8, is_services = $9, modes = $10, \
+               away_message = $11, last_seen = $12, vhost = $13, metadata = $14 WHERE id = $1";
+
+    let result = with_pool!(db, |pool| {
+        sqlx::query(sql)
+            .bind(&user.id)
+            .bind(&user.nickname)
+            .bind(&user.username)
+            .bind(&user.realname)
+            .bind(&user.password_hash)
+            .bind(&user.email)
+            .bind(&user.account_name)
+            .bind(user.is_operator)
+            .bind(user.is_services)
+            .bind(&user.modes)
+            .bind(&user.away_message)
+            .bind(user.last_seen)
+            .bind(&user.vhost)
+            .bind(&user.metadata)
+            .execute(pool)
+            .await
+            .map(|result| result.rows_affected())
+    })
+    .map_err(map_unique_violation)?;
+
+    if result == 0 {
+        return Err(DatabaseError::UserNotFound);
+    }
+
     Ok(())
 }
 
 pub async fn authenticate_user(
-    _db: &Database,
-    _nickname: &str,
-    _password: &str,
+    db: &Database,
+    nickname: &str,
+    password: &str,
 ) -> Result<User, DatabaseError> {
-    // TODO: Implement database authentication
-    Err(DatabaseError::UserNotFound)
-}
\ No newline at end of file
+    let user = get_user_by_nickname(db, nickname)
+        .await?
+        .ok_or(DatabaseError::UserNotFound)?;
+
+    // Argon2 is slow by design, so keep it off the executor
+    let hash = user.password_hash.clone();
+    let password = password.to_string();
+    let verified = tokio::task::spawn_blocking(move || {
+        LocalPasswordHasher::verify_password(&password, &hash).unwrap_or(false)
+    })
+    .await
+    .unwrap_or(false);
+
+    if verified {
+        Ok(user)
+    } else {
+        Err(DatabaseError::InvalidCredentials)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_database;
+
+    fn test_user(nickname: &str) -> User {
+        let hasher = LocalPasswordHasher::new(8 * 1024, 1, 1).unwrap();
+        User::new(
+            nickname.to_string(),
+            nickname.to_string(),
+            "Test User".to_string(),
+            hasher.hash_password("hunter2").unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_create_and_get_user() {
+        let db = test_database().await;
+        let user = create_user(&db, &test_user("Alice")).await.unwrap();
+
+        let by_nick = get_user_by_nickname(&db, "alice").await.unwrap().unwrap();
+        assert_eq!(by_nick.id, user.id);
+        assert_eq!(by_nick.metadata, serde_json::json!({}));
+        assert!(get_user_by_id(&db, &user.id).await.unwrap().is_some());
+        assert!(get_user_by_nickname(&db, "bob").await.unwrap().is_none());
+
+        assert!(matches!(create_user(&db, &test_user("Alice")).await, Err(DatabaseError::DuplicateEntry)));
+    }
+
+    #[tokio::test]
+    async fn test_update_and_authenticate_user() {
+        let db = test_database().await;
+        let mut user = create_user(&db, &test_user("alice")).await.unwrap();
+
+        user.away_message = Some("lunch".to_string());
+        update_user(&db, &user).await.unwrap();
+        assert_eq!(get_user_by_id(&db, &user.id).await.unwrap().unwrap().away_message.as_deref(), Some("lunch"));
+
+        assert!(authenticate_user(&db, "ALICE", "hunter2").await.is_ok());
+        assert!(matches!(authenticate_user(&db, "alice", "wrong").await, Err(DatabaseError::InvalidCredentials)));
+        assert!(matches!(authenticate_user(&db, "bob", "hunter2").await, Err(DatabaseError::UserNotFound)));
+    }
+}
@@ -15,7 +15,7 @@ pub enum CenturionError {
     
     /// Database error
     #[error("Database error: {0}")]
-    Database(#[from] sqlx::Error),
+    Database(#[from] crate::db::DatabaseError),
     
     /// Configuration error
     #[error("Configuration error: {0}")]
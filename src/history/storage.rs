@@ -1,6 +1,6 @@
 use super::{HistoryItem, MessageType, format_timestamp};
+use dashmap::DashMap;
 use std::collections::{BTreeMap, VecDeque};
-use std::sync::RwLock;
 use std::time::{SystemTime, UNIX_EPOCH, Duration};
 
 /// Configuration for history storage
@@ -187,15 +187,16 @@ impl HistoryBuffer {
 
 /// Main history storage manager
 pub struct HistoryStorage {
-    /// Buffers for each target (channel or user)
-    buffers: RwLock<BTreeMap<String, HistoryBuffer>>,
+    /// Buffers for each target (channel or user), locked per target so
+    /// busy channels don't contend with each other
+    buffers: DashMap<String, HistoryBuffer>,
     config: HistoryConfig,
 }
 
 impl HistoryStorage {
     pub fn new(config: HistoryConfig) -> Self {
         Self {
-            buffers: RwLock::new(BTreeMap::new()),
+            buffers: DashMap::new(),
             config,
         }
     }
@@ -212,9 +213,9 @@ impl HistoryStorage {
 
         let target = item.target.clone();
         
-        let mut buffers = self.buffers.write().unwrap();
-        let buffer = buffers.entry(target).or_insert_with(|| HistoryBuffer::new(&self.config));
-        buffer.add_message(item);
+        self.buffers.entry(target)
+            .or_insert_with(|| HistoryBuffer::new(&self.config))
+            .add_message(item);
     }
 
     /// Get messages for a target between two points
@@ -226,9 +227,7 @@ impl HistoryStorage {
         limit: usize,
         ascending: bool,
     ) -> Vec<HistoryItem> {
-        let buffers = self.buffers.read().unwrap();
-        
-        if let Some(buffer) = buffers.get(target) {
+        if let Some(buffer) = self.buffers.get(target) {
             buffer.get_messages_between(start, end, limit, ascending)
         } else {
             Vec::new()
@@ -243,9 +242,7 @@ impl HistoryStorage {
         center_msgid: Option<&str>,
         limit: usize,
     ) -> Vec<HistoryItem> {
-        let buffers = self.buffers.read().unwrap();
-        
-        if let Some(buffer) = buffers.get(target) {
+        if let Some(buffer) = self.buffers.get(target) {
             buffer.get_messages_around(center_time, center_msgid, limit)
         } else {
             Vec::new()
@@ -254,9 +251,7 @@ impl HistoryStorage {
 
     /// Find message by ID
     pub fn find_message_by_id(&self, target: &str, msgid: &str) -> Option<HistoryItem> {
-        let buffers = self.buffers.read().unwrap();
-        
-        if let Some(buffer) = buffers.get(target) {
+        if let Some(buffer) = self.buffers.get(target) {
             buffer.find_by_msgid(msgid).cloned()
         } else {
             None
@@ -265,10 +260,9 @@ impl HistoryStorage {
 
     /// Get conversation targets for a user (for TARGETS subcommand)
     pub fn get_targets_for_user(&self, _user: &str) -> Vec<(String, SystemTime)> {
-        let buffers = self.buffers.read().unwrap();
         let mut all_targets = Vec::new();
         
-        for buffer in buffers.values() {
+        for buffer in self.buffers.iter() {
             all_targets.extend(buffer.get_targets());
         }
 
@@ -284,8 +278,7 @@ impl HistoryStorage {
 
     /// Clean up old messages across all buffers
     pub fn cleanup_old_messages(&self) {
-        let mut buffers = self.buffers.write().unwrap();
-        for buffer in buffers.values_mut() {
+        for mut buffer in self.buffers.iter_mut() {
             buffer.cleanup_old_messages();
         }
     }
@@ -2,9 +2,12 @@ use std::error::Error;
 use std::net::SocketAddr;
 use std::sync::Arc;
 
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::RwLock;
-use tracing::{error, info, warn};
+use futures::SinkExt;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::codec::Framed;
+use tracing::{debug, error, info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 mod actors;
@@ -18,10 +21,16 @@ mod security;
 mod state;
 mod utils;
 
-use crate::actors::{ConnectionActor, ServerActor};
+use crate::actors::ConnectionActor;
+use crate::protocol::{IrcCodec, Message};
+use crate::security::SecurityError;
 use crate::state::ServerState;
 use crate::utils::config::ServerConfig;
 
+const TLS_HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+/// How long a rejected client gets to take its ERROR line
+const REJECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     // Print startup banner to stderr (not logged)
@@ -59,51 +68,174 @@ async fn main() -> Result<(), Box<dyn Error>> {
         None
     };
 
-    // Use configured address or default
-    let addr = if let Some(ref cfg) = config {
-        if !cfg.server.listen_addresses.is_empty() {
-            cfg.server.listen_addresses[0].parse::<SocketAddr>()?
-        } else {
-            "127.0.0.1:6667".parse::<SocketAddr>()?
+    let config = config.unwrap_or_default();
+
+    // `--rollback <version>` reverts the schema to that version and exits
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--rollback" {
+            let target: i64 = args.next()
+                .ok_or("--rollback needs a schema version")?
+                .parse()
+                .map_err(|e| format!("invalid schema version: {}", e))?;
+            return rollback_database(&config, target).await;
         }
+    }
+
+    // TLS listeners need a certificate; refuse to start without the ports
+    // the config asks for
+    let tls_acceptor = if config.server.tls_listen_addresses.is_empty() {
+        None
+    } else if config.security.tls_cert_file.is_none() || config.security.tls_key_file.is_none() {
+        return Err("tls_listen_addresses is set but tls_cert_file/tls_key_file are not".into());
     } else {
-        "127.0.0.1:6667".parse::<SocketAddr>()?
+        Some(security::tls::acceptor_from_settings(&config.security)
+            .map_err(|e| format!("failed to load TLS certificate: {}", e))?)
     };
 
-    let listener = TcpListener::bind(addr).await?;
+    let listeners = bind_listeners(&config, tls_acceptor.as_ref()).await?;
     
     // Print startup info to stderr 
     eprintln!("✅ Server started successfully!");
-    eprintln!("📡 Listening on: {}", addr);
-    eprintln!("🔐 TLS support: Available on port 6697");
+    for (addr, _, tls) in &listeners {
+        if tls.is_some() {
+            eprintln!("🔐 TLS listening on: {}", addr);
+        } else {
+            eprintln!("📡 Listening on: {}", addr);
+        }
+    }
+    if tls_acceptor.is_none() {
+        eprintln!("🔓 TLS support: Disabled");
+    }
+    if config.security.require_tls {
+        eprintln!("🔒 Plaintext registration: Rejected (require_tls)");
+    }
     eprintln!("🚀 Legion Protocol: Enabled");
     eprintln!("");
     eprintln!("Press Ctrl+C to shutdown gracefully");
     eprintln!("{}", "-".repeat(60));
     
-    info!("Centurion server with Legion Protocol starting on {}", addr);
+    let addresses: Vec<String> = listeners.iter().map(|(addr, _, _)| addr.to_string()).collect();
+    info!("Centurion server with Legion Protocol starting on {}", addresses.join(", "));
 
-    let mut server_state = ServerState::new();
+    let mut server_state = ServerState::with_config(config)?;
+    server_state.init_database().await
+        .map_err(|e| format!("failed to initialize database: {}", e))?;
     
     // Initialize Legion Protocol support
     if let Err(e) = server_state.init_legion().await {
         warn!("Legion Protocol initialization failed: {}. Running without Legion support.", e);
     }
     
-    let server_state = Arc::new(RwLock::new(server_state));
+    let server_state = Arc::new(server_state);
     
-    // Start server actor
-    let (server_actor, _server_tx) = ServerActor::new(Arc::clone(&server_state));
-    tokio::spawn(server_actor.run());
+    // One accept loop per listener, all sharing the same server state
+    for (_, listener, tls) in listeners {
+        tokio::spawn(accept_loop(listener, tls, Arc::clone(&server_state)));
+    }
+
+    tokio::signal::ctrl_c().await?;
+    info!("Received Ctrl+C, shutting down");
+
+    Ok(())
+}
 
+/// Revert the configured database to schema version `target`
+async fn rollback_database(config: &ServerConfig, target: i64) -> Result<(), Box<dyn Error>> {
+    if config.database.url.is_empty() {
+        return Err("--rollback needs a database url in the config".into());
+    }
+    let database = db::Database::connect(&config.database).await?;
+    let reverted = database.rollback_migrations(target).await?;
+    if reverted.is_empty() {
+        eprintln!("Schema is already at or below version {}", target);
+    } else {
+        for version in reverted {
+            eprintln!("Rolled back migration {}", version);
+        }
+    }
+    Ok(())
+}
+
+/// Bind every plaintext and TLS listen address, failing on the first error
+async fn bind_listeners(
+    config: &ServerConfig,
+    tls_acceptor: Option<&TlsAcceptor>,
+) -> Result<Vec<(SocketAddr, TcpListener, Option<TlsAcceptor>)>, Box<dyn Error>> {
+    let mut plaintext = config.server.listen_addresses.clone();
+    if plaintext.is_empty() && config.server.tls_listen_addresses.is_empty() {
+        plaintext.push("127.0.0.1:6667".to_string());
+    }
+
+    let mut addresses: Vec<(String, Option<TlsAcceptor>)> = plaintext
+        .into_iter()
+        .map(|addr| (addr, None))
+        .collect();
+    if let Some(acceptor) = tls_acceptor {
+        for addr in &config.server.tls_listen_addresses {
+            addresses.push((addr.clone(), Some(acceptor.clone())));
+        }
+    }
+
+    let mut listeners = Vec::with_capacity(addresses.len());
+    for (address, tls) in addresses {
+        let kind = if tls.is_some() { "TLS" } else { "plaintext" };
+        let addr = address.parse::<SocketAddr>()
+            .map_err(|e| format!("invalid {} listen address '{}': {}", kind, address, e))?;
+        let listener = TcpListener::bind(addr).await
+            .map_err(|e| format!("failed to bind {} listener on {}: {}", kind, addr, e))?;
+        listeners.push((addr, listener, tls));
+    }
+
+    Ok(listeners)
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    tls_acceptor: Option<TlsAcceptor>,
+    server_state: Arc<ServerState>,
+) {
     loop {
         match listener.accept().await {
             Ok((stream, peer_addr)) => {
+                // D-lined addresses are dropped before anything is spawned
+                if let Err(SecurityError::Banned(reason)) = server_state.server_bans.check_address(peer_addr.ip()) {
+                    info!("Rejecting D-lined connection from {}: {}", peer_addr, reason);
+                    if tls_acceptor.is_none() {
+                        let error = format!("ERROR :Closing Link: {} (D-Lined: {})\r\n", peer_addr.ip(), reason);
+                        let _ = stream.try_write(error.as_bytes());
+                    }
+                    continue;
+                }
+                
                 info!("New connection from {}", peer_addr);
                 let state = Arc::clone(&server_state);
-                
+                let tls_acceptor = tls_acceptor.clone();
+
                 tokio::spawn(async move {
-                    if let Err(e) = handle_connection(stream, peer_addr, state).await {
+                    let result = match tls_acceptor {
+                        Some(acceptor) => {
+                            let tls_stream = match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                                Ok(Ok(tls_stream)) => tls_stream,
+                                Ok(Err(e)) => {
+                                    debug!("TLS handshake with {} failed: {}", peer_addr, e);
+                                    return;
+                                }
+                                Err(_) => {
+                                    debug!("TLS handshake with {} timed out", peer_addr);
+                                    return;
+                                }
+                            };
+                            let certfp = tls_stream.get_ref().1
+                                .peer_certificates()
+                                .and_then(|certs| certs.first())
+                                .map(security::tls::certificate_fingerprint);
+                            handle_connection(tls_stream, peer_addr, state, true, certfp).await
+                        }
+                        None => handle_connection(stream, peer_addr, state, false, None).await,
+                    };
+
+                    if let Err(e) = result {
                         error!("Connection error for {}: {}", peer_addr, e);
                     }
                 });
@@ -115,24 +247,48 @@ async fn main() -> Result<(), Box<dyn Error>> {
     }
 }
 
-async fn handle_connection(
-    stream: TcpStream,
+async fn handle_connection<S>(
+    stream: S,
     peer_addr: SocketAddr,
-    server_state: Arc<RwLock<ServerState>>,
-) -> Result<(), Box<dyn Error>> {
-    let connection_id = {
-        let state = server_state.read().await;
-        state.generate_connection_id()
-    };
+    server_state: Arc<ServerState>,
+    secure: bool,
+    certfp: Option<String>,
+) -> Result<(), Box<dyn Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    // Admit the client into its connection class; from here on the actor
+    // owns the slot and releases it on cleanup
+    let ip = peer_addr.ip();
+    let class = server_state.classes.for_address(ip);
+    let admitted = server_state.connection_limiter.throttle(ip, &class)
+        .and_then(|()| server_state.connection_limiter.check_and_add(ip, &class));
+    if let Err(e) = admitted {
+        info!("Rejecting {} (class {}): {}", peer_addr, class.name, e);
+        let reason = match e {
+            SecurityError::Throttled => "Trying to reconnect too fast",
+            SecurityError::ClassFull => "Server is full",
+            _ => "Too many connections from your host",
+        };
+        let mut stream = Framed::new(stream, IrcCodec::new());
+        let error = Message::new("ERROR").with_params(vec![reason.to_string()]);
+        let _ = tokio::time::timeout(REJECT_TIMEOUT, stream.send(error)).await;
+        return Ok(());
+    }
+    
+    let connection_id = server_state.generate_connection_id();
     
     let connection_actor = ConnectionActor::new(
         connection_id,
         stream,
         peer_addr,
         server_state,
+        secure,
+        certfp,
+        class,
     ).await;
     
     connection_actor.run().await;
     
     Ok(())
-}
\ No newline at end of file
+}
@@ -22,6 +22,9 @@ pub enum Command {
     Notice { target: String, message: String },
     
     // User queries
+    Away(Option<String>),
+    Ison(Vec<String>),
+    Userhost(Vec<String>),
     Who(Option<String>),
     Whois(Vec<String>),
     Whowas(String, Option<i32>),
@@ -48,6 +51,14 @@ pub enum Command {
     TagMsg { target: String },
     Batch { reference: String, batch_type: Option<String>, params: Vec<String> },
     
+    // Account management
+    Certfp { subcommand: String, params: Vec<String> },
+    // Parameters are checked by the handler so it can answer with FAIL replies
+    Register(Vec<String>),
+    Verify(Vec<String>),
+    Unregister(Vec<String>),
+    ChanReg { subcommand: String, params: Vec<String> },
+    
     // 2024 Bleeding-edge IRCv3 commands
     Redact { target: String, msgid: String, reason: Option<String> },
     MarkRead { target: String, timestamp: Option<String> },
@@ -57,9 +68,15 @@ pub enum Command {
     // Operator commands
     Oper { name: String, password: String },
     Kill { nick: String, reason: String },
+    Wallops(String),
     Rehash,
     Restart,
     Die,
+    // `[minutes] <mask> [reason]`, taken apart by the handler
+    Kline(Vec<String>),
+    Unkline(String),
+    Dline(Vec<String>),
+    Undline(String),
     
     // CTCP
     CtcpRequest { target: String, command: String, params: String },
@@ -131,6 +148,100 @@ impl Command {
                     Command::Unknown(command.to_string(), params)
                 }
             }
+            "TOPIC" => {
+                if let Some(channel) = params.first() {
+                    Command::Topic {
+                        channel: channel.clone(),
+                        topic: params.get(1).cloned(),
+                    }
+                } else {
+                    Command::Unknown(command.to_string(), params)
+                }
+            }
+            // The NAMES and LIST handlers split the channel list themselves
+            "NAMES" => Command::Names(params.first().cloned().into_iter().collect()),
+            "LIST" => Command::List(params.first().map(|channels| vec![channels.clone()])),
+            "WHO" => Command::Who(params.first().cloned()),
+            "MOTD" => Command::Motd(params.first().cloned()),
+            "KICK" => {
+                if params.len() >= 2 {
+                    Command::Kick {
+                        channel: params[0].clone(),
+                        user: params[1].clone(),
+                        reason: params.get(2).cloned(),
+                    }
+                } else {
+                    Command::Unknown(command.to_string(), params)
+                }
+            }
+            "INVITE" => {
+                if params.len() >= 2 {
+                    Command::Invite {
+                        nick: params[0].clone(),
+                        channel: params[1].clone(),
+                    }
+                } else {
+                    Command::Unknown(command.to_string(), params)
+                }
+            }
+            "AWAY" => Command::Away(params.first().cloned()),
+            "ISON" => {
+                // Some clients send the nicknames as one trailing parameter
+                let nicks: Vec<String> = params.iter()
+                    .flat_map(|param| param.split_whitespace().map(str::to_string))
+                    .collect();
+                if !nicks.is_empty() {
+                    Command::Ison(nicks)
+                } else {
+                    Command::Unknown(command.to_string(), params)
+                }
+            }
+            "WHOWAS" => {
+                if let Some(nick) = params.first() {
+                    // Only the first of a comma-separated list is looked up
+                    let nick = nick.split(',').next().unwrap_or(nick).to_string();
+                    Command::Whowas(nick, params.get(1).and_then(|count| count.parse().ok()))
+                } else {
+                    Command::Unknown(command.to_string(), params)
+                }
+            }
+            "USERHOST" => {
+                if !params.is_empty() {
+                    Command::Userhost(params)
+                } else {
+                    Command::Unknown(command.to_string(), params)
+                }
+            }
+            "MONITOR" => {
+                if let Some(subcommand) = params.first() {
+                    Command::Monitor {
+                        subcommand: subcommand.clone(),
+                        targets: params.get(1)
+                            .map(|targets| targets.split(',').filter(|target| !target.is_empty()).map(str::to_string).collect())
+                            .unwrap_or_default(),
+                    }
+                } else {
+                    Command::Unknown(command.to_string(), params)
+                }
+            }
+            "WALLOPS" => {
+                if let Some(text) = params.first() {
+                    Command::Wallops(text.clone())
+                } else {
+                    Command::Unknown(command.to_string(), params)
+                }
+            }
+            "MODE" => {
+                if let Some(target) = params.first() {
+                    Command::Mode {
+                        target: target.clone(),
+                        modes: params.get(1).cloned(),
+                        params: params.iter().skip(2).cloned().collect(),
+                    }
+                } else {
+                    Command::Unknown(command.to_string(), params)
+                }
+            }
             "PRIVMSG" => {
                 if params.len() >= 2 {
                     Command::Privmsg {
@@ -191,6 +302,53 @@ impl Command {
                     Command::Unknown(command.to_string(), params)
                 }
             }
+            "CERTFP" => {
+                // A bare CERTFP lists the fingerprints on the account
+                Command::Certfp {
+                    subcommand: params.first().cloned().unwrap_or_else(|| "LIST".to_string()),
+                    params: params.into_iter().skip(1).collect(),
+                }
+            }
+            "OPER" => {
+                if params.len() >= 2 {
+                    Command::Oper {
+                        name: params[0].clone(),
+                        password: params[1].clone(),
+                    }
+                } else {
+                    Command::Unknown(command.to_string(), params)
+                }
+            }
+            "STATS" => Command::Stats(params.first().cloned(), params.get(1).cloned()),
+            "KLINE" => Command::Kline(params),
+            "DLINE" => Command::Dline(params),
+            "UNKLINE" => {
+                if let Some(mask) = params.first() {
+                    Command::Unkline(mask.clone())
+                } else {
+                    Command::Unknown(command.to_string(), params)
+                }
+            }
+            "UNDLINE" => {
+                if let Some(mask) = params.first() {
+                    Command::Undline(mask.clone())
+                } else {
+                    Command::Unknown(command.to_string(), params)
+                }
+            }
+            "REGISTER" => Command::Register(params),
+            "VERIFY" => Command::Verify(params),
+            "UNREGISTER" => Command::Unregister(params),
+            "CHANREG" => {
+                if let Some(subcommand) = params.first() {
+                    Command::ChanReg {
+                        subcommand: subcommand.clone(),
+                        params: params[1..].to_vec(),
+                    }
+                } else {
+                    Command::Unknown(command.to_string(), params)
+                }
+            }
             "REDACT" => {
                 if params.len() >= 2 {
                     Command::Redact {
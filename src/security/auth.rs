@@ -1,11 +1,13 @@
-use argon2::{Argon2, PasswordHash};
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordVerifier, Version};
 use argon2::password_hash::{rand_core::OsRng, SaltString, PasswordHasher as ArgonPasswordHasher};
 use base64::{Engine as _, engine::general_purpose};
 use hmac::{Hmac, Mac};
 use pbkdf2::pbkdf2_hmac;
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
+use crate::utils::config::SecuritySettings;
+
 #[derive(Error, Debug)]
 pub enum AuthError {
     #[error("Invalid credentials")]
@@ -19,6 +21,9 @@ pub enum AuthError {
     
     #[error("Invalid auth data")]
     InvalidAuthData,
+    
+    #[error("Invalid password hashing parameters: {0}")]
+    InvalidParameters(String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -59,17 +64,99 @@ pub enum AuthMethod {
     },
 }
 
-pub struct LocalPasswordHasher;
+/// Argon2id password hashing. Hashes are stored as PHC strings, which carry
+/// their own salt and cost parameters, so verification works across
+/// parameter changes and `needs_rehash` tells us when to upgrade a hash.
+#[derive(Debug, Clone, Default)]
+pub struct LocalPasswordHasher {
+    params: Params,
+}
 
 impl LocalPasswordHasher {
-    pub fn hash_password(password: &str) -> Result<String, AuthError> {
-        // Simplified for now - in production use proper hashing
-        Ok(format!("hash_{}", password))
+    pub fn new(memory_cost: u32, time_cost: u32, parallelism: u32) -> Result<Self, AuthError> {
+        let params = Params::new(memory_cost, time_cost, parallelism, None)
+            .map_err(|e| AuthError::InvalidParameters(e.to_string()))?;
+        Ok(Self { params })
+    }
+    
+    pub fn from_settings(settings: &SecuritySettings) -> Result<Self, AuthError> {
+        match settings.password_hash_algorithm.to_lowercase().as_str() {
+            "argon2" | "argon2id" => Self::new(
+                settings.argon2_memory_cost,
+                settings.argon2_time_cost,
+                settings.argon2_parallelism,
+            ),
+            other => Err(AuthError::InvalidParameters(format!("unsupported password_hash_algorithm '{}'", other))),
+        }
     }
     
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+    
+    pub fn hash_password(&self, password: &str) -> Result<String, AuthError> {
+        let salt = SaltString::generate(&mut OsRng);
+        self.argon2()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| AuthError::InvalidParameters(e.to_string()))
+    }
+    
+    /// Verify against any Argon2 PHC string, using the parameters embedded in
+    /// it. The digest comparison is constant-time.
     pub fn verify_password(password: &str, hash: &str) -> Result<bool, AuthError> {
-        // Simplified for now - in production use proper verification
-        Ok(hash == &format!("hash_{}", password))
+        let parsed = PasswordHash::new(hash).map_err(|_| AuthError::InvalidAuthData)?;
+        match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+            Ok(()) => Ok(true),
+            Err(argon2::password_hash::Error::Password) => Ok(false),
+            Err(_) => Err(AuthError::InvalidAuthData),
+        }
+    }
+    
+    /// True if `hash` was not produced with Argon2id at our current parameters
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        let Ok(parsed) = PasswordHash::new(hash) else {
+            return true;
+        };
+        
+        parsed.algorithm != Algorithm::Argon2id.ident()
+            || parsed.version != Some(Version::V0x13.into())
+            || Params::try_from(&parsed).map_or(true, |params| {
+                params.m_cost() != self.params.m_cost()
+                    || params.t_cost() != self.params.t_cost()
+                    || params.p_cost() != self.params.p_cost()
+            })
+    }
+}
+
+/// Salted SCRAM-SHA-256 keys (RFC 5802 / RFC 7677) derived from an account
+/// password. Only these are kept, never the password itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScramCredentials {
+    pub salt: Vec<u8>,
+    pub iterations: u32,
+    pub stored_key: Vec<u8>,
+    pub server_key: Vec<u8>,
+}
+
+impl ScramCredentials {
+    pub const DEFAULT_ITERATIONS: u32 = 4096;
+
+    pub fn new(password: &str, salt: &[u8], iterations: u32) -> Self {
+        let salted_password = derive_salted_password(password.as_bytes(), salt, iterations);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+
+        Self {
+            salt: salt.to_vec(),
+            iterations,
+            stored_key: Sha256::digest(&client_key).to_vec(),
+            server_key: hmac_sha256(&salted_password, b"Server Key"),
+        }
+    }
+
+    /// Derive credentials with a fresh random salt
+    pub fn generate(password: &str) -> Self {
+        Self::new(password, &generate_salt(), Self::DEFAULT_ITERATIONS)
     }
 }
 
@@ -78,10 +165,9 @@ pub struct SaslAuthenticator {
 }
 
 struct ScramServerState {
-    client_nonce: String,
-    server_nonce: String,
-    salt: Vec<u8>,
-    iterations: u32,
+    gs2_header: String,
+    nonce: String,
+    credentials: ScramCredentials,
     auth_message: String,
 }
 
@@ -112,73 +198,103 @@ impl SaslAuthenticator {
         }
     }
     
-    pub fn start_scram_sha256(&mut self, client_first: &str) -> Result<String, AuthError> {
-        // Parse client first message
-        let parts: Vec<&str> = client_first.split(',').collect();
-        if parts.len() < 3 {
+    /// Extract the username from a SCRAM client-first message so the caller
+    /// can look up the stored credentials before starting the exchange
+    pub fn scram_username(client_first: &str) -> Result<String, AuthError> {
+        let (_, bare) = split_client_first(client_first)?;
+        let name = bare
+            .split(',')
+            .next()
+            .and_then(|attr| attr.strip_prefix("n="))
+            .ok_or(AuthError::InvalidAuthData)?;
+
+        if name.is_empty() {
             return Err(AuthError::InvalidAuthData);
         }
+
+        Ok(name.replace("=2C", ",").replace("=3D", "="))
+    }
+    
+    pub fn start_scram_sha256(
+        &mut self,
+        client_first: &str,
+        credentials: ScramCredentials,
+    ) -> Result<String, AuthError> {
+        let (gs2_header, client_first_bare) = split_client_first(client_first)?;
         
-        let client_nonce = parts[2]
-            .strip_prefix("r=")
+        let client_nonce = client_first_bare
+            .split(',')
+            .find_map(|attr| attr.strip_prefix("r="))
+            .filter(|nonce| !nonce.is_empty())
             .ok_or(AuthError::InvalidAuthData)?;
         
-        // Generate server nonce
-        let server_nonce = format!("{}{}", client_nonce, generate_nonce());
-        
-        // Generate salt and iteration count
-        let salt = generate_salt();
-        let iterations = 4096u32;
+        // Server nonce extends the client nonce
+        let nonce = format!("{}{}", client_nonce, generate_nonce());
         
-        // Build server first message
+        // Build server first message from the stored salt and iteration count
         let server_first = format!(
             "r={},s={},i={}",
-            server_nonce,
-            general_purpose::STANDARD.encode(&salt),
-            iterations
+            nonce,
+            general_purpose::STANDARD.encode(&credentials.salt),
+            credentials.iterations
         );
         
         self.scram_server_state = Some(ScramServerState {
-            client_nonce: client_nonce.to_string(),
-            server_nonce: server_nonce.clone(),
-            salt,
-            iterations,
-            auth_message: format!("{},{}", client_first, server_first),
+            gs2_header: gs2_header.to_string(),
+            nonce,
+            credentials,
+            auth_message: format!("{},{}", client_first_bare, server_first),
         });
         
         Ok(server_first)
     }
     
-    pub fn verify_scram_sha256(
-        &mut self,
-        client_final: &str,
-        stored_password: &str,
-    ) -> Result<String, AuthError> {
+    /// Check the client proof against the stored key and return the
+    /// server-final message carrying our signature
+    pub fn verify_scram_sha256(&mut self, client_final: &str) -> Result<String, AuthError> {
         let state = self.scram_server_state
-            .as_mut()
+            .take()
             .ok_or(AuthError::InvalidAuthData)?;
         
         // Parse client final message
-        let parts: Vec<&str> = client_final.split(',').collect();
-        let _client_proof = parts.iter()
-            .find(|p| p.starts_with("p="))
-            .and_then(|p| p.strip_prefix("p="))
+        let (without_proof, proof) = client_final
+            .rsplit_once(",p=")
+            .ok_or(AuthError::InvalidAuthData)?;
+        let client_proof = general_purpose::STANDARD
+            .decode(proof)
+            .map_err(|_| AuthError::InvalidAuthData)?;
+        
+        let mut attrs = without_proof.split(',');
+        let channel_binding = attrs.next()
+            .and_then(|attr| attr.strip_prefix("c="))
+            .ok_or(AuthError::InvalidAuthData)?;
+        let nonce = attrs.next()
+            .and_then(|attr| attr.strip_prefix("r="))
             .ok_or(AuthError::InvalidAuthData)?;
         
+        if channel_binding != general_purpose::STANDARD.encode(&state.gs2_header) || nonce != state.nonce {
+            return Err(AuthError::InvalidAuthData);
+        }
+        
         // Complete auth message
-        let channel_binding = "c=biws";
-        let without_proof = format!("{},r={}", channel_binding, state.server_nonce);
-        state.auth_message = format!("{},{}", state.auth_message, without_proof);
-        
-        // Calculate server signature
-        let salted_password = derive_salted_password(
-            stored_password.as_bytes(),
-            &state.salt,
-            state.iterations,
-        );
+        let auth_message = format!("{},{}", state.auth_message, without_proof);
+        let credentials = &state.credentials;
         
-        let server_key = hmac_sha256(&salted_password, b"Server Key");
-        let server_signature = hmac_sha256(&server_key, state.auth_message.as_bytes());
+        // ClientKey = ClientProof XOR HMAC(StoredKey, AuthMessage)
+        let client_signature = hmac_sha256(&credentials.stored_key, auth_message.as_bytes());
+        if client_proof.len() != client_signature.len() {
+            return Err(AuthError::InvalidCredentials);
+        }
+        let client_key: Vec<u8> = client_proof.iter()
+            .zip(&client_signature)
+            .map(|(p, s)| p ^ s)
+            .collect();
+        
+        if !constant_time_eq(&Sha256::digest(&client_key), &credentials.stored_key) {
+            return Err(AuthError::InvalidCredentials);
+        }
+        
+        let server_signature = hmac_sha256(&credentials.server_key, auth_message.as_bytes());
         
         // Build server final message
         let server_final = format!(
@@ -228,6 +344,22 @@ fn generate_salt() -> Vec<u8> {
     (0..16).map(|_| rng.gen()).collect()
 }
 
+/// Split a client-first message into its GS2 header and the bare message.
+/// Channel binding is not supported, so "p=" headers are rejected.
+fn split_client_first(client_first: &str) -> Result<(&str, &str), AuthError> {
+    let mut split = client_first.splitn(3, ',');
+    let binding = split.next().ok_or(AuthError::InvalidAuthData)?;
+    let authzid = split.next().ok_or(AuthError::InvalidAuthData)?;
+    let bare = split.next().ok_or(AuthError::InvalidAuthData)?;
+
+    if binding != "n" && binding != "y" {
+        return Err(AuthError::UnsupportedMechanism);
+    }
+
+    let header_len = binding.len() + authzid.len() + 2;
+    Ok((&client_first[..header_len], bare))
+}
+
 fn derive_salted_password(password: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
     let mut result = vec![0u8; 32];
     pbkdf2_hmac::<Sha256>(password, salt, iterations, &mut result);
@@ -238,4 +370,94 @@ fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
     let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
     mac.update(data);
     mac.finalize().into_bytes().to_vec()
-}
\ No newline at end of file
+}
+
+/// Compare secrets without leaking the position of the first mismatch
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Client side of SCRAM-SHA-256, returning (client-final, expected server-final)
+    fn client_final(password: &str, client_first_bare: &str, server_first: &str) -> (String, String) {
+        let attrs: Vec<&str> = server_first.split(',').collect();
+        let nonce = attrs[0].strip_prefix("r=").unwrap();
+        let salt = general_purpose::STANDARD.decode(attrs[1].strip_prefix("s=").unwrap()).unwrap();
+        let iterations: u32 = attrs[2].strip_prefix("i=").unwrap().parse().unwrap();
+
+        let salted_password = derive_salted_password(password.as_bytes(), &salt, iterations);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+        let stored_key = Sha256::digest(&client_key);
+        let without_proof = format!("c=biws,r={}", nonce);
+        let auth_message = format!("{},{},{}", client_first_bare, server_first, without_proof);
+        let client_signature = hmac_sha256(&stored_key, auth_message.as_bytes());
+        let proof: Vec<u8> = client_key.iter().zip(&client_signature).map(|(k, s)| k ^ s).collect();
+
+        let server_key = hmac_sha256(&salted_password, b"Server Key");
+        let server_signature = hmac_sha256(&server_key, auth_message.as_bytes());
+
+        (
+            format!("{},p={}", without_proof, general_purpose::STANDARD.encode(proof)),
+            format!("v={}", general_purpose::STANDARD.encode(server_signature)),
+        )
+    }
+
+    #[test]
+    fn test_argon2id_hash_and_verify() {
+        let hasher = LocalPasswordHasher::new(8 * 1024, 1, 1).unwrap();
+        let hash = hasher.hash_password("hunter2").unwrap();
+
+        assert!(hash.starts_with("$argon2id$v=19$m=8192,t=1,p=1$"));
+        assert!(LocalPasswordHasher::verify_password("hunter2", &hash).unwrap());
+        assert!(!LocalPasswordHasher::verify_password("hunter3", &hash).unwrap());
+        assert!(LocalPasswordHasher::verify_password("hunter2", "hash_hunter2").is_err());
+    }
+
+    #[test]
+    fn test_needs_rehash_on_parameter_change() {
+        let old = LocalPasswordHasher::new(8 * 1024, 1, 1).unwrap();
+        let new = LocalPasswordHasher::new(8 * 1024, 2, 1).unwrap();
+        let hash = old.hash_password("hunter2").unwrap();
+
+        assert!(!old.needs_rehash(&hash));
+        assert!(new.needs_rehash(&hash));
+        assert!(new.needs_rehash("not a phc string"));
+        assert!(LocalPasswordHasher::new(1, 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_scram_sha256_exchange() {
+        let credentials = ScramCredentials::generate("pencil");
+        let client_first = "n,,n=user,r=rOprNGfwEbeRWgbNEkqO";
+        assert_eq!(SaslAuthenticator::scram_username(client_first).unwrap(), "user");
+
+        let mut auth = SaslAuthenticator::new();
+        let server_first = auth.start_scram_sha256(client_first, credentials).unwrap();
+        assert!(server_first.starts_with("r=rOprNGfwEbeRWgbNEkqO"));
+
+        let (final_message, expected) = client_final("pencil", "n=user,r=rOprNGfwEbeRWgbNEkqO", &server_first);
+        assert_eq!(auth.verify_scram_sha256(&final_message).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_scram_sha256_rejects_wrong_password() {
+        let credentials = ScramCredentials::generate("pencil");
+        let client_first = "n,,n=user,r=abcdef";
+
+        let mut auth = SaslAuthenticator::new();
+        let server_first = auth.start_scram_sha256(client_first, credentials).unwrap();
+        let (final_message, _) = client_final("crayon", "n=user,r=abcdef", &server_first);
+
+        assert!(matches!(auth.verify_scram_sha256(&final_message), Err(AuthError::InvalidCredentials)));
+    }
+
+    #[test]
+    fn test_scram_sha256_rejects_channel_binding() {
+        assert!(SaslAuthenticator::new()
+            .start_scram_sha256("p=tls-unique,,n=user,r=abc", ScramCredentials::generate("pencil"))
+            .is_err());
+    }
+}
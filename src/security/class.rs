@@ -0,0 +1,138 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ipnet::IpNet;
+
+use crate::utils::config::{ClassSettings, LimitSettings, ServerConfig};
+
+/// Limits applied to every connection placed in a class
+#[derive(Debug, Clone)]
+pub struct ConnectClass {
+    pub name: String,
+    hosts: Vec<IpNet>,
+    accounts: Vec<String>,
+    pub max_clients: usize,
+    pub max_clients_per_ip: usize,
+    pub max_clients_per_64: usize,
+    /// Connections one address may open per `connect_throttle_interval`
+    pub connect_throttle: usize,
+    pub connect_throttle_interval: Duration,
+    pub ping_frequency: Duration,
+    pub sendq: usize,
+    pub flood_messages: usize,
+    pub flood_interval: Duration,
+    pub flood_ceiling: usize,
+    pub flood_exempt: bool,
+}
+
+impl ConnectClass {
+    /// The class used for clients no configured class matches
+    fn from_limits(limits: &LimitSettings) -> Self {
+        Self::from_settings(&ClassSettings { name: "default".to_string(), ..Default::default() }, limits)
+    }
+
+    fn from_settings(settings: &ClassSettings, limits: &LimitSettings) -> Self {
+        Self {
+            name: settings.name.clone(),
+            hosts: settings.hosts.clone(),
+            accounts: settings.accounts.clone(),
+            max_clients: settings.max_clients.unwrap_or(limits.max_clients),
+            max_clients_per_ip: settings.max_clients_per_ip.unwrap_or(limits.max_clients_per_ip),
+            max_clients_per_64: settings.max_clients_per_64.unwrap_or(limits.max_clients_per_64),
+            connect_throttle: settings.connect_throttle.unwrap_or(limits.connect_throttle),
+            connect_throttle_interval: Duration::from_secs(
+                settings.connect_throttle_interval.unwrap_or(limits.connect_throttle_interval),
+            ),
+            ping_frequency: Duration::from_secs(settings.ping_frequency.unwrap_or(limits.ping_frequency)),
+            sendq: settings.sendq.unwrap_or(limits.sendq),
+            flood_messages: settings.flood_messages.unwrap_or(limits.flood_messages),
+            flood_interval: Duration::from_secs(settings.flood_interval.unwrap_or(limits.flood_interval)),
+            flood_ceiling: settings.flood_ceiling.unwrap_or(limits.flood_ceiling),
+            flood_exempt: settings.flood_exempt,
+        }
+    }
+
+    fn matches_host(&self, ip: IpAddr) -> bool {
+        self.hosts.is_empty() || self.hosts.iter().any(|net| net.contains(&ip))
+    }
+
+    fn matches_account(&self, account: &str) -> bool {
+        self.accounts.iter().any(|name| name.eq_ignore_ascii_case(account))
+    }
+}
+
+/// The configured connection classes, in the order they are tried
+#[derive(Debug)]
+pub struct ConnectClasses {
+    classes: Vec<Arc<ConnectClass>>,
+    default: Arc<ConnectClass>,
+}
+
+impl ConnectClasses {
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self {
+            classes: config.classes.iter()
+                .map(|settings| Arc::new(ConnectClass::from_settings(settings, &config.limits)))
+                .collect(),
+            default: Arc::new(ConnectClass::from_limits(&config.limits)),
+        }
+    }
+
+    /// The class a new connection from `ip` starts in. Account classes
+    /// aren't considered until the client has logged in.
+    pub fn for_address(&self, ip: IpAddr) -> Arc<ConnectClass> {
+        self.classes.iter()
+            .find(|class| class.accounts.is_empty() && class.matches_host(ip))
+            .unwrap_or(&self.default)
+            .clone()
+    }
+
+    /// The account class, if any, for a client from `ip` logged in as
+    /// `account`
+    pub fn for_account(&self, ip: IpAddr, account: &str) -> Option<Arc<ConnectClass>> {
+        self.classes.iter()
+            .find(|class| class.matches_account(account) && class.matches_host(ip))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classes_match_by_host_then_account() {
+        let config = ServerConfig {
+            classes: vec![
+                ClassSettings {
+                    name: "staff".to_string(),
+                    accounts: vec!["Admin".to_string()],
+                    max_clients_per_ip: Some(100),
+                    ..Default::default()
+                },
+                ClassSettings {
+                    name: "lan".to_string(),
+                    hosts: vec!["10.0.0.0/8".parse().unwrap(), "fd00::/8".parse().unwrap()],
+                    sendq: Some(4096),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let classes = ConnectClasses::from_config(&config);
+
+        let lan = classes.for_address("10.1.2.3".parse().unwrap());
+        assert_eq!(lan.name, "lan");
+        assert_eq!(lan.sendq, 4096);
+        // Unset limits fall back to the global ones
+        assert_eq!(lan.max_clients_per_ip, config.limits.max_clients_per_ip);
+        assert_eq!(classes.for_address("fd00::1".parse().unwrap()).name, "lan");
+        assert_eq!(classes.for_address("192.0.2.1".parse().unwrap()).name, "default");
+
+        let staff = classes.for_account("192.0.2.1".parse().unwrap(), "admin").unwrap();
+        assert_eq!(staff.name, "staff");
+        assert_eq!(staff.max_clients_per_ip, 100);
+        assert!(classes.for_account("192.0.2.1".parse().unwrap(), "someone").is_none());
+    }
+}
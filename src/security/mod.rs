@@ -1,17 +1,20 @@
 use std::collections::HashMap;
-use std::net::{IpAddr, SocketAddr};
+use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use governor::{Quota, RateLimiter as GovernorRateLimiter, state::direct::NotKeyed};
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter as GovernorRateLimiter, state::direct::NotKeyed};
 use parking_lot::RwLock;
 use thiserror::Error;
 
 pub mod auth;
+pub mod class;
+pub mod sasl;
 pub mod tls;
 pub mod validation;
 
 pub use self::auth::{AuthMethod, SaslMechanism, authenticate};
+pub use self::class::{ConnectClass, ConnectClasses};
 pub use self::validation::{validate_nickname, validate_channel_name, validate_message};
 
 #[derive(Error, Debug)]
@@ -22,6 +25,12 @@ pub enum SecurityError {
     #[error("Connection limit exceeded")]
     ConnectionLimitExceeded,
     
+    #[error("Connection class is full")]
+    ClassFull,
+    
+    #[error("Connecting too fast")]
+    Throttled,
+    
     #[error("Invalid credentials")]
     InvalidCredentials,
     
@@ -31,13 +40,15 @@ pub enum SecurityError {
     #[error("Access denied")]
     AccessDenied,
     
-    #[error("Banned")]
-    Banned,
+    #[error("Banned: {0}")]
+    Banned(String),
 }
 
 pub struct RateLimiter {
     quota: Quota,
     limiter: GovernorRateLimiter<NotKeyed, governor::state::InMemoryState, governor::clock::DefaultClock>,
+    /// Separate buckets, one per key, for `check_key`
+    keyed: DefaultKeyedRateLimiter<String>,
 }
 
 impl RateLimiter {
@@ -49,6 +60,7 @@ impl RateLimiter {
         Self {
             quota,
             limiter: GovernorRateLimiter::direct(quota),
+            keyed: GovernorRateLimiter::keyed(quota),
         }
     }
     
@@ -56,8 +68,8 @@ impl RateLimiter {
         self.limiter.check().is_ok()
     }
     
-    pub async fn check_key(&self, _key: &str) -> bool {
-        self.limiter.check().is_ok()
+    pub async fn check_key(&self, key: &str) -> bool {
+        self.keyed.check_key(&key.to_string()).is_ok()
     }
 }
 
@@ -66,94 +78,197 @@ pub struct ConnectionLimiter {
 }
 
 struct ConnectionLimits {
-    per_ip: HashMap<IpAddr, usize>,
+    classes: HashMap<String, ClassCount>,
+    /// Recent connection attempts per address, for the connect throttle
+    attempts: HashMap<IpAddr, Vec<Instant>>,
     global_count: usize,
-    max_per_ip: usize,
     max_global: usize,
 }
 
+/// Clients currently in one connection class
+#[derive(Default)]
+struct ClassCount {
+    clients: usize,
+    per_ip: HashMap<IpAddr, usize>,
+    per_64: HashMap<u64, usize>,
+}
+
+impl ClassCount {
+    /// Count a client from `ip`, unless `class`, the address or its /64 is
+    /// already full
+    fn admit(&mut self, ip: IpAddr, class: &ConnectClass) -> Result<(), SecurityError> {
+        if self.clients >= class.max_clients {
+            return Err(SecurityError::ClassFull);
+        }
+        if self.per_ip.get(&ip).copied().unwrap_or(0) >= class.max_clients_per_ip {
+            return Err(SecurityError::ConnectionLimitExceeded);
+        }
+        let prefix = prefix_64(ip);
+        if let Some(prefix) = prefix {
+            if self.per_64.get(&prefix).copied().unwrap_or(0) >= class.max_clients_per_64 {
+                return Err(SecurityError::ConnectionLimitExceeded);
+            }
+            *self.per_64.entry(prefix).or_insert(0) += 1;
+        }
+
+        *self.per_ip.entry(ip).or_insert(0) += 1;
+        self.clients += 1;
+        Ok(())
+    }
+
+    fn release(&mut self, ip: IpAddr) {
+        self.clients = self.clients.saturating_sub(1);
+        if let Some(per_ip) = self.per_ip.get_mut(&ip) {
+            *per_ip = per_ip.saturating_sub(1);
+            if *per_ip == 0 {
+                self.per_ip.remove(&ip);
+            }
+        }
+        if let Some(prefix) = prefix_64(ip) {
+            if let Some(per_64) = self.per_64.get_mut(&prefix) {
+                *per_64 = per_64.saturating_sub(1);
+                if *per_64 == 0 {
+                    self.per_64.remove(&prefix);
+                }
+            }
+        }
+    }
+}
+
+/// The /64 an IPv6 address belongs to. IPv4 clients are only limited per
+/// address.
+fn prefix_64(ip: IpAddr) -> Option<u64> {
+    match ip {
+        IpAddr::V4(_) => None,
+        IpAddr::V6(ip) => Some((u128::from(ip) >> 64) as u64),
+    }
+}
+
 impl ConnectionLimiter {
-    pub fn new(max_per_ip: usize, max_global: usize) -> Self {
+    pub fn new(max_global: usize) -> Self {
         Self {
             limits: Arc::new(RwLock::new(ConnectionLimits {
-                per_ip: HashMap::new(),
+                classes: HashMap::new(),
+                attempts: HashMap::new(),
                 global_count: 0,
-                max_per_ip,
                 max_global,
             })),
         }
     }
-    
-    pub fn check_and_add(&self, addr: SocketAddr) -> Result<(), SecurityError> {
+
+    /// Record a connection attempt from `ip`, failing once it has made more
+    /// than its class allows within the throttle interval
+    pub fn throttle(&self, ip: IpAddr, class: &ConnectClass) -> Result<(), SecurityError> {
         let mut limits = self.limits.write();
-        
-        if limits.global_count >= limits.max_global {
-            return Err(SecurityError::ConnectionLimitExceeded);
+        let now = Instant::now();
+
+        let attempts = limits.attempts.entry(ip).or_default();
+        attempts.retain(|&at| now.duration_since(at) < class.connect_throttle_interval);
+        attempts.push(now);
+        let throttled = attempts.len() > class.connect_throttle;
+
+        // Forget addresses that have gone quiet now and then
+        if limits.attempts.len() > 4096 {
+            limits.attempts.retain(|_, attempts| {
+                attempts.last().is_some_and(|&at| now.duration_since(at) < class.connect_throttle_interval)
+            });
         }
-        
-        let ip = addr.ip();
-        let max_per_ip = limits.max_per_ip;
-        let count = limits.per_ip.entry(ip).or_insert(0);
-        
-        if *count >= max_per_ip {
-            return Err(SecurityError::ConnectionLimitExceeded);
+
+        if throttled {
+            return Err(SecurityError::Throttled);
         }
-        
-        *count += 1;
+        Ok(())
+    }
+
+    /// Count a client from `ip` against `class`, unless the class, its
+    /// address or its /64 is already full
+    pub fn check_and_add(&self, ip: IpAddr, class: &ConnectClass) -> Result<(), SecurityError> {
+        let mut limits = self.limits.write();
+
+        if limits.global_count >= limits.max_global {
+            return Err(SecurityError::ClassFull);
+        }
+
+        limits.classes.entry(class.name.clone()).or_default().admit(ip, class)?;
         limits.global_count += 1;
-        
+
         Ok(())
     }
-    
-    pub fn remove(&self, addr: SocketAddr) {
+
+    /// Move a client counted in `from` over to `to`, unless `to` has no
+    /// room for it. The client is already connected, so the server-wide
+    /// limit doesn't apply.
+    pub fn move_class(&self, ip: IpAddr, from: &ConnectClass, to: &ConnectClass) -> Result<(), SecurityError> {
         let mut limits = self.limits.write();
-        let ip = addr.ip();
-        
-        if let Some(count) = limits.per_ip.get_mut(&ip) {
-            *count = count.saturating_sub(1);
-            if *count == 0 {
-                limits.per_ip.remove(&ip);
-            }
+
+        limits.classes.entry(to.name.clone()).or_default().admit(ip, to)?;
+        if let Some(count) = limits.classes.get_mut(&from.name) {
+            count.release(ip);
         }
-        
+
+        Ok(())
+    }
+
+    /// Release a client counted with `check_and_add`
+    pub fn remove(&self, ip: IpAddr, class: &ConnectClass) {
+        let mut limits = self.limits.write();
+
+        if let Some(count) = limits.classes.get_mut(&class.name) {
+            count.release(ip);
+        }
+
         limits.global_count = limits.global_count.saturating_sub(1);
     }
 }
 
+/// What to do with a command after charging its penalty
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloodVerdict {
+    /// Process it at this instant, which is later than now for a client
+    /// over its allowance (fakelag)
+    Due(Instant),
+    /// The client's backlog is past the hard ceiling
+    Excess,
+}
+
+/// Penalty-based flood control (fakelag) for one client. Each command
+/// pushes the client's penalty clock forward by its weight. A client may
+/// run up to `time_window` ahead of real time; beyond that its commands are
+/// held back until the clock catches up, and once it is `ceiling` further
+/// ahead it is disconnected.
 pub struct FloodProtection {
-    message_times: Arc<RwLock<HashMap<u64, Vec<Instant>>>>,
-    max_messages: usize,
+    /// Time one penalty unit costs
+    unit: Duration,
     time_window: Duration,
+    ceiling: Duration,
+    penalty_until: Instant,
 }
 
 impl FloodProtection {
-    pub fn new(max_messages: usize, time_window: Duration) -> Self {
+    /// Allow `max_messages` single-unit commands per `time_window`, with
+    /// `ceiling` units of backlog before disconnecting
+    pub fn new(max_messages: usize, time_window: Duration, ceiling: usize) -> Self {
+        let max_messages = max_messages.max(1) as u32;
+        let time_window = time_window.max(Duration::from_millis(1));
+        let unit = time_window / max_messages;
         Self {
-            message_times: Arc::new(RwLock::new(HashMap::new())),
-            max_messages,
+            unit,
             time_window,
+            ceiling: unit * ceiling as u32,
+            penalty_until: Instant::now(),
         }
     }
-    
-    pub fn check_flood(&self, connection_id: u64) -> bool {
-        let mut times = self.message_times.write();
-        let now = Instant::now();
-        
-        let messages = times.entry(connection_id).or_insert_with(Vec::new);
-        
-        // Remove old messages outside the time window
-        messages.retain(|&time| now.duration_since(time) < self.time_window);
-        
-        if messages.len() >= self.max_messages {
-            return false;
+
+    /// Charge a command of `weight` units received at `now`
+    pub fn penalize(&mut self, weight: usize, now: Instant) -> FloodVerdict {
+        self.penalty_until = self.penalty_until.max(now) + self.unit * weight as u32;
+        let ahead = self.penalty_until - now;
+
+        if ahead > self.time_window + self.ceiling {
+            FloodVerdict::Excess
+        } else {
+            FloodVerdict::Due(now + ahead.saturating_sub(self.time_window))
         }
-        
-        messages.push(now);
-        true
-    }
-    
-    pub fn clear(&self, connection_id: u64) {
-        self.message_times.write().remove(&connection_id);
     }
 }
 
@@ -219,7 +334,7 @@ impl BanManager {
     }
 }
 
-fn mask_matches(pattern: &str, text: &str) -> bool {
+pub fn mask_matches(pattern: &str, text: &str) -> bool {
     let pattern_chars: Vec<char> = pattern.chars().collect();
     let text_chars: Vec<char> = text.chars().collect();
     
@@ -249,4 +364,112 @@ fn mask_matches(pattern: &str, text: &str) -> bool {
     }
     
     dp[pattern_chars.len()][text_chars.len()]
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::config::{ClassSettings, ServerConfig};
+
+    fn class(settings: ClassSettings) -> ConnectClass {
+        let config = ServerConfig { classes: vec![settings], ..Default::default() };
+        ConnectClasses::from_config(&config).for_address("::1".parse().unwrap()).as_ref().clone()
+    }
+
+    #[test]
+    fn test_connection_limits_per_address_and_64() {
+        let class = class(ClassSettings {
+            name: "users".to_string(),
+            max_clients: Some(4),
+            max_clients_per_ip: Some(2),
+            max_clients_per_64: Some(3),
+            ..Default::default()
+        });
+        let limiter = ConnectionLimiter::new(100);
+        let ip = |addr: &str| addr.parse::<IpAddr>().unwrap();
+
+        limiter.check_and_add(ip("2001:db8::1"), &class).unwrap();
+        limiter.check_and_add(ip("2001:db8::1"), &class).unwrap();
+        assert!(matches!(limiter.check_and_add(ip("2001:db8::1"), &class), Err(SecurityError::ConnectionLimitExceeded)));
+
+        // A different address in the same /64 still counts against it
+        limiter.check_and_add(ip("2001:db8::2"), &class).unwrap();
+        assert!(matches!(limiter.check_and_add(ip("2001:db8::3"), &class), Err(SecurityError::ConnectionLimitExceeded)));
+
+        limiter.check_and_add(ip("192.0.2.1"), &class).unwrap();
+        assert!(matches!(limiter.check_and_add(ip("192.0.2.2"), &class), Err(SecurityError::ClassFull)));
+
+        limiter.remove(ip("2001:db8::1"), &class);
+        limiter.check_and_add(ip("2001:db8::3"), &class).unwrap();
+    }
+
+    #[test]
+    fn test_move_class_skips_global_limit() {
+        let users = class(ClassSettings { name: "users".to_string(), ..Default::default() });
+        let staff = class(ClassSettings { name: "staff".to_string(), max_clients: Some(1), ..Default::default() });
+        let limiter = ConnectionLimiter::new(2);
+        let ip = |addr: &str| addr.parse::<IpAddr>().unwrap();
+
+        limiter.check_and_add(ip("192.0.2.1"), &users).unwrap();
+        limiter.check_and_add(ip("192.0.2.2"), &users).unwrap();
+        assert!(matches!(limiter.check_and_add(ip("192.0.2.3"), &users), Err(SecurityError::ClassFull)));
+
+        // A full server doesn't stop a connected client changing class
+        limiter.move_class(ip("192.0.2.1"), &users, &staff).unwrap();
+        assert!(matches!(limiter.move_class(ip("192.0.2.2"), &users, &staff), Err(SecurityError::ClassFull)));
+
+        let limits = limiter.limits.read();
+        assert_eq!(limits.classes["users"].clients, 1);
+        assert_eq!(limits.classes["staff"].clients, 1);
+        assert_eq!(limits.global_count, 2);
+    }
+
+    #[test]
+    fn test_fakelag_delays_then_disconnects() {
+        // Ten single-unit commands a second, 100ms each, with 20 units of
+        // backlog allowed
+        let mut flood = FloodProtection::new(10, Duration::from_secs(1), 20);
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            assert_eq!(flood.penalize(1, now), FloodVerdict::Due(now));
+        }
+        assert_eq!(flood.penalize(1, now), FloodVerdict::Due(now + Duration::from_millis(100)));
+        assert_eq!(flood.penalize(4, now), FloodVerdict::Due(now + Duration::from_millis(500)));
+
+        // Idling pays the penalty off
+        let later = now + Duration::from_secs(3);
+        assert_eq!(flood.penalize(1, later), FloodVerdict::Due(later));
+
+        for _ in 0..29 {
+            assert!(matches!(flood.penalize(1, later), FloodVerdict::Due(_)));
+        }
+        assert_eq!(flood.penalize(1, later), FloodVerdict::Excess);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_keys_are_independent() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+
+        assert!(limiter.check_key("192.0.2.1").await);
+        assert!(limiter.check_key("192.0.2.1").await);
+        assert!(!limiter.check_key("192.0.2.1").await);
+        assert!(limiter.check_key("192.0.2.2").await);
+    }
+
+    #[test]
+    fn test_connect_throttle() {
+        let class = class(ClassSettings {
+            name: "users".to_string(),
+            connect_throttle: Some(2),
+            ..Default::default()
+        });
+        let limiter = ConnectionLimiter::new(100);
+        let ip = "192.0.2.1".parse().unwrap();
+
+        limiter.throttle(ip, &class).unwrap();
+        limiter.throttle(ip, &class).unwrap();
+        assert!(matches!(limiter.throttle(ip, &class), Err(SecurityError::Throttled)));
+        limiter.throttle("192.0.2.2".parse().unwrap(), &class).unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+//! Server side of the IRCv3 SASL exchange carried over AUTHENTICATE
+
+use base64::{Engine as _, engine::general_purpose};
+
+use crate::security::auth::{SaslAuthenticator, SaslMechanism};
+use crate::state::AccountStore;
+
+/// AUTHENTICATE payloads are split into lines of at most this many bytes
+pub const CHUNK_SIZE: usize = 400;
+
+/// Upper bound on a reassembled client payload
+const MAX_PAYLOAD: usize = 8192;
+
+/// Mechanisms offered in `CAP LS` and RPL_SASLMECHS
+pub fn supported_mechanisms() -> Vec<SaslMechanism> {
+    vec![SaslMechanism::Plain, SaslMechanism::ScramSha256, SaslMechanism::External]
+}
+
+pub fn mechanism_list() -> String {
+    supported_mechanisms()
+        .iter()
+        .map(|mechanism| mechanism.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Result of feeding one AUTHENTICATE line into the session
+#[derive(Debug, PartialEq)]
+pub enum Payload {
+    /// A full 400-byte chunk arrived, more data follows
+    Pending,
+    Complete(Vec<u8>),
+    TooLong,
+    Aborted,
+    Invalid,
+}
+
+/// What the server does after processing a complete client payload
+#[derive(Debug, PartialEq)]
+pub enum SaslStep {
+    Challenge(Vec<u8>),
+    Success(String),
+    Failure,
+}
+
+enum Stage {
+    Initial,
+    ScramFinal(String),
+    ScramDone(String),
+}
+
+pub struct SaslSession {
+    mechanism: SaslMechanism,
+    certfp: Option<String>,
+    buffer: String,
+    stage: Stage,
+    authenticator: SaslAuthenticator,
+}
+
+impl SaslSession {
+    /// `certfp` is the fingerprint of the client's TLS certificate, if any,
+    /// and is only consulted by EXTERNAL
+    pub fn new(mechanism: SaslMechanism, certfp: Option<String>) -> Self {
+        Self {
+            mechanism,
+            certfp,
+            buffer: String::new(),
+            stage: Stage::Initial,
+            authenticator: SaslAuthenticator::new(),
+        }
+    }
+
+    /// Reassemble chunked client data, decoding it once the final chunk arrives
+    pub fn feed(&mut self, chunk: &str) -> Payload {
+        if chunk == "*" {
+            return Payload::Aborted;
+        }
+        if chunk.len() > CHUNK_SIZE {
+            return Payload::TooLong;
+        }
+
+        if chunk != "+" {
+            self.buffer.push_str(chunk);
+        }
+        if self.buffer.len() > MAX_PAYLOAD {
+            return Payload::TooLong;
+        }
+        if chunk.len() == CHUNK_SIZE {
+            return Payload::Pending;
+        }
+
+        let encoded = std::mem::take(&mut self.buffer);
+        match general_purpose::STANDARD.decode(encoded) {
+            Ok(data) => Payload::Complete(data),
+            Err(_) => Payload::Invalid,
+        }
+    }
+
+    /// Advance the mechanism with a complete client payload
+    pub async fn step(&mut self, data: &[u8], accounts: &AccountStore) -> SaslStep {
+        match (&self.mechanism, &self.stage) {
+            (SaslMechanism::Plain, Stage::Initial) => {
+                let Ok((authcid, password)) = self.authenticator.authenticate_plain(data) else {
+                    return SaslStep::Failure;
+                };
+
+                match accounts.authenticate(&authcid, &password).await {
+                    Some(account) => SaslStep::Success(account.name),
+                    None => SaslStep::Failure,
+                }
+            }
+            (SaslMechanism::ScramSha256, Stage::Initial) => {
+                let Ok(client_first) = std::str::from_utf8(data) else {
+                    return SaslStep::Failure;
+                };
+                let Some(account) = SaslAuthenticator::scram_username(client_first)
+                    .ok()
+                    .and_then(|name| accounts.get(&name))
+                else {
+                    return SaslStep::Failure;
+                };
+
+                match self.authenticator.start_scram_sha256(client_first, account.scram) {
+                    Ok(server_first) => {
+                        self.stage = Stage::ScramFinal(account.name);
+                        SaslStep::Challenge(server_first.into_bytes())
+                    }
+                    Err(_) => SaslStep::Failure,
+                }
+            }
+            (SaslMechanism::ScramSha256, Stage::ScramFinal(account)) => {
+                let account = account.clone();
+                let Ok(client_final) = std::str::from_utf8(data) else {
+                    return SaslStep::Failure;
+                };
+
+                match self.authenticator.verify_scram_sha256(client_final) {
+                    Ok(server_final) => {
+                        self.stage = Stage::ScramDone(account);
+                        SaslStep::Challenge(server_final.into_bytes())
+                    }
+                    Err(_) => SaslStep::Failure,
+                }
+            }
+            // EXTERNAL carries an optional authzid, which must name the
+            // account the certificate is bound to
+            (SaslMechanism::External, Stage::Initial) => {
+                let Some(account) = self.certfp.as_deref().and_then(|fp| accounts.find_by_certfp(fp)) else {
+                    return SaslStep::Failure;
+                };
+
+                match std::str::from_utf8(data) {
+                    Ok("") => SaslStep::Success(account.name),
+                    Ok(authzid) if authzid.eq_ignore_ascii_case(&account.name) => SaslStep::Success(account.name),
+                    _ => SaslStep::Failure,
+                }
+            }
+            // The client acknowledges our signature with an empty response
+            (SaslMechanism::ScramSha256, Stage::ScramDone(account)) if data.is_empty() => {
+                SaslStep::Success(account.clone())
+            }
+            _ => SaslStep::Failure,
+        }
+    }
+}
+
+/// Base64-encode server data and split it into AUTHENTICATE lines. An empty
+/// payload, or one that ends exactly on a chunk boundary, is terminated by "+".
+pub fn encode_payload(data: &[u8]) -> Vec<String> {
+    let encoded = general_purpose::STANDARD.encode(data);
+    let mut chunks: Vec<String> = encoded
+        .as_bytes()
+        .chunks(CHUNK_SIZE)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect();
+
+    if encoded.len().is_multiple_of(CHUNK_SIZE) {
+        chunks.push("+".to_string());
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::auth::LocalPasswordHasher;
+
+    fn test_accounts() -> AccountStore {
+        AccountStore::new(LocalPasswordHasher::new(8 * 1024, 1, 1).unwrap())
+    }
+
+    #[test]
+    fn test_encode_payload_chunking() {
+        assert_eq!(encode_payload(b""), vec!["+"]);
+        assert_eq!(encode_payload(b"abc"), vec!["YWJj"]);
+
+        // 300 bytes encode to exactly 400 base64 characters
+        let chunks = encode_payload(&[0u8; 300]);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), CHUNK_SIZE);
+        assert_eq!(chunks[1], "+");
+    }
+
+    #[test]
+    fn test_feed_reassembles_chunks() {
+        let mut session = SaslSession::new(SaslMechanism::Plain, None);
+        let encoded = general_purpose::STANDARD.encode([7u8; 450]);
+
+        assert_eq!(session.feed(&encoded[..CHUNK_SIZE]), Payload::Pending);
+        assert_eq!(session.feed(&encoded[CHUNK_SIZE..]), Payload::Complete(vec![7u8; 450]));
+        assert_eq!(session.feed(&"A".repeat(CHUNK_SIZE + 1)), Payload::TooLong);
+        assert_eq!(session.feed("*"), Payload::Aborted);
+    }
+
+    #[tokio::test]
+    async fn test_plain_step() {
+        let accounts = test_accounts();
+        accounts.create("alice", "hunter2").await.unwrap();
+
+        let mut session = SaslSession::new(SaslMechanism::Plain, None);
+        assert_eq!(session.step(b"\0alice\0hunter2", &accounts).await, SaslStep::Success("alice".to_string()));
+
+        let mut session = SaslSession::new(SaslMechanism::Plain, None);
+        assert_eq!(session.step(b"\0alice\0wrong", &accounts).await, SaslStep::Failure);
+    }
+
+    #[tokio::test]
+    async fn test_external_step() {
+        let certfp = "ab".repeat(32);
+        let accounts = test_accounts();
+        accounts.create("bot", "secret").await.unwrap();
+        assert!(accounts.add_certfp("bot", &certfp.to_uppercase()));
+
+        let mut session = SaslSession::new(SaslMechanism::External, Some(certfp.clone()));
+        assert_eq!(session.step(b"", &accounts).await, SaslStep::Success("bot".to_string()));
+
+        let mut session = SaslSession::new(SaslMechanism::External, Some(certfp));
+        assert_eq!(session.step(b"someoneelse", &accounts).await, SaslStep::Failure);
+
+        let mut session = SaslSession::new(SaslMechanism::External, None);
+        assert_eq!(session.step(b"", &accounts).await, SaslStep::Failure);
+    }
+}
@@ -1,7 +1,239 @@
-use std::io;
+use std::fs::File;
+use std::io::{self, BufReader};
 use std::path::Path;
+use std::sync::Arc;
 
-pub async fn create_tls_acceptor(_cert_path: &Path, _key_path: &Path) -> io::Result<()> {
-    // TLS implementation disabled for now - requires rustls version upgrade
-    Err(io::Error::new(io::ErrorKind::Unsupported, "TLS not implemented"))
-}
\ No newline at end of file
+use rustls::client::danger::HandshakeSignatureValid;
+use rustls::crypto::{CryptoProvider, WebPkiSupportedAlgorithms};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, UnixTime};
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::{DigitallySignedStruct, DistinguishedName, ServerConfig, SignatureScheme, SupportedProtocolVersion};
+use sha2::{Digest, Sha256};
+use tokio_rustls::TlsAcceptor;
+
+use crate::utils::config::SecuritySettings;
+
+static TLS12_AND_LATER: &[&SupportedProtocolVersion] = &[
+    &rustls::version::TLS13,
+    &rustls::version::TLS12,
+];
+
+static TLS13_ONLY: &[&SupportedProtocolVersion] = &[&rustls::version::TLS13];
+
+/// Map the `min_tls_version` setting onto the protocol versions we accept
+pub fn protocol_versions(min_tls_version: &str) -> io::Result<&'static [&'static SupportedProtocolVersion]> {
+    match min_tls_version.trim().trim_start_matches("TLSv") {
+        "1.2" => Ok(TLS12_AND_LATER),
+        "1.3" => Ok(TLS13_ONLY),
+        other => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported min_tls_version '{}' (expected 1.2 or 1.3)", other),
+        )),
+    }
+}
+
+/// Requests a client certificate but never requires one, and accepts any
+/// certificate that proves possession of its key. Clients are identified by
+/// fingerprint rather than by a CA, so self-signed certificates are the norm.
+#[derive(Debug)]
+struct OptionalClientCert {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ClientCertVerifier for OptionalClientCert {
+    fn client_auth_mandatory(&self) -> bool {
+        false
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// Lowercase hex SHA-256 of the DER certificate, as shown in RPL_WHOISCERTFP
+pub fn certificate_fingerprint(cert: &CertificateDer<'_>) -> String {
+    hex::encode(Sha256::digest(cert.as_ref()))
+}
+
+pub fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no certificates found in {}", path.display()),
+        ));
+    }
+
+    Ok(certs)
+}
+
+pub fn load_private_key(path: &Path) -> io::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(open(path)?);
+    rustls_pemfile::private_key(&mut reader)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no private key found in {}", path.display()),
+        )
+    })
+}
+
+pub fn create_tls_acceptor(
+    cert_path: &Path,
+    key_path: &Path,
+    min_tls_version: &str,
+) -> io::Result<TlsAcceptor> {
+    let certs = load_certs(cert_path)?;
+    let key = load_private_key(key_path)?;
+
+    // Pin the provider explicitly: more than one rustls backend is compiled
+    // into the dependency graph, so there is no usable process default.
+    let provider: Arc<CryptoProvider> = Arc::new(rustls::crypto::ring::default_provider());
+    let client_verifier = Arc::new(OptionalClientCert {
+        algorithms: provider.signature_verification_algorithms,
+    });
+
+    let config = ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(protocol_versions(min_tls_version)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        .with_client_cert_verifier(client_verifier)
+        .with_single_cert(certs, key)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Build the acceptor for the TLS listeners from `[security]`
+pub fn acceptor_from_settings(settings: &SecuritySettings) -> io::Result<TlsAcceptor> {
+    let (cert_file, key_file) = match (&settings.tls_cert_file, &settings.tls_key_file) {
+        (Some(cert), Some(key)) => (cert, key),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "tls_cert_file and tls_key_file must both be set",
+            ))
+        }
+    };
+
+    create_tls_acceptor(Path::new(cert_file), Path::new(key_file), &settings.min_tls_version)
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    File::open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_test_cert(dir: &Path) -> (std::path::PathBuf, std::path::PathBuf) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_path = dir.join("server.crt");
+        let key_path = dir.join("server.key");
+        std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+        std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+        (cert_path, key_path)
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("centurion-tls-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_protocol_versions() {
+        assert_eq!(protocol_versions("1.2").unwrap().len(), 2);
+        assert_eq!(protocol_versions("TLSv1.3").unwrap().len(), 1);
+        assert!(protocol_versions("1.0").is_err());
+    }
+
+    #[test]
+    fn test_create_tls_acceptor() {
+        let dir = temp_dir("acceptor");
+        let (cert_path, key_path) = write_test_cert(&dir);
+
+        assert!(create_tls_acceptor(&cert_path, &key_path, "1.2").is_ok());
+        assert!(create_tls_acceptor(&cert_path, &key_path, "1.3").is_ok());
+        assert!(create_tls_acceptor(&key_path, &key_path, "1.2").is_err());
+        assert!(create_tls_acceptor(&dir.join("missing.crt"), &key_path, "1.2").is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_certificate_fingerprint() {
+        let dir = temp_dir("certfp");
+        let (cert_path, key_path) = write_test_cert(&dir);
+        let acceptor = create_tls_acceptor(&cert_path, &key_path, "1.2").unwrap();
+
+        let client_cert = rcgen::generate_simple_self_signed(vec!["bot".to_string()]).unwrap();
+        let client_der = client_cert.cert.der().clone();
+        let client_key = PrivateKeyDer::try_from(client_cert.key_pair.serialize_der()).unwrap();
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(load_certs(&cert_path).unwrap().remove(0)).unwrap();
+        let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(vec![client_der.clone()], client_key)
+            .unwrap();
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+
+        let (client, server) = tokio::io::duplex(16384);
+        let server_name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+        let (client_result, server_result) = tokio::join!(
+            connector.connect(server_name, client),
+            acceptor.accept(server),
+        );
+        client_result.unwrap();
+        let server_stream = server_result.unwrap();
+
+        let presented = server_stream.get_ref().1.peer_certificates().unwrap();
+        let fingerprint = certificate_fingerprint(&presented[0]);
+        assert_eq!(fingerprint, certificate_fingerprint(&client_der));
+        assert_eq!(fingerprint.len(), 64);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_acceptor_requires_cert_and_key() {
+        let settings = crate::utils::config::ServerConfig::default().security;
+        assert!(acceptor_from_settings(&settings).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+use dashmap::DashMap;
+use rand::distributions::{Alphanumeric, DistString};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::db::Database;
+use crate::security::auth::{constant_time_eq, AuthError, LocalPasswordHasher, ScramCredentials};
+
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub name: String,
+    pub password_hash: String,
+    pub scram: ScramCredentials,
+    pub certfps: Vec<String>,
+}
+
+impl Account {
+    pub fn new(name: String, password: &str, hasher: &LocalPasswordHasher) -> Result<Self, AuthError> {
+        Ok(Self {
+            name,
+            password_hash: hasher.hash_password(password)?,
+            scram: ScramCredentials::generate(password),
+            certfps: Vec::new(),
+        })
+    }
+
+    pub fn verify_password(&self, password: &str) -> bool {
+        LocalPasswordHasher::verify_password(password, &self.password_hash).unwrap_or(false)
+    }
+}
+
+/// A change queued for the database writer task
+#[derive(Debug)]
+enum AccountChange {
+    Save(Account),
+    Delete(String),
+}
+
+/// A registration waiting for its emailed verification code
+#[derive(Debug, Clone)]
+pub struct PendingAccount {
+    pub account: Account,
+    pub email: Option<String>,
+    code: String,
+    expires: Instant,
+}
+
+/// Registered accounts, keyed by lowercased account name
+#[derive(Debug, Default)]
+pub struct AccountStore {
+    accounts: DashMap<String, Account>,
+    /// Certificate fingerprint -> lowercased account name
+    certfps: DashMap<String, String>,
+    /// Registrations awaiting VERIFY, keyed by lowercased account name
+    pending: DashMap<String, PendingAccount>,
+    hasher: LocalPasswordHasher,
+    /// Changed accounts are queued here for the database writer task
+    writer: Option<mpsc::UnboundedSender<AccountChange>>,
+}
+
+impl AccountStore {
+    pub fn new(hasher: LocalPasswordHasher) -> Self {
+        Self {
+            accounts: DashMap::new(),
+            certfps: DashMap::new(),
+            pending: DashMap::new(),
+            hasher,
+            writer: None,
+        }
+    }
+
+    /// Populate the store from previously saved accounts and write every
+    /// later change back to `db`. Writes happen in order on a background
+    /// task, so callers never wait on the database.
+    pub fn attach_database(&mut self, db: Database, saved: Vec<Account>) {
+        for account in saved {
+            self.insert(account);
+        }
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<AccountChange>();
+        tokio::spawn(async move {
+            while let Some(change) = rx.recv().await {
+                let (name, result) = match change {
+                    AccountChange::Save(account) => {
+                        let result = db.save_account(&account).await;
+                        (account.name, result)
+                    }
+                    AccountChange::Delete(name) => {
+                        let result = db.delete_account(&name).await;
+                        (name, result)
+                    }
+                };
+                if let Err(e) = result {
+                    tracing::error!("Failed to write account {}: {}", name, e);
+                }
+            }
+        });
+        self.writer = Some(tx);
+    }
+
+    fn persist(&self, account: &Account) {
+        if let Some(writer) = &self.writer {
+            let _ = writer.send(AccountChange::Save(account.clone()));
+        }
+    }
+
+    /// Hash `password` with the configured parameters and add the account
+    pub async fn create(&self, name: &str, password: &str) -> Result<bool, AuthError> {
+        Ok(self.insert(self.hash_account(name, password).await?))
+    }
+
+    /// Build an account on the blocking pool, since Argon2 and the SCRAM
+    /// key derivation would otherwise stall the executor
+    async fn hash_account(&self, name: &str, password: &str) -> Result<Account, AuthError> {
+        let hasher = self.hasher.clone();
+        let name = name.to_string();
+        let password = password.to_string();
+        tokio::task::spawn_blocking(move || Account::new(name, &password, &hasher))
+            .await
+            .map_err(|_| AuthError::AuthenticationFailed)?
+    }
+
+    /// Add an account, returning false if the name is already taken
+    pub fn insert(&self, account: Account) -> bool {
+        let key = account.name.to_lowercase();
+        match self.accounts.entry(key.clone()) {
+            dashmap::mapref::entry::Entry::Occupied(_) => false,
+            dashmap::mapref::entry::Entry::Vacant(entry) => {
+                for certfp in &account.certfps {
+                    self.certfps.insert(certfp.clone(), key.clone());
+                }
+                self.persist(&account);
+                entry.insert(account);
+                true
+            }
+        }
+    }
+
+    /// Whether `name` belongs to an account or an unexpired pending registration
+    pub fn is_taken(&self, name: &str) -> bool {
+        let key = name.to_lowercase();
+        self.accounts.contains_key(&key)
+            || self.pending.get(&key).is_some_and(|pending| pending.expires > Instant::now())
+    }
+
+    /// Hold a new account until `verify` is called with the returned code.
+    /// Returns None if the name is already taken.
+    pub async fn create_pending(
+        &self,
+        name: &str,
+        password: &str,
+        email: Option<String>,
+        ttl: Duration,
+    ) -> Result<Option<String>, AuthError> {
+        if self.is_taken(name) {
+            return Ok(None);
+        }
+
+        let account = self.hash_account(name, password).await?;
+        // Another registration may have claimed the name while hashing
+        if self.is_taken(name) {
+            return Ok(None);
+        }
+
+        let code = Alphanumeric.sample_string(&mut rand::thread_rng(), 12);
+        let pending = PendingAccount {
+            account,
+            email,
+            code: code.clone(),
+            expires: Instant::now() + ttl,
+        };
+        self.pending.insert(name.to_lowercase(), pending);
+        Ok(Some(code))
+    }
+
+    /// Forget pending registrations whose code has expired
+    pub fn prune_pending(&self) {
+        let now = Instant::now();
+        self.pending.retain(|_, pending| pending.expires > now);
+    }
+
+    /// Drop a pending registration, e.g. when its code could not be delivered
+    pub fn cancel_pending(&self, name: &str) {
+        self.pending.remove(&name.to_lowercase());
+    }
+
+    /// Complete a pending registration if `code` matches and has not expired
+    pub fn verify(&self, name: &str, code: &str) -> Option<PendingAccount> {
+        let key = name.to_lowercase();
+        let (_, pending) = self.pending.remove_if(&key, |_, pending| {
+            pending.expires > Instant::now() && constant_time_eq(pending.code.as_bytes(), code.as_bytes())
+        })?;
+
+        self.insert(pending.account.clone()).then_some(pending)
+    }
+
+    /// Delete an account and release its certificate fingerprints
+    pub fn remove(&self, name: &str) -> Option<Account> {
+        let (_, account) = self.accounts.remove(&name.to_lowercase())?;
+        for certfp in &account.certfps {
+            self.certfps.remove(certfp);
+        }
+
+        if let Some(writer) = &self.writer {
+            let _ = writer.send(AccountChange::Delete(account.name.clone()));
+        }
+        Some(account)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Account> {
+        self.accounts.get(&name.to_lowercase()).map(|account| account.clone())
+    }
+
+    /// Check a password login. On success, a hash made with outdated
+    /// parameters is transparently replaced by one using the current ones.
+    /// Argon2 runs on the blocking pool with no lock held.
+    pub async fn authenticate(&self, name: &str, password: &str) -> Option<Account> {
+        let account = self.get(name)?;
+        let hasher = self.hasher.clone();
+        let password = password.to_string();
+        let checked = account.clone();
+        let (verified, rehashed) = tokio::task::spawn_blocking(move || {
+            if !checked.verify_password(&password) {
+                return (false, None);
+            }
+            let rehashed = hasher.needs_rehash(&checked.password_hash)
+                .then(|| hasher.hash_password(&password));
+            (true, rehashed)
+        })
+        .await
+        .ok()?;
+        if !verified {
+            return None;
+        }
+
+        match rehashed {
+            Some(Ok(hash)) => {
+                let mut current = self.accounts.get_mut(&name.to_lowercase())?;
+                // Leave the hash alone if the password changed meanwhile
+                if current.password_hash == account.password_hash {
+                    current.password_hash = hash;
+                    self.persist(&current);
+                }
+                Some(current.clone())
+            }
+            Some(Err(e)) => {
+                tracing::warn!("Failed to rehash password for {}: {}", account.name, e);
+                Some(account)
+            }
+            None => Some(account),
+        }
+    }
+
+    pub fn find_by_certfp(&self, certfp: &str) -> Option<Account> {
+        let key = self.certfps.get(&normalize_certfp(certfp))?.clone();
+        self.accounts.get(&key).map(|account| account.clone())
+    }
+
+    /// Bind a fingerprint to an account. Fails if the account does not exist
+    /// or the fingerprint already belongs to any account.
+    pub fn add_certfp(&self, name: &str, certfp: &str) -> bool {
+        let key = name.to_lowercase();
+        let certfp = normalize_certfp(certfp);
+        let Some(mut account) = self.accounts.get_mut(&key) else {
+            return false;
+        };
+
+        match self.certfps.entry(certfp.clone()) {
+            dashmap::mapref::entry::Entry::Occupied(_) => false,
+            dashmap::mapref::entry::Entry::Vacant(entry) => {
+                entry.insert(key);
+                account.certfps.push(certfp);
+                self.persist(&account);
+                true
+            }
+        }
+    }
+
+    pub fn remove_certfp(&self, name: &str, certfp: &str) -> bool {
+        let key = name.to_lowercase();
+        let certfp = normalize_certfp(certfp);
+        let Some(mut account) = self.accounts.get_mut(&key) else {
+            return false;
+        };
+
+        let before = account.certfps.len();
+        account.certfps.retain(|existing| existing != &certfp);
+        if account.certfps.len() == before {
+            return false;
+        }
+
+        self.certfps.remove(&certfp);
+        self.persist(&account);
+        true
+    }
+}
+
+/// Fingerprints are compared as lowercase hex without separators, so both
+/// `AB:CD:..` and `abcd..` forms are accepted
+pub fn normalize_certfp(certfp: &str) -> String {
+    certfp.chars()
+        .filter(|c| *c != ':')
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+pub fn is_valid_certfp(certfp: &str) -> bool {
+    let certfp = normalize_certfp(certfp);
+    certfp.len() == 64 && certfp.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_authenticate_rehashes_outdated_password() {
+        let old = LocalPasswordHasher::new(8 * 1024, 1, 1).unwrap();
+        let accounts = AccountStore::new(LocalPasswordHasher::new(8 * 1024, 2, 1).unwrap());
+        accounts.insert(Account::new("alice".to_string(), "hunter2", &old).unwrap());
+
+        assert!(accounts.authenticate("alice", "wrong").await.is_none());
+        assert!(accounts.get("alice").unwrap().password_hash.contains("t=1"));
+
+        let account = accounts.authenticate("Alice", "hunter2").await.unwrap();
+        assert!(account.password_hash.contains("m=8192,t=2,p=1"));
+        assert!(accounts.authenticate("alice", "hunter2").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_pending_registration() {
+        let accounts = AccountStore::new(LocalPasswordHasher::new(8 * 1024, 1, 1).unwrap());
+        let code = accounts.create_pending("alice", "hunter22", None, Duration::from_secs(60)).await.unwrap().unwrap();
+
+        assert!(accounts.is_taken("Alice"));
+        assert!(accounts.create_pending("alice", "other", None, Duration::from_secs(60)).await.unwrap().is_none());
+        assert!(accounts.get("alice").is_none());
+        assert!(accounts.verify("alice", "wrong").is_none());
+
+        assert_eq!(accounts.verify("ALICE", &code).unwrap().account.name, "alice");
+        assert!(accounts.authenticate("alice", "hunter22").await.is_some());
+        assert!(accounts.verify("alice", &code).is_none());
+
+        accounts.create_pending("bob", "hunter22", None, Duration::ZERO).await.unwrap().unwrap();
+        assert!(!accounts.is_taken("bob"));
+        accounts.prune_pending();
+        assert!(accounts.pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_changes_are_written_to_database() {
+        let db = crate::db::test_database().await;
+        let mut accounts = AccountStore::new(LocalPasswordHasher::new(8 * 1024, 1, 1).unwrap());
+        accounts.attach_database(db.clone(), Vec::new());
+
+        accounts.create("alice", "hunter2").await.unwrap();
+        assert!(accounts.add_certfp("alice", &"ab".repeat(32)));
+
+        // The writer runs in the background; give it a moment to catch up
+        let mut saved = Vec::new();
+        for _ in 0..50 {
+            saved = db.load_accounts().await.unwrap();
+            if saved.first().is_some_and(|account| !account.certfps.is_empty()) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].certfps, vec!["ab".repeat(32)]);
+
+        let mut restored = AccountStore::new(LocalPasswordHasher::default());
+        restored.attach_database(db, saved);
+        assert_eq!(restored.find_by_certfp(&"AB".repeat(32)).unwrap().name, "alice");
+    }
+}
@@ -1,5 +1,11 @@
+use std::collections::HashMap;
 use dashmap::DashMap;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::db::models::{BanEntry, Channel as ChannelRecord};
+use crate::security::mask_matches;
+use super::channel_modes::{Rank, LIST_MODES};
+use super::Connection;
 
 #[derive(Debug, Clone)]
 pub struct ChannelMember {
@@ -8,6 +14,14 @@ pub struct ChannelMember {
     pub joined_at: DateTime<Utc>,
 }
 
+/// An entry in one of the +b, +e or +I lists
+#[derive(Debug, Clone)]
+pub struct ListEntry {
+    pub mask: String,
+    pub set_by: String,
+    pub set_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Channel {
     pub name: String,
@@ -19,6 +33,11 @@ pub struct Channel {
     pub created_at: DateTime<Utc>,
     pub key: Option<String>,
     pub limit: Option<usize>,
+    pub bans: Vec<ListEntry>,
+    pub ban_exceptions: Vec<ListEntry>,
+    pub invite_exceptions: Vec<ListEntry>,
+    /// Pending invites, by invitee connection, with their expiry
+    pub invites: HashMap<u64, DateTime<Utc>>,
 }
 
 impl Channel {
@@ -33,9 +52,62 @@ impl Channel {
             created_at: Utc::now(),
             key: None,
             limit: None,
+            bans: Vec::new(),
+            ban_exceptions: Vec::new(),
+            invite_exceptions: Vec::new(),
+            invites: HashMap::new(),
         }
     }
 
+    /// Recreate a registered channel with its saved topic, modes and lists
+    pub fn from_registration(record: &ChannelRecord, entries: &[BanEntry]) -> Self {
+        let mut channel = Self::new(record.name.clone());
+        channel.topic = record.topic.clone();
+        channel.topic_set_by = record.topic_set_by.clone();
+        channel.topic_set_at = record.topic_set_at;
+        channel.modes = record.modes.chars().collect();
+        channel.key = record.key.clone();
+        channel.limit = record.limit.and_then(|limit| usize::try_from(limit).ok());
+        for entry in entries {
+            let Some(list) = entry.mode.chars().next().and_then(|mode| channel.list_mut(mode)) else {
+                continue;
+            };
+            list.push(ListEntry {
+                mask: entry.mask.clone(),
+                set_by: entry.set_by.clone(),
+                set_at: entry.set_at,
+            });
+        }
+        channel
+    }
+
+    /// Every list entry in the form the database stores it
+    pub fn list_records(&self) -> Vec<BanEntry> {
+        LIST_MODES.iter()
+            .flat_map(|&mode| self.list(mode).unwrap_or_default().iter().map(move |entry| (mode, entry)))
+            .map(|(mode, entry)| BanEntry {
+                channel_name: self.name.clone(),
+                mode: mode.to_string(),
+                mask: entry.mask.clone(),
+                set_by: entry.set_by.clone(),
+                set_at: entry.set_at,
+                reason: None,
+                expires_at: None,
+            })
+            .collect()
+    }
+
+    /// Copy the topic and modes into a registration so they survive the
+    /// channel being emptied
+    pub fn save_to(&self, record: &mut ChannelRecord) {
+        record.topic = self.topic.clone();
+        record.topic_set_by = self.topic_set_by.clone();
+        record.topic_set_at = self.topic_set_at;
+        record.modes = self.modes.iter().collect();
+        record.key = self.key.clone();
+        record.limit = self.limit.and_then(|limit| i32::try_from(limit).ok());
+    }
+
     pub fn add_member(&self, connection_id: u64, is_operator: bool) {
         let mut modes = Vec::new();
         if is_operator {
@@ -59,14 +131,206 @@ impl Channel {
         self.members.contains_key(&connection_id)
     }
 
-    pub fn is_operator(&self, connection_id: u64) -> bool {
+    /// The member's highest rank, Member for non-members too
+    pub fn rank(&self, connection_id: u64) -> Rank {
         self.members
             .get(&connection_id)
-            .map(|member| member.modes.contains(&'o'))
-            .unwrap_or(false)
+            .map(|member| Rank::of(&member.modes))
+            .unwrap_or(Rank::Member)
+    }
+
+    /// Whether the member is opped or ranks above op
+    pub fn is_operator(&self, connection_id: u64) -> bool {
+        self.rank(connection_id) >= Rank::Op
     }
 
     pub fn member_count(&self) -> usize {
         self.members.len()
     }
+
+    /// Whether the member holds any prefix mode, which lets them speak while
+    /// banned or moderated
+    pub fn has_voice(&self, connection_id: u64) -> bool {
+        self.rank(connection_id) >= Rank::Voice
+    }
+
+    /// Whether the client may send `text` here under +n, +C, +m, +M and the
+    /// ban list
+    pub fn can_send(&self, conn: &Connection, text: &str) -> bool {
+        if self.modes.contains(&'n') && !self.is_member(conn.id) {
+            return false;
+        }
+        let rank = self.rank(conn.id);
+        if self.modes.contains(&'C') && rank < Rank::Op && is_ctcp(text) {
+            return false;
+        }
+        if rank >= Rank::Voice {
+            return true;
+        }
+        if self.modes.contains(&'m') || (self.modes.contains(&'M') && conn.account.is_none()) {
+            return false;
+        }
+        !self.is_banned(conn)
+    }
+
+    /// The flags for RPL_CHANNELMODEIS, followed by the key and limit
+    pub fn mode_params(&self) -> Vec<String> {
+        let mut modestring = String::from("+");
+        let mut params = Vec::new();
+        for &mode in &self.modes {
+            match mode {
+                'k' => params.extend(self.key.clone()),
+                'l' => params.extend(self.limit.map(|limit| limit.to_string())),
+                _ => {}
+            }
+            modestring.push(mode);
+        }
+        let mut result = vec![modestring];
+        result.extend(params);
+        result
+    }
+
+    /// The list behind a list mode, or None if `mode` is not one
+    pub fn list(&self, mode: char) -> Option<&[ListEntry]> {
+        match mode {
+            'b' => Some(&self.bans),
+            'e' => Some(&self.ban_exceptions),
+            'I' => Some(&self.invite_exceptions),
+            _ => None,
+        }
+    }
+
+    pub fn list_mut(&mut self, mode: char) -> Option<&mut Vec<ListEntry>> {
+        match mode {
+            'b' => Some(&mut self.bans),
+            'e' => Some(&mut self.ban_exceptions),
+            'I' => Some(&mut self.invite_exceptions),
+            _ => None,
+        }
+    }
+
+    /// Total entries across the +b, +e and +I lists, which share MAXLIST
+    pub fn list_entry_count(&self) -> usize {
+        self.bans.len() + self.ban_exceptions.len() + self.invite_exceptions.len()
+    }
+
+    /// Whether a ban matches the client and no ban exception does
+    pub fn is_banned(&self, conn: &Connection) -> bool {
+        self.bans.iter().any(|entry| entry_matches(&entry.mask, conn))
+            && !self.ban_exceptions.iter().any(|entry| entry_matches(&entry.mask, conn))
+    }
+
+    /// Record an invite valid for `ttl`, dropping any that have expired
+    pub fn add_invite(&mut self, connection_id: u64, ttl: Duration) {
+        let now = Utc::now();
+        self.invites.retain(|_, expires| *expires > now);
+        self.invites.insert(connection_id, now + ttl);
+    }
+
+    /// Whether the client holds an invite that has not expired
+    pub fn is_invited(&self, connection_id: u64) -> bool {
+        self.invites.get(&connection_id).is_some_and(|expires| *expires > Utc::now())
+    }
+
+    /// Whether the client may join while the channel is invite-only
+    pub fn is_invite_exempt(&self, conn: &Connection) -> bool {
+        self.invite_exceptions.iter().any(|entry| entry_matches(&entry.mask, conn))
+    }
+}
+
+/// A CTCP request other than ACTION
+fn is_ctcp(text: &str) -> bool {
+    text.starts_with('\x01') && !text.starts_with("\x01ACTION")
+}
+
+/// Match a list entry against a client. `$a:<pattern>` matches the account
+/// name and a bare `$a` any logged-in client; anything else is a hostmask,
+/// checked against both the hostname and the IP address.
+fn entry_matches(mask: &str, conn: &Connection) -> bool {
+    if let Some(extban) = mask.strip_prefix('$') {
+        return match extban.split_once(':') {
+            Some(("a", pattern)) => conn.account.as_deref().is_some_and(|account| mask_matches(pattern, account)),
+            None => extban == "a" && conn.account.is_some(),
+            _ => false,
+        };
+    }
+
+    let ip_mask = format!(
+        "{}!{}@{}",
+        conn.nickname.as_deref().unwrap_or("*"),
+        conn.username.as_deref().unwrap_or("*"),
+        conn.addr.ip(),
+    );
+    mask_matches(mask, &conn.full_mask()) || mask_matches(mask, &ip_mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::sendq;
+
+    fn client(id: u64, nick: &str, account: Option<&str>) -> Connection {
+        let (tx, _rx) = sendq::channel(64 * 1024);
+        let mut conn = Connection::new(id, "192.0.2.7:40000".parse().unwrap(), tx);
+        conn.nickname = Some(nick.to_string());
+        conn.username = Some("user".to_string());
+        conn.hostname = "host.example".to_string();
+        conn.account = account.map(str::to_string);
+        conn
+    }
+
+    fn entry(mask: &str) -> ListEntry {
+        ListEntry { mask: mask.to_string(), set_by: "op".to_string(), set_at: Utc::now() }
+    }
+
+    #[test]
+    fn test_bans_and_exceptions() {
+        let mut channel = Channel::new("#rust".to_string());
+        channel.bans.push(entry("*!*@host.example"));
+        channel.bans.push(entry("*!*@198.51.100.*"));
+        assert!(channel.is_banned(&client(1, "bob", None)));
+
+        channel.ban_exceptions.push(entry("$a:trusted*"));
+        assert!(!channel.is_banned(&client(1, "bob", Some("TrustedBob"))));
+        assert!(channel.is_banned(&client(1, "bob", Some("mallory"))));
+
+        channel.bans.clear();
+        channel.bans.push(entry("*!*@192.0.2.7"));
+        assert!(channel.is_banned(&client(1, "carol", None)));
+    }
+
+    #[test]
+    fn test_account_extban() {
+        let mut channel = Channel::new("#rust".to_string());
+        channel.bans.push(entry("$a"));
+        assert!(channel.is_banned(&client(1, "bob", Some("bob"))));
+        assert!(!channel.is_banned(&client(1, "bob", None)));
+
+        channel.invite_exceptions.push(entry("$a:bob"));
+        assert!(channel.is_invite_exempt(&client(1, "anyone", Some("BOB"))));
+        assert!(!channel.is_invite_exempt(&client(1, "bob", None)));
+    }
+
+    #[test]
+    fn test_can_send() {
+        let mut channel = Channel::new("#rust".to_string());
+        channel.modes = vec!['n', 'M', 'C'];
+        let guest = client(1, "guest", None);
+        assert!(!channel.can_send(&guest, "hello"));
+
+        channel.add_member(guest.id, false);
+        assert!(!channel.can_send(&guest, "hello"));
+
+        let bob = client(2, "bob", Some("bob"));
+        channel.add_member(bob.id, false);
+        assert!(channel.can_send(&bob, "hello"));
+        assert!(!channel.can_send(&bob, "\x01VERSION\x01"));
+        assert!(channel.can_send(&bob, "\x01ACTION waves\x01"));
+        // +n keeps out non-members even when they are logged in
+        assert!(!channel.can_send(&client(3, "carol", Some("carol")), "hello"));
+
+        channel.members.get_mut(&guest.id).unwrap().modes.push('h');
+        assert!(channel.can_send(&guest, "hello"));
+        assert!(!channel.is_operator(guest.id));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,269 @@
+// The channel mode table. Every mode's CHANMODES type decides how it takes
+// a parameter; the MODE parser and the CHANMODES, PREFIX and MODES ISUPPORT
+// tokens are all derived from it, so adding a mode is a one-line change.
+
+/// How a mode takes its parameter, following the CHANMODES ISUPPORT types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeKind {
+    /// Type A: adds or removes a list entry. Without a parameter the list
+    /// is shown instead.
+    List,
+    /// Type B: always takes a parameter
+    Parameter,
+    /// Type C: takes a parameter only when set
+    SetParameter,
+    /// Type D: a flag without a parameter
+    Flag,
+    /// A membership rank, given a nickname (PREFIX)
+    Prefix,
+}
+
+/// Channel membership ranks, lowest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rank {
+    Member,
+    Voice,
+    Halfop,
+    Op,
+    Protected,
+    Founder,
+}
+
+impl Rank {
+    /// The rank a member's prefix modes give them
+    pub fn of(modes: &[char]) -> Rank {
+        PREFIXES.iter()
+            .find(|prefix| modes.contains(&prefix.letter))
+            .map(|prefix| prefix.rank)
+            .unwrap_or(Rank::Member)
+    }
+}
+
+/// A prefix mode with the symbol shown before nicknames
+#[derive(Debug)]
+pub struct PrefixMode {
+    pub letter: char,
+    pub symbol: char,
+    pub rank: Rank,
+}
+
+/// Prefix modes, highest first
+pub const PREFIXES: [PrefixMode; 5] = [
+    PrefixMode { letter: 'q', symbol: '~', rank: Rank::Founder },
+    PrefixMode { letter: 'a', symbol: '&', rank: Rank::Protected },
+    PrefixMode { letter: 'o', symbol: '@', rank: Rank::Op },
+    PrefixMode { letter: 'h', symbol: '%', rank: Rank::Halfop },
+    PrefixMode { letter: 'v', symbol: '+', rank: Rank::Voice },
+];
+
+/// A channel mode and who may change it
+#[derive(Debug)]
+pub struct ModeDef {
+    pub letter: char,
+    pub kind: ModeKind,
+    /// Lowest rank that may set or unset the mode
+    pub min_rank: Rank,
+    /// Only IRC operators may set or unset the mode
+    pub oper_only: bool,
+}
+
+const fn mode(letter: char, kind: ModeKind, min_rank: Rank) -> ModeDef {
+    ModeDef { letter, kind, min_rank, oper_only: false }
+}
+
+pub const CHANNEL_MODES: &[ModeDef] = &[
+    mode('b', ModeKind::List, Rank::Halfop),
+    mode('e', ModeKind::List, Rank::Op),
+    mode('I', ModeKind::List, Rank::Op),
+    mode('k', ModeKind::Parameter, Rank::Halfop),
+    mode('l', ModeKind::SetParameter, Rank::Halfop),
+    // No CTCP other than ACTION
+    mode('C', ModeKind::Flag, Rank::Op),
+    // Moderated for users who are not logged in
+    mode('M', ModeKind::Flag, Rank::Op),
+    // IRC operators only
+    ModeDef { letter: 'O', kind: ModeKind::Flag, min_rank: Rank::Op, oper_only: true },
+    // Logged-in users only
+    mode('R', ModeKind::Flag, Rank::Op),
+    // TLS connections only
+    mode('S', ModeKind::Flag, Rank::Op),
+    // Strip colours and formatting
+    mode('c', ModeKind::Flag, Rank::Op),
+    mode('i', ModeKind::Flag, Rank::Halfop),
+    mode('m', ModeKind::Flag, Rank::Halfop),
+    mode('n', ModeKind::Flag, Rank::Halfop),
+    mode('p', ModeKind::Flag, Rank::Op),
+    mode('s', ModeKind::Flag, Rank::Op),
+    mode('t', ModeKind::Flag, Rank::Halfop),
+    mode('q', ModeKind::Prefix, Rank::Founder),
+    mode('a', ModeKind::Prefix, Rank::Founder),
+    mode('o', ModeKind::Prefix, Rank::Op),
+    mode('h', ModeKind::Prefix, Rank::Op),
+    mode('v', ModeKind::Prefix, Rank::Halfop),
+];
+
+/// Modes holding a list of masks rather than a flag or a single value
+pub const LIST_MODES: [char; 3] = ['b', 'e', 'I'];
+
+/// Mode changes with a parameter allowed in one MODE command (MODES)
+pub const MAX_PARAM_MODES: usize = 3;
+
+pub fn lookup(letter: char) -> Option<&'static ModeDef> {
+    CHANNEL_MODES.iter().find(|def| def.letter == letter)
+}
+
+fn letters(kind: ModeKind) -> String {
+    CHANNEL_MODES.iter().filter(|def| def.kind == kind).map(|def| def.letter).collect()
+}
+
+/// Value of the CHANMODES ISUPPORT token
+pub fn chanmodes_token() -> String {
+    [ModeKind::List, ModeKind::Parameter, ModeKind::SetParameter, ModeKind::Flag]
+        .map(letters)
+        .join(",")
+}
+
+/// Value of the PREFIX ISUPPORT token
+pub fn prefix_token() -> String {
+    let letters: String = PREFIXES.iter().map(|prefix| prefix.letter).collect();
+    let symbols: String = PREFIXES.iter().map(|prefix| prefix.symbol).collect();
+    format!("({}){}", letters, symbols)
+}
+
+/// Every channel mode letter, for RPL_MYINFO
+pub fn all_letters() -> String {
+    let mut letters: Vec<char> = CHANNEL_MODES.iter().map(|def| def.letter).collect();
+    letters.sort_unstable();
+    letters.into_iter().collect()
+}
+
+/// Symbol of the highest prefix mode in `modes`
+pub fn prefix_symbol(modes: &[char]) -> Option<char> {
+    PREFIXES.iter()
+        .find(|prefix| modes.contains(&prefix.letter))
+        .map(|prefix| prefix.symbol)
+}
+
+/// One requested mode change. `param` is None when a parameter was needed
+/// but none was left, which for a list mode means the list was asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModeChange {
+    pub adding: bool,
+    pub mode: char,
+    pub param: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ParsedModes {
+    pub changes: Vec<ModeChange>,
+    pub unknown: Vec<char>,
+}
+
+/// Split a mode string and its arguments into changes, taking parameters
+/// according to each mode's type. Changes past MAX_PARAM_MODES that take a
+/// parameter are dropped.
+pub fn parse(modestring: &str, args: &[String]) -> ParsedModes {
+    let mut parsed = ParsedModes::default();
+    let mut args = args.iter();
+    let mut adding = true;
+    let mut param_modes = 0;
+
+    for letter in modestring.chars() {
+        let def = match letter {
+            '+' => {
+                adding = true;
+                continue;
+            }
+            '-' => {
+                adding = false;
+                continue;
+            }
+            _ => match lookup(letter) {
+                Some(def) => def,
+                None => {
+                    parsed.unknown.push(letter);
+                    continue;
+                }
+            },
+        };
+
+        let takes_param = match def.kind {
+            ModeKind::List | ModeKind::Parameter | ModeKind::Prefix => true,
+            ModeKind::SetParameter => adding,
+            ModeKind::Flag => false,
+        };
+        let param = if takes_param { args.next().cloned() } else { None };
+
+        if param.is_some() {
+            param_modes += 1;
+            if param_modes > MAX_PARAM_MODES {
+                continue;
+            }
+        }
+
+        parsed.changes.push(ModeChange { adding, mode: letter, param });
+    }
+
+    parsed
+}
+
+/// Merge applied changes into MODE parameters, e.g. `+ov-b alice bob *!*@x`
+pub fn format_changes(changes: &[ModeChange]) -> Vec<String> {
+    let mut modestring = String::new();
+    let mut params = Vec::new();
+    let mut sign = None;
+
+    for change in changes {
+        if sign != Some(change.adding) {
+            modestring.push(if change.adding { '+' } else { '-' });
+            sign = Some(change.adding);
+        }
+        modestring.push(change.mode);
+        params.extend(change.param.clone());
+    }
+
+    let mut result = vec![modestring];
+    result.extend(params);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_tokens() {
+        assert_eq!(chanmodes_token(), "beI,k,l,CMORScimnpst");
+        assert_eq!(prefix_token(), "(qaohv)~&@%+");
+        assert_eq!(Rank::of(&['v', 'o']), Rank::Op);
+        assert_eq!(prefix_symbol(&['v', 'h']), Some('%'));
+    }
+
+    #[test]
+    fn test_parse_follows_mode_types() {
+        let parsed = parse("+kl-lk+bX", &args(&["key", "10", "oldkey"]));
+        assert_eq!(parsed.unknown, vec!['X']);
+        assert_eq!(parsed.changes, vec![
+            ModeChange { adding: true, mode: 'k', param: Some("key".to_string()) },
+            ModeChange { adding: true, mode: 'l', param: Some("10".to_string()) },
+            // Type C takes no parameter when unset
+            ModeChange { adding: false, mode: 'l', param: None },
+            ModeChange { adding: false, mode: 'k', param: Some("oldkey".to_string()) },
+            // A list mode with nothing left is a query
+            ModeChange { adding: true, mode: 'b', param: None },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_limits_and_merges() {
+        let parsed = parse("+vvvv", &args(&["a", "b", "c", "d"]));
+        assert_eq!(parsed.changes.len(), MAX_PARAM_MODES);
+
+        let changes = parse("+o-v+m", &args(&["alice", "bob"])).changes;
+        assert_eq!(format_changes(&changes), args(&["+o-v+m", "alice", "bob"]));
+    }
+}
@@ -0,0 +1,282 @@
+use dashmap::DashMap;
+use tokio::sync::mpsc;
+
+use crate::db::{Database, DatabaseError};
+use crate::db::models::{BanEntry, Channel as ChannelRecord};
+
+/// A change queued for the database writer task
+#[derive(Debug)]
+enum ChannelChange {
+    Save(Box<ChannelRecord>),
+    Delete(String),
+    AddListEntry(BanEntry),
+    RemoveListEntry { channel: String, mode: String, mask: String },
+}
+
+/// Registered channels, keyed by lowercased channel name. A registration
+/// outlives the in-memory channel: its topic and modes are restored when the
+/// channel is next created.
+#[derive(Debug, Default)]
+pub struct ChannelRegistry {
+    channels: DashMap<String, ChannelRecord>,
+    /// +b/+e/+I entries of registered channels, under the same keys
+    list_entries: DashMap<String, Vec<BanEntry>>,
+    /// Changed registrations are queued here for the database writer task
+    writer: Option<mpsc::UnboundedSender<ChannelChange>>,
+}
+
+/// What happened to a channel when its founder's account was dropped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Succession {
+    /// The successor became founder
+    Transferred { channel: String, founder: String },
+    /// No successor was set, so the registration was dropped
+    Dropped { channel: String },
+}
+
+impl ChannelRegistry {
+    /// Populate the registry from saved channels and their list entries, and
+    /// write every later change back to `db` on a background task
+    pub fn attach_database(&mut self, db: Database, saved: Vec<ChannelRecord>, entries: Vec<BanEntry>) {
+        for record in saved.into_iter().filter(|record| record.founder.is_some()) {
+            self.channels.insert(record.name.to_lowercase(), record);
+        }
+        for entry in entries {
+            let key = entry.channel_name.to_lowercase();
+            if self.channels.contains_key(&key) {
+                self.list_entries.entry(key).or_default().push(entry);
+            }
+        }
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<ChannelChange>();
+        tokio::spawn(async move {
+            while let Some(change) = rx.recv().await {
+                let (name, result) = match change {
+                    ChannelChange::Save(record) => {
+                        let result = match db.update_channel(&record).await {
+                            Err(DatabaseError::ChannelNotFound) => db.create_channel(&record).await.map(|_| ()),
+                            result => result,
+                        };
+                        (record.name, result)
+                    }
+                    ChannelChange::Delete(name) => {
+                        let result = db.delete_channel(&name).await;
+                        (name, result)
+                    }
+                    ChannelChange::AddListEntry(entry) => {
+                        let result = match db.add_ban(&entry).await {
+                            // Already saved, e.g. re-added after a failed removal
+                            Err(DatabaseError::DuplicateEntry) => Ok(()),
+                            result => result,
+                        };
+                        (entry.channel_name, result)
+                    }
+                    ChannelChange::RemoveListEntry { channel, mode, mask } => {
+                        let result = db.remove_ban(&channel, &mode, &mask).await;
+                        (channel, result)
+                    }
+                };
+                if let Err(e) = result {
+                    tracing::error!("Failed to write channel registration {}: {}", name, e);
+                }
+            }
+        });
+        self.writer = Some(tx);
+    }
+
+    fn persist(&self, change: ChannelChange) {
+        if let Some(writer) = &self.writer {
+            let _ = writer.send(change);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<ChannelRecord> {
+        self.channels.get(&name.to_lowercase()).map(|record| record.clone())
+    }
+
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.channels.contains_key(&name.to_lowercase())
+    }
+
+    /// Whether `account` is the founder of `name`
+    pub fn is_founder(&self, name: &str, account: &str) -> bool {
+        self.channels.get(&name.to_lowercase())
+            .and_then(|record| record.founder.as_ref().map(|founder| founder.eq_ignore_ascii_case(account)))
+            .unwrap_or(false)
+    }
+
+    /// Register a channel, returning false if it already is
+    pub fn register(&self, record: ChannelRecord) -> bool {
+        match self.channels.entry(record.name.to_lowercase()) {
+            dashmap::mapref::entry::Entry::Occupied(_) => false,
+            dashmap::mapref::entry::Entry::Vacant(entry) => {
+                self.persist(ChannelChange::Save(Box::new(record.clone())));
+                entry.insert(record);
+                true
+            }
+        }
+    }
+
+    /// Apply `change` to a registration and persist it. Returns false if the
+    /// channel is not registered.
+    pub fn update(&self, name: &str, change: impl FnOnce(&mut ChannelRecord)) -> bool {
+        let Some(mut record) = self.channels.get_mut(&name.to_lowercase()) else {
+            return false;
+        };
+        change(&mut record);
+        self.persist(ChannelChange::Save(Box::new(record.clone())));
+        true
+    }
+
+    /// Saved +b/+e/+I entries of a registered channel
+    pub fn list_entries(&self, name: &str) -> Vec<BanEntry> {
+        self.list_entries.get(&name.to_lowercase()).map(|entries| entries.clone()).unwrap_or_default()
+    }
+
+    /// Save a list entry if its channel is registered
+    pub fn add_list_entry(&self, entry: BanEntry) {
+        let key = entry.channel_name.to_lowercase();
+        let Some(record) = self.channels.get(&key) else {
+            return;
+        };
+        // Rows reference the channel by its registered spelling
+        let entry = BanEntry { channel_name: record.name.clone(), ..entry };
+        self.persist(ChannelChange::AddListEntry(entry.clone()));
+        self.list_entries.entry(key).or_default().push(entry);
+    }
+
+    pub fn remove_list_entry(&self, name: &str, mode: char, mask: &str) {
+        let key = name.to_lowercase();
+        let Some(record) = self.channels.get(&key) else {
+            return;
+        };
+        if let Some(mut entries) = self.list_entries.get_mut(&key) {
+            entries.retain(|entry| !(entry.mode == mode.to_string() && entry.mask == mask));
+        }
+        self.persist(ChannelChange::RemoveListEntry {
+            channel: record.name.clone(),
+            mode: mode.to_string(),
+            mask: mask.to_string(),
+        });
+    }
+
+    pub fn unregister(&self, name: &str) -> Option<ChannelRecord> {
+        self.list_entries.remove(&name.to_lowercase());
+        let (_, record) = self.channels.remove(&name.to_lowercase())?;
+        self.persist(ChannelChange::Delete(record.name.clone()));
+        Some(record)
+    }
+
+    /// Hand channels founded by a dropped account to their successors, drop
+    /// those without one, and forget the account as a successor elsewhere
+    pub fn account_dropped(&self, account: &str) -> Vec<Succession> {
+        let mut outcomes = Vec::new();
+        let mut orphaned = Vec::new();
+
+        for mut entry in self.channels.iter_mut() {
+            let record = entry.value_mut();
+            let is_founder = record.founder.as_deref().is_some_and(|founder| founder.eq_ignore_ascii_case(account));
+            let is_successor = record.successor.as_deref().is_some_and(|successor| successor.eq_ignore_ascii_case(account));
+
+            if is_founder {
+                match record.successor.take().filter(|_| !is_successor) {
+                    Some(successor) => {
+                        record.founder = Some(successor.clone());
+                        outcomes.push(Succession::Transferred { channel: record.name.clone(), founder: successor });
+                    }
+                    None => {
+                        orphaned.push(entry.key().clone());
+                        continue;
+                    }
+                }
+            } else if is_successor {
+                record.successor = None;
+            } else {
+                continue;
+            }
+            self.persist(ChannelChange::Save(Box::new(record.clone())));
+        }
+
+        // Removal happens after iterating so the shard locks are released
+        for key in orphaned {
+            if let Some(record) = self.unregister(&key) {
+                outcomes.push(Succession::Dropped { channel: record.name });
+            }
+        }
+
+        outcomes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, founder: &str, successor: Option<&str>) -> ChannelRecord {
+        let mut record = ChannelRecord::new(name.to_string(), Some(founder.to_string()));
+        record.successor = successor.map(str::to_string);
+        record
+    }
+
+    #[test]
+    fn test_succession() {
+        let registry = ChannelRegistry::default();
+        registry.register(record("#rust", "alice", Some("bob")));
+        registry.register(record("#go", "alice", None));
+        registry.register(record("#zig", "carol", Some("alice")));
+        assert!(!registry.register(record("#RUST", "mallory", None)));
+
+        let mut outcomes = registry.account_dropped("Alice");
+        outcomes.sort_by_key(|outcome| format!("{:?}", outcome));
+        assert_eq!(outcomes, vec![
+            Succession::Dropped { channel: "#go".to_string() },
+            Succession::Transferred { channel: "#rust".to_string(), founder: "bob".to_string() },
+        ]);
+
+        assert!(registry.is_founder("#Rust", "BOB"));
+        assert!(registry.get("#rust").unwrap().successor.is_none());
+        assert!(!registry.is_registered("#go"));
+        assert!(registry.get("#zig").unwrap().successor.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_registrations_are_written_to_database() {
+        let db = crate::db::test_database().await;
+        let mut registry = ChannelRegistry::default();
+        registry.attach_database(db.clone(), Vec::new(), Vec::new());
+
+        registry.register(record("#rust", "alice", None));
+        registry.update("#rust", |record| record.entry_message = Some("Welcome".to_string()));
+        registry.add_list_entry(BanEntry {
+            channel_name: "#RUST".to_string(),
+            mode: "e".to_string(),
+            mask: "$a:bob".to_string(),
+            set_by: "alice".to_string(),
+            set_at: chrono::Utc::now(),
+            reason: None,
+            expires_at: None,
+        });
+
+        let mut saved = None;
+        for _ in 0..50 {
+            saved = db.get_channel("#rust").await.unwrap();
+            if saved.as_ref().is_some_and(|record| record.entry_message.is_some()) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(saved.unwrap().entry_message.as_deref(), Some("Welcome"));
+
+        // The writer is sequential, so the list entry follows shortly after
+        let mut entries = Vec::new();
+        for _ in 0..50 {
+            entries = db.get_channel_bans("#rust").await.unwrap();
+            if !entries.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(entries[0].mask, "$a:bob");
+        assert_eq!(registry.list_entries("#Rust").len(), 1);
+    }
+}
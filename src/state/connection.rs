@@ -1,8 +1,12 @@
 use std::net::SocketAddr;
-use tokio::sync::mpsc;
 use chrono::{DateTime, Utc};
 
-use crate::protocol::Message;
+use super::SendQueue;
+use super::operators::OperPrivilege;
+
+/// User modes: bot, registered-only messages, secure connection,
+/// invisible, IRC operator, server notices and wallops
+pub const USER_MODES: &str = "BRZiosw";
 
 #[derive(Debug, Clone)]
 pub struct Connection {
@@ -13,14 +17,26 @@ pub struct Connection {
     pub realname: Option<String>,
     pub hostname: String,
     pub registered: bool,
+    pub secure: bool,
+    pub account: Option<String>,
+    pub certfp: Option<String>,
     pub capabilities: Vec<String>,
+    /// User modes, such as +o for IRC operators
+    pub modes: Vec<char>,
+    /// What OPER granted, cleared again with -o
+    pub privileges: Vec<OperPrivilege>,
+    /// Server notices received while +s, see `operators::SNOMASKS`
+    pub snomask: Vec<char>,
+    /// Away message, set with AWAY
+    pub away: Option<String>,
     pub created_at: DateTime<Utc>,
     pub last_activity: DateTime<Utc>,
-    pub tx: mpsc::Sender<Message>,
+    /// Outgoing lines, written out by the connection actor
+    pub tx: SendQueue,
 }
 
 impl Connection {
-    pub fn new(id: u64, addr: SocketAddr, tx: mpsc::Sender<Message>) -> Self {
+    pub fn new(id: u64, addr: SocketAddr, tx: SendQueue) -> Self {
         let now = Utc::now();
         Self {
             id,
@@ -30,7 +46,14 @@ impl Connection {
             realname: None,
             hostname: addr.ip().to_string(),
             registered: false,
+            secure: false,
+            account: None,
+            certfp: None,
             capabilities: Vec::new(),
+            modes: Vec::new(),
+            privileges: Vec::new(),
+            snomask: Vec::new(),
+            away: None,
             created_at: now,
             last_activity: now,
             tx,
@@ -41,6 +64,11 @@ impl Connection {
         self.nickname.is_some() && self.username.is_some()
     }
 
+    /// Whether this client is an operator holding `privilege`
+    pub fn has_privilege(&self, privilege: OperPrivilege) -> bool {
+        self.modes.contains(&'o') && self.privileges.contains(&privilege)
+    }
+
     pub fn update_activity(&mut self) {
         self.last_activity = Utc::now();
     }
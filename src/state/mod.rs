@@ -1,36 +1,133 @@
 use dashmap::DashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
 
+pub mod account;
 pub mod channel;
+pub mod channel_modes;
+pub mod channel_registry;
 pub mod connection;
+pub mod monitor;
+pub mod operators;
+pub mod sendq;
+pub mod server_bans;
+pub mod whowas;
 
+pub use self::account::AccountStore;
 pub use self::channel::{Channel, ChannelMember};
+pub use self::channel_registry::ChannelRegistry;
 pub use self::connection::Connection;
+pub use self::monitor::MonitorRegistry;
+pub use self::operators::{OperPrivilege, OperatorStore};
+pub use self::sendq::{SendQueue, SendQueueReceiver};
+pub use self::server_bans::{BanKind, ServerBans};
+pub use self::whowas::{WhowasEntry, WhowasHistory};
 
+use crate::db::Database;
+use crate::protocol::Message;
 use crate::history::{HistoryStorage};
 use crate::legion::LegionManager;
+use crate::security::{ConnectClasses, ConnectionLimiter};
+use crate::security::auth::LocalPasswordHasher;
+use crate::utils::config::ServerConfig;
+use crate::utils::mail::{self, MailSender};
 
 pub struct ServerState {
     pub connections: DashMap<u64, Connection>,
     pub channels: DashMap<String, Channel>,
     pub nicknames: DashMap<String, u64>,
+    pub accounts: AccountStore,
+    pub registered_channels: ChannelRegistry,
+    pub monitors: MonitorRegistry,
+    pub whowas: WhowasHistory,
+    /// K-lines and D-lines
+    pub server_bans: ServerBans,
+    /// Credentials OPER checks against
+    pub operators: OperatorStore,
+    pub classes: ConnectClasses,
+    /// Clients counted against their connection class
+    pub connection_limiter: ConnectionLimiter,
     next_connection_id: AtomicU64,
     pub server_name: String,
+    pub config: ServerConfig,
     pub history: HistoryStorage,
     pub legion: Option<LegionManager>,
+    /// Persistent storage, absent when `database.url` is empty
+    pub database: Option<Database>,
+    /// Delivers account verification codes, absent when mail is disabled
+    pub mailer: Option<Arc<dyn MailSender>>,
 }
 
 impl ServerState {
     pub fn new() -> Self {
-        Self {
+        Self::with_config(ServerConfig::default()).expect("default config is valid")
+    }
+
+    /// Build the server state for `config`. Fails if the password hashing
+    /// settings are invalid rather than quietly using other costs, or if
+    /// the configured operator password has no operator name.
+    pub fn with_config(config: ServerConfig) -> Result<Self, crate::error::CenturionError> {
+        let hasher = LocalPasswordHasher::from_settings(&config.security)
+            .map_err(|e| crate::error::CenturionError::Config(e.to_string()))?;
+        let operators = OperatorStore::from_settings(&config.security, &hasher)?;
+        Ok(Self {
             connections: DashMap::new(),
             channels: DashMap::new(),
             nicknames: DashMap::new(),
+            accounts: AccountStore::new(hasher),
+            registered_channels: ChannelRegistry::default(),
+            monitors: MonitorRegistry::default(),
+            whowas: WhowasHistory::new(config.limits.whowas_per_nick, config.limits.whowas_max_nicks),
+            server_bans: ServerBans::default(),
+            operators,
+            classes: ConnectClasses::from_config(&config),
+            connection_limiter: ConnectionLimiter::new(config.limits.max_clients),
             next_connection_id: AtomicU64::new(1),
             server_name: "centurion.local".to_string(),
             history: HistoryStorage::default(),
             legion: None,
+            database: None,
+            mailer: mail::sender_from_settings(&config.registration).unwrap_or_else(|e| {
+                tracing::warn!("{}, account verification mail disabled", e);
+                None
+            }),
+            config,
+        })
+    }
+    
+    /// Connect to the configured database, bring its schema up to date and
+    /// load persisted accounts and channel registrations. An empty
+    /// `database.url` keeps everything in memory.
+    pub async fn init_database(&mut self) -> Result<(), crate::error::CenturionError> {
+        if self.config.database.url.is_empty() {
+            tracing::info!("No database configured; state will not survive restarts");
+            return Ok(());
         }
+
+        let database = Database::connect(&self.config.database).await?;
+        database.run_migrations().await?;
+
+        let accounts = database.load_accounts().await?;
+        let channels = database.list_channels().await?;
+        let server_bans = database.load_server_bans().await?;
+        let operators = database.load_operators().await?;
+        let mut list_entries = Vec::new();
+        for channel in &channels {
+            list_entries.extend(database.get_channel_bans(&channel.name).await?);
+        }
+        tracing::info!("Database ready, loaded {} accounts and {} channels", accounts.len(), channels.len());
+        self.accounts.attach_database(database.clone(), accounts);
+        self.registered_channels.attach_database(database.clone(), channels, list_entries);
+        self.server_bans.attach_database(database.clone(), server_bans);
+        self.operators.attach_database(database.clone(), operators);
+        self.database = Some(database);
+        Ok(())
+    }
+    
+    /// Get the database handle, if one is configured
+    pub fn database(&self) -> Option<&Database> {
+        self.database.as_ref()
     }
     
     /// Initialize Legion Protocol support
@@ -58,26 +155,240 @@ impl ServerState {
         self.legion.as_ref()
     }
 
+    /// Delete an account, log out its sessions and pass its channels on to
+    /// their successors
+    pub fn drop_account(&self, name: &str) -> Option<Vec<channel_registry::Succession>> {
+        let account = self.accounts.remove(name)?;
+        for mut conn in self.connections.iter_mut() {
+            if conn.account.as_deref().is_some_and(|logged_in| logged_in.eq_ignore_ascii_case(&account.name)) {
+                conn.account = None;
+            }
+        }
+
+        tracing::info!("Account {} dropped", account.name);
+        Some(self.registered_channels.account_dropped(&account.name))
+    }
+
+    /// Whether the channel's modes and bans let the client send `text` to
+    /// it. Unknown channels are left for the caller to report.
+    pub fn can_send_to_channel(&self, channel_name: &str, connection_id: u64, text: &str) -> bool {
+        let (Some(channel), Some(conn)) = (self.channels.get(channel_name), self.connections.get(&connection_id)) else {
+            return true;
+        };
+        channel.can_send(&conn, text)
+    }
+
+    /// Everyone sharing a channel with the client, the client included.
+    /// Invisible (+i) users are only shown to these in WHO and NAMES.
+    pub fn channel_peers(&self, connection_id: u64) -> HashSet<u64> {
+        let mut peers: HashSet<u64> = self.channels.iter()
+            .filter(|channel| channel.is_member(connection_id))
+            .flat_map(|channel| channel.members.iter().map(|member| *member.key()).collect::<Vec<_>>())
+            .collect();
+        peers.insert(connection_id);
+        peers
+    }
+
+    /// Whether `target` is a user who only accepts private messages from
+    /// logged-in clients (+R)
+    pub fn rejects_unregistered(&self, target: &str) -> bool {
+        self.nicknames.get(&target.to_lowercase())
+            .and_then(|id| self.connections.get(&*id).map(|conn| conn.modes.contains(&'R')))
+            .unwrap_or(false)
+    }
+
+    /// The text as the channel delivers it, without formatting under +c
+    pub fn channel_text(&self, channel_name: &str, text: String) -> String {
+        match self.channels.get(channel_name) {
+            Some(channel) if channel.modes.contains(&'c') => crate::utils::strip_formatting(&text),
+            _ => text,
+        }
+    }
+
     pub fn generate_connection_id(&self) -> u64 {
         self.next_connection_id.fetch_add(1, Ordering::SeqCst)
     }
 
-    pub fn is_nickname_available(&self, nickname: &str) -> bool {
-        !self.nicknames.contains_key(&nickname.to_lowercase())
+    /// The client using `nick`, found through the nickname index
+    pub fn connection_by_nick(&self, nick: &str) -> Option<Connection> {
+        let id = *self.nicknames.get(&nick.to_lowercase())?;
+        self.connections.get(&id).map(|conn| conn.clone())
     }
 
-    pub fn register_nickname(&self, nickname: String, connection_id: u64) -> bool {
-        let key = nickname.to_lowercase();
-        match self.nicknames.entry(key) {
-            dashmap::mapref::entry::Entry::Occupied(_) => false,
+    /// Move a client from `old` to `new`, returning false if `new` belongs
+    /// to someone else. The new nickname is claimed through its map entry
+    /// before the old one is released, so concurrent claims can't both
+    /// succeed. A change of case keeps the existing entry.
+    pub fn change_nickname(&self, old: Option<&str>, new: &str, connection_id: u64) -> bool {
+        let key = new.to_lowercase();
+        match self.nicknames.entry(key.clone()) {
+            dashmap::mapref::entry::Entry::Occupied(entry) if *entry.get() != connection_id => return false,
+            dashmap::mapref::entry::Entry::Occupied(_) => {}
             dashmap::mapref::entry::Entry::Vacant(entry) => {
                 entry.insert(connection_id);
-                true
             }
         }
+
+        if let Some(old_key) = old.map(str::to_lowercase).filter(|old_key| *old_key != key) {
+            self.nicknames.remove_if(&old_key, |_, id| *id == connection_id);
+        }
+        true
     }
 
     pub fn unregister_nickname(&self, nickname: &str) {
         self.nicknames.remove(&nickname.to_lowercase());
     }
-}
\ No newline at end of file
+
+    /// Remember a client's identity for WHOWAS before it signs off or
+    /// changes nickname
+    pub fn record_whowas(&self, conn: &Connection) {
+        if let Some(entry) = WhowasEntry::from_connection(conn, &self.server_name) {
+            self.whowas.record(entry);
+        }
+    }
+
+    /// nick!user@host of the registered client using `nick`, if any
+    pub fn online_mask(&self, nick: &str) -> Option<String> {
+        let id = *self.nicknames.get(&nick.to_lowercase())?;
+        self.connections.get(&id)
+            .filter(|conn| conn.registered)
+            .map(|conn| conn.full_mask())
+    }
+
+    /// Tell clients monitoring `nick` that it came online as `mask` (730)
+    /// or, given None, went offline (731)
+    pub async fn notify_monitors(&self, nick: &str, mask: Option<&str>) {
+        let (numeric, target) = match mask {
+            Some(mask) => ("730", mask),
+            None => ("731", nick),
+        };
+        let watchers: Vec<_> = self.monitors.watchers(nick).into_iter()
+            .filter_map(|id| self.connections.get(&id).map(|conn| (conn.tx.clone(), conn.nickname.clone())))
+            .collect();
+        for (tx, watcher_nick) in watchers {
+            let _ = tx.try_send(Message::new(numeric)
+                .with_prefix(self.server_name.clone())
+                .with_params(vec![watcher_nick.unwrap_or_else(|| "*".to_string()), target.to_string()]));
+        }
+    }
+
+    /// Disconnect every client a newly added ban covers, returning their
+    /// nicknames. K-lines only reach registered clients here; the rest are
+    /// caught when they register.
+    pub fn enforce_server_ban(&self, kind: BanKind, mask: &str, reason: &str) -> Vec<String> {
+        let mut dropped = Vec::new();
+        for conn in self.connections.iter() {
+            if kind == BanKind::Kline && !conn.registered {
+                continue;
+            }
+            let username = conn.username.as_deref().unwrap_or("*");
+            if ServerBans::matches(kind, mask, username, &conn.hostname, conn.addr.ip()) {
+                conn.tx.close(format!("{}d: {}", kind.name(), reason));
+                dropped.push(conn.nickname.clone().unwrap_or_else(|| "*".to_string()));
+            }
+        }
+        dropped
+    }
+
+    /// Send a server notice to every operator whose snomask includes
+    /// `snomask`
+    pub fn server_notice(&self, snomask: char, text: &str) {
+        for conn in self.connections.iter() {
+            if !conn.modes.contains(&'s') || !conn.snomask.contains(&snomask) {
+                continue;
+            }
+            let Some(nick) = &conn.nickname else {
+                continue;
+            };
+            let _ = conn.tx.try_send(Message::new("NOTICE")
+                .with_prefix(self.server_name.clone())
+                .with_params(vec![nick.clone(), format!("*** Notice -- {}", text)]));
+        }
+    }
+
+    /// Take a departing client out of every channel. `quit` goes once to
+    /// each client sharing a channel with them, and channels left empty are
+    /// dropped. Returns the channels they were in.
+    pub async fn quit_channels(&self, connection_id: u64, quit: &Message) -> Vec<String> {
+        let mut peers = self.channel_peers(connection_id);
+        peers.remove(&connection_id);
+        let recipients: Vec<_> = peers.into_iter()
+            .filter_map(|id| self.connections.get(&id).map(|conn| conn.tx.clone()))
+            .collect();
+        for tx in recipients {
+            let _ = tx.try_send(quit.clone());
+        }
+
+        let channels: Vec<String> = self.channels.iter()
+            .filter(|channel| channel.remove_member(connection_id))
+            .map(|channel| channel.key().clone())
+            .collect();
+        for name in &channels {
+            self.channels.remove_if(name, |_, channel| channel.members.is_empty());
+        }
+        channels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::handlers::test_support::add_user;
+
+    #[test]
+    fn test_nickname_changes_are_atomic() {
+        let state = Arc::new(ServerState::new());
+        assert!(state.change_nickname(None, "alice", 1));
+        assert!(state.change_nickname(Some("alice"), "Alice", 1));
+        assert!(!state.change_nickname(None, "ALICE", 2));
+
+        // Many clients racing for one nickname: exactly one gets it, and
+        // every loser keeps the nickname it had
+        let winners: usize = std::thread::scope(|scope| {
+            let racers: Vec<_> = (10..50u64).map(|id| {
+                let state = &state;
+                scope.spawn(move || {
+                    let own = format!("racer{}", id);
+                    state.change_nickname(None, &own, id);
+                    state.change_nickname(Some(&own), "prize", id)
+                })
+            }).collect();
+            racers.into_iter().map(|racer| racer.join().unwrap()).filter(|&won| won).count()
+        });
+        assert_eq!(winners, 1);
+        assert_eq!(state.nicknames.len(), 1 + 40);
+    }
+
+    #[test]
+    fn test_invalid_password_hashing_settings_are_fatal() {
+        let mut config = ServerConfig::default();
+        config.security.password_hash_algorithm = "md5".to_string();
+        assert!(ServerState::with_config(config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_quit_reaches_each_peer_once() {
+        let state = ServerState::new();
+        let _alice = add_user(1, "alice").insert(&state);
+        let mut bob = add_user(2, "bob").insert(&state);
+        let mut carol = add_user(3, "carol").insert(&state);
+        for (name, members) in [("#a", &[1, 2][..]), ("#b", &[1, 2, 3]), ("#c", &[1])] {
+            let channel = Channel::new(name.to_string());
+            for &id in members {
+                channel.add_member(id, false);
+            }
+            state.channels.insert(name.to_string(), channel);
+        }
+
+        let quit = Message::new("QUIT").with_params(vec!["Quit: bye".to_string()]);
+        let mut left = state.quit_channels(1, &quit).await;
+        left.sort();
+        assert_eq!(left, ["#a", "#b", "#c"]);
+
+        assert_eq!(bob.try_recv().unwrap().command, "QUIT");
+        assert!(bob.try_recv().is_err());
+        assert_eq!(carol.try_recv().unwrap().params, ["Quit: bye"]);
+        assert!(!state.channels.contains_key("#c"));
+        assert_eq!(state.channels.get("#b").unwrap().member_count(), 2);
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+use dashmap::DashMap;
+
+/// MONITOR lists, indexed both ways: the nicknames each client watches,
+/// and the clients watching each nickname.
+#[derive(Debug, Default)]
+pub struct MonitorRegistry {
+    /// Watched nicknames per connection, as the client spelled them
+    targets: DashMap<u64, Vec<String>>,
+    /// Watching connections, keyed by lowercased nickname
+    watchers: DashMap<String, HashSet<u64>>,
+}
+
+impl MonitorRegistry {
+    /// Start watching `nick`, returning false if it already was
+    pub fn add(&self, connection_id: u64, nick: &str) -> bool {
+        let key = nick.to_lowercase();
+        let mut targets = self.targets.entry(connection_id).or_default();
+        if targets.iter().any(|target| target.to_lowercase() == key) {
+            return false;
+        }
+        targets.push(nick.to_string());
+        self.watchers.entry(key).or_default().insert(connection_id);
+        true
+    }
+
+    pub fn remove(&self, connection_id: u64, nick: &str) {
+        let key = nick.to_lowercase();
+        if let Some(mut targets) = self.targets.get_mut(&connection_id) {
+            targets.retain(|target| target.to_lowercase() != key);
+        }
+        self.unwatch(connection_id, &key);
+    }
+
+    /// Drop everything the client watches
+    pub fn clear(&self, connection_id: u64) {
+        let Some((_, targets)) = self.targets.remove(&connection_id) else {
+            return;
+        };
+        for target in targets {
+            self.unwatch(connection_id, &target.to_lowercase());
+        }
+    }
+
+    fn unwatch(&self, connection_id: u64, key: &str) {
+        let now_empty = self.watchers.get_mut(key).is_some_and(|mut watchers| {
+            watchers.remove(&connection_id);
+            watchers.is_empty()
+        });
+        if now_empty {
+            self.watchers.remove_if(key, |_, watchers| watchers.is_empty());
+        }
+    }
+
+    pub fn list(&self, connection_id: u64) -> Vec<String> {
+        self.targets.get(&connection_id).map(|targets| targets.clone()).unwrap_or_default()
+    }
+
+    pub fn count(&self, connection_id: u64) -> usize {
+        self.targets.get(&connection_id).map(|targets| targets.len()).unwrap_or(0)
+    }
+
+    /// Clients watching `nick`
+    pub fn watchers(&self, nick: &str) -> Vec<u64> {
+        self.watchers.get(&nick.to_lowercase())
+            .map(|watchers| watchers.iter().copied().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_remove_clear() {
+        let registry = MonitorRegistry::default();
+        assert!(registry.add(1, "Alice"));
+        assert!(!registry.add(1, "alice"));
+        registry.add(1, "bob");
+        registry.add(2, "ALICE");
+        assert_eq!(registry.list(1), ["Alice", "bob"]);
+
+        let mut watchers = registry.watchers("alice");
+        watchers.sort_unstable();
+        assert_eq!(watchers, [1, 2]);
+
+        registry.remove(1, "ALICE");
+        assert_eq!(registry.watchers("alice"), [2]);
+        registry.clear(1);
+        assert_eq!(registry.count(1), 0);
+        assert!(registry.watchers("bob").is_empty());
+    }
+}
@@ -0,0 +1,268 @@
+use chrono::Utc;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use tokio::sync::mpsc;
+
+use crate::db::Database;
+use crate::db::models::{OperLogEntry, OperatorCredential};
+use crate::error::CenturionError;
+use crate::security::auth::LocalPasswordHasher;
+use crate::security::mask_matches;
+use crate::utils::config::SecuritySettings;
+
+/// What an IRC operator may do beyond being +o
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperPrivilege {
+    Kill,
+    /// KLINE, DLINE and their removal
+    Kline,
+    Rehash,
+    Die,
+    /// See secret and private channels in LIST
+    SeeHidden,
+    /// Change channel modes and kick without channel status
+    Override,
+}
+
+impl OperPrivilege {
+    pub const ALL: [OperPrivilege; 6] = [
+        OperPrivilege::Kill,
+        OperPrivilege::Kline,
+        OperPrivilege::Rehash,
+        OperPrivilege::Die,
+        OperPrivilege::SeeHidden,
+        OperPrivilege::Override,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OperPrivilege::Kill => "kill",
+            OperPrivilege::Kline => "kline",
+            OperPrivilege::Rehash => "rehash",
+            OperPrivilege::Die => "die",
+            OperPrivilege::SeeHidden => "see-hidden",
+            OperPrivilege::Override => "override",
+        }
+    }
+
+    /// Parse a comma or space separated privilege list, where `all` grants
+    /// everything. Unknown names are skipped.
+    pub fn parse_list(list: &str) -> Vec<Self> {
+        let mut privileges = Vec::new();
+        for name in list.split([',', ' ']).filter(|name| !name.is_empty()) {
+            if name.eq_ignore_ascii_case("all") {
+                return Self::ALL.to_vec();
+            }
+            match Self::ALL.into_iter().find(|privilege| privilege.name().eq_ignore_ascii_case(name)) {
+                Some(privilege) if !privileges.contains(&privilege) => privileges.push(privilege),
+                Some(_) => {}
+                None => tracing::warn!("Unknown operator privilege {}", name),
+            }
+        }
+        privileges
+    }
+}
+
+/// Server notice masks an operator can subscribe to with user mode +s:
+/// K-line and D-line changes, and OPER attempts
+pub const SNOMASKS: &str = "ko";
+
+/// Checked against when the operator name is unknown, so that attempt
+/// costs as much as any other
+static DUMMY_HASH: Lazy<String> = Lazy::new(|| {
+    LocalPasswordHasher::default().hash_password("not an operator password").unwrap_or_default()
+});
+
+/// Why OPER was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperFailure {
+    NoSuchOperator,
+    HostMismatch,
+    CertfpMismatch,
+    BadPassword,
+}
+
+impl OperFailure {
+    /// Recorded in the audit log and shown to other operators
+    pub fn reason(self) -> &'static str {
+        match self {
+            OperFailure::NoSuchOperator => "no such operator",
+            OperFailure::HostMismatch => "host mismatch",
+            OperFailure::CertfpMismatch => "certificate mismatch",
+            OperFailure::BadPassword => "bad password",
+        }
+    }
+}
+
+/// Operator credentials, keyed by lowercased name
+#[derive(Debug, Default)]
+pub struct OperatorStore {
+    operators: DashMap<String, OperatorCredential>,
+    /// OPER attempts are queued here for the database writer task
+    writer: Option<mpsc::UnboundedSender<OperLogEntry>>,
+}
+
+impl OperatorStore {
+    /// A store holding the operator set in the config, if any.
+    /// `security.operator_password` belongs to `operator_name` only and
+    /// grants `operator_privileges`, which default to none beyond +o. A
+    /// plain-text password is hashed here.
+    pub fn from_settings(settings: &SecuritySettings, hasher: &LocalPasswordHasher) -> Result<Self, CenturionError> {
+        let store = Self::default();
+        let Some(password) = &settings.operator_password else {
+            return Ok(store);
+        };
+        let Some(name) = &settings.operator_name else {
+            return Err(CenturionError::Config("operator_password is set but operator_name is not".to_string()));
+        };
+
+        let password_hash = match password.starts_with("$argon2") {
+            true => password.clone(),
+            false => hasher.hash_password(password).map_err(|e| CenturionError::Config(e.to_string()))?,
+        };
+        store.insert(OperatorCredential {
+            id: "config".to_string(),
+            name: name.clone(),
+            password_hash,
+            host_mask: None,
+            certfp: None,
+            privileges: settings.operator_privileges.clone(),
+            created_at: Utc::now(),
+            last_used: None,
+        });
+        Ok(store)
+    }
+
+    /// Load the saved credentials and write the audit log back to `db` on a
+    /// background task
+    pub fn attach_database(&mut self, db: Database, saved: Vec<OperatorCredential>) {
+        for oper in saved {
+            self.insert(oper);
+        }
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<OperLogEntry>();
+        tokio::spawn(async move {
+            while let Some(entry) = rx.recv().await {
+                if let Err(e) = db.log_oper_attempt(&entry).await {
+                    tracing::error!("Failed to log OPER attempt by {}: {}", entry.nick, e);
+                }
+            }
+        });
+        self.writer = Some(tx);
+    }
+
+    pub fn insert(&self, oper: OperatorCredential) {
+        self.operators.insert(oper.name.to_lowercase(), oper);
+    }
+
+    /// Check an OPER attempt. `user_hosts` are the client's user@host and
+    /// user@ip, either of which may match the operator's host mask. Returns
+    /// the privileges to grant. The password is verified on every attempt,
+    /// even for unknown names, so the time taken reveals nothing about
+    /// which check failed. Argon2 runs on the blocking pool with no lock
+    /// held.
+    pub async fn authenticate(
+        &self,
+        name: &str,
+        password: &str,
+        user_hosts: &[String],
+        certfp: Option<&str>,
+    ) -> Result<Vec<OperPrivilege>, OperFailure> {
+        let oper = self.operators.get(&name.to_lowercase()).map(|oper| oper.clone());
+
+        let password = password.to_string();
+        let hash = oper.as_ref().map(|oper| oper.password_hash.clone());
+        let verified = tokio::task::spawn_blocking(move || {
+            let hash = hash.as_deref().unwrap_or(DUMMY_HASH.as_str());
+            LocalPasswordHasher::verify_password(&password, hash).unwrap_or(false)
+        })
+        .await
+        .unwrap_or(false);
+
+        let Some(oper) = oper else {
+            return Err(OperFailure::NoSuchOperator);
+        };
+        if let Some(host_mask) = &oper.host_mask {
+            // A mask without a user part applies to any username
+            let host_mask = match host_mask.contains('@') {
+                true => host_mask.clone(),
+                false => format!("*@{}", host_mask),
+            };
+            let lowered = host_mask.to_lowercase();
+            if !user_hosts.iter().any(|user_host| mask_matches(&lowered, &user_host.to_lowercase())) {
+                return Err(OperFailure::HostMismatch);
+            }
+        }
+        if let Some(expected) = &oper.certfp {
+            if !certfp.is_some_and(|certfp| certfp.eq_ignore_ascii_case(expected)) {
+                return Err(OperFailure::CertfpMismatch);
+            }
+        }
+        if !verified {
+            return Err(OperFailure::BadPassword);
+        }
+        Ok(OperPrivilege::parse_list(&oper.privileges))
+    }
+
+    /// Add an attempt to the audit trail. Successful ones also stamp the
+    /// operator's last use.
+    pub fn record(&self, entry: OperLogEntry) {
+        if let Some(writer) = &self.writer {
+            let _ = writer.send(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_authenticate_checks_host_certfp_and_password() {
+        let hash = LocalPasswordHasher::default().hash_password("hunter2").unwrap();
+        let store = OperatorStore::default();
+        store.insert(OperatorCredential {
+            id: "1".to_string(),
+            name: "Alice".to_string(),
+            password_hash: hash,
+            host_mask: Some("*.staff.example".to_string()),
+            certfp: Some("ABCDEF".to_string()),
+            privileges: "kline, see-hidden,bogus".to_string(),
+            created_at: Utc::now(),
+            last_used: None,
+        });
+
+        let staff = ["alice@gw.staff.example".to_string(), "alice@192.0.2.1".to_string()];
+        assert_eq!(
+            store.authenticate("alice", "hunter2", &staff, Some("abcdef")).await,
+            Ok(vec![OperPrivilege::Kline, OperPrivilege::SeeHidden]),
+        );
+        assert_eq!(store.authenticate("alice", "wrong", &staff, Some("abcdef")).await, Err(OperFailure::BadPassword));
+        assert_eq!(store.authenticate("alice", "hunter2", &staff, None).await, Err(OperFailure::CertfpMismatch));
+        let elsewhere = ["alice@192.0.2.1".to_string()];
+        assert_eq!(store.authenticate("alice", "hunter2", &elsewhere, Some("abcdef")).await, Err(OperFailure::HostMismatch));
+        assert_eq!(store.authenticate("bob", "hunter2", &elsewhere, None).await, Err(OperFailure::NoSuchOperator));
+    }
+
+    #[tokio::test]
+    async fn test_configured_password_is_bound_to_its_name() {
+        let hasher = LocalPasswordHasher::new(8 * 1024, 1, 1).unwrap();
+        let mut settings = crate::utils::config::ServerConfig::default().security;
+        settings.operator_password = Some("letmein".to_string());
+        assert!(OperatorStore::from_settings(&settings, &hasher).is_err());
+
+        settings.operator_name = Some("admin".to_string());
+        let store = OperatorStore::from_settings(&settings, &hasher).unwrap();
+        let anywhere = ["root@192.0.2.1".to_string()];
+        assert_eq!(store.authenticate("Admin", "letmein", &anywhere, None).await, Ok(Vec::new()));
+        assert_eq!(store.authenticate("admin", "nope", &anywhere, None).await, Err(OperFailure::BadPassword));
+        assert_eq!(store.authenticate("bob", "letmein", &anywhere, None).await, Err(OperFailure::NoSuchOperator));
+
+        settings.operator_privileges = "kline,rehash".to_string();
+        let store = OperatorStore::from_settings(&settings, &hasher).unwrap();
+        assert_eq!(
+            store.authenticate("admin", "letmein", &anywhere, None).await,
+            Ok(vec![OperPrivilege::Kline, OperPrivilege::Rehash]),
+        );
+    }
+}
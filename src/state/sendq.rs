@@ -0,0 +1,145 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::{mpsc, Notify};
+
+use crate::protocol::Message;
+
+/// Why a message couldn't be queued
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+    /// The client's SendQ is full; it is being disconnected
+    Exceeded,
+    /// The connection is gone or being closed
+    Closed,
+}
+
+#[derive(Debug)]
+struct Shared {
+    /// Bytes queued but not yet written to the socket
+    queued: AtomicUsize,
+    limit: AtomicUsize,
+    /// Why the connection is being dropped, once it is
+    closing: OnceLock<String>,
+    close: Notify,
+}
+
+/// The sending half of a client's outgoing queue. Queuing never waits: a
+/// client that doesn't read fast enough to keep its backlog under the
+/// SendQ limit is cut off instead of holding up whoever is sending.
+#[derive(Debug, Clone)]
+pub struct SendQueue {
+    tx: mpsc::UnboundedSender<Message>,
+    shared: Arc<Shared>,
+}
+
+/// The connection actor's end of a SendQueue
+#[derive(Debug)]
+pub struct SendQueueReceiver {
+    rx: mpsc::UnboundedReceiver<Message>,
+    shared: Arc<Shared>,
+}
+
+/// A send queue holding at most `limit` bytes of unwritten lines
+pub fn channel(limit: usize) -> (SendQueue, SendQueueReceiver) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let shared = Arc::new(Shared {
+        queued: AtomicUsize::new(0),
+        limit: AtomicUsize::new(limit),
+        closing: OnceLock::new(),
+        close: Notify::new(),
+    });
+    (SendQueue { tx, shared: shared.clone() }, SendQueueReceiver { rx, shared })
+}
+
+/// Bytes a message takes on the wire, CRLF included
+fn wire_len(message: &Message) -> usize {
+    message.to_string().len() + 2
+}
+
+impl SendQueue {
+    pub fn try_send(&self, message: Message) -> Result<(), SendError> {
+        if self.shared.closing.get().is_some() {
+            return Err(SendError::Closed);
+        }
+
+        let len = wire_len(&message);
+        let queued = self.shared.queued.fetch_add(len, Ordering::AcqRel) + len;
+        if queued > self.shared.limit.load(Ordering::Acquire) {
+            self.shared.queued.fetch_sub(len, Ordering::AcqRel);
+            self.close("SendQ exceeded");
+            return Err(SendError::Exceeded);
+        }
+
+        self.tx.send(message).map_err(|_| {
+            self.shared.queued.fetch_sub(len, Ordering::AcqRel);
+            SendError::Closed
+        })
+    }
+
+    /// Change the SendQ limit, as when a client moves connection class
+    pub fn set_limit(&self, limit: usize) {
+        self.shared.limit.store(limit, Ordering::Release);
+    }
+
+    /// Bytes waiting to be written
+    pub fn queued(&self) -> usize {
+        self.shared.queued.load(Ordering::Acquire)
+    }
+
+    /// Have the connection actor drop the client, showing `reason`. Only
+    /// the first reason given is kept.
+    pub fn close(&self, reason: impl Into<String>) {
+        if self.shared.closing.set(reason.into()).is_ok() {
+            self.shared.close.notify_one();
+        }
+    }
+
+    /// Resolves with the reason once the connection is to be closed, such
+    /// as when a sender overran the SendQ limit
+    pub async fn closed(&self) -> String {
+        if self.shared.closing.get().is_none() {
+            self.shared.close.notified().await;
+        }
+        self.shared.closing.get().cloned().unwrap_or_default()
+    }
+}
+
+impl SendQueueReceiver {
+    pub async fn recv(&mut self) -> Option<Message> {
+        let message = self.rx.recv().await?;
+        self.shared.queued.fetch_sub(wire_len(&message), Ordering::AcqRel);
+        Some(message)
+    }
+
+    #[cfg(test)]
+    pub fn try_recv(&mut self) -> Result<Message, mpsc::error::TryRecvError> {
+        let message = self.rx.try_recv()?;
+        self.shared.queued.fetch_sub(wire_len(&message), Ordering::AcqRel);
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_is_byte_limited() {
+        let line = Message::new("PRIVMSG").with_params(vec!["#test".to_string(), "hello".to_string()]);
+        let len = wire_len(&line);
+        let (tx, mut rx) = channel(len * 2);
+
+        tx.try_send(line.clone()).unwrap();
+        tx.try_send(line.clone()).unwrap();
+        assert_eq!(tx.queued(), len * 2);
+
+        // Draining makes room again
+        rx.try_recv().unwrap();
+        tx.try_send(line.clone()).unwrap();
+
+        // Overrunning the limit cuts the client off for good
+        assert_eq!(tx.try_send(line.clone()), Err(SendError::Exceeded));
+        rx.try_recv().unwrap();
+        assert_eq!(tx.try_send(line), Err(SendError::Closed));
+    }
+}
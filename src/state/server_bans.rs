@@ -0,0 +1,242 @@
+use std::net::IpAddr;
+
+use chrono::Utc;
+use dashmap::DashMap;
+use ipnet::IpNet;
+use tokio::sync::mpsc;
+
+use crate::db::Database;
+use crate::db::models::ServerBan;
+use crate::security::{mask_matches, SecurityError};
+
+/// Which kind of server ban a mask is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BanKind {
+    /// A user@host mask, checked at registration
+    Kline,
+    /// An IP address or CIDR range, checked as soon as a connection is accepted
+    Dline,
+}
+
+impl BanKind {
+    /// The letter stored with the ban and shown in STATS
+    pub fn letter(self) -> &'static str {
+        match self {
+            BanKind::Kline => "K",
+            BanKind::Dline => "D",
+        }
+    }
+
+    /// "K-Line" or "D-Line"
+    pub fn name(self) -> &'static str {
+        match self {
+            BanKind::Kline => "K-Line",
+            BanKind::Dline => "D-Line",
+        }
+    }
+
+    fn of(ban: &ServerBan) -> Option<Self> {
+        match ban.kind.as_str() {
+            "K" => Some(BanKind::Kline),
+            "D" => Some(BanKind::Dline),
+            _ => None,
+        }
+    }
+}
+
+/// A change queued for the database writer task
+#[derive(Debug)]
+enum ServerBanChange {
+    Save(ServerBan),
+    Delete { kind: &'static str, mask: String },
+}
+
+/// K-lines and D-lines, keyed by lowercased mask
+#[derive(Debug, Default)]
+pub struct ServerBans {
+    klines: DashMap<String, ServerBan>,
+    dlines: DashMap<String, ServerBan>,
+    /// Changed bans are queued here for the database writer task
+    writer: Option<mpsc::UnboundedSender<ServerBanChange>>,
+}
+
+fn is_active(ban: &ServerBan) -> bool {
+    ban.expires_at.is_none_or(|expires| expires > Utc::now())
+}
+
+/// Whether a D-line mask, a single address or a CIDR range, covers `ip`
+fn dline_matches(mask: &str, ip: IpAddr) -> bool {
+    match mask.parse::<IpNet>() {
+        Ok(net) => net.contains(&ip),
+        Err(_) => mask.parse::<IpAddr>().is_ok_and(|addr| addr == ip),
+    }
+}
+
+/// Whether a K-line's user@host mask covers a client. The host part may be
+/// a wildcard hostname or an address range.
+fn kline_matches(mask: &str, username: &str, hostname: &str, ip: IpAddr) -> bool {
+    let Some((user, host)) = mask.rsplit_once('@') else {
+        return false;
+    };
+    mask_matches(user, username)
+        && (dline_matches(host, ip) || mask_matches(host, hostname) || mask_matches(host, &ip.to_string()))
+}
+
+/// Shortest CIDR prefixes DLINE accepts, so that a typo can't ban a whole
+/// address family
+const MIN_DLINE_PREFIX_V4: u8 = 8;
+const MIN_DLINE_PREFIX_V6: u8 = 16;
+
+/// Whether `mask` is something DLINE can match on: an address, or a range
+/// no wider than a /8 (IPv4) or /16 (IPv6)
+pub fn is_valid_dline(mask: &str) -> bool {
+    match mask.parse::<IpNet>() {
+        Ok(IpNet::V4(net)) => net.prefix_len() >= MIN_DLINE_PREFIX_V4,
+        Ok(IpNet::V6(net)) => net.prefix_len() >= MIN_DLINE_PREFIX_V6,
+        Err(_) => mask.parse::<IpAddr>().is_ok(),
+    }
+}
+
+impl ServerBans {
+    /// Load saved bans and write every later change back to `db` on a
+    /// background task
+    pub fn attach_database(&mut self, db: Database, saved: Vec<ServerBan>) {
+        for ban in saved {
+            if let Some(kind) = BanKind::of(&ban) {
+                self.map(kind).insert(ban.mask.to_lowercase(), ban);
+            }
+        }
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<ServerBanChange>();
+        tokio::spawn(async move {
+            while let Some(change) = rx.recv().await {
+                let (mask, result) = match change {
+                    ServerBanChange::Save(ban) => {
+                        let result = db.save_server_ban(&ban).await;
+                        (ban.mask, result)
+                    }
+                    ServerBanChange::Delete { kind, mask } => {
+                        let result = db.delete_server_ban(kind, &mask).await;
+                        (mask, result)
+                    }
+                };
+                if let Err(e) = result {
+                    tracing::error!("Failed to write server ban {}: {}", mask, e);
+                }
+            }
+        });
+        self.writer = Some(tx);
+    }
+
+    fn persist(&self, change: ServerBanChange) {
+        if let Some(writer) = &self.writer {
+            let _ = writer.send(change);
+        }
+    }
+
+    fn map(&self, kind: BanKind) -> &DashMap<String, ServerBan> {
+        match kind {
+            BanKind::Kline => &self.klines,
+            BanKind::Dline => &self.dlines,
+        }
+    }
+
+    /// Add a ban, replacing any on the same mask
+    pub fn add(&self, kind: BanKind, ban: ServerBan) {
+        let ban = ServerBan { kind: kind.letter().to_string(), ..ban };
+        self.persist(ServerBanChange::Save(ban.clone()));
+        self.map(kind).insert(ban.mask.to_lowercase(), ban);
+    }
+
+    /// Lift a ban. Returns None if there was no such ban in force.
+    pub fn remove(&self, kind: BanKind, mask: &str) -> Option<ServerBan> {
+        let (_, ban) = self.map(kind).remove(&mask.to_lowercase())?;
+        self.persist(ServerBanChange::Delete { kind: kind.letter(), mask: ban.mask.clone() });
+        is_active(&ban).then_some(ban)
+    }
+
+    /// Bans of one kind still in force, oldest first. Expired ones are
+    /// dropped along the way.
+    pub fn list(&self, kind: BanKind) -> Vec<ServerBan> {
+        let map = self.map(kind);
+        let expired: Vec<String> = map.iter()
+            .filter(|entry| !is_active(entry.value()))
+            .map(|entry| entry.key().clone())
+            .collect();
+        for key in expired {
+            if let Some((_, ban)) = map.remove(&key) {
+                self.persist(ServerBanChange::Delete { kind: kind.letter(), mask: ban.mask });
+            }
+        }
+
+        let mut bans: Vec<ServerBan> = map.iter().map(|entry| entry.value().clone()).collect();
+        bans.sort_by_key(|ban| ban.set_at);
+        bans
+    }
+
+    /// Refuse a connection from a D-lined address
+    pub fn check_address(&self, ip: IpAddr) -> Result<(), SecurityError> {
+        match self.dlines.iter().find(|entry| is_active(entry.value()) && dline_matches(&entry.mask, ip)) {
+            Some(entry) => Err(SecurityError::Banned(entry.reason.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Refuse registration to a K-lined client
+    pub fn check_user(&self, username: &str, hostname: &str, ip: IpAddr) -> Result<(), SecurityError> {
+        let found = self.klines.iter()
+            .find(|entry| is_active(entry.value()) && kline_matches(&entry.mask, username, hostname, ip));
+        match found {
+            Some(entry) => Err(SecurityError::Banned(entry.reason.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether a ban of `kind` on `mask` covers a client
+    pub fn matches(kind: BanKind, mask: &str, username: &str, hostname: &str, ip: IpAddr) -> bool {
+        match kind {
+            BanKind::Kline => kline_matches(mask, username, hostname, ip),
+            BanKind::Dline => dline_matches(mask, ip),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ban(mask: &str, expires_in: Option<i64>) -> ServerBan {
+        ServerBan {
+            kind: String::new(),
+            mask: mask.to_string(),
+            set_by: "oper".to_string(),
+            set_at: Utc::now(),
+            reason: "spam".to_string(),
+            expires_at: expires_in.map(|secs| Utc::now() + chrono::Duration::seconds(secs)),
+        }
+    }
+
+    #[test]
+    fn test_klines_and_dlines_match() {
+        let bans = ServerBans::default();
+        let ip: IpAddr = "192.0.2.7".parse().unwrap();
+
+        bans.add(BanKind::Kline, ban("evil@*", None));
+        bans.add(BanKind::Kline, ban("*@198.51.100.0/24", None));
+        bans.add(BanKind::Dline, ban("2001:db8::/32", None));
+        bans.add(BanKind::Dline, ban("203.0.113.9", Some(-60)));
+
+        assert!(matches!(bans.check_user("evil", "192.0.2.7", ip), Err(SecurityError::Banned(reason)) if reason == "spam"));
+        assert!(bans.check_user("good", "192.0.2.7", ip).is_ok());
+        assert!(bans.check_user("good", "x", "198.51.100.20".parse().unwrap()).is_err());
+
+        assert!(bans.check_address("2001:db8::1".parse().unwrap()).is_err());
+        // Expired bans no longer apply and are dropped when listed
+        assert!(bans.check_address("203.0.113.9".parse().unwrap()).is_ok());
+        assert_eq!(bans.list(BanKind::Dline).len(), 1);
+        assert!(bans.remove(BanKind::Dline, "203.0.113.9").is_none());
+
+        assert!(bans.remove(BanKind::Kline, "EVIL@*").is_some());
+        assert!(bans.check_user("evil", "192.0.2.7", ip).is_ok());
+    }
+}
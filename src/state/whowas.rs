@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+
+use super::Connection;
+
+/// A past identity, recorded when a client disconnects or changes nickname
+#[derive(Debug, Clone)]
+pub struct WhowasEntry {
+    pub nick: String,
+    pub username: String,
+    pub hostname: String,
+    pub ip: IpAddr,
+    pub realname: String,
+    pub account: Option<String>,
+    pub server: String,
+    pub signoff: DateTime<Utc>,
+}
+
+impl WhowasEntry {
+    pub fn from_connection(conn: &Connection, server: &str) -> Option<Self> {
+        Some(Self {
+            nick: conn.nickname.clone()?,
+            username: conn.username.clone().unwrap_or_else(|| "*".to_string()),
+            hostname: conn.hostname.clone(),
+            ip: conn.addr.ip(),
+            realname: conn.realname.clone().unwrap_or_default(),
+            account: conn.account.clone(),
+            server: server.to_string(),
+            signoff: Utc::now(),
+        })
+    }
+}
+
+/// Past identities keyed by lowercased nickname, newest first. Each nickname
+/// keeps at most `per_nick` entries and at most `max_nicks` nicknames are
+/// remembered; the one signed off longest ago makes way for a new one.
+#[derive(Debug)]
+pub struct WhowasHistory {
+    entries: DashMap<String, VecDeque<WhowasEntry>>,
+    per_nick: usize,
+    max_nicks: usize,
+}
+
+impl WhowasHistory {
+    pub fn new(per_nick: usize, max_nicks: usize) -> Self {
+        Self {
+            entries: DashMap::new(),
+            per_nick,
+            max_nicks,
+        }
+    }
+
+    pub fn record(&self, entry: WhowasEntry) {
+        if self.per_nick == 0 || self.max_nicks == 0 {
+            return;
+        }
+
+        let key = entry.nick.to_lowercase();
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.max_nicks {
+            let oldest = self.entries.iter()
+                .min_by_key(|history| history.front().map(|entry| entry.signoff))
+                .map(|history| history.key().clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        let mut history = self.entries.entry(key).or_default();
+        history.push_front(entry);
+        history.truncate(self.per_nick);
+    }
+
+    /// Up to `count` entries for `nick`, newest first; None means all
+    pub fn lookup(&self, nick: &str, count: Option<usize>) -> Vec<WhowasEntry> {
+        self.entries.get(&nick.to_lowercase())
+            .map(|history| history.iter().take(count.unwrap_or(usize::MAX)).cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(nick: &str, user: &str) -> WhowasEntry {
+        WhowasEntry {
+            nick: nick.to_string(),
+            username: user.to_string(),
+            hostname: "host.example".to_string(),
+            ip: "192.0.2.7".parse().unwrap(),
+            realname: String::new(),
+            account: None,
+            server: "centurion.local".to_string(),
+            signoff: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let history = WhowasHistory::new(2, 2);
+        history.record(entry("alice", "first"));
+        history.record(entry("Alice", "second"));
+        history.record(entry("ALICE", "third"));
+
+        let users: Vec<_> = history.lookup("alice", None).into_iter().map(|entry| entry.username).collect();
+        assert_eq!(users, ["third", "second"]);
+        assert_eq!(history.lookup("alice", Some(1)).len(), 1);
+
+        // A third nickname pushes out the one signed off longest ago
+        history.record(entry("bob", "bob"));
+        history.record(entry("carol", "carol"));
+        assert!(history.lookup("alice", None).is_empty());
+        assert_eq!(history.lookup("carol", None).len(), 1);
+    }
+}
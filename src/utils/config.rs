@@ -1,5 +1,7 @@
 use config::{Config, ConfigError, File};
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -10,6 +12,37 @@ pub struct ServerConfig {
     pub security: SecuritySettings,
     pub limits: LimitSettings,
     pub features: FeatureSettings,
+    #[serde(default)]
+    pub registration: RegistrationSettings,
+    /// Connection classes, tried in order; clients matching none of them
+    /// get the defaults from `limits`
+    #[serde(default)]
+    pub classes: Vec<ClassSettings>,
+}
+
+/// A named connection class (`[[classes]]`). Limits left unset are taken
+/// from `limits`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ClassSettings {
+    pub name: String,
+    /// Address ranges in CIDR form; empty matches every address
+    pub hosts: Vec<IpNet>,
+    /// Accounts placed in this class once logged in; empty matches
+    /// connections by address alone
+    pub accounts: Vec<String>,
+    pub max_clients: Option<usize>,
+    pub max_clients_per_ip: Option<usize>,
+    pub max_clients_per_64: Option<usize>,
+    pub connect_throttle: Option<usize>,
+    pub connect_throttle_interval: Option<u64>,
+    pub ping_frequency: Option<u64>,
+    pub sendq: Option<usize>,
+    pub flood_messages: Option<usize>,
+    pub flood_interval: Option<u64>,
+    pub flood_ceiling: Option<usize>,
+    /// Skip flood control entirely
+    pub flood_exempt: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -43,23 +76,128 @@ pub struct SecuritySettings {
     pub require_tls: bool,
     pub min_tls_version: String,
     pub password_hash_algorithm: String,
+    /// Argon2id memory cost in KiB
+    #[serde(default = "default_argon2_memory_cost")]
+    pub argon2_memory_cost: u32,
+    /// Argon2id number of passes
+    #[serde(default = "default_argon2_time_cost")]
+    pub argon2_time_cost: u32,
+    /// Argon2id degree of parallelism
+    #[serde(default = "default_argon2_parallelism")]
+    pub argon2_parallelism: u32,
+    /// Password for the operator named `operator_name`, as an Argon2 hash
+    /// or plain text
     pub operator_password: Option<String>,
+    #[serde(default)]
+    pub operator_name: Option<String>,
+    /// Privileges for `operator_name`, none beyond +o unless listed
+    #[serde(default)]
+    pub operator_privileges: String,
+}
+
+fn default_argon2_memory_cost() -> u32 {
+    argon2::Params::DEFAULT_M_COST
+}
+
+fn default_argon2_time_cost() -> u32 {
+    argon2::Params::DEFAULT_T_COST
+}
+
+fn default_argon2_parallelism() -> u32 {
+    argon2::Params::DEFAULT_P_COST
+}
+
+fn default_max_list_entries() -> usize {
+    100
+}
+
+fn default_invite_expiry() -> u64 {
+    3600
+}
+
+fn default_max_monitor_entries() -> usize {
+    100
+}
+
+fn default_whowas_per_nick() -> usize {
+    8
+}
+
+fn default_whowas_max_nicks() -> usize {
+    4096
+}
+
+fn default_sendq() -> usize {
+    1024 * 1024
+}
+
+fn default_flood_ceiling() -> usize {
+    100
+}
+
+fn default_max_clients_per_64() -> usize {
+    20
+}
+
+fn default_connect_throttle() -> usize {
+    10
+}
+
+fn default_connect_throttle_interval() -> u64 {
+    60
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LimitSettings {
     pub max_clients: usize,
     pub max_clients_per_ip: usize,
+    /// Clients allowed from one IPv6 /64
+    #[serde(default = "default_max_clients_per_64")]
+    pub max_clients_per_64: usize,
+    /// Connections one address may open per `connect_throttle_interval`
+    /// seconds
+    #[serde(default = "default_connect_throttle")]
+    pub connect_throttle: usize,
+    #[serde(default = "default_connect_throttle_interval")]
+    pub connect_throttle_interval: u64,
     pub max_channels_per_user: usize,
     pub max_nickname_length: usize,
     pub max_channel_name_length: usize,
     pub max_topic_length: usize,
     pub max_message_length: usize,
     pub max_away_length: usize,
+    /// Combined size cap of a channel's +b, +e and +I lists (MAXLIST)
+    #[serde(default = "default_max_list_entries")]
+    pub max_list_entries: usize,
+    /// Seconds an INVITE stays usable
+    #[serde(default = "default_invite_expiry")]
+    pub invite_expiry: u64,
+    /// Nicknames one client may MONITOR
+    #[serde(default = "default_max_monitor_entries")]
+    pub max_monitor_entries: usize,
+    /// WHOWAS entries kept for each nickname
+    #[serde(default = "default_whowas_per_nick")]
+    pub whowas_per_nick: usize,
+    /// Nicknames WHOWAS remembers at once
+    #[serde(default = "default_whowas_max_nicks")]
+    pub whowas_max_nicks: usize,
+    /// Bytes of unwritten output a client may have queued before it is
+    /// disconnected
+    #[serde(default = "default_sendq")]
+    pub sendq: usize,
     pub ping_frequency: u64,
     pub ping_timeout: u64,
+    /// Single-penalty commands a client may send per `flood_interval`
+    /// seconds before its commands are delayed
     pub flood_messages: usize,
     pub flood_interval: u64,
+    /// Penalty units of delayed commands a client may build up before it
+    /// is disconnected for flooding
+    #[serde(default = "default_flood_ceiling")]
+    pub flood_ceiling: usize,
+    /// Per-command penalties overriding the built-in ones, e.g. `LIST = 10`
+    #[serde(default)]
+    pub command_penalties: HashMap<String, usize>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -81,6 +219,40 @@ pub struct FeatureSettings {
     pub enable_setname: bool,
 }
 
+/// Self-service account registration (IRCv3 draft/account-registration)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RegistrationSettings {
+    pub enabled: bool,
+    /// Allow REGISTER before the connection has completed registration
+    pub before_connect: bool,
+    pub email_required: bool,
+    /// Hold new accounts until the emailed code is confirmed with VERIFY
+    pub email_verification: bool,
+    /// Seconds an unverified registration is kept
+    pub verification_timeout: u64,
+    pub min_password_length: usize,
+    /// "none" or "file"
+    pub mail_sender: String,
+    /// Directory used by the file mail sender
+    pub mail_drop_dir: String,
+}
+
+impl Default for RegistrationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            before_connect: true,
+            email_required: false,
+            email_verification: false,
+            verification_timeout: 3600,
+            min_password_length: 8,
+            mail_sender: "none".to_string(),
+            mail_drop_dir: "mail".to_string(),
+        }
+    }
+}
+
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
@@ -88,7 +260,7 @@ impl Default for ServerConfig {
                 name: "ironchatd.local".to_string(),
                 description: "IronChat IRC Server".to_string(),
                 listen_addresses: vec!["127.0.0.1:6667".to_string()],
-                tls_listen_addresses: vec!["127.0.0.1:6697".to_string()],
+                tls_listen_addresses: Vec::new(),
                 motd_file: None,
             },
             network: NetworkSettings {
@@ -108,21 +280,37 @@ impl Default for ServerConfig {
                 require_tls: false,
                 min_tls_version: "1.2".to_string(),
                 password_hash_algorithm: "argon2".to_string(),
+                argon2_memory_cost: default_argon2_memory_cost(),
+                argon2_time_cost: default_argon2_time_cost(),
+                argon2_parallelism: default_argon2_parallelism(),
                 operator_password: None,
+                operator_name: None,
+                operator_privileges: String::new(),
             },
             limits: LimitSettings {
                 max_clients: 10000,
                 max_clients_per_ip: 10,
+                max_clients_per_64: default_max_clients_per_64(),
+                connect_throttle: default_connect_throttle(),
+                connect_throttle_interval: default_connect_throttle_interval(),
                 max_channels_per_user: 50,
                 max_nickname_length: 30,
                 max_channel_name_length: 50,
                 max_topic_length: 390,
                 max_message_length: 512,
                 max_away_length: 255,
+                max_list_entries: default_max_list_entries(),
+                invite_expiry: default_invite_expiry(),
+                max_monitor_entries: default_max_monitor_entries(),
+                whowas_per_nick: default_whowas_per_nick(),
+                whowas_max_nicks: default_whowas_max_nicks(),
+                sendq: default_sendq(),
                 ping_frequency: 120,
                 ping_timeout: 60,
                 flood_messages: 10,
                 flood_interval: 1,
+                flood_ceiling: default_flood_ceiling(),
+                command_penalties: HashMap::new(),
             },
             features: FeatureSettings {
                 enable_sasl: true,
@@ -141,6 +329,8 @@ impl Default for ServerConfig {
                 enable_multi_prefix: true,
                 enable_setname: true,
             },
+            registration: RegistrationSettings::default(),
+            classes: Vec::new(),
         }
     }
 }
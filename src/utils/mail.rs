@@ -0,0 +1,105 @@
+//! Outgoing mail for account verification
+
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::utils::config::RegistrationSettings;
+
+#[derive(Error, Debug)]
+pub enum MailError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Unknown mail sender '{0}', expected none or file")]
+    UnknownSender(String),
+}
+
+/// A single outgoing message
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Mail {
+    /// RFC 5322 rendering with CRLF line endings
+    pub fn to_rfc5322(&self) -> String {
+        format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n",
+            self.from,
+            self.to,
+            self.subject,
+            chrono::Utc::now().to_rfc2822(),
+            self.body.replace('\n', "\r\n"),
+        )
+    }
+}
+
+/// Transport for outgoing mail
+#[async_trait]
+pub trait MailSender: Send + Sync + std::fmt::Debug {
+    async fn send(&self, mail: &Mail) -> Result<(), MailError>;
+}
+
+/// Writes each message as an `.eml` file into a directory, for testing or
+/// for handing off to a local MTA that watches a spool directory
+#[derive(Debug)]
+pub struct FileDropSender {
+    dir: PathBuf,
+}
+
+impl FileDropSender {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl MailSender for FileDropSender {
+    async fn send(&self, mail: &Mail) -> Result<(), MailError> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let name = format!(
+            "{}-{}.eml",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%.6f"),
+            crate::utils::generate_message_id()
+        );
+        tokio::fs::write(self.dir.join(name), mail.to_rfc5322()).await?;
+        Ok(())
+    }
+}
+
+/// Build the sender named by `registration.mail_sender`. "none" disables mail.
+pub fn sender_from_settings(settings: &RegistrationSettings) -> Result<Option<Arc<dyn MailSender>>, MailError> {
+    match settings.mail_sender.to_lowercase().as_str() {
+        "none" | "" => Ok(None),
+        "file" => Ok(Some(Arc::new(FileDropSender::new(&settings.mail_drop_dir)))),
+        other => Err(MailError::UnknownSender(other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_drop_writes_message() {
+        let dir = std::env::temp_dir().join(format!("centurion-mail-{}", crate::utils::generate_message_id()));
+        let sender = FileDropSender::new(&dir);
+        sender.send(&Mail {
+            from: "admin@example.com".to_string(),
+            to: "alice@example.com".to_string(),
+            subject: "Verify".to_string(),
+            body: "code: 1234".to_string(),
+        }).await.unwrap();
+
+        let mut entries = std::fs::read_dir(&dir).unwrap();
+        let contents = std::fs::read_to_string(entries.next().unwrap().unwrap().path()).unwrap();
+        assert!(contents.contains("To: alice@example.com\r\n"));
+        assert!(contents.ends_with("code: 1234\r\n"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
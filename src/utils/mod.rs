@@ -2,6 +2,7 @@ use std::net::IpAddr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub mod config;
+pub mod mail;
 
 pub fn get_timestamp() -> u64 {
     SystemTime::now()
@@ -80,4 +81,57 @@ pub fn normalize_channel_name(name: &str) -> String {
 
 pub fn normalize_nickname(nick: &str) -> String {
     nick.to_lowercase()
-}
\ No newline at end of file
+}
+/// Remove mIRC colour codes and bold, italic, underline, strikethrough,
+/// monospace, reverse and reset control characters
+pub fn strip_formatting(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\x02' | '\x1d' | '\x1f' | '\x1e' | '\x11' | '\x16' | '\x0f' => {}
+            // \x03<fg>[,<bg>] with up to two digits each
+            '\x03' => {
+                for _ in 0..2 {
+                    chars.next_if(char::is_ascii_digit);
+                }
+                let mut lookahead = chars.clone();
+                if lookahead.next() == Some(',') && lookahead.peek().is_some_and(char::is_ascii_digit) {
+                    chars.next();
+                    for _ in 0..2 {
+                        chars.next_if(char::is_ascii_digit);
+                    }
+                }
+            }
+            // \x04<rrggbb>[,<rrggbb>]
+            '\x04' => {
+                for _ in 0..6 {
+                    chars.next_if(char::is_ascii_hexdigit);
+                }
+                let mut lookahead = chars.clone();
+                if lookahead.next() == Some(',') && lookahead.peek().is_some_and(char::is_ascii_hexdigit) {
+                    chars.next();
+                    for _ in 0..6 {
+                        chars.next_if(char::is_ascii_hexdigit);
+                    }
+                }
+            }
+            _ => stripped.push(ch),
+        }
+    }
+
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_formatting() {
+        assert_eq!(strip_formatting("\x02bold\x02 \x0304,12red\x03 \x1fu\x0f"), "bold red u");
+        assert_eq!(strip_formatting("\x035,text"), ",text");
+        assert_eq!(strip_formatting("\x04ff0000hex\x03"), "hex");
+    }
+}